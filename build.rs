@@ -0,0 +1,78 @@
+//! Generates the build information shown on the splash and about screens (see `src/about.rs`)
+//!
+//! The name shown is taken from `GARDEN_SYSTEM_NAME` if it is set. The build date is taken from
+//! `SOURCE_DATE_EPOCH` if it is set, so that builds can be reproduced, otherwise from the time of
+//! the build.
+
+use std::{
+	env, fs,
+	path::Path,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Name shown if `GARDEN_SYSTEM_NAME` isn't set
+const DEFAULT_NAME: &str = "Garden System";
+/// Longest name which fits on a row of the display
+const MAX_NAME_LENGTH: usize = 16;
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+fn main() {
+	// The date is only updated when the firmware changes, rather than on every build.
+	println!("cargo:rerun-if-changed=src");
+	println!("cargo:rerun-if-changed=Cargo.toml");
+	println!("cargo:rerun-if-env-changed=GARDEN_SYSTEM_NAME");
+	println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+	let name = env::var("GARDEN_SYSTEM_NAME").unwrap_or_else(|_| DEFAULT_NAME.into());
+	// The display only has ASCII characters.
+	if name.is_empty() || name.len() > MAX_NAME_LENGTH || !name.is_ascii() {
+		panic!(
+			"GARDEN_SYSTEM_NAME must be 1 to {} ASCII characters",
+			MAX_NAME_LENGTH
+		);
+	}
+
+	let secs = env::var("SOURCE_DATE_EPOCH")
+		.ok()
+		.and_then(|epoch| epoch.parse().ok())
+		.unwrap_or_else(|| {
+			SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map(|duration| duration.as_secs())
+				.unwrap_or(0)
+		});
+	let (year, month, day) = civil_from_days((secs / SECS_PER_DAY) as i64);
+
+	let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+	let contents = format!(
+		"/// Name of the system\n\
+		 pub const NAME: &str = {:?};\n\
+		 /// Date the firmware was built, as `YYYY-MM-DD`\n\
+		 pub const BUILD_DATE: &str = \"{:04}-{:02}-{:02}\";\n",
+		name, year, month, day
+	);
+	fs::write(Path::new(&out_dir).join("build_info.rs"), contents)
+		.expect("failed to write the build information");
+}
+
+/// Year, month and day of the amount of days since 1970-01-01
+///
+/// Ref: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	let days = days + 719_468;
+	let era = days.div_euclid(146_097);
+	let day_of_era = days.rem_euclid(146_097);
+	let year_of_era =
+		(day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month_index = (5 * day_of_year + 2) / 153;
+	let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+	let month = if month_index < 10 {
+		month_index + 3
+	} else {
+		month_index - 9
+	} as u32;
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+	(year, month, day)
+}
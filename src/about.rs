@@ -0,0 +1,87 @@
+//! Firmware information
+//!
+//! The name, version and build date of the firmware are shown on a splash screen while the system
+//! starts up along with the cause of the last reset, and on the about screen along with the uptime
+//! since the system started. The name and build date are generated by `build.rs`, and the version
+//! is the one in `Cargo.toml`.
+
+use crate::{display::BODY_START_ROW, fmt::DaysHoursMins, hal::DisplayBus, lang};
+
+include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
+
+/// Version of the firmware
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Render the splash screen in the middle of the display
+///
+/// Only the body of the display is cleared, so this should be rendered before the header.
+pub fn render_splash(display: &mut impl DisplayBus, reset_cause: &str) {
+	display.clear_body();
+	render_build(display, BODY_START_ROW + 1);
+	display.set_position(0, BODY_START_ROW + 5);
+	let _ = ufmt::uwrite!(display, "{}: {}", lang::RESET_CAUSE, reset_cause);
+}
+
+/// Render the about screen in the body of the display
+pub fn render(display: &mut impl DisplayBus, uptime_secs: u32, reset_cause: &str) {
+	display.clear_body();
+	display.set_position(0, BODY_START_ROW);
+	let _ = ufmt::uwrite!(display, "{}", lang::ABOUT);
+	render_build(display, BODY_START_ROW + 1);
+
+	display.set_position(0, BODY_START_ROW + 4);
+	let _ = ufmt::uwrite!(display, "{}: {}", lang::UPTIME, DaysHoursMins(uptime_secs));
+	display.set_position(0, BODY_START_ROW + 5);
+	let _ = ufmt::uwrite!(display, "{}: {}", lang::RESET_CAUSE, reset_cause);
+}
+
+/// Render the name, version and build date, from `row` onwards
+fn render_build(display: &mut impl DisplayBus, row: u8) {
+	display.set_position(0, row);
+	let _ = ufmt::uwrite!(display, "{}", NAME);
+	display.set_position(0, row + 1);
+	let _ = ufmt::uwrite!(display, "v{}", VERSION);
+	display.set_position(0, row + 2);
+	let _ = ufmt::uwrite!(display, "{} {}", lang::BUILT, BUILD_DATE);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::hal::mock::MockDisplay;
+
+	#[test]
+	fn renders_about_screen() {
+		let mut display = MockDisplay::new();
+		render(&mut display, 90_000, "Brown-out");
+
+		assert_eq!(display.row(BODY_START_ROW).trim_end(), "About");
+		assert_eq!(display.row(BODY_START_ROW + 1).trim_end(), NAME);
+		assert_eq!(
+			display.row(BODY_START_ROW + 2).trim_end(),
+			format!("v{}", VERSION)
+		);
+		assert!(display.row(BODY_START_ROW + 3).starts_with("Built 20"));
+		assert_eq!(display.row(BODY_START_ROW + 4).trim_end(), "Up: 1d 1h 0m");
+		assert_eq!(display.row(BODY_START_ROW + 5), "Reset: Brown-out");
+	}
+
+	#[test]
+	fn splash_screen_shows_reset_cause() {
+		let mut display = MockDisplay::new();
+		render_splash(&mut display, "Power-on");
+
+		assert_eq!(display.row(BODY_START_ROW + 1).trim_end(), NAME);
+		assert_eq!(
+			display.row(BODY_START_ROW + 5).trim_end(),
+			"Reset: Power-on"
+		);
+	}
+
+	#[test]
+	fn build_date_fits_on_a_row() {
+		assert_eq!(BUILD_DATE.len(), 10);
+		assert_eq!(&BUILD_DATE[4..5], "-");
+		assert_eq!(&BUILD_DATE[7..8], "-");
+	}
+}
@@ -0,0 +1,884 @@
+//! Activation state machine
+//!
+//! Moves the system between waiting, activated, suspended, faulted and winterized (see
+//! [`SystemState`]), based on the sensor readings and how long it has been in each state. It
+//! doesn't use any hardware directly, so that it can also be run by the simulation (see
+//! `src/sim.rs`).
+//!
+//! Every change of state goes through [`transition`], which runs the exit hook of the current state
+//! and the entry hook of the next one. The hooks start and stop the timers of the states, and
+//! return the [`Transition`]s for the caller to announce, such as by beeping or by rendering.
+//!
+//! A fault (see [`FaultKind`]) stops the system, and nothing but acknowledging it from the menu
+//! moves the system out of the fault state.
+//!
+//! Winterizing the system stops it for the winter. Unlike a suspension, it doesn't end after a
+//! while, and nothing but re-enabling the system (or resetting the configuration) moves it out of
+//! the winterized state.
+//!
+//! While the system is waiting, it is activated by one of three strategies (see [`Strategy`]). By
+//! default, the light and moisture readings activate it. Under band control (see [`crate::band`]),
+//! the moisture falling below the band activates it, and it waters in pulses until the moisture is
+//! back in the band. In misting mode, meant for greenhouses, it is instead activated for a few
+//! seconds at a time, every so often, while it is hot.
+
+use ufmt::{derive::uDebug, uDisplay, uWrite};
+
+use crate::{
+	band,
+	bus::Bus,
+	config::{SystemConfig, UpdateSystemValue},
+	selftest,
+	timer::{Duration, SoftTimer},
+};
+
+/// How often the sensors are read to check whether the system should activate
+const SENSOR_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the sensor readings have to be implausible for before they are treated as a fault
+const SENSOR_FAULT_DURATION: Duration = Duration::from_mins(1);
+
+/// Fault which stops the system until it is acknowledged
+#[derive(uDebug, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FaultKind {
+	/// The light or moisture sensor kept giving readings at either end of the ADC's range
+	Sensor,
+	/// The valve was open for longer than the fail-safe allows, so the water isn't flowing as it
+	/// should
+	Valve,
+	/// The system was restarted by the watchdog
+	Watchdog,
+}
+
+impl FaultKind {
+	/// Code of the fault, which is shown on the display
+	pub fn code(self) -> u8 {
+		match self {
+			Self::Sensor => 1,
+			Self::Valve => 2,
+			Self::Watchdog => 3,
+		}
+	}
+
+	/// Fault with the given code, if it is valid
+	pub fn from_code(code: u8) -> Option<Self> {
+		match code {
+			1 => Some(Self::Sensor),
+			2 => Some(Self::Valve),
+			3 => Some(Self::Watchdog),
+			_ => None,
+		}
+	}
+}
+
+/// Used when rendering the fault code, such as `E2`
+impl uDisplay for FaultKind {
+	fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		ufmt::uwrite!(f, "E{}", self.code())
+	}
+}
+
+/// Way in which the system is activated while it is waiting
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Strategy {
+	/// The light and moisture readings activate the system
+	Sensors,
+	/// Pulses until the moisture is back in the moisture band, once it falls below it
+	Band,
+	/// Short bursts every misting interval, while the temperature is above the misting threshold
+	Misting,
+}
+
+impl Strategy {
+	/// Strategy selected by the configuration
+	pub fn of(system_config: &SystemConfig) -> Self {
+		if system_config.mist_celsius != 0 {
+			Self::Misting
+		} else if system_config.band_low != 0 {
+			Self::Band
+		} else {
+			Self::Sensors
+		}
+	}
+}
+
+/// State of the system
+#[derive(uDebug, Clone, Copy, PartialEq, Debug)]
+pub enum SystemState {
+	/// Waiting to be activated - Sensor readings are being performed
+	Waiting,
+	/// Currently activated - No sensor readings are performed
+	Activated,
+	/// Suspended - No sensor readings are performed
+	Suspended,
+	/// Stopped by a fault until it is acknowledged - No sensor readings are performed
+	Fault(FaultKind),
+	/// Winterized until it is re-enabled - No sensor readings are performed, and every output is
+	/// kept off
+	Winterized,
+}
+
+impl SystemState {
+	/// Whether the system is currently activated
+	pub fn is_activated(self) -> bool {
+		matches!(self, Self::Activated)
+	}
+
+	/// Whether the system is currently suspended
+	pub fn is_suspended(self) -> bool {
+		matches!(self, Self::Suspended)
+	}
+
+	/// Whether the system is currently waiting
+	pub fn is_waiting(self) -> bool {
+		matches!(self, Self::Waiting)
+	}
+
+	/// Whether the system is currently winterized
+	pub fn is_winterized(self) -> bool {
+		matches!(self, Self::Winterized)
+	}
+
+	/// Fault which has stopped the system, if there is one
+	pub fn fault(self) -> Option<FaultKind> {
+		match self {
+			Self::Fault(kind) => Some(kind),
+			_ => None,
+		}
+	}
+
+	/// State which `update` moves the system to, if it is an update to the state
+	fn after(self, update: &UpdateSystemValue) -> Option<Self> {
+		match (update, self) {
+			// A fault can only be left by acknowledging it, and the first fault is kept until then.
+			(UpdateSystemValue::Acknowledge, Self::Fault(_)) => Some(Self::Waiting),
+			(_, Self::Fault(_)) => None,
+			// Winterize toggles the winterization, which nothing else but resetting the
+			// configuration leaves.
+			(UpdateSystemValue::Winterize, Self::Winterized) => Some(Self::Waiting),
+			(UpdateSystemValue::Winterize, _) => Some(Self::Winterized),
+			(UpdateSystemValue::Reset, Self::Winterized) => Some(Self::Waiting),
+			(_, Self::Winterized) => None,
+			(&UpdateSystemValue::Fault(kind), _) => Some(Self::Fault(kind)),
+			// Activate and Suspend toggle the activation and the suspension.
+			(UpdateSystemValue::Activate, Self::Activated) => Some(Self::Waiting),
+			(UpdateSystemValue::Activate, _) => Some(Self::Activated),
+			(UpdateSystemValue::Suspend, Self::Suspended) => Some(Self::Waiting),
+			(UpdateSystemValue::Suspend, _) => Some(Self::Suspended),
+			// The state machine moves the system on from each state.
+			(UpdateSystemValue::ActivationState, Self::Waiting) => Some(Self::Activated),
+			(UpdateSystemValue::ActivationState, _) => Some(Self::Waiting),
+			// Resetting the configuration leaves the system waiting.
+			(UpdateSystemValue::Reset, _) => Some(Self::Waiting),
+			_ => None,
+		}
+	}
+}
+
+/// Change to the state of the system
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Transition {
+	/// The system started watering
+	Started,
+	/// The system stopped watering
+	Stopped,
+	/// The system was suspended
+	Suspended,
+	/// The suspension ended, or was cancelled
+	Resumed,
+	/// A fault stopped the system
+	Faulted(FaultKind),
+	/// The fault was acknowledged
+	Cleared,
+	/// The system was winterized
+	Winterized,
+	/// The system was re-enabled after being winterized
+	Reenabled,
+}
+
+/// Changes made by a single [`transition`], from leaving the current state and entering the next
+pub type Transitions = [Option<Transition>; 2];
+
+/// Move the system to the state which `update` requests, if it is an update to the state
+///
+/// Returns the changes to the state, for the caller to announce.
+pub fn transition(system_config: &mut SystemConfig, update: &UpdateSystemValue) -> Transitions {
+	match system_config.activation_state.after(update) {
+		Some(next) => move_to(system_config, next),
+		None => [None, None],
+	}
+}
+
+/// Leave the current state and enter `next`, unless the system is already in it
+fn move_to(system_config: &mut SystemConfig, next: SystemState) -> Transitions {
+	let current = system_config.activation_state;
+	if current == next {
+		return [None, None];
+	}
+
+	let exited = on_exit(system_config, current);
+	system_config.activation_state = next;
+	[exited, on_enter(next)]
+}
+
+/// Hook run as the system enters `state`
+fn on_enter(state: SystemState) -> Option<Transition> {
+	match state {
+		SystemState::Waiting => None,
+		SystemState::Fault(kind) => Some(Transition::Faulted(kind)),
+		SystemState::Activated => {
+			SoftTimer::Activation.start();
+			Some(Transition::Started)
+		}
+		SystemState::Suspended => {
+			SoftTimer::Suspension.start();
+			Some(Transition::Suspended)
+		}
+		SystemState::Winterized => Some(Transition::Winterized),
+	}
+}
+
+/// Hook run as the system leaves `state`
+fn on_exit(system_config: &mut SystemConfig, state: SystemState) -> Option<Transition> {
+	match state {
+		SystemState::Waiting => None,
+		SystemState::Activated => {
+			SoftTimer::Activation.stop();
+			SoftTimer::SinceWatering.start();
+			// An adjusted activation time only lasts for the activation it was adjusted for.
+			system_config.adjusted_duration = None;
+			Some(Transition::Stopped)
+		}
+		SystemState::Suspended => {
+			SoftTimer::Suspension.stop();
+			Some(Transition::Resumed)
+		}
+		SystemState::Fault(_) => {
+			SoftTimer::SensorFault.stop();
+			Some(Transition::Cleared)
+		}
+		SystemState::Winterized => Some(Transition::Reenabled),
+	}
+}
+
+/// Send the next update to the state onto `bus`, if it should change
+///
+/// `read_sensors` returns the light and moisture readings. It is only called at most once every
+/// [`SENSOR_SAMPLE_INTERVAL`], while the system is waiting and can water and the minimum interval
+/// has passed since the last activation, or while band control is watering. `can_trigger` is
+/// whether the readings may activate the system (see [`crate::evening`]); unlike `can_water`, it
+/// doesn't cancel an activation which has already started. `temperature` is the latest
+/// temperature in tenths of a degree, if there is one, which is only used in misting mode.
+///
+/// If the readings stay at either end of the ADC's range for [`SENSOR_FAULT_DURATION`], a sensor
+/// fault is sent instead.
+///
+/// Returns the strategy which activated the system, if it was activated.
+pub fn update_state(
+	system_config: &mut SystemConfig,
+	bus: &mut Bus,
+	can_water: bool,
+	can_trigger: bool,
+	temperature: Option<i16>,
+	read_sensors: impl FnOnce() -> (u16, u16),
+) -> Option<Strategy> {
+	let mut started = None;
+
+	let state = system_config.activation_state;
+	if state.fault().is_some() || state.is_winterized() {
+		// If the system has faulted or is winterized, nothing happens until the fault is
+		// acknowledged or the system is re-enabled.
+		return None;
+	} else if system_config.activation_state.is_suspended() {
+		// If the system is suspended and the timer has reached the suspension time, move it
+		// into the waiting state.
+		// TODO add suspension time value
+		if SoftTimer::Suspension.elapsed() >= system_config.activation_duration() {
+			bus.send(UpdateSystemValue::ActivationState);
+		}
+	} else if system_config.activation_state.is_activated() {
+		// If the system is activated and the valve has been open for the activation time, or the
+		// valve can't be opened, move it into the waiting state.
+		let elapsed = SoftTimer::Activation.elapsed();
+		if !can_water || system_config.watered(elapsed) >= system_config.run_duration() {
+			bus.send(UpdateSystemValue::ActivationState);
+		} else if system_config.is_band_control() && sensors_due() {
+			// If band control is watering, stop once the moisture is in the band after the water
+			// of a pulse has reached the sensor, or as soon as a pulse raises it above the band.
+			let (_, moisture) = read_sensors();
+			if (band::is_settled(elapsed) && moisture >= system_config.band_low)
+				|| moisture > system_config.band_high
+			{
+				bus.send(UpdateSystemValue::ActivationState);
+			}
+		}
+	} else if can_water {
+		let strategy = Strategy::of(system_config);
+		match strategy {
+			Strategy::Sensors | Strategy::Band
+				if can_trigger && interval_passed(system_config) && sensors_due() =>
+			{
+				let (light, moisture) = read_sensors();
+				if selftest::is_plausible(light) && selftest::is_plausible(moisture) {
+					// If the valve can be opened, and the sensors indicate that the system should
+					// be activated, move it into the activated state.
+					SoftTimer::SensorFault.stop();
+					if system_config.should_activate(light, moisture) {
+						bus.send(UpdateSystemValue::ActivationState);
+						started = Some(strategy);
+					}
+				} else if sensor_failed() {
+					// If the readings have been implausible for long enough, the sensor has most
+					// likely been disconnected.
+					bus.send(UpdateSystemValue::Fault(FaultKind::Sensor));
+				}
+			}
+			Strategy::Misting if is_hot(system_config, temperature) && mist_due(system_config) => {
+				// If it is hot, and the misting interval has passed since the last burst, mist
+				// for a few seconds.
+				bus.send(UpdateSystemValue::ActivationState);
+				system_config.adjusted_duration = Some(system_config.mist_duration());
+				started = Some(Strategy::Misting);
+			}
+			_ => {}
+		}
+	}
+
+	started
+}
+
+/// Whether the valve is closed between the runs or pulses of the current activation, to let the
+/// water soak in
+pub fn is_soaking(system_config: &SystemConfig) -> bool {
+	system_config.activation_state.is_activated()
+		&& system_config.is_soaking(SoftTimer::Activation.elapsed())
+}
+
+/// Whether the minimum interval has passed since the system last stopped watering (see
+/// [`SystemConfig::min_interval`])
+fn interval_passed(system_config: &SystemConfig) -> bool {
+	let timer = SoftTimer::SinceWatering;
+	!timer.is_running() || timer.elapsed() >= system_config.min_interval()
+}
+
+/// Whether `temperature` is above the misting threshold
+fn is_hot(system_config: &SystemConfig, temperature: Option<i16>) -> bool {
+	match temperature {
+		Some(temperature) => temperature as i32 > system_config.mist_celsius as i32 * 10,
+		None => false,
+	}
+}
+
+/// Whether the misting interval has passed since the system last stopped watering
+fn mist_due(system_config: &SystemConfig) -> bool {
+	let timer = SoftTimer::SinceWatering;
+	!timer.is_running() || timer.elapsed() >= system_config.mist_interval()
+}
+
+/// Whether the sensor readings have been implausible for [`SENSOR_FAULT_DURATION`], starting to
+/// time it if they have only just become implausible
+fn sensor_failed() -> bool {
+	let timer = SoftTimer::SensorFault;
+	if !timer.is_running() {
+		timer.start();
+	}
+	timer.elapsed() >= SENSOR_FAULT_DURATION
+}
+
+/// Whether the sensors should be read, which is at most once every [`SENSOR_SAMPLE_INTERVAL`]
+fn sensors_due() -> bool {
+	let timer = SoftTimer::SensorSampling;
+	if timer.is_running() && !timer.has_expired() {
+		return false;
+	}
+
+	timer.start_countdown(SENSOR_SAMPLE_INTERVAL);
+	true
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		bus::Notification,
+		config::Trigger,
+		profile::Profile,
+		timer::{self, tests::TimersGuard},
+	};
+
+	/// Readings below the default thresholds, which activate the system
+	const DARK_AND_DRY: (u16, u16) = (50, 50);
+	/// Readings above the default thresholds
+	const BRIGHT_AND_WET: (u16, u16) = (500, 500);
+
+	/// Configuration in the given state
+	fn config_in(state: SystemState) -> SystemConfig {
+		let mut system_config = SystemConfig::new();
+		move_to(&mut system_config, state);
+		system_config
+	}
+
+	/// Update the state, and apply the commands it sent
+	///
+	/// Returns the strategy which activated the system, and the changes made by the commands.
+	fn step(
+		system_config: &mut SystemConfig,
+		can_water: bool,
+		can_trigger: bool,
+		temperature: Option<i16>,
+		read_sensors: impl FnOnce() -> (u16, u16),
+	) -> (Option<Strategy>, Vec<Notification>) {
+		let mut bus = Bus::new();
+		let started = update_state(
+			system_config,
+			&mut bus,
+			can_water,
+			can_trigger,
+			temperature,
+			read_sensors,
+		);
+		bus.apply(system_config);
+
+		let mut notifications = Vec::new();
+		while let Some(notification) = bus.next_notification() {
+			notifications.push(notification);
+		}
+		(started, notifications)
+	}
+
+	/// Update the state with the given readings, returning the transitions it caused
+	fn tick(
+		system_config: &mut SystemConfig,
+		can_water: bool,
+		readings: (u16, u16),
+	) -> Vec<Transition> {
+		let (_, notifications) = step(system_config, can_water, true, None, || readings);
+		notifications
+			.into_iter()
+			.filter_map(|notification| match notification {
+				Notification::Transition(transition) => Some(transition),
+				Notification::Changed(..) => None,
+			})
+			.collect()
+	}
+
+	/// State after performing `update` from `state`
+	fn state_after(state: SystemState, update: UpdateSystemValue) -> SystemState {
+		let mut system_config = config_in(state);
+		transition(&mut system_config, &update);
+		system_config.activation_state
+	}
+
+	#[test]
+	fn waiting_activates_when_dark_and_dry() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Waiting);
+
+		assert_eq!(
+			tick(&mut system_config, true, DARK_AND_DRY),
+			vec![Transition::Started]
+		);
+		assert!(system_config.activation_state.is_activated());
+	}
+
+	#[test]
+	fn waiting_stays_when_either_threshold_is_met() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Waiting);
+
+		for &readings in [BRIGHT_AND_WET, (50, 500), (500, 50)].iter() {
+			timer::advance(SENSOR_SAMPLE_INTERVAL.as_millis());
+			tick(&mut system_config, true, readings);
+			assert!(system_config.activation_state.is_waiting());
+		}
+	}
+
+	#[test]
+	fn trigger_selects_which_readings_activate() {
+		let _timers = TimersGuard::take();
+		let cases = [
+			(Trigger::MoistureOnly, (500, 50), true),
+			(Trigger::MoistureOnly, (50, 500), false),
+			(Trigger::LightOnly, (50, 500), true),
+			(Trigger::LightOnly, (500, 50), false),
+			(Trigger::MoistureOrLight, (50, 500), true),
+			(Trigger::MoistureOrLight, BRIGHT_AND_WET, false),
+		];
+
+		for &(trigger, readings, activates) in cases.iter() {
+			let mut system_config = config_in(SystemState::Waiting);
+			system_config.trigger = trigger;
+			timer::advance(SENSOR_SAMPLE_INTERVAL.as_millis());
+			tick(&mut system_config, true, readings);
+			assert_eq!(system_config.activation_state.is_activated(), activates);
+		}
+	}
+
+	#[test]
+	fn sensors_are_read_once_per_interval() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Waiting);
+
+		tick(&mut system_config, true, BRIGHT_AND_WET);
+		step(&mut system_config, true, true, None, || {
+			panic!("read before the interval")
+		});
+
+		timer::advance(SENSOR_SAMPLE_INTERVAL.as_millis());
+		tick(&mut system_config, true, DARK_AND_DRY);
+		assert!(system_config.activation_state.is_activated());
+	}
+
+	#[test]
+	fn waiting_doesnt_activate_when_it_cant_water() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Waiting);
+
+		let (_, notifications) = step(&mut system_config, false, true, None, || {
+			panic!("read while it can't water")
+		});
+		assert!(notifications.is_empty());
+	}
+
+	#[test]
+	fn waiting_doesnt_activate_outside_the_evening_window() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Waiting);
+
+		let (_, notifications) = step(&mut system_config, true, false, None, || {
+			panic!("read outside the evening window")
+		});
+		assert!(notifications.is_empty());
+
+		// An activation which has already started carries on.
+		let mut system_config = config_in(SystemState::Activated);
+		let (_, notifications) = step(&mut system_config, true, false, None, || DARK_AND_DRY);
+		assert!(notifications.is_empty());
+	}
+
+	#[test]
+	fn activated_stops_after_activation_time() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Activated);
+
+		timer::advance(system_config.activation_duration().as_millis() - 10);
+		tick(&mut system_config, true, DARK_AND_DRY);
+		assert!(system_config.activation_state.is_activated());
+
+		timer::advance(10);
+		assert_eq!(
+			tick(&mut system_config, true, DARK_AND_DRY),
+			vec![Transition::Stopped]
+		);
+		assert!(system_config.activation_state.is_waiting());
+	}
+
+	#[test]
+	fn profile_soaks_and_waits_between_activations() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Activated);
+		system_config.profile = Profile::Drip;
+		system_config.activate_mins = 20;
+
+		// The soaks don't count towards the activation time, which is split into two runs.
+		let mins = |mins| Duration::from_config_mins(mins).as_millis();
+		timer::advance(mins(30));
+		tick(&mut system_config, true, DARK_AND_DRY);
+		assert!(system_config.activation_state.is_activated());
+		assert!(is_soaking(&system_config));
+
+		timer::advance(mins(40) - 10);
+		tick(&mut system_config, true, DARK_AND_DRY);
+		assert!(!is_soaking(&system_config));
+		timer::advance(10);
+		tick(&mut system_config, true, DARK_AND_DRY);
+		assert!(system_config.activation_state.is_waiting());
+
+		// The sensors can't start the next activation until the minimum interval has passed.
+		timer::advance(SENSOR_SAMPLE_INTERVAL.as_millis());
+		step(&mut system_config, true, true, None, || {
+			panic!("read before the minimum interval")
+		});
+		timer::advance(Profile::Drip.min_interval().as_millis());
+		tick(&mut system_config, true, DARK_AND_DRY);
+		assert!(system_config.activation_state.is_activated());
+	}
+
+	#[test]
+	fn band_control_pulses_until_moisture_is_in_band() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Waiting);
+		system_config.band_low = 300;
+		let secs = |secs| Duration::from_secs(secs).as_millis();
+
+		// Only the moisture falling below the band starts it, whatever the light.
+		let (started, _) = step(&mut system_config, true, true, None, || (900, 300));
+		assert_eq!(started, None);
+		timer::advance(secs(1));
+		let (started, _) = step(&mut system_config, true, true, None, || (900, 250));
+		assert_eq!(started, Some(Strategy::Band));
+
+		// The moisture is only checked against the band once the water of the pulse has reached
+		// the sensor.
+		timer::advance(secs(60));
+		tick(&mut system_config, true, (900, 350));
+		assert!(system_config.activation_state.is_activated());
+		assert!(is_soaking(&system_config));
+		timer::advance(secs(60));
+		tick(&mut system_config, true, (900, 350));
+		assert!(system_config.activation_state.is_waiting());
+	}
+
+	#[test]
+	fn band_control_stops_above_band_or_at_cap() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Activated);
+		system_config.band_low = 300;
+		system_config.band_cap_mins = 1;
+		let secs = |secs| Duration::from_secs(secs).as_millis();
+
+		// Pulses which don't raise the moisture stop once they add up to the cap.
+		timer::advance(secs(179));
+		tick(&mut system_config, true, (900, 100));
+		assert!(system_config.activation_state.is_activated());
+		timer::advance(secs(1));
+		tick(&mut system_config, true, (900, 100));
+		assert!(system_config.activation_state.is_waiting());
+
+		// A pulse which raises the moisture above the band stops straight away.
+		move_to(&mut system_config, SystemState::Activated);
+		timer::advance(secs(10));
+		tick(&mut system_config, true, (900, 650));
+		assert!(system_config.activation_state.is_waiting());
+	}
+
+	#[test]
+	fn misting_bursts_while_hot() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Waiting);
+		system_config.mist_celsius = 30;
+		let mist = |system_config: &mut SystemConfig, temperature| {
+			let (started, _) = step(system_config, true, true, Some(temperature), || {
+				panic!("read in misting mode")
+			});
+			started
+		};
+
+		assert_eq!(mist(&mut system_config, 300), None);
+		assert_eq!(mist(&mut system_config, 301), Some(Strategy::Misting));
+		assert!(system_config.activation_state.is_activated());
+
+		timer::advance(system_config.mist_duration().as_millis());
+		mist(&mut system_config, 301);
+		assert!(system_config.activation_state.is_waiting());
+
+		// The next burst waits for the misting interval.
+		timer::advance(system_config.mist_interval().as_millis() - 1);
+		assert_eq!(mist(&mut system_config, 301), None);
+		timer::advance(1);
+		assert_eq!(mist(&mut system_config, 301), Some(Strategy::Misting));
+	}
+
+	#[test]
+	fn activated_is_cancelled_when_it_cant_water() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Activated);
+
+		tick(&mut system_config, false, DARK_AND_DRY);
+		assert!(system_config.activation_state.is_waiting());
+	}
+
+	#[test]
+	fn suspended_resumes_after_suspension_time() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Waiting);
+		assert_eq!(
+			transition(&mut system_config, &UpdateSystemValue::Suspend),
+			[None, Some(Transition::Suspended)]
+		);
+
+		timer::advance(system_config.activation_duration().as_millis() - 10);
+		tick(&mut system_config, true, DARK_AND_DRY);
+		assert!(system_config.activation_state.is_suspended());
+
+		timer::advance(10);
+		assert_eq!(
+			tick(&mut system_config, true, DARK_AND_DRY),
+			vec![Transition::Resumed]
+		);
+		assert!(system_config.activation_state.is_waiting());
+	}
+
+	#[test]
+	fn adjusted_duration_lasts_for_one_activation() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Waiting);
+		system_config.adjusted_duration = Some(Duration::from_config_mins(3));
+		transition(&mut system_config, &UpdateSystemValue::ActivationState);
+		assert_eq!(
+			system_config.run_duration().as_millis(),
+			Duration::from_config_mins(3).as_millis()
+		);
+
+		transition(&mut system_config, &UpdateSystemValue::ActivationState);
+		assert!(system_config.adjusted_duration.is_none());
+	}
+
+	#[test]
+	fn activation_state_moves_to_next_state() {
+		let _timers = TimersGuard::take();
+		let next = |state| state_after(state, UpdateSystemValue::ActivationState);
+
+		assert!(next(SystemState::Suspended).is_waiting());
+		assert!(next(SystemState::Activated).is_waiting());
+		assert!(next(SystemState::Waiting).is_activated());
+	}
+
+	#[test]
+	fn activate_toggles_activation() {
+		let _timers = TimersGuard::take();
+		let activate = |state| state_after(state, UpdateSystemValue::Activate);
+
+		assert!(activate(SystemState::Waiting).is_activated());
+		assert!(activate(SystemState::Suspended).is_activated());
+		assert!(activate(SystemState::Activated).is_waiting());
+	}
+
+	#[test]
+	fn suspend_toggles_suspension() {
+		let _timers = TimersGuard::take();
+		let suspend = |state| state_after(state, UpdateSystemValue::Suspend);
+
+		assert!(suspend(SystemState::Waiting).is_suspended());
+		assert!(suspend(SystemState::Activated).is_suspended());
+		assert!(suspend(SystemState::Suspended).is_waiting());
+	}
+
+	#[test]
+	fn transitions_run_exit_and_entry_hooks() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Activated);
+		assert!(SoftTimer::Activation.is_running());
+
+		assert_eq!(
+			transition(&mut system_config, &UpdateSystemValue::Suspend),
+			[Some(Transition::Stopped), Some(Transition::Suspended)]
+		);
+		assert!(!SoftTimer::Activation.is_running());
+		assert!(SoftTimer::Suspension.is_running());
+
+		assert_eq!(
+			transition(&mut system_config, &UpdateSystemValue::Reset),
+			[Some(Transition::Resumed), None]
+		);
+		assert!(!SoftTimer::Suspension.is_running());
+		assert_eq!(
+			transition(&mut system_config, &UpdateSystemValue::Reset),
+			[None, None]
+		);
+		assert_eq!(
+			transition(&mut system_config, &UpdateSystemValue::Sound),
+			[None, None]
+		);
+	}
+
+	#[test]
+	fn implausible_readings_fault_after_a_while() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Waiting);
+
+		for _ in 0..SENSOR_FAULT_DURATION.as_millis() / SENSOR_SAMPLE_INTERVAL.as_millis() {
+			tick(&mut system_config, true, (1023, 50));
+			timer::advance(SENSOR_SAMPLE_INTERVAL.as_millis());
+		}
+		assert!(system_config.activation_state.is_waiting());
+
+		// A plausible reading starts the timing again.
+		tick(&mut system_config, true, BRIGHT_AND_WET);
+		timer::advance(SENSOR_SAMPLE_INTERVAL.as_millis());
+		tick(&mut system_config, true, (1023, 50));
+		timer::advance(SENSOR_FAULT_DURATION.as_millis());
+		assert_eq!(
+			tick(&mut system_config, true, (1023, 50)),
+			vec![Transition::Faulted(FaultKind::Sensor)]
+		);
+	}
+
+	#[test]
+	fn fault_is_only_cleared_by_acknowledging_it() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Activated);
+
+		assert_eq!(
+			transition(
+				&mut system_config,
+				&UpdateSystemValue::Fault(FaultKind::Valve)
+			),
+			[
+				Some(Transition::Stopped),
+				Some(Transition::Faulted(FaultKind::Valve))
+			]
+		);
+		for update in [
+			UpdateSystemValue::Activate,
+			UpdateSystemValue::Suspend,
+			UpdateSystemValue::Reset,
+			UpdateSystemValue::Fault(FaultKind::Sensor),
+		]
+		.iter()
+		{
+			assert_eq!(transition(&mut system_config, update), [None, None]);
+		}
+		let (_, notifications) = step(&mut system_config, true, true, None, || {
+			panic!("read while faulted")
+		});
+		assert!(notifications.is_empty());
+		assert_eq!(
+			system_config.activation_state.fault(),
+			Some(FaultKind::Valve)
+		);
+
+		assert_eq!(
+			transition(&mut system_config, &UpdateSystemValue::Acknowledge),
+			[Some(Transition::Cleared), None]
+		);
+		assert!(system_config.activation_state.is_waiting());
+	}
+
+	#[test]
+	fn winterized_stays_stopped_until_re_enabled() {
+		let _timers = TimersGuard::take();
+		let mut system_config = config_in(SystemState::Activated);
+
+		assert_eq!(
+			transition(&mut system_config, &UpdateSystemValue::Winterize),
+			[Some(Transition::Stopped), Some(Transition::Winterized)]
+		);
+		for update in [
+			UpdateSystemValue::Activate,
+			UpdateSystemValue::Suspend,
+			UpdateSystemValue::ActivationState,
+			UpdateSystemValue::Fault(FaultKind::Sensor),
+		]
+		.iter()
+		{
+			assert_eq!(transition(&mut system_config, update), [None, None]);
+		}
+
+		// Unlike a suspension, it doesn't end after a while.
+		timer::advance(system_config.activation_duration().as_millis());
+		let (_, notifications) = step(&mut system_config, true, true, None, || {
+			panic!("read while winterized")
+		});
+		assert!(notifications.is_empty());
+		assert!(system_config.activation_state.is_winterized());
+
+		assert_eq!(
+			transition(&mut system_config, &UpdateSystemValue::Winterize),
+			[Some(Transition::Reenabled), None]
+		);
+		assert!(system_config.activation_state.is_waiting());
+	}
+}
@@ -0,0 +1,108 @@
+//! Moisture band control
+//!
+//! While **Band lo** isn't 0, the sensors water the soil up into a band of moisture instead of
+//! watering for the activation time. Once the smoothed moisture falls below the bottom of the band
+//! (**Band lo**), the valve is opened in short pulses with pauses in between, so that the water can
+//! reach the sensor before the moisture is checked again. The activation stops once the moisture
+//! has risen into the band by the end of a pause, as soon as a pulse raises it above the top of the
+//! band (**Band hi**), or once the pulses add up to **Band cap**, so that a sensor which has come
+//! out of the soil can't keep the valve opening.
+//!
+//! The moisture is smoothed (see [`Smoother`]), so that a single noisy reading doesn't start or
+//! stop an activation. There is only one valve, so the band applies to the whole system rather
+//! than to each zone. Misting mode takes precedence over band control.
+
+use crate::timer::Duration;
+
+/// How long the valve is open for in each pulse
+const PULSE: Duration = Duration::from_secs(30);
+/// How long the valve is closed for after each pulse
+const PAUSE: Duration = Duration::from_secs(120);
+/// How long into each pause the water has spread far enough for the moisture to be checked
+const SETTLE: Duration = Duration::from_secs(90);
+/// How many readings the smoothed moisture is averaged over, roughly
+const SMOOTHING: u32 = 4;
+
+/// Exponential moving average of the moisture readings
+pub struct Smoother {
+	/// Smoothed moisture multiplied by [`SMOOTHING`], so that it isn't stuck short of a steady
+	/// reading by rounding, if there has been a reading
+	total: Option<u32>,
+}
+
+impl Smoother {
+	/// Create a new [`Smoother`] without any readings
+	pub fn new() -> Self {
+		Self { total: None }
+	}
+
+	/// Add a moisture reading, and return the smoothed moisture
+	pub fn update(&mut self, moisture: u16) -> u16 {
+		let total = match self.total {
+			Some(total) => total - total / SMOOTHING + moisture as u32,
+			None => moisture as u32 * SMOOTHING,
+		};
+		self.total = Some(total);
+		((total + SMOOTHING / 2) / SMOOTHING) as u16
+	}
+}
+
+/// How long the valve has been open for, `elapsed` into an activation
+pub fn pulsed(elapsed: Duration) -> Duration {
+	let (pulse, pause) = (PULSE.as_millis(), PAUSE.as_millis());
+	let pulses = elapsed.as_millis() / (pulse + pause);
+	let into_pulse = elapsed.as_millis() % (pulse + pause);
+	Duration::from_millis(pulses * pulse + into_pulse.min(pulse))
+}
+
+/// Whether the valve is closed between pulses, `elapsed` into an activation
+pub fn is_pausing(elapsed: Duration) -> bool {
+	elapsed.as_millis() % (PULSE.as_millis() + PAUSE.as_millis()) >= PULSE.as_millis()
+}
+
+/// Whether the water of the last pulse has had time to reach the sensor, `elapsed` into an
+/// activation
+pub fn is_settled(elapsed: Duration) -> bool {
+	let into_pulse = elapsed.as_millis() % (PULSE.as_millis() + PAUSE.as_millis());
+	into_pulse >= PULSE.as_millis() + SETTLE.as_millis()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn secs(secs: u16) -> Duration {
+		Duration::from_secs(secs)
+	}
+
+	#[test]
+	fn pulses_with_pauses() {
+		assert_eq!(pulsed(secs(20)).as_millis(), secs(20).as_millis());
+		assert!(!is_pausing(secs(29)));
+		assert!(is_pausing(secs(30)));
+		assert_eq!(pulsed(secs(100)).as_millis(), secs(30).as_millis());
+		assert!(!is_settled(secs(119)));
+		assert!(is_settled(secs(120)));
+
+		// The next pulse starts once the pause is over.
+		assert!(!is_pausing(secs(150)));
+		assert!(!is_settled(secs(150)));
+		assert_eq!(pulsed(secs(160)).as_millis(), secs(40).as_millis());
+	}
+
+	#[test]
+	fn smooths_noisy_readings() {
+		let mut smoother = Smoother::new();
+		assert_eq!(smoother.update(400), 400);
+
+		// A single spike only moves the smoothed moisture part of the way.
+		assert_eq!(smoother.update(600), 450);
+		assert_eq!(smoother.update(400), 438);
+
+		// A lasting change is followed all the way.
+		for _ in 0..40 {
+			smoother.update(500);
+		}
+		assert_eq!(smoother.update(500), 500);
+	}
+}
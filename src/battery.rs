@@ -0,0 +1,142 @@
+//! Battery voltage monitoring
+//!
+//! The supply voltage is measured against the internal 1.1V bandgap reference, so no extra
+//! components are needed as long as the Nano is powered straight from the battery.
+//!
+//! Below the configured low battery threshold (see [`SystemConfig::low_battery_dv`]), the system
+//! enters a low-power mode: the valve and every other output are switched off, the display is
+//! switched off, and nothing but the clock and the supply voltage is kept running until the
+//! battery has recovered.
+//!
+//! The brown-out detector resets the MCU if the supply drops below 2.7V (with the Nano's default
+//! fuses), which could happen while the valve is open. So once the supply drops to
+//! [`CRITICAL_BATTERY_MV`], the system closes the valve and saves its counters straight away, while
+//! there is still enough voltage to do so.
+
+use crate::{
+	config::SystemConfig,
+	display::{Display, BODY_START_ROW},
+	hal::{Adc, AnalogReader, Bandgap, Clock},
+	lang,
+	timer::{Duration, TIMER},
+};
+
+/// Internal bandgap reference voltage in millivolts
+const BANDGAP_MV: u32 = 1_100;
+/// Millivolts above the low battery threshold which a low battery has to recover to before it is
+/// no longer considered to be low
+///
+/// So that the system doesn't flip in and out of low-power mode while the voltage is close to the
+/// threshold.
+const RECOVERY_MARGIN_MV: u16 = 200;
+/// Header row the supply voltage is shown on
+const STATUS_ROW: u8 = 1;
+/// Supply voltage in millivolts below which the MCU is close to being reset by the brown-out
+/// detector
+const CRITICAL_BATTERY_MV: u16 = 3_000;
+/// How often the supply voltage is measured
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+/// How often the supply voltage is measured while the battery is low, so that a sagging supply is
+/// caught before the brown-out detector resets the MCU
+const LOW_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Supply voltage monitor
+pub struct Battery {
+	/// Last measured supply voltage in millivolts
+	millivolts: u16,
+	/// Whether the battery is low
+	low: bool,
+	/// Whether the supply is close to the brown-out threshold
+	critical: bool,
+	/// [`TIMER`] clock value of the last time the supply voltage was measured
+	last_sample_ms: Option<u32>,
+}
+
+impl Battery {
+	/// Create a new [`Battery`]
+	pub fn new() -> Self {
+		Self {
+			millivolts: 0,
+			low: false,
+			critical: false,
+			last_sample_ms: None,
+		}
+	}
+
+	/// Whether the battery is low, in which case the system is in low-power mode
+	pub fn is_low(&self) -> bool {
+		self.low
+	}
+
+	/// Whether the supply is close to the brown-out threshold, in which case the valve should be
+	/// closed straight away
+	pub fn is_critical(&self) -> bool {
+		self.critical
+	}
+
+	/// Measure the supply voltage if it is due to be measured, and compare it with the low battery
+	/// threshold in `system_config`
+	///
+	/// Returns `true` if a new measurement was taken.
+	pub fn update(&mut self, adc: &mut Adc, system_config: &SystemConfig) -> bool {
+		let interval = if self.low {
+			LOW_SAMPLE_INTERVAL
+		} else {
+			SAMPLE_INTERVAL
+		};
+		if let Some(last_sample_ms) = self.last_sample_ms {
+			if !TIMER.has_elapsed(last_sample_ms, interval) {
+				return false;
+			}
+		}
+		self.last_sample_ms = Some(TIMER.millis());
+
+		// The bandgap reading is a fraction of the supply voltage, so work backwards from it.
+		let reading = adc.read(&Bandgap).max(1) as u32;
+		self.millivolts = (BANDGAP_MV * 1_024 / reading) as u16;
+		debug!("battery={}mV", self.millivolts);
+
+		let low_mv = system_config.low_battery_dv * 100;
+		if self.millivolts < low_mv {
+			self.low = true;
+		} else if self.millivolts >= low_mv + RECOVERY_MARGIN_MV {
+			self.low = false;
+		}
+		// The supply has to recover past the low threshold before it is no longer critical.
+		self.critical = self.millivolts < CRITICAL_BATTERY_MV || (self.critical && self.low);
+
+		true
+	}
+
+	/// Render the supply voltage on the info screen
+	pub fn render(&self, display: &mut Display) {
+		display.clear_row(BODY_START_ROW + 5);
+		let _ = ufmt::uwrite!(display, "{}: ", lang::BATTERY);
+		self.render_volts(display);
+		if self.low {
+			let _ = ufmt::uwrite!(display, " {}", lang::LOW);
+		}
+	}
+
+	/// Render the supply voltage at the start of the second header row, once it has been measured
+	pub fn render_status(&self, display: &mut Display) {
+		if self.last_sample_ms.is_none() {
+			return;
+		}
+		display.set_position(0, STATUS_ROW);
+		self.render_volts(display);
+	}
+
+	/// Render the supply voltage in volts, to two decimal places
+	fn render_volts(&self, display: &mut Display) {
+		let hundredths = self.millivolts % 1_000 / 10;
+		let padding = if hundredths < 10 { "0" } else { "" };
+		let _ = ufmt::uwrite!(
+			display,
+			"{}.{}{}V",
+			self.millivolts / 1_000,
+			padding,
+			hundredths
+		);
+	}
+}
@@ -0,0 +1,70 @@
+//! Board configuration
+//!
+//! Each supported board has a module with the pins the sensors, buttons, valve and status LED are
+//! wired to, selected with a cargo feature: `board-uno`, `board-nano` (the default if no board is
+//! selected) or `board-promini`. Wiring things up differently only needs the board's module to be
+//! changed, rather than `main.rs` and the types of every peripheral. The valve and status LED can
+//! also be moved at runtime, as the board only sets their default pins (see [`crate::pinmap`]).
+//!
+//! The boards all use the ATmega328P at 16MHz, so they share the Arduino Nano HAL. Some pins are
+//! tied to the microcontroller's hardware, and are the same on every board: the serial connection
+//! on D0/D1, the display's I2C bus on A4/A5, the buzzer on D9 (Timer1), the pump on D11 (Timer2),
+//! the rotary encoder on D4/D5/D6 (pin change interrupts on port D), and the DHT22 on D7.
+
+use arduino_hal::{
+	hal::port::{PC0, PC1, PC2, PC3, PC4, PC5},
+	port::{mode::Analog, Pin},
+};
+
+#[cfg(any(
+	all(feature = "board-uno", feature = "board-nano"),
+	all(feature = "board-uno", feature = "board-promini"),
+	all(feature = "board-nano", feature = "board-promini"),
+))]
+compile_error!(
+	"only one of the `board-uno`, `board-nano` and `board-promini` features can be enabled"
+);
+
+#[cfg(not(any(feature = "board-uno", feature = "board-promini")))]
+#[macro_use]
+mod nano;
+#[cfg(not(any(feature = "board-uno", feature = "board-promini")))]
+pub use nano::*;
+#[cfg(feature = "board-promini")]
+#[macro_use]
+mod promini;
+#[cfg(feature = "board-promini")]
+pub use promini::*;
+#[cfg(feature = "board-uno")]
+#[macro_use]
+mod uno;
+#[cfg(feature = "board-uno")]
+pub use uno::*;
+
+/// Pin which can be read by the ADC
+pub trait AnalogPin {
+	/// ADC multiplexer setting which reads the pin
+	const CHANNEL: u8;
+}
+
+macro_rules! analog_pins {
+	($($pin:ident => $channel:literal),*) => {
+		$(impl AnalogPin for $pin {
+			const CHANNEL: u8 = $channel;
+		})*
+	};
+}
+
+analog_pins!(PC0 => 0, PC1 => 1, PC2 => 2, PC3 => 3, PC4 => 4, PC5 => 5);
+
+/// Pins the system uses, taken from the board's pins with `board_pins!`
+pub struct BoardPins {
+	pub light_sensor: Pin<Analog, LightSensor>,
+	pub moisture_sensor: Pin<Analog, MoistureSensor>,
+	/// Resistor ladder of the buttons
+	#[cfg(not(feature = "encoder"))]
+	pub buttons: Pin<Analog, Buttons>,
+	/// Soil temperature probe of the heat mat
+	#[cfg(feature = "heat-mat")]
+	pub soil_probe: Pin<Analog, SoilProbe>,
+}
@@ -0,0 +1,68 @@
+//! Arduino Nano
+//!
+//! The board the system was designed for, and the wiring shown in the schematic.
+
+use arduino_hal::hal::port::{PC0, PC1, PC2, PC3};
+
+use crate::pinmap::{DigitalPin, PinMap};
+
+/// Name of the board, logged when the system starts up
+pub const NAME: &str = "Arduino Nano";
+
+/// Light sensor on A0
+pub type LightSensor = PC0;
+/// Moisture sensor on A1
+pub type MoistureSensor = PC1;
+/// Resistor ladder of the buttons on A2
+pub type Buttons = PC2;
+/// Soil temperature probe of the heat mat on A3
+pub type SoilProbe = PC3;
+
+/// Pins of the digital outputs, unless others have been saved in the EEPROM
+///
+/// The valve relay (or the H-bridge input which opens a latching valve, or the serial data of the
+/// shift registers) is on D3, the H-bridge input which closes a latching valve (or the clock of the
+/// shift registers) on D2, the status LED is the on-board LED on D13, the dosing pump is on D8, the
+/// grow-light relay is on D12, the fan relay is on D10, the heat mat relay is on D4, or on A2 in
+/// place of the buttons if the rotary encoder is used, the master valve relay is on D5, or on A3 if
+/// the rotary encoder is used, and the latch of the shift registers is on D6, or on D7 if the
+/// rotary encoder is used.
+pub const PIN_MAP: PinMap = PinMap::new(
+	DigitalPin::d(3),
+	DigitalPin::d(2),
+	DigitalPin::d(13),
+	DigitalPin::d(8),
+	DigitalPin::d(12),
+	DigitalPin::d(10),
+	if cfg!(feature = "encoder") {
+		DigitalPin::a(2)
+	} else {
+		DigitalPin::d(4)
+	},
+	if cfg!(feature = "encoder") {
+		DigitalPin::a(3)
+	} else {
+		DigitalPin::d(5)
+	},
+	if cfg!(feature = "encoder") {
+		DigitalPin::d(7)
+	} else {
+		DigitalPin::d(6)
+	},
+);
+
+/// Take the pins the system uses from the board's `pins`, as [`super::BoardPins`]
+///
+/// The analog inputs are set up with `adc`.
+macro_rules! board_pins {
+	($pins:ident, $adc:expr) => {
+		$crate::boards::BoardPins {
+			light_sensor: $pins.a0.into_analog_input($adc),
+			moisture_sensor: $pins.a1.into_analog_input($adc),
+			#[cfg(not(feature = "encoder"))]
+			buttons: $pins.a2.into_analog_input($adc),
+			#[cfg(feature = "heat-mat")]
+			soil_probe: $pins.a3.into_analog_input($adc),
+		}
+	};
+}
@@ -0,0 +1,163 @@
+//! Command bus between the system and its frontends
+//!
+//! Nothing but the bus changes [`SystemConfig`]. The menu, the presets screen, the commands
+//! received over the USART and the activation state machine each send an [`UpdateSystemValue`]
+//! onto the [`Bus`] instead, and the system applies the queued commands in order with
+//! [`Bus::apply`] once it has handled the button presses and the serial input. So a button press
+//! and a remote command which ask for the same change go through the same code path.
+//!
+//! Every change is sent back as a [`Notification`], which the system handles by announcing the
+//! change of state and updating the menu, whichever frontend asked for it. Changes carry the value
+//! of the setting from before the command, so that they can be kept in the audit trail (see
+//! [`crate::log::changes`]).
+
+use crate::{
+	activation::{self, Transition},
+	config::{SystemConfig, SystemValue, UpdateSystemValue},
+	queue::Queue,
+};
+
+/// How many commands can wait to be applied
+const COMMAND_CAPACITY: usize = 4;
+/// How many notifications can wait to be handled, which is enough for every queued command to
+/// cause two transitions as well as its change
+const NOTIFICATION_CAPACITY: usize = COMMAND_CAPACITY * 3;
+
+/// Change which was made by applying a command
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Notification {
+	/// The system moved from one state to another
+	Transition(Transition),
+	/// A command was applied to the configuration, with the value of the setting before it was
+	/// applied, if the command changes a single setting
+	Changed(UpdateSystemValue, Option<SystemValue>),
+}
+
+/// Queue of commands for the system, and of notifications of the changes they made
+pub struct Bus {
+	commands: Queue<UpdateSystemValue, COMMAND_CAPACITY>,
+	notifications: Queue<Notification, NOTIFICATION_CAPACITY>,
+}
+
+impl Bus {
+	/// Create a new [`Bus`] with nothing queued
+	pub fn new() -> Self {
+		Self {
+			commands: Queue::new(),
+			notifications: Queue::new(),
+		}
+	}
+
+	/// Queue a command to be applied on the next call to [`Bus::apply`]
+	///
+	/// Returns `false` if too many commands are already waiting, in which case the command is
+	/// dropped.
+	pub fn send(&mut self, command: UpdateSystemValue) -> bool {
+		self.commands.push(command)
+	}
+
+	/// Apply every queued command to `system_config`, and move the system to the state each one
+	/// requests
+	///
+	/// The transitions, followed by the command itself, are queued as notifications.
+	pub fn apply(&mut self, system_config: &mut SystemConfig) {
+		while let Some(command) = self.commands.pop() {
+			let old = command.to_value(system_config);
+			system_config.update(&command);
+			for transition in activation::transition(system_config, &command)
+				.iter()
+				.flatten()
+			{
+				self.notifications
+					.push(Notification::Transition(*transition));
+			}
+			self.notifications.push(Notification::Changed(command, old));
+		}
+	}
+
+	/// Take the oldest notification which hasn't been handled yet
+	pub fn next_notification(&mut self) -> Option<Notification> {
+		self.notifications.pop()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		activation::SystemState,
+		config::{Number, ValueAction},
+		timer::tests::TimersGuard,
+	};
+
+	#[test]
+	fn commands_are_applied_once_in_order() {
+		let mut bus = Bus::new();
+		let mut system_config = SystemConfig::new();
+		assert!(bus.send(UpdateSystemValue::Number(
+			Number::Time,
+			ValueAction::Set(30)
+		)));
+		assert!(bus.send(UpdateSystemValue::Number(
+			Number::Time,
+			ValueAction::Increment
+		)));
+
+		bus.apply(&mut system_config);
+		let incremented = system_config.activate_mins;
+		assert!(incremented > 30);
+		bus.apply(&mut system_config);
+		assert_eq!(system_config.activate_mins, incremented);
+
+		assert_eq!(
+			bus.next_notification(),
+			Some(Notification::Changed(
+				UpdateSystemValue::Number(Number::Time, ValueAction::Set(30)),
+				Some(SystemValue::Number(
+					Number::Time,
+					Number::Time.default_value(),
+					system_config.units
+				))
+			))
+		);
+		assert_eq!(
+			bus.next_notification(),
+			Some(Notification::Changed(
+				UpdateSystemValue::Number(Number::Time, ValueAction::Increment),
+				Some(SystemValue::Number(Number::Time, 30, system_config.units))
+			))
+		);
+		assert_eq!(bus.next_notification(), None);
+	}
+
+	#[test]
+	fn transitions_are_notified_before_the_change() {
+		let _timers = TimersGuard::take();
+		let mut bus = Bus::new();
+		let mut system_config = SystemConfig::new();
+		bus.send(UpdateSystemValue::Activate);
+
+		bus.apply(&mut system_config);
+		assert!(system_config.activation_state.is_activated());
+		assert_eq!(
+			bus.next_notification(),
+			Some(Notification::Transition(Transition::Started))
+		);
+		assert_eq!(
+			bus.next_notification(),
+			Some(Notification::Changed(
+				UpdateSystemValue::Activate,
+				Some(SystemValue::Activate(SystemState::Waiting))
+			))
+		);
+	}
+
+	#[test]
+	fn full_bus_drops_commands() {
+		let mut bus = Bus::new();
+		for _ in 0..COMMAND_CAPACITY {
+			assert!(bus.send(UpdateSystemValue::Sound));
+		}
+		assert!(!bus.send(UpdateSystemValue::Sound));
+	}
+}
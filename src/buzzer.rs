@@ -0,0 +1,120 @@
+//! Audible alerts through a piezo buzzer on D9
+//!
+//! The display can't be read in direct sunlight, so button presses, activation, and faults are
+//! also confirmed with a beep. The tone is generated by Timer1 toggling its output, and patterns
+//! are played without blocking the rest of the system.
+
+use arduino_hal::{
+	hal::port::PB1,
+	pac::TC1,
+	port::{mode::Output, Pin},
+};
+
+use crate::{
+	hal::Clock,
+	timer::{Duration, TIMER},
+};
+
+/// Clear timer on compare match mode bit in TCCR1B
+const WGM12: u8 = 1 << 3;
+/// Divide by 8 prescaler bit in TCCR1B
+const CS11: u8 = 1 << 1;
+/// Compare value for a tone of 16MHz / (2 * 8 * (1 + TONE_TOP)), which is 2kHz
+const TONE_TOP: u16 = 499;
+
+/// Patterns which can be played by the buzzer
+#[derive(Clone, Copy)]
+pub enum Pattern {
+	/// A button has been pressed
+	Click,
+	/// The system has been activated
+	ActivationStart,
+	/// The system is no longer activated
+	ActivationStop,
+	/// Something has gone wrong
+	Fault,
+}
+
+impl Pattern {
+	/// Lengths of time in milliseconds to play the pattern for, alternating between the tone
+	/// being on and off
+	fn steps(self) -> &'static [u16] {
+		match self {
+			Self::Click => &[15],
+			Self::ActivationStart => &[80, 60, 80],
+			Self::ActivationStop => &[300],
+			Self::Fault => &[150, 100, 150, 100, 150],
+		}
+	}
+}
+
+/// Piezo buzzer
+pub struct Buzzer {
+	tc1: TC1,
+	_pin: Pin<Output, PB1>,
+	/// Steps of the pattern being played
+	steps: &'static [u16],
+	/// Index of the current step of the pattern
+	step: usize,
+	/// [`TIMER`] clock value of when the current step started
+	step_started_ms: u32,
+}
+
+impl Buzzer {
+	/// Create a new [`Buzzer`] from Timer1 and its output pin
+	pub fn new(tc1: TC1, pin: Pin<Output, PB1>) -> Self {
+		tc1.tccr1a.write(|w| w.com1a().disconnected());
+		tc1.tccr1b.write(|w| unsafe { w.bits(WGM12 | CS11) });
+		tc1.ocr1a.write(|w| unsafe { w.bits(TONE_TOP) });
+
+		Self {
+			tc1,
+			_pin: pin,
+			steps: &[],
+			step: 0,
+			step_started_ms: 0,
+		}
+	}
+
+	/// Start playing a pattern, replacing any pattern which is already playing
+	pub fn play(&mut self, pattern: Pattern) {
+		self.steps = pattern.steps();
+		self.step = 0;
+		self.step_started_ms = TIMER.millis();
+		self.set_tone(true);
+	}
+
+	/// Move on to the next step of the pattern being played, if it is time to
+	///
+	/// Should be called on every tick of the system.
+	pub fn update(&mut self) {
+		if self.step >= self.steps.len() {
+			return;
+		}
+
+		let step_duration = Duration::from_millis(self.steps[self.step] as u32);
+		if TIMER.has_elapsed(self.step_started_ms, step_duration) {
+			self.step += 1;
+			self.step_started_ms = TIMER.millis();
+			// Even steps play the tone, and odd steps are silent.
+			self.set_tone(self.step < self.steps.len() && self.step % 2 == 0);
+		}
+	}
+
+	/// Stop the pattern being played, leaving the buzzer silent
+	pub fn stop(&mut self) {
+		self.steps = &[];
+		self.step = 0;
+		self.set_tone(false);
+	}
+
+	/// Turn the tone on or off
+	fn set_tone(&mut self, on: bool) {
+		if on {
+			self.tc1.tccr1a.write(|w| w.com1a().match_toggle());
+		} else {
+			// Disconnecting the output from the timer leaves the pin low.
+			self.tc1.tccr1a.write(|w| w.com1a().disconnected());
+		}
+	}
+}
@@ -0,0 +1,104 @@
+//! Button calibration
+//!
+//! Entered by holding the Select button down while the system boots. Each button is pressed in
+//! turn, and the thresholds for each button are worked out from the readings and stored in the
+//! EEPROM.
+
+use crate::{
+	control_pad::{ButtonThresholds, ButtonType, ControlPad, BUTTON_COUNT},
+	display::{Display, BODY_START_ROW},
+	eeprom::Eeprom,
+	hal::{Adc, Clock},
+	timer::{Duration, TIMER},
+};
+
+/// Readings below this value are considered to be no button being pressed
+const PRESSED_MIN: u16 = 50;
+/// How long in milliseconds to wait for a reading to settle after a button is pressed or released
+const SETTLE_MS: u16 = 50;
+/// Amount of readings to average for each button
+const SAMPLE_COUNT: u16 = 16;
+/// How long in milliseconds to wait between readings
+const SAMPLE_DELAY_MS: u16 = 5;
+/// How long in milliseconds to show the result of the calibration for
+const RESULT_DELAY_MS: u16 = 1_500;
+/// How long to wait for an optional button to be pressed before skipping it
+const OPTIONAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run through the calibration of each button
+///
+/// The control pad uses the new thresholds straight away if the calibration succeeds. Otherwise,
+/// the current thresholds are kept.
+pub fn run(
+	control_pad: &mut ControlPad,
+	adc: &mut Adc,
+	display: &mut Display,
+	eeprom: &mut Eeprom,
+) {
+	display.clear_body();
+	display.set_position(0, BODY_START_ROW);
+	let _ = ufmt::uwrite!(display, "Calibrating");
+	display.flush();
+
+	// Select is being held down to enter the calibration, so wait for it to be released first.
+	wait_for_release(control_pad, adc);
+
+	let mut samples = [None; BUTTON_COUNT];
+	for (sample, button) in samples.iter_mut().zip(ButtonType::ALL.iter()) {
+		display.clear_row(BODY_START_ROW + 2);
+		let _ = ufmt::uwrite!(display, "Press {}", button.label());
+		// Optional buttons are skipped if they aren't pressed in time.
+		display.clear_row(BODY_START_ROW + 3);
+		if button.is_optional() {
+			let _ = ufmt::uwrite!(display, "(or wait)");
+		}
+		display.flush();
+
+		*sample = sample_press(control_pad, adc, button.is_optional());
+		wait_for_release(control_pad, adc);
+	}
+	display.clear_row(BODY_START_ROW + 3);
+
+	display.clear_row(BODY_START_ROW + 2);
+	match ButtonThresholds::from_samples(&samples) {
+		Some(thresholds) => {
+			thresholds.save(eeprom);
+			control_pad.set_thresholds(thresholds);
+			let _ = ufmt::uwrite!(display, "Done");
+		}
+		None => {
+			let _ = ufmt::uwrite!(display, "Failed");
+		}
+	}
+	display.flush();
+
+	arduino_hal::delay_ms(RESULT_DELAY_MS);
+}
+
+/// Wait for a button to be pressed, and take the average of a number of readings
+///
+/// If `optional` is `true`, returns `None` if the button isn't pressed within
+/// [`OPTIONAL_TIMEOUT`].
+fn sample_press(control_pad: &ControlPad, adc: &mut Adc, optional: bool) -> Option<u16> {
+	let started_ms = TIMER.millis();
+	while control_pad.read_raw(adc) < PRESSED_MIN {
+		if optional && TIMER.has_elapsed(started_ms, OPTIONAL_TIMEOUT) {
+			return None;
+		}
+	}
+	arduino_hal::delay_ms(SETTLE_MS);
+
+	let mut total: u32 = 0;
+	for _ in 0..SAMPLE_COUNT {
+		total += control_pad.read_raw(adc) as u32;
+		arduino_hal::delay_ms(SAMPLE_DELAY_MS);
+	}
+
+	Some((total / SAMPLE_COUNT as u32) as u16)
+}
+
+/// Wait for all buttons to be released
+fn wait_for_release(control_pad: &ControlPad, adc: &mut Adc) {
+	while control_pad.read_raw(adc) >= PRESSED_MIN {}
+	arduino_hal::delay_ms(SETTLE_MS);
+}
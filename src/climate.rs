@@ -0,0 +1,274 @@
+//! Temperature and humidity, and evapotranspiration-scaled watering times
+//!
+//! With a DHT22 attached (see [`dht22`]), the activations started by the sensors can water for
+//! longer on hot, dry and sunny days, and for less time when it is cool, humid or overcast. The
+//! amount of water the garden loses is estimated with a simplified evapotranspiration (ET) model,
+//! relative to a reference day of 20C, 50% humidity and medium light:
+//!
+//! - Temperature, as in the Hargreaves equation: ET rises with the temperature above -17.8C;
+//! - Humidity: ET rises with the vapour pressure deficit, approximated by the dryness of the air;
+//! - Light: ET rises with the light reading, standing in for the solar radiation.
+//!
+//! The readings are averaged over the last few hours, since the activation is usually started in
+//! the evening, after the heat of the day which it should make up for. Everything is integer math,
+//! and the estimate is a percentage of the configured activation time.
+
+#[cfg(all(feature = "atmega328p", feature = "dht22"))]
+pub mod dht22;
+
+use crate::{
+	hal::Clock,
+	timer::{Duration, TIMER},
+};
+
+/// How often the temperature, humidity and light are sampled
+///
+/// The DHT22 can't be read more than once every 2 seconds.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+/// Averages are kept as exponential moving averages with a weight of 1/2^AVERAGE_SHIFT, which
+/// averages over roughly 4 hours of samples
+const AVERAGE_SHIFT: u32 = 8;
+/// Amount of reads in a row which can fail before the averages are dropped
+const MAX_FAILURES: u8 = 5;
+/// Temperature of the reference day, in tenths of a degree
+const REFERENCE_TENTHS: i32 = 200;
+/// Temperature at which the Hargreaves equation estimates no evapotranspiration, in tenths of a
+/// degree
+const ZERO_ET_TENTHS: i32 = -178;
+/// Humidity of the reference day, in tenths of a percent
+const REFERENCE_HUMIDITY: i32 = 500;
+/// Light reading of the reference day
+const REFERENCE_LIGHT: i32 = 512;
+/// Lowest estimate, as a percentage of the reference day
+const ET_PERCENT_MIN: i32 = 25;
+/// Highest estimate, as a percentage of the reference day
+const ET_PERCENT_MAX: i32 = 300;
+
+/// Temperature and humidity reading
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Reading {
+	/// Temperature in tenths of a degree Celsius
+	pub temperature: i16,
+	/// Relative humidity in tenths of a percent
+	pub humidity: u16,
+}
+
+impl Reading {
+	/// Decode the 5 bytes sent by a DHT22, if their checksum matches
+	///
+	/// The humidity and temperature are each sent as 16 bits in tenths, with the sign of the
+	/// temperature in its highest bit, followed by the low byte of the sum of the other bytes.
+	pub fn decode(bytes: &[u8; 5]) -> Option<Self> {
+		let sum = bytes[..4]
+			.iter()
+			.fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+		if sum != bytes[4] {
+			return None;
+		}
+
+		let humidity = u16::from_be_bytes([bytes[0], bytes[1]]);
+		let magnitude = u16::from_be_bytes([bytes[2] & 0x7f, bytes[3]]) as i16;
+		let temperature = if bytes[2] & 0x80 != 0 {
+			-magnitude
+		} else {
+			magnitude
+		};
+		if humidity > 1_000 {
+			return None;
+		}
+
+		Some(Self {
+			temperature,
+			humidity,
+		})
+	}
+}
+
+/// Exponential moving average, kept with [`AVERAGE_SHIFT`] extra bits so that small changes
+/// aren't lost
+#[derive(Clone, Copy)]
+struct Average(i32);
+
+impl Average {
+	fn new(value: i32) -> Self {
+		Self(value << AVERAGE_SHIFT)
+	}
+
+	fn add(&mut self, value: i32) {
+		self.0 += value - (self.0 >> AVERAGE_SHIFT);
+	}
+
+	fn get(self) -> i32 {
+		self.0 >> AVERAGE_SHIFT
+	}
+}
+
+/// Recent averages of the temperature, humidity and light
+#[derive(Clone, Copy)]
+struct Averages {
+	temperature: Average,
+	humidity: Average,
+	light: Average,
+}
+
+/// Samples the temperature, humidity and light, and estimates the evapotranspiration from them
+pub struct Climate {
+	/// [`TIMER`] clock value of the last time the readings were sampled
+	last_sample_ms: Option<u32>,
+	/// Recent averages, if the sensor has been read
+	averages: Option<Averages>,
+	/// Latest reading, if the last read succeeded
+	latest: Option<Reading>,
+	/// Amount of reads in a row which have failed
+	failures: u8,
+}
+
+impl Climate {
+	/// Create a new [`Climate`], without any readings
+	pub fn new() -> Self {
+		Self {
+			last_sample_ms: None,
+			averages: None,
+			latest: None,
+			failures: 0,
+		}
+	}
+
+	/// Sample the readings if they are due to be sampled
+	///
+	/// `sample` returns the temperature and humidity (or `None` if the sensor couldn't be read),
+	/// and the light reading. If the sensor can't be read several times in a row, the averages are
+	/// dropped so that the estimate isn't based on old readings.
+	pub fn update(&mut self, sample: impl FnOnce() -> (Option<Reading>, u16)) {
+		if let Some(last_sample_ms) = self.last_sample_ms {
+			if !TIMER.has_elapsed(last_sample_ms, SAMPLE_INTERVAL) {
+				return;
+			}
+		}
+		self.last_sample_ms = Some(TIMER.millis());
+
+		let (reading, light) = sample();
+		self.latest = reading;
+		let reading = match reading {
+			Some(reading) => reading,
+			None => {
+				self.failures = self.failures.saturating_add(1);
+				if self.failures >= MAX_FAILURES {
+					self.averages = None;
+				}
+				return;
+			}
+		};
+		self.failures = 0;
+
+		let (temperature, humidity, light) = (
+			reading.temperature as i32,
+			reading.humidity as i32,
+			light as i32,
+		);
+		match &mut self.averages {
+			Some(averages) => {
+				averages.temperature.add(temperature);
+				averages.humidity.add(humidity);
+				averages.light.add(light);
+			}
+			None => {
+				self.averages = Some(Averages {
+					temperature: Average::new(temperature),
+					humidity: Average::new(humidity),
+					light: Average::new(light),
+				})
+			}
+		}
+	}
+
+	/// Latest reading, if the last read succeeded
+	pub fn latest(&self) -> Option<Reading> {
+		self.latest
+	}
+
+	/// Estimated evapotranspiration, as a percentage of the reference day
+	///
+	/// `None` until the sensor has been read.
+	pub fn et_percent(&self) -> Option<u16> {
+		self.averages.map(|averages| {
+			et_percent(
+				averages.temperature.get(),
+				averages.humidity.get(),
+				averages.light.get(),
+			)
+		})
+	}
+}
+
+/// Estimate the evapotranspiration from the temperature and humidity in tenths, and the light
+/// reading, as a percentage of the reference day
+fn et_percent(temperature: i32, humidity: i32, light: i32) -> u16 {
+	let temperature =
+		(temperature - ZERO_ET_TENTHS).max(0) * 100 / (REFERENCE_TENTHS - ZERO_ET_TENTHS);
+	let dryness = (1_000 - humidity).max(0) * 100 / (1_000 - REFERENCE_HUMIDITY);
+	let light = light.max(0) * 100 / REFERENCE_LIGHT;
+	let percent = temperature * dryness / 100 * light / 100;
+	percent.clamp(ET_PERCENT_MIN, ET_PERCENT_MAX) as u16
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::timer::{self, tests::TimersGuard};
+
+	#[test]
+	fn decodes_readings() {
+		// 65.2%, 35.1C
+		assert_eq!(
+			Reading::decode(&[0x02, 0x8c, 0x01, 0x5f, 0xee]),
+			Some(Reading {
+				temperature: 351,
+				humidity: 652
+			})
+		);
+		// 65.2%, -10.1C
+		assert_eq!(
+			Reading::decode(&[0x02, 0x8c, 0x80, 0x65, 0x73]),
+			Some(Reading {
+				temperature: -101,
+				humidity: 652
+			})
+		);
+		assert_eq!(Reading::decode(&[0x02, 0x8c, 0x01, 0x5f, 0xef]), None);
+	}
+
+	#[test]
+	fn estimates_relative_to_reference_day() {
+		assert_eq!(
+			et_percent(REFERENCE_TENTHS, REFERENCE_HUMIDITY, REFERENCE_LIGHT),
+			100
+		);
+		// Hot, dry and sunny
+		assert!(et_percent(320, 250, 900) > 200);
+		// Cool and overcast
+		assert!(et_percent(120, 850, 200) < 50);
+		assert_eq!(et_percent(-200, 1_000, 0), ET_PERCENT_MIN as u16);
+		assert_eq!(et_percent(450, 0, 1_023), ET_PERCENT_MAX as u16);
+	}
+
+	#[test]
+	fn averages_are_dropped_after_failed_reads() {
+		let _timers = TimersGuard::take();
+		let mut climate = Climate::new();
+		let reading = Reading {
+			temperature: REFERENCE_TENTHS as i16,
+			humidity: REFERENCE_HUMIDITY as u16,
+		};
+
+		climate.update(|| (Some(reading), REFERENCE_LIGHT as u16));
+		assert_eq!(climate.et_percent(), Some(100));
+
+		for _ in 0..MAX_FAILURES {
+			timer::advance(SAMPLE_INTERVAL.as_millis());
+			climate.update(|| (None, 0));
+		}
+		assert_eq!(climate.latest(), None);
+		assert_eq!(climate.et_percent(), None);
+	}
+}
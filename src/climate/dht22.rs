@@ -0,0 +1,103 @@
+//! DHT22 (AM2302) temperature and humidity sensor on D7
+//!
+//! The sensor talks over a single open-drain wire. The system pulls the wire low for a couple of
+//! milliseconds to start a reading, and then lets it go. The sensor answers with an 80us low and
+//! 80us high pulse, followed by 40 bits, each of which is a 50us low pulse followed by a high pulse
+//! of about 27us for a 0 or 70us for a 1.
+//!
+//! The pulses are timed by counting loop iterations, and each high pulse is compared against the
+//! low pulse before it rather than against a fixed length, so that no calibration is needed.
+//! Interrupts are left enabled so that serial input isn't lost, so a reading is occasionally
+//! garbled by an interrupt, but the checksum catches it and the next sample is used instead.
+//!
+//! The pin is driven through the port's registers, like the pin-mapped outputs (see
+//! [`crate::hal::MappedOutput`]).
+
+use arduino_hal::pac::PORTD;
+
+use super::Reading;
+use crate::hal::free;
+
+/// Bit of D7 in port D's registers
+const MASK: u8 = 1 << 7;
+/// How long to pull the wire low for to start a reading, in milliseconds
+const START_MS: u16 = 2;
+/// Most loop iterations to wait for a pulse to end, well over 100us at 16MHz
+const PULSE_TIMEOUT: u16 = 1_000;
+
+/// DHT22 on D7
+pub struct Dht22 {
+	_private: (),
+}
+
+impl Dht22 {
+	/// Create a new [`Dht22`], leaving the wire pulled up until the first reading
+	pub fn new() -> Self {
+		release();
+		Self { _private: () }
+	}
+
+	/// Read the temperature and humidity, if the sensor answers with a valid reading
+	///
+	/// Blocks for about 7ms.
+	pub fn read(&mut self) -> Option<Reading> {
+		// Wake the sensor up, and let it answer.
+		pull_low();
+		arduino_hal::delay_ms(START_MS);
+		release();
+
+		// The wire is pulled up until the sensor answers with a low and a high pulse.
+		pulse(true)?;
+		pulse(false)?;
+		pulse(true)?;
+
+		let mut bytes = [0; 5];
+		for bit in 0..40 {
+			let low = pulse(false)?;
+			let high = pulse(true)?;
+			if high > low {
+				bytes[bit / 8] |= 0x80 >> (bit % 8);
+			}
+		}
+
+		Reading::decode(&bytes)
+	}
+}
+
+/// Drive the wire low
+fn pull_low() {
+	modify(|ddr| ddr | MASK, |port| port & !MASK);
+}
+
+/// Stop driving the wire, and pull it up
+fn release() {
+	modify(|ddr| ddr & !MASK, |port| port | MASK);
+}
+
+/// Update the data direction and output registers of port D
+fn modify(ddr: impl FnOnce(u8) -> u8, port: impl FnOnce(u8) -> u8) {
+	// Other pins of the port are written by the HAL, so the read-modify-write can't be
+	// interrupted.
+	free(|_cs| unsafe {
+		let regs = &*PORTD::ptr();
+		regs.ddrd.modify(|r, w| w.bits(ddr(r.bits())));
+		regs.portd.modify(|r, w| w.bits(port(r.bits())));
+	});
+}
+
+/// Whether the wire is high
+fn is_high() -> bool {
+	unsafe { (*PORTD::ptr()).pind.read().bits() & MASK != 0 }
+}
+
+/// Wait for the wire to change from `high`, returning how long it took in loop iterations
+fn pulse(high: bool) -> Option<u16> {
+	let mut count = 0;
+	while is_high() == high {
+		count += 1;
+		if count >= PULSE_TIMEOUT {
+			return None;
+		}
+	}
+	Some(count)
+}
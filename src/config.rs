@@ -1,232 +1,769 @@
-use core::{
-	mem::{take, MaybeUninit},
-	str,
+use ufmt::{derive::uDebug, uDisplay, uWrite};
+
+use crate::{
+	activation::{FaultKind, Strategy, SystemState},
+	band,
+	display::{self, ROW_LENGTH},
+	fmt::{Buffer, Flag, Tenths, Units},
+	hal::Clock,
+	lang,
+	menu::MENU_ITEM_PADDING,
+	presets::PRESETS,
+	profile::Profile,
+	timer::{Duration, TIMER},
 };
 
-use ufmt::{derive::uDebug, uDisplay, uWrite};
+/// Whether the display is dimmed at night by default
+const DEFAULT_NIGHT_DIM: bool = true;
+/// Units which values are shown in by default
+const DEFAULT_UNITS: Units = Units::Metric;
+/// Sensor readings which activate the system by default
+const DEFAULT_TRIGGER: Trigger = Trigger::MoistureAndLight;
+/// Whether the activation time is scaled by the estimated evapotranspiration by default
+const DEFAULT_ET_SCALING: bool = false;
+/// Type of output which the valve feeds by default
+const DEFAULT_PROFILE: Profile = Profile::Spray;
+/// Whether the dosing pump runs at the start of each activation by default
+const DEFAULT_DOSE: bool = false;
+/// Whether the buzzer is enabled by default
+const DEFAULT_SOUND: bool = true;
+/// Whether commands received over the USART can change the system, by default
+const DEFAULT_REMOTE_CONTROL: bool = false;
+/// Smallest pairing code for Bluetooth control, so that every code has 4 digits
+const MIN_PAIRING_CODE: u16 = 1_000;
+/// Amount of pairing codes for Bluetooth control to choose from
+const PAIRING_CODES: u32 = 9_000;
+/// Whether the relay is energized when its input is low, by default
+pub const DEFAULT_RELAY_ACTIVE_LOW: bool = cfg!(feature = "relay-active-low");
+/// Whether the valve lets water through when it isn't energized, by default
+pub const DEFAULT_VALVE_NORMALLY_OPEN: bool = cfg!(feature = "valve-normally-open");
 
-use crate::{display::ROW_LENGTH, menu::MENU_ITEM_PADDING};
-
-/// Default amount of time in minutes which the system should be activated
-const DEFAULT_ACTIVATE_MINS: u16 = 10;
-/// Default minimum amount of light required for the system to potentially activate
-const DEFAULT_MIN_LIGHT: u16 = 100;
-/// Default minimum amount of moisture required for the system to potentially activate
-const DEFAULT_MIN_MOISTURE: u16 = 100;
-
-/// The shortest amount of time in minutes that can be configured for the system activation time
-const ACTIVATION_TIME_MIN: u16 = 5;
-/// The longest amount of time in minutes that can be configured for the system activation time
-const ACTIVATION_TIME_MAX: u16 = 60;
-/// The smallest minimum value for available light
-const MIN_LIGHT_MIN: u16 = 0;
-/// The largest minimum value for available light
-const MIN_LIGHT_MAX: u16 = 1050;
-/// The smallest minimum value for moisture
-const MIN_MOISTURE_MIN: u16 = 0;
-/// The largest minimum value for moisture
-const MIN_MOISTURE_MAX: u16 = 1050;
-
-/// Amount in minutes to increment the activation time by
-const ACTIVATION_TIME_INCREMENT: u16 = 5;
-/// Amount to increment the minimum light value by
-const MIN_LIGHT_INCREMENT: u16 = 25;
-/// Amount to increment the minimum moisture value by
-const MIN_MOISTURE_INCREMENT: u16 = 25;
+/// Longest value shown in the menu, such as the largest minimum light value
+pub const VALUE_LENGTH: usize = 5;
 
-/// Display representation of a value in [`SystemConfig`]
-#[derive(uDebug)]
-pub enum SystemValue {
+macro_rules! update_value {
+	(add $current:expr, $add:expr, $max:expr) => {{
+		let max_diff = $max - $add;
+		if $current >= max_diff {
+			$max
+		} else {
+			$current + $add
+		}
+	}};
+
+	(set $value:expr, $min:expr, $max:expr) => {{
+		if $value < $min {
+			$min
+		} else if $value > $max {
+			$max
+		} else {
+			$value
+		}
+	}};
+
+	(subtract $current:expr, $subtract:expr, $min:expr) => {{
+		let min_diff = $min + $subtract;
+		if $current <= min_diff {
+			$min
+		} else {
+			$current - $subtract
+		}
+	}};
+}
+
+/// Declare every numeric setting in [`SystemConfig`] in one place
+///
+/// Each setting names the field which holds it, its label in the menu, how its value is shown,
+/// its default value, the range of allowed values, and the amount it is incremented by. The
+/// [`Number`] enum is generated from the declarations, so that the menu, the commands and
+/// [`SystemConfig::update`] handle every numeric setting in the same way.
+macro_rules! numbers {
+	($(
+		$(#[doc = $doc:literal])*
+		$variant:ident($field:ident) {
+			label: $label:expr,
+			style: $style:ident,
+			default: $default:literal,
+			range: $min:literal..=$max:literal,
+			step: $step:literal $(,)?
+		}
+	),* $(,)?) => {
+		/// Numeric setting in [`SystemConfig`], which is changed with a [`ValueAction`]
+		#[derive(uDebug, Clone, Copy, PartialEq, Debug)]
+		pub enum Number {
+			$($(#[doc = $doc])* $variant,)*
+		}
+
+		impl Number {
+			/// Every numeric setting, in the order they are declared
+			pub const ALL: &'static [Self] = &[$(Self::$variant),*];
+
+			/// Label of the setting, shown in the menu
+			fn label(self) -> &'static str {
+				match self {
+					$(Self::$variant => $label,)*
+				}
+			}
+
+			/// How the value of the setting is shown in the menu
+			fn style(self) -> Style {
+				match self {
+					$(Self::$variant => Style::$style,)*
+				}
+			}
+
+			/// Value of the setting in a new [`SystemConfig`]
+			pub fn default_value(self) -> u16 {
+				match self {
+					$(Self::$variant => $default,)*
+				}
+			}
+
+			/// Range of allowed values, and the amount the setting is incremented by
+			pub fn range(self) -> Range {
+				match self {
+					$(Self::$variant => Range { min: $min, max: $max, step: $step },)*
+				}
+			}
+
+			/// Current value of the setting in `system_config`
+			pub fn get(self, system_config: &SystemConfig) -> u16 {
+				match self {
+					$(Self::$variant => system_config.$field,)*
+				}
+			}
+
+			/// Field which holds the setting in `system_config`
+			fn get_mut(self, system_config: &mut SystemConfig) -> &mut u16 {
+				match self {
+					$(Self::$variant => &mut system_config.$field,)*
+				}
+			}
+		}
+	};
+}
+
+numbers! {
 	/// Activation time minutes
-	Time(u16),
+	Time(activate_mins) {
+		label: lang::TIME,
+		style: Plain,
+		default: 10,
+		range: 5..=60,
+		step: 5,
+	},
 	/// Minimum light value
-	Light(u16),
+	Light(min_light) {
+		label: lang::LIGHT,
+		style: Plain,
+		default: 100,
+		range: 0..=1050,
+		step: 25,
+	},
 	/// Minimum moisture value
-	Moisture(u16),
-	/// Activation suspended
-	Suspend(ActivationState),
-	/// Activated
-	Activate(ActivationState),
+	Moisture(min_moisture) {
+		label: lang::MOISTURE,
+		style: Plain,
+		default: 100,
+		range: 0..=1050,
+		step: 25,
+	},
+	/// Speed of the PWM pump, as a percentage of its full speed
+	Flow(flow_percent) {
+		label: lang::FLOW,
+		style: Plain,
+		default: 100,
+		range: 10..=100,
+		step: 10,
+	},
+	/// Contrast of the display, as a percentage of its full contrast
+	///
+	/// The display can still be read at its lowest contrast.
+	Brightness(brightness_percent) {
+		label: lang::BRIGHTNESS,
+		style: Plain,
+		default: 100,
+		range: 0..=100,
+		step: 10,
+	},
+	/// Hours after dusk which the sensors can activate the system in, where 0 is any time
+	Evening(evening_hours) {
+		label: lang::EVENING,
+		style: OffAtZero,
+		default: 0,
+		range: 0..=8,
+		step: 1,
+	},
+	/// Dosing time seconds
+	DoseTime(dose_secs) {
+		label: lang::DOSE_TIME,
+		style: Plain,
+		default: 30,
+		range: 10..=120,
+		step: 10,
+	},
+	/// Weekly dosing cap minutes
+	DoseCap(dose_cap_mins) {
+		label: lang::DOSE_CAP,
+		style: Plain,
+		default: 5,
+		range: 1..=30,
+		step: 1,
+	},
+	/// Misting temperature degrees Celsius, where 0 is never
+	MistTemp(mist_celsius) {
+		label: lang::MIST_TEMP,
+		style: Degrees,
+		default: 0,
+		range: 0..=45,
+		step: 1,
+	},
+	/// Misting burst seconds
+	MistTime(mist_secs) {
+		label: lang::MIST_TIME,
+		style: Plain,
+		default: 10,
+		range: 5..=60,
+		step: 5,
+	},
+	/// Misting interval minutes
+	MistInterval(mist_interval_mins) {
+		label: lang::MIST_INTERVAL,
+		style: Plain,
+		default: 10,
+		range: 1..=60,
+		step: 1,
+	},
+	/// Grow-light day length hours, where 0 is off
+	DayHours(day_hours) {
+		label: lang::DAY_HOURS,
+		style: OffAtZero,
+		default: 0,
+		range: 0..=20,
+		step: 1,
+	},
+	/// Fan temperature degrees Celsius, where 0 is never
+	FanTemp(fan_celsius) {
+		label: lang::FAN_TEMP,
+		style: Degrees,
+		default: 0,
+		range: 0..=45,
+		step: 1,
+	},
+	/// Fan humidity percent, where 0 is never
+	FanHumidity(fan_humidity) {
+		label: lang::FAN_HUMIDITY,
+		style: OffAtZero,
+		default: 0,
+		range: 0..=95,
+		step: 5,
+	},
+	/// Heat mat temperature degrees Celsius, where 0 is off
+	MatTemp(mat_celsius) {
+		label: lang::MAT_TEMP,
+		style: Degrees,
+		default: 0,
+		range: 0..=35,
+		step: 1,
+	},
+	/// Heat mat hysteresis band tenths of a degree
+	MatBand(mat_band) {
+		label: lang::MAT_BAND,
+		style: Band,
+		default: 10,
+		range: 5..=50,
+		step: 5,
+	},
+	/// Heat mat cut-out minutes
+	MatMax(mat_max_mins) {
+		label: lang::MAT_MAX,
+		style: Plain,
+		default: 120,
+		range: 10..=480,
+		step: 10,
+	},
+	/// Thousands of valve cycles between services, where 0 is never
+	ServiceCycles(service_kcycles) {
+		label: lang::SERVICE,
+		style: OffAtZero,
+		default: 10,
+		range: 0..=100,
+		step: 1,
+	},
+	/// Days left of vacation mode, where 0 is off
+	VacationDays(vacation_days) {
+		label: lang::VACATION,
+		style: OffAtZero,
+		default: 0,
+		range: 0..=30,
+		step: 1,
+	},
+	/// Factor the intervals between activations are multiplied by on vacation
+	VacationInterval(vacation_interval_factor) {
+		label: lang::VACATION_INTERVAL,
+		style: Plain,
+		default: 2,
+		range: 1..=10,
+		step: 1,
+	},
+	/// Percentage of the activation time the valve is open for on vacation
+	VacationTime(vacation_time_percent) {
+		label: lang::VACATION_TIME,
+		style: Plain,
+		default: 50,
+		range: 10..=100,
+		step: 10,
+	},
+	/// Seconds the master valve is opened for before the valve
+	MasterLead(master_lead_secs) {
+		label: lang::MASTER_LEAD,
+		style: Plain,
+		default: 2,
+		range: 0..=30,
+		step: 1,
+	},
+	/// Seconds the master valve is kept open for after the valve is closed
+	MasterLag(master_lag_secs) {
+		label: lang::MASTER_LAG,
+		style: Plain,
+		default: 5,
+		range: 0..=60,
+		step: 1,
+	},
+	/// Seconds to wait between opening the valve and starting the pump, and between stopping the
+	/// pump and closing the valve
+	PumpDelay(pump_delay_secs) {
+		label: lang::PUMP_DELAY,
+		style: Plain,
+		default: 2,
+		range: 0..=30,
+		step: 1,
+	},
+	/// Supply voltage in tenths of a volt below which the system enters low-power mode
+	LowBattery(low_battery_dv) {
+		label: lang::LOW_BATTERY,
+		style: Tenths,
+		default: 34,
+		range: 30..=45,
+		step: 1,
+	},
+	/// Seconds between telemetry records (see [`crate::scheduler`])
+	Telemetry(telemetry_secs) {
+		label: lang::TELEMETRY,
+		style: Plain,
+		default: 10,
+		range: 5..=300,
+		step: 5,
+	},
+	/// Bottom of the moisture band, where 0 is off
+	BandLow(band_low) {
+		label: lang::BAND_LOW,
+		style: OffAtZero,
+		default: 0,
+		range: 0..=1050,
+		step: 25,
+	},
+	/// Top of the moisture band
+	BandHigh(band_high) {
+		label: lang::BAND_HIGH,
+		style: Plain,
+		default: 600,
+		range: 0..=1050,
+		step: 25,
+	},
+	/// Band control pulse cap minutes
+	BandCap(band_cap_mins) {
+		label: lang::BAND_CAP,
+		style: Plain,
+		default: 10,
+		range: 1..=60,
+		step: 1,
+	},
 }
 
-/// Format a u16 value as a &str
-/// Courtesy of
-/// https://github.com/japaric/ufmt/blob/master/src/impls/uxx.rs#L5-L23
-fn format_u16<'val, 'buf>(value: &'val u16, buf: &'buf mut [u8; 5]) -> &'buf str {
-	let mut idx = buf.len() - 1;
-	let mut n = *value;
-	loop {
-		*buf.get_mut(idx).unwrap() = (n % 10) as u8 + b'0';
+impl Number {
+	/// Display representation of the setting in `system_config`
+	pub fn value(self, system_config: &SystemConfig) -> SystemValue {
+		SystemValue::Number(self, self.get(system_config), system_config.units)
+	}
+}
 
-		n /= 10;
+/// How the value of a [`Number`] is shown in the menu
+#[derive(Clone, Copy)]
+enum Style {
+	/// Shown as it is
+	Plain,
+	/// Shown as it is, except for 0 which is shown as off
+	OffAtZero,
+	/// Shown in tenths, such as 1.5 for 15
+	Tenths,
+	/// Degrees Celsius, shown in the chosen units, except for 0 which is shown as off
+	Degrees,
+	/// Difference in tenths of a degree Celsius, shown in tenths of a degree in the chosen units
+	Band,
+}
 
-		if n == 0 {
-			break;
-		} else {
-			idx -= 1;
+/// Range of allowed values of a [`Number`]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Range {
+	/// Smallest allowed value
+	pub min: u16,
+	/// Largest allowed value
+	pub max: u16,
+	/// Amount to increment or decrement the value by
+	pub step: u16,
+}
+
+impl Range {
+	/// Apply `action` to `current`, limited to the range
+	pub fn apply(&self, current: u16, action: &ValueAction) -> u16 {
+		match *action {
+			ValueAction::Increment => update_value!(add current, self.step, self.max),
+			ValueAction::Decrement => update_value!(subtract current, self.step, self.min),
+			ValueAction::Set(value) => update_value!(set value, self.min, self.max),
 		}
 	}
-	unsafe { str::from_utf8_unchecked(buf.get(idx..).unwrap()) }
 }
 
-/// Format a bool value as a &str
-fn format_bool<'val, 'buf>(value: &'val bool, buf: &'buf mut [u8; 5]) -> &'buf str {
-	let symbol = if *value { '@' } else { '-' };
-	let idx = buf.len() - 1;
-	*buf.get_mut(idx).unwrap() = symbol as u8;
-	unsafe { str::from_utf8_unchecked(buf.get(idx..).unwrap()) }
+/// Display representation of a value in [`SystemConfig`]
+#[derive(uDebug, Clone, Copy, PartialEq, Debug)]
+pub enum SystemValue {
+	/// Numeric setting, its value, and the units it is shown in
+	Number(Number, u16, Units),
+	/// Activation suspended
+	Suspend(SystemState),
+	/// Activated
+	Activate(SystemState),
+	/// Winterized
+	Winterize(SystemState),
+	/// Buzzer enabled
+	Sound(bool),
+	/// Remote control enabled
+	RemoteControl(bool),
+	/// Pairing code of Bluetooth control, while it is enabled
+	BtControl(Option<u16>),
+	/// Relay is energized when its input is low
+	RelayActiveLow(bool),
+	/// Valve lets water through when it isn't energized
+	ValveNormallyOpen(bool),
+	/// Display dimmed at night
+	NightDim(bool),
+	/// Units which values are shown in
+	Units(Units),
+	/// Sensor readings which activate the system
+	Trigger(Trigger),
+	/// Activation time scaled by the estimated evapotranspiration
+	EtScaling(bool),
+	/// Type of output which the valve feeds
+	Profile(Profile),
+	/// Dosing pump enabled
+	Dose(bool),
 }
 
-impl uDisplay for SystemValue {
-	/// Used when rendering the [`crate::menu::Menu`]
-	fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
-	where
-		W: uWrite + ?Sized,
-	{
-		let mut buf = unsafe { MaybeUninit::<[u8; 5]>::uninit().assume_init() };
-		let (label, value) = match self {
-			Self::Time(value) => ("Time", format_u16(value, &mut buf)),
-			Self::Light(value) => ("Light", format_u16(value, &mut buf)),
-			Self::Moisture(value) => ("Moisture", format_u16(value, &mut buf)),
+impl SystemValue {
+	/// Value of the same setting in `system_config`
+	pub fn current(&self, system_config: &SystemConfig) -> Self {
+		UpdateSystemValue::from_value(self, ValueAction::Increment)
+			.to_value(system_config)
+			.unwrap_or(*self)
+	}
+
+	/// Label of the setting, and its value as shown in the menu
+	pub fn label_and_value(&self) -> (&'static str, Buffer<VALUE_LENGTH>) {
+		match self {
+			Self::Number(number, value, units) => (
+				number.label(),
+				match number.style() {
+					Style::OffAtZero | Style::Degrees if *value == 0 => {
+						Buffer::<VALUE_LENGTH>::from_display(&Flag(false))
+					}
+					Style::Plain | Style::OffAtZero => Buffer::from_display(value),
+					Style::Tenths => Buffer::from_display(&Tenths(*value as i32)),
+					Style::Degrees => {
+						// Round to the nearest whole degree.
+						let tenths = units.tenths(*value as i16 * 10);
+						let mut degrees = Buffer::new();
+						let _ = ufmt::uwrite!(degrees, "{}{}", (tenths + 5) / 10, units.symbol());
+						degrees
+					}
+					Style::Band => Buffer::from_display(&Tenths(units.difference(*value as i16))),
+				},
+			),
 			Self::Suspend(value) => {
-				let is_suspended = value.is_suspending() || value.is_suspended();
+				let is_suspended = value.is_suspended();
 				(
-					if !is_suspended { "Suspend" } else { "Resume" },
-					format_bool(&is_suspended, &mut buf),
+					if !is_suspended {
+						lang::SUSPEND
+					} else {
+						lang::RESUME
+					},
+					Buffer::from_display(&Flag(is_suspended)),
 				)
 			}
-			Self::Activate(value) => {
-				let is_activated = value.is_activating() || value.is_activated();
+			Self::Winterize(value) => {
+				let is_winterized = value.is_winterized();
 				(
-					if !is_activated { "Activate" } else { "Cancel" },
-					format_bool(&is_activated, &mut buf),
+					if !is_winterized {
+						lang::WINTERIZE
+					} else {
+						lang::REENABLE
+					},
+					Buffer::from_display(&Flag(is_winterized)),
 				)
 			}
-		};
+			Self::Activate(value) => match value.fault() {
+				// While there is a fault, the item acknowledges it, and shows its code.
+				Some(kind) => (lang::ACK_FAULT, Buffer::from_display(&kind)),
+				None => {
+					let is_activated = value.is_activated();
+					(
+						if !is_activated {
+							lang::ACTIVATE
+						} else {
+							lang::CANCEL
+						},
+						Buffer::from_display(&Flag(is_activated)),
+					)
+				}
+			},
+			Self::Sound(value) => (lang::SOUND, Buffer::from_display(&Flag(*value))),
+			Self::RemoteControl(value) => (lang::REMOTE, Buffer::from_display(&Flag(*value))),
+			Self::BtControl(code) => (
+				lang::BT_CONTROL,
+				match code {
+					Some(code) => Buffer::from_display(code),
+					None => Buffer::from_display(&Flag(false)),
+				},
+			),
+			Self::RelayActiveLow(value) => (lang::RELAY_LOW, Buffer::from_display(&Flag(*value))),
+			Self::ValveNormallyOpen(value) => (lang::VALVE_NO, Buffer::from_display(&Flag(*value))),
+			Self::NightDim(value) => (lang::NIGHT_DIM, Buffer::from_display(&Flag(*value))),
+			Self::Units(value) => (lang::UNITS, Buffer::from_display(&value.symbol())),
+			Self::Trigger(value) => (lang::TRIGGER, Buffer::from_display(&value.label())),
+			Self::EtScaling(value) => (lang::ET_TIME, Buffer::from_display(&Flag(*value))),
+			Self::Profile(value) => (lang::PROFILE, Buffer::from_display(&value.label())),
+			Self::Dose(value) => (lang::DOSE, Buffer::from_display(&Flag(*value))),
+		}
+	}
+}
 
-		// Working out how much whitespace exists between the label and the value, with the value
-		// aligned to the right of the display
-		let separator = ":";
-		let remaining =
-			ROW_LENGTH - (label.len() as u8 + MENU_ITEM_PADDING + separator.len() as u8);
-		let whitespace_count = remaining - value.len() as u8;
+impl uDisplay for SystemValue {
+	/// Used when rendering the [`crate::menu::Menu`]
+	fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		let (label, value) = self.label_and_value();
 
-		ufmt::uwrite!(f, "{}{}", label, separator)?;
-		for _ in 0..whitespace_count {
-			ufmt::uwrite!(f, " ")?;
-		}
-		ufmt::uwrite!(f, "{}", value)
+		// Align the value to the right of the display, after the padding for the selector.
+		let width = ROW_LENGTH - MENU_ITEM_PADDING;
+		ufmt::uwrite!(f, "{}", display::align(label, value.as_str(), width))
 	}
 }
 
 /// Represents a future change to a value in [`SystemConfig`]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum UpdateSystemValue {
-	/// Update activation time according to the [`ValueAction`] variant
-	Time(ValueAction),
-	/// Update the minimum light value according to the [`ValueAction`] variant
-	Light(ValueAction),
-	/// Update the minimum moisture value according to the [`ValueAction`] variant
-	Moisture(ValueAction),
+	/// Update a numeric setting according to the [`ValueAction`] variant
+	Number(Number, ValueAction),
 	/// Put the system in the activated state
 	Activate,
 	/// Put the system in the suspended state
 	Suspend,
+	/// Put the system in the winterized state
+	Winterize,
 	/// Move the activation state to the next logical state
 	ActivationState,
+	/// Stop the system with a fault
+	Fault(FaultKind),
+	/// Acknowledge the fault which stopped the system, so that it can water again
+	Acknowledge,
+	/// Toggle whether the buzzer is enabled
+	Sound,
+	/// Toggle whether commands received over the USART can change the system
+	RemoteControl,
+	/// Toggle whether commands can be received over Bluetooth, with a new pairing code each time it
+	/// is enabled
+	BtControl,
+	/// Toggle whether the relay is energized when its input is low
+	RelayPolarity,
+	/// Toggle whether the valve lets water through when it isn't energized
+	ValveType,
+	/// Toggle whether the display is dimmed at night
+	NightDim,
+	/// Toggle between metric and imperial units
+	Units,
+	/// Move to the next trigger policy
+	Trigger,
+	/// Toggle whether the activation time is scaled by the estimated evapotranspiration
+	EtScaling,
+	/// Move to the next output profile
+	Profile,
+	/// Toggle whether the dosing pump runs at the start of each activation
+	Dose,
+	/// Apply the preset at the index in [`PRESETS`]
+	Preset(u8),
+	/// Start counting the valve cycles again, after the valve has been serviced
+	Serviced,
 	/// Reset [`SystemConfig`]
 	Reset,
 }
 
 impl UpdateSystemValue {
 	/// Get a new [`UpdateSystemValue`] from a [`SystemValue`]
+	///
+	/// Values which can't be incremented or decremented ignore `action`, and are toggled instead.
+	/// If the system has faulted, the activation value acknowledges the fault.
 	pub fn from_value(system_value: &SystemValue, action: ValueAction) -> Self {
 		match system_value {
-			SystemValue::Time(_) => Self::Time(action),
-			SystemValue::Light(_) => Self::Light(action),
-			SystemValue::Moisture(_) => Self::Moisture(action),
+			SystemValue::Number(number, _, _) => Self::Number(*number, action),
 			SystemValue::Suspend(_) => Self::Suspend,
+			SystemValue::Winterize(_) => Self::Winterize,
+			SystemValue::Activate(state) if state.fault().is_some() => Self::Acknowledge,
 			SystemValue::Activate(_) => Self::Activate,
+			SystemValue::Sound(_) => Self::Sound,
+			SystemValue::RemoteControl(_) => Self::RemoteControl,
+			SystemValue::BtControl(_) => Self::BtControl,
+			SystemValue::RelayActiveLow(_) => Self::RelayPolarity,
+			SystemValue::ValveNormallyOpen(_) => Self::ValveType,
+			SystemValue::NightDim(_) => Self::NightDim,
+			SystemValue::Units(_) => Self::Units,
+			SystemValue::Trigger(_) => Self::Trigger,
+			SystemValue::EtScaling(_) => Self::EtScaling,
+			SystemValue::Profile(_) => Self::Profile,
+			SystemValue::Dose(_) => Self::Dose,
 		}
 	}
 
 	/// Get a new [`SystemValue`] from the current [`UpdateSystemValue`]
-	pub fn to_value(&self, system_config: &SystemConfig) -> Option<SystemValue> {
+	pub fn to_value(self, system_config: &SystemConfig) -> Option<SystemValue> {
 		match self {
-			Self::Time(_) => Some(SystemValue::Time(system_config.activate_mins)),
-			Self::Light(_) => Some(SystemValue::Light(system_config.min_light)),
-			Self::Moisture(_) => Some(SystemValue::Moisture(system_config.min_moisture)),
-			Self::Activate => Some(SystemValue::Activate(
-				system_config.activation_state.clone(),
-			)),
-			Self::Suspend => Some(SystemValue::Suspend(system_config.activation_state.clone())),
-			Self::ActivationState => Some(SystemValue::Activate(
-				system_config.activation_state.clone(),
+			Self::Number(number, _) => Some(number.value(system_config)),
+			Self::Activate => Some(SystemValue::Activate(system_config.activation_state)),
+			Self::Suspend => Some(SystemValue::Suspend(system_config.activation_state)),
+			Self::Winterize => Some(SystemValue::Winterize(system_config.activation_state)),
+			Self::ActivationState | Self::Fault(_) | Self::Acknowledge => {
+				Some(SystemValue::Activate(system_config.activation_state))
+			}
+			Self::Sound => Some(SystemValue::Sound(system_config.sound)),
+			Self::RemoteControl => Some(SystemValue::RemoteControl(system_config.remote_control)),
+			Self::BtControl => Some(SystemValue::BtControl(system_config.bt_code)),
+			Self::RelayPolarity => {
+				Some(SystemValue::RelayActiveLow(system_config.relay_active_low))
+			}
+			Self::ValveType => Some(SystemValue::ValveNormallyOpen(
+				system_config.valve_normally_open,
 			)),
-			Self::Reset => None,
+			Self::NightDim => Some(SystemValue::NightDim(system_config.night_dim)),
+			Self::Units => Some(SystemValue::Units(system_config.units)),
+			Self::Trigger => Some(SystemValue::Trigger(system_config.trigger)),
+			Self::EtScaling => Some(SystemValue::EtScaling(system_config.et_scaling)),
+			Self::Profile => Some(SystemValue::Profile(system_config.profile)),
+			Self::Dose => Some(SystemValue::Dose(system_config.dose)),
+			Self::Preset(_) | Self::Serviced | Self::Reset => None,
 		}
 	}
 
 	/// Get a reference to the inner [`ValueAction`]
 	pub fn inner_as_ref(&self) -> Option<&ValueAction> {
 		match self {
-			Self::Time(action) => Some(action),
-			Self::Light(action) => Some(action),
-			Self::Moisture(action) => Some(action),
-			Self::Activate | Self::Suspend | Self::ActivationState | Self::Reset => None,
+			Self::Number(_, action) => Some(action),
+			Self::Activate
+			| Self::Suspend
+			| Self::Winterize
+			| Self::ActivationState
+			| Self::Fault(_)
+			| Self::Acknowledge
+			| Self::Sound
+			| Self::RemoteControl
+			| Self::BtControl
+			| Self::RelayPolarity
+			| Self::ValveType
+			| Self::NightDim
+			| Self::Units
+			| Self::Trigger
+			| Self::EtScaling
+			| Self::Profile
+			| Self::Dose
+			| Self::Preset(_)
+			| Self::Serviced
+			| Self::Reset => None,
+		}
+	}
+
+	/// Whether a command received over the USART may perform the update
+	///
+	/// While remote control is disabled, remote commands can only stop the system from watering,
+	/// so that a misbehaving script can't open the valve. Remote control can be disabled remotely,
+	/// but can only be enabled from the menu.
+	pub fn is_remote_allowed(&self, system_config: &SystemConfig) -> bool {
+		let state = &system_config.activation_state;
+		match self {
+			// If the system is activated, Activate cancels the activation.
+			Self::Activate => system_config.remote_control || state.is_activated(),
+			// If the system isn't suspended, Suspend suspends it.
+			Self::Suspend => system_config.remote_control || !state.is_suspended(),
+			// If the system isn't winterized, Winterize winterizes it.
+			Self::Winterize => system_config.remote_control || !state.is_winterized(),
+			// Anything else could lead to the valve being opened.
+			_ => system_config.remote_control,
 		}
 	}
 }
 
 /// Type of action to perform for the [`SystemConfig`] update
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum ValueAction {
 	/// Increment the value
 	Increment,
 	/// Decrement the value
 	Decrement,
+	/// Set the value, limited to the range of allowed values
+	Set(u16),
 }
 
-/// System state of activation
-#[derive(uDebug, Clone)]
-pub enum ActivationState {
-	/// Activating - The next update will put the system into the activated state.
-	///
-	/// Helper variant to handle moving to the correct state from menu actions.
-	Activating,
-	/// Currently activated - No sensor readings are performed
-	Activated,
-	/// Waiting to be activated - Sensor readings are being performed
-	Waiting,
-	/// Suspending - The next update will put the system into suspended state.
-	///
-	/// Helper variant to handle moving to the correct state from menu actions.
-	Suspending,
-	/// Suspended - No sensor readings are performed
-	Suspended,
+/// Sensor readings which activate the system, while it is waiting
+#[derive(uDebug, Clone, Copy, PartialEq, Debug)]
+pub enum Trigger {
+	/// Activate when it is both dry and dark
+	MoistureAndLight,
+	/// Activate when it is dry, whatever the light
+	MoistureOnly,
+	/// Activate when it is dark, whatever the moisture
+	LightOnly,
+	/// Activate when it is either dry or dark
+	MoistureOrLight,
 }
 
-impl ActivationState {
-	/// Whether the system is currently being activated
-	pub fn is_activating(&self) -> bool {
-		matches!(self, Self::Activating)
-	}
-
-	/// Whether the system is currently activated
-	pub fn is_activated(&self) -> bool {
-		matches!(self, Self::Activated)
+impl Trigger {
+	/// Whether the system should be activated, given whether each reading is below its minimum
+	pub fn should_activate(self, dry: bool, dark: bool) -> bool {
+		match self {
+			Self::MoistureAndLight => dry && dark,
+			Self::MoistureOnly => dry,
+			Self::LightOnly => dark,
+			Self::MoistureOrLight => dry || dark,
+		}
 	}
 
-	/// Whether the system is currently being suspended
-	pub fn is_suspending(&self) -> bool {
-		matches!(self, Self::Suspending)
-	}
-	/// Whether the system is currently suspended
-	pub fn is_suspended(&self) -> bool {
-		matches!(self, Self::Suspended)
+	/// Trigger selected after this one in the menu
+	fn next(self) -> Self {
+		match self {
+			Self::MoistureAndLight => Self::MoistureOnly,
+			Self::MoistureOnly => Self::LightOnly,
+			Self::LightOnly => Self::MoistureOrLight,
+			Self::MoistureOrLight => Self::MoistureAndLight,
+		}
 	}
 
-	/// Whether the system is currently waiting
-	pub fn is_waiting(&self) -> bool {
-		matches!(self, Self::Waiting)
+	/// Short name of the trigger, shown in the menu and logged over serial
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::MoistureAndLight => "Both",
+			Self::MoistureOnly => "Moist",
+			Self::LightOnly => "Light",
+			Self::MoistureOrLight => "Any",
+		}
 	}
 }
 
@@ -238,142 +775,732 @@ pub struct SystemConfig {
 	pub min_light: u16,
 	/// Minimum amount of moisture required for the system to potentially activate
 	pub min_moisture: u16,
+	/// Speed of the PWM pump, as a percentage of its full speed
+	pub flow_percent: u16,
 	/// Current activation state of the system
-	pub activation_state: ActivationState,
-	/// Indicates the next update, if any, to make for a value
-	update: Option<UpdateSystemValue>,
+	pub activation_state: SystemState,
+	/// Whether the buzzer is enabled
+	pub sound: bool,
+	/// Whether commands received over the USART can change the system
+	pub remote_control: bool,
+	/// Code which authenticates commands received over Bluetooth, where `None` disables them
+	pub bt_code: Option<u16>,
+	/// Whether the relay is energized when its input is low
+	pub relay_active_low: bool,
+	/// Whether the valve lets water through when it isn't energized
+	pub valve_normally_open: bool,
+	/// Contrast of the display, as a percentage of its full contrast
+	pub brightness_percent: u16,
+	/// Whether the display is dimmed at night
+	pub night_dim: bool,
+	/// Units which values are shown in on the display
+	pub units: Units,
+	/// Sensor readings which activate the system
+	pub trigger: Trigger,
+	/// Hours after dusk which the sensors can activate the system in, where 0 is any time
+	pub evening_hours: u16,
+	/// Whether the activation time is scaled by the estimated evapotranspiration
+	pub et_scaling: bool,
+	/// Type of output which the valve feeds
+	pub profile: Profile,
+	/// Whether the dosing pump runs at the start of each activation
+	pub dose: bool,
+	/// How long in seconds the dosing pump runs for at the start of each activation
+	pub dose_secs: u16,
+	/// How long in minutes the dosing pump can run for each week
+	pub dose_cap_mins: u16,
+	/// Temperature in degrees Celsius above which the system mists instead of being activated by
+	/// the sensors, where 0 disables misting
+	pub mist_celsius: u16,
+	/// How long in seconds each misting burst lasts
+	pub mist_secs: u16,
+	/// How long in minutes to wait between misting bursts
+	pub mist_interval_mins: u16,
+	/// Hours of light the grow lights top the day up to after dusk, where 0 disables them
+	pub day_hours: u16,
+	/// Temperature in degrees Celsius above which the fan runs, where 0 disables it
+	pub fan_celsius: u16,
+	/// Humidity in percent above which the fan runs, where 0 disables it
+	pub fan_humidity: u16,
+	/// Soil temperature in degrees Celsius which the heat mat keeps, where 0 disables it
+	pub mat_celsius: u16,
+	/// How far in tenths of a degree the soil can cool below the target before the heat mat is
+	/// switched on
+	pub mat_band: u16,
+	/// How long in minutes the heat mat can be on for before it is cut out
+	pub mat_max_mins: u16,
+	/// Thousands of times the valve can be opened before it is due to be serviced, where 0 never
+	/// reminds
+	pub service_kcycles: u16,
+	/// Days left of vacation mode, which is counted down once a day, where 0 is off
+	pub vacation_days: u16,
+	/// Factor the intervals between activations are multiplied by on vacation
+	pub vacation_interval_factor: u16,
+	/// Percentage of the activation time the valve is open for on vacation
+	pub vacation_time_percent: u16,
+	/// How long in seconds the master valve is opened for before the valve
+	pub master_lead_secs: u16,
+	/// How long in seconds the master valve is kept open for after the valve is closed
+	pub master_lag_secs: u16,
+	/// How long in seconds to wait between opening the valve and starting the pump, and between
+	/// stopping the pump and closing the valve
+	pub pump_delay_secs: u16,
+	/// Supply voltage in tenths of a volt below which the system enters low-power mode
+	pub low_battery_dv: u16,
+	/// How long in seconds between telemetry records
+	pub telemetry_secs: u16,
+	/// Moisture which the sensors water the soil up to in pulses, where 0 waters for the
+	/// activation time instead
+	pub band_low: u16,
+	/// Moisture above which a pulse stops the activation early
+	pub band_high: u16,
+	/// How long in minutes the pulses of an activation can add up to
+	pub band_cap_mins: u16,
+	/// How long the valve should be open for in the current activation, if it was adjusted when the
+	/// sensors started it
+	pub adjusted_duration: Option<Duration>,
 }
 
-macro_rules! update_value {
-	(add $current:expr, $add:expr, $max:expr) => {{
-		let max_diff = $max - $add;
-		if $current >= max_diff {
-			$max
+impl SystemConfig {
+	/// Create a new [`SystemConfig`] with default values
+	pub fn new() -> Self {
+		Self {
+			activate_mins: Number::Time.default_value(),
+			min_light: Number::Light.default_value(),
+			min_moisture: Number::Moisture.default_value(),
+			flow_percent: Number::Flow.default_value(),
+			activation_state: SystemState::Waiting,
+			sound: DEFAULT_SOUND,
+			remote_control: DEFAULT_REMOTE_CONTROL,
+			bt_code: None,
+			relay_active_low: DEFAULT_RELAY_ACTIVE_LOW,
+			valve_normally_open: DEFAULT_VALVE_NORMALLY_OPEN,
+			brightness_percent: Number::Brightness.default_value(),
+			night_dim: DEFAULT_NIGHT_DIM,
+			units: DEFAULT_UNITS,
+			trigger: DEFAULT_TRIGGER,
+			evening_hours: Number::Evening.default_value(),
+			et_scaling: DEFAULT_ET_SCALING,
+			profile: DEFAULT_PROFILE,
+			dose: DEFAULT_DOSE,
+			dose_secs: Number::DoseTime.default_value(),
+			dose_cap_mins: Number::DoseCap.default_value(),
+			mist_celsius: Number::MistTemp.default_value(),
+			mist_secs: Number::MistTime.default_value(),
+			mist_interval_mins: Number::MistInterval.default_value(),
+			day_hours: Number::DayHours.default_value(),
+			fan_celsius: Number::FanTemp.default_value(),
+			fan_humidity: Number::FanHumidity.default_value(),
+			mat_celsius: Number::MatTemp.default_value(),
+			mat_band: Number::MatBand.default_value(),
+			mat_max_mins: Number::MatMax.default_value(),
+			service_kcycles: Number::ServiceCycles.default_value(),
+			vacation_days: Number::VacationDays.default_value(),
+			vacation_interval_factor: Number::VacationInterval.default_value(),
+			vacation_time_percent: Number::VacationTime.default_value(),
+			master_lead_secs: Number::MasterLead.default_value(),
+			master_lag_secs: Number::MasterLag.default_value(),
+			pump_delay_secs: Number::PumpDelay.default_value(),
+			low_battery_dv: Number::LowBattery.default_value(),
+			telemetry_secs: Number::Telemetry.default_value(),
+			band_low: Number::BandLow.default_value(),
+			band_high: Number::BandHigh.default_value(),
+			band_cap_mins: Number::BandCap.default_value(),
+			adjusted_duration: None,
+		}
+	}
+
+	/// How long the system should be activated for
+	pub fn activation_duration(&self) -> Duration {
+		Duration::from_config_mins(self.activate_mins)
+	}
+
+	/// How long the valve should be open for in an activation, scaled by the output profile, and
+	/// reduced on vacation
+	///
+	/// Under band control, it is the cap on the pulses instead.
+	pub fn watering_duration(&self) -> Duration {
+		if self.is_band_control() {
+			return self.vacation_duration(Duration::from_config_mins(self.band_cap_mins));
+		}
+		self.vacation_duration(self.profile.scale(self.activation_duration()))
+	}
+
+	/// How long the valve has been open for, `elapsed` into an activation
+	pub fn watered(&self, elapsed: Duration) -> Duration {
+		if self.is_band_control() {
+			band::pulsed(elapsed)
 		} else {
-			$current + $add
+			self.profile.watered(elapsed)
 		}
-	}};
+	}
 
-	(subtract $current:expr, $subtract:expr, $min:expr) => {{
-		let min_diff = $min + $subtract;
-		if $current <= min_diff {
-			$min
+	/// Whether the valve is closed between the runs or pulses of an activation, `elapsed` into it
+	pub fn is_soaking(&self, elapsed: Duration) -> bool {
+		if self.is_band_control() {
+			band::is_pausing(elapsed)
 		} else {
-			$current - $subtract
+			self.profile.is_soaking(elapsed)
 		}
-	}};
-}
+	}
 
-impl SystemConfig {
-	/// Create a new [`SystemConfig`] with default values
-	pub fn new() -> Self {
-		Self {
-			activate_mins: DEFAULT_ACTIVATE_MINS,
-			min_light: DEFAULT_MIN_LIGHT,
-			min_moisture: DEFAULT_MIN_MOISTURE,
-			activation_state: ActivationState::Waiting,
-			update: None,
+	/// Whether the sensors water the soil up into the moisture band (see [`crate::band`])
+	pub fn is_band_control(&self) -> bool {
+		Strategy::of(self) == Strategy::Band
+	}
+
+	/// How long the valve should be open for in the current activation
+	pub fn run_duration(&self) -> Duration {
+		self.adjusted_duration
+			.unwrap_or_else(|| self.watering_duration())
+	}
+
+	/// How long each misting burst lasts, reduced on vacation
+	pub fn mist_duration(&self) -> Duration {
+		self.vacation_duration(Duration::from_secs(self.mist_secs))
+	}
+
+	/// How long to wait between misting bursts, lengthened on vacation
+	pub fn mist_interval(&self) -> Duration {
+		self.vacation_interval(Duration::from_config_mins(self.mist_interval_mins))
+	}
+
+	/// Shortest time between the end of an activation and the sensors starting the next one,
+	/// lengthened on vacation
+	pub fn min_interval(&self) -> Duration {
+		self.vacation_interval(self.profile.min_interval())
+	}
+
+	/// Whether vacation mode is on
+	pub fn is_on_vacation(&self) -> bool {
+		self.vacation_days != 0
+	}
+
+	/// `duration` reduced to the vacation time percentage, if on vacation
+	fn vacation_duration(&self, duration: Duration) -> Duration {
+		if !self.is_on_vacation() {
+			return duration;
 		}
+		Duration::from_millis(duration.as_millis() / 100 * self.vacation_time_percent as u32)
+	}
+
+	/// `interval` multiplied by the vacation interval factor, if on vacation
+	fn vacation_interval(&self, interval: Duration) -> Duration {
+		if !self.is_on_vacation() {
+			return interval;
+		}
+		Duration::from_millis(
+			interval
+				.as_millis()
+				.saturating_mul(self.vacation_interval_factor as u32),
+		)
+	}
+
+	/// Activation time scaled by `et_percent` of the estimated evapotranspiration, limited to the
+	/// range of allowed activation times, and then scaled by the output profile and reduced on
+	/// vacation
+	pub fn et_duration(&self, et_percent: u16) -> Duration {
+		let mins = self.activate_mins as u32 * et_percent as u32 / 100;
+		let range = Number::Time.range();
+		let mins = update_value!(set mins, range.min as u32, range.max as u32);
+		self.vacation_duration(self.profile.scale(Duration::from_config_mins(mins as u16)))
 	}
 
 	/// Reset to defaults
+	///
+	/// The relay wiring is kept, like the pin map, as it depends on the hardware rather than on
+	/// what is being watered.
 	pub fn reset(&mut self) {
-		self.activate_mins = DEFAULT_ACTIVATE_MINS;
-		self.min_light = DEFAULT_MIN_LIGHT;
-		self.min_moisture = DEFAULT_MIN_MOISTURE;
-		self.activation_state = ActivationState::Waiting;
-	}
-
-	/// Set an update action to be performed on the next call to [`SystemConfig::update`]
-	pub fn update_next_tick(&mut self, update: UpdateSystemValue) {
-		self.update = Some(update);
-	}
-
-	/// Makes an update to a value if necessary
-	pub fn update(&mut self) -> Option<UpdateSystemValue> {
-		// Set self.update to None so that the next call to `update` doesn't peform another update.
-		let update = take(&mut self.update);
-		if let Some(update) = &update {
-			match update {
-				// If the activation time value has changed, then increment or decrement it
-				UpdateSystemValue::Time(_) => match update.inner_as_ref() {
-					Some(ValueAction::Increment) => {
-						self.activate_mins = update_value!(add self.activate_mins, ACTIVATION_TIME_INCREMENT, ACTIVATION_TIME_MAX);
-					}
-					Some(ValueAction::Decrement) => {
-						self.activate_mins = update_value!(subtract self.activate_mins, ACTIVATION_TIME_INCREMENT, ACTIVATION_TIME_MIN);
-					}
-					_ => {}
-				},
-				// If the minimum light value has changed, then increment or decrement it
-				UpdateSystemValue::Light(_) => match update.inner_as_ref() {
-					Some(ValueAction::Increment) => {
-						self.min_light =
-							update_value!(add self.min_light, MIN_LIGHT_INCREMENT, MIN_LIGHT_MAX);
-					}
-					Some(ValueAction::Decrement) => {
-						self.min_light = update_value!(subtract self.min_light, MIN_LIGHT_INCREMENT, MIN_LIGHT_MIN);
-					}
-					_ => {}
-				},
-				// If the minimum moisture value has changed, then increment or decrement it
-				UpdateSystemValue::Moisture(_) => match update.inner_as_ref() {
-					Some(ValueAction::Increment) => {
-						self.min_moisture = update_value!(add self.min_moisture, MIN_MOISTURE_INCREMENT, MIN_MOISTURE_MAX);
-					}
-					Some(ValueAction::Decrement) => {
-						self.min_moisture = update_value!(subtract self.min_moisture, MIN_MOISTURE_INCREMENT, MIN_MOISTURE_MIN);
-					}
-					_ => {}
-				},
-				// If the activation state should be changed...
-				UpdateSystemValue::ActivationState => {
-					self.activation_state = match self.activation_state {
-						// If it is currently suspending, move it to the suspended state;
-						ActivationState::Suspending => ActivationState::Suspended,
-						// If it is suspended, move it to the wating state;
-						ActivationState::Suspended => ActivationState::Waiting,
-						// If it is currently activating, move it to the activated state;
-						ActivationState::Activating => ActivationState::Activated,
-						// If it is activated, move it to the waiting state;
-						ActivationState::Activated => ActivationState::Waiting,
-						// If it is waiting, move it to the activated state.
-						ActivationState::Waiting => ActivationState::Activated,
-					}
+		for number in Number::ALL {
+			*number.get_mut(self) = number.default_value();
+		}
+		self.sound = DEFAULT_SOUND;
+		self.remote_control = DEFAULT_REMOTE_CONTROL;
+		self.bt_code = None;
+		self.night_dim = DEFAULT_NIGHT_DIM;
+		self.units = DEFAULT_UNITS;
+		self.trigger = DEFAULT_TRIGGER;
+		self.et_scaling = DEFAULT_ET_SCALING;
+		self.profile = DEFAULT_PROFILE;
+		self.dose = DEFAULT_DOSE;
+		self.adjusted_duration = None;
+	}
+
+	/// Whether the light and moisture readings should activate the system
+	///
+	/// Under band control, only the moisture falling below the band activates it.
+	pub fn should_activate(&self, light: u16, moisture: u16) -> bool {
+		if self.is_band_control() {
+			return moisture < self.band_low;
+		}
+		self.trigger
+			.should_activate(moisture < self.min_moisture, light < self.min_light)
+	}
+
+	/// Apply a command to the configuration
+	///
+	/// Changes to the activation state are left to [`crate::activation::transition`].
+	pub fn update(&mut self, update: &UpdateSystemValue) {
+		match update {
+			// If a numeric setting has changed, then increment, decrement or set it
+			UpdateSystemValue::Number(number, action) => {
+				let value = number.get_mut(self);
+				*value = number.range().apply(*value, action);
+			}
+			// The state of the system is moved on by the state machine (see
+			// `crate::activation::transition`).
+			UpdateSystemValue::ActivationState
+			| UpdateSystemValue::Activate
+			| UpdateSystemValue::Suspend
+			| UpdateSystemValue::Winterize
+			| UpdateSystemValue::Fault(_)
+			| UpdateSystemValue::Acknowledge => {}
+			// The valve cycles are counted by `crate::stats`.
+			UpdateSystemValue::Serviced => {}
+			// Toggle the buzzer
+			UpdateSystemValue::Sound => self.sound = !self.sound,
+			// Toggle remote control
+			UpdateSystemValue::RemoteControl => self.remote_control = !self.remote_control,
+			// Toggle Bluetooth control, with a new pairing code each time it is enabled
+			UpdateSystemValue::BtControl => {
+				self.bt_code = match self.bt_code {
+					Some(_) => None,
+					None => Some(pairing_code()),
 				}
-				// If the suspended state should be toggled...
-				UpdateSystemValue::Activate => {
-					let is_activated = self.activation_state.is_activating()
-						|| self.activation_state.is_activated();
-					if !is_activated {
-						// If the system is not currently activating or activated, move it to the
-						// activating state;
-						self.activation_state = ActivationState::Activating;
-					} else {
-						// Otherwise, if it is activating or activated, move it to the waiting
-						// state.
-						self.activation_state = ActivationState::Waiting;
-					}
+			}
+			// Toggle the relay polarity
+			UpdateSystemValue::RelayPolarity => self.relay_active_low = !self.relay_active_low,
+			// Toggle the valve type
+			UpdateSystemValue::ValveType => self.valve_normally_open = !self.valve_normally_open,
+			// Toggle night dimming
+			UpdateSystemValue::NightDim => self.night_dim = !self.night_dim,
+			UpdateSystemValue::Units => {
+				self.units = match self.units {
+					Units::Metric => Units::Imperial,
+					Units::Imperial => Units::Metric,
 				}
-				// If the suspended state should be toggled...
-				UpdateSystemValue::Suspend => {
-					let is_suspended = self.activation_state.is_suspending()
-						|| self.activation_state.is_suspended();
-					if !is_suspended {
-						// If the system is not currently suspending or suspended, move it to the
-						// suspending state;
-						self.activation_state = ActivationState::Suspending;
-					} else {
-						// Otherwise, if it is suspending or suspended, move it to the waiting
-						// state.
-						self.activation_state = ActivationState::Waiting;
-					}
+			}
+			// Move to the next trigger policy
+			UpdateSystemValue::Trigger => self.trigger = self.trigger.next(),
+			// Toggle evapotranspiration scaling
+			UpdateSystemValue::EtScaling => self.et_scaling = !self.et_scaling,
+			// Move to the next output profile
+			UpdateSystemValue::Profile => self.profile = self.profile.next(),
+			// Toggle the dosing pump
+			UpdateSystemValue::Dose => self.dose = !self.dose,
+			// Set the values of a preset
+			UpdateSystemValue::Preset(idx) => {
+				if let Some(preset) = PRESETS.get(*idx as usize) {
+					preset.apply(self);
 				}
-				// Reset the configuration values
-				UpdateSystemValue::Reset => self.reset(),
 			}
+			// Reset the configuration values
+			UpdateSystemValue::Reset => self.reset(),
+		}
+	}
+}
+
+/// New pairing code for Bluetooth control
+///
+/// The code is taken from the clock when Bluetooth control is enabled from the menu, as the
+/// millisecond of a button press can't be guessed. The clock only counts up, so it is scrambled
+/// with xorshift first.
+fn pairing_code() -> u16 {
+	let mut bits = TIMER.millis() | 1;
+	bits ^= bits << 13;
+	bits ^= bits >> 17;
+	bits ^= bits << 5;
+	MIN_PAIRING_CODE + (bits % PAIRING_CODES) as u16
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::timer::{self, tests::TimersGuard};
+
+	fn apply(system_config: &mut SystemConfig, update: UpdateSystemValue) {
+		system_config.update(&update);
+	}
+
+	#[test]
+	fn increment_stops_at_max() {
+		let mut system_config = SystemConfig::new();
+		for _ in 0..100 {
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(Number::Time, ValueAction::Increment),
+			);
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(Number::Light, ValueAction::Increment),
+			);
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(Number::Moisture, ValueAction::Increment),
+			);
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(Number::Flow, ValueAction::Increment),
+			);
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(Number::Brightness, ValueAction::Increment),
+			);
+		}
+
+		assert_eq!(system_config.activate_mins, Number::Time.range().max);
+		assert_eq!(system_config.min_light, Number::Light.range().max);
+		assert_eq!(system_config.min_moisture, Number::Moisture.range().max);
+		assert_eq!(system_config.flow_percent, Number::Flow.range().max);
+		assert_eq!(
+			system_config.brightness_percent,
+			Number::Brightness.range().max
+		);
+	}
+
+	#[test]
+	fn decrement_stops_at_min() {
+		let mut system_config = SystemConfig::new();
+		for _ in 0..100 {
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(Number::Time, ValueAction::Decrement),
+			);
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(Number::Light, ValueAction::Decrement),
+			);
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(Number::Moisture, ValueAction::Decrement),
+			);
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(Number::Flow, ValueAction::Decrement),
+			);
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(Number::Brightness, ValueAction::Decrement),
+			);
+		}
+
+		assert_eq!(system_config.activate_mins, Number::Time.range().min);
+		assert_eq!(system_config.min_light, Number::Light.range().min);
+		assert_eq!(system_config.min_moisture, Number::Moisture.range().min);
+		assert_eq!(system_config.flow_percent, Number::Flow.range().min);
+		assert_eq!(
+			system_config.brightness_percent,
+			Number::Brightness.range().min
+		);
+	}
+
+	#[test]
+	fn increment_near_max_is_clamped() {
+		let mut system_config = SystemConfig::new();
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Time, ValueAction::Set(Number::Time.range().max - 2)),
+		);
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Time, ValueAction::Increment),
+		);
+		assert_eq!(system_config.activate_mins, Number::Time.range().max);
+
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(
+				Number::Light,
+				ValueAction::Set(Number::Light.range().max - 1),
+			),
+		);
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Light, ValueAction::Increment),
+		);
+		assert_eq!(system_config.min_light, Number::Light.range().max);
+	}
+
+	#[test]
+	fn decrement_near_min_is_clamped() {
+		let mut system_config = SystemConfig::new();
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Time, ValueAction::Set(Number::Time.range().min + 2)),
+		);
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Time, ValueAction::Decrement),
+		);
+		assert_eq!(system_config.activate_mins, Number::Time.range().min);
+
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(
+				Number::Moisture,
+				ValueAction::Set(Number::Moisture.range().min + 1),
+			),
+		);
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Moisture, ValueAction::Decrement),
+		);
+		assert_eq!(system_config.min_moisture, Number::Moisture.range().min);
+	}
+
+	#[test]
+	fn set_is_limited_to_range() {
+		let mut system_config = SystemConfig::new();
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Time, ValueAction::Set(0)),
+		);
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Flow, ValueAction::Set(0)),
+		);
+		assert_eq!(system_config.activate_mins, Number::Time.range().min);
+		assert_eq!(system_config.flow_percent, Number::Flow.range().min);
+
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Time, ValueAction::Set(u16::MAX)),
+		);
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Light, ValueAction::Set(u16::MAX)),
+		);
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Moisture, ValueAction::Set(u16::MAX)),
+		);
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Flow, ValueAction::Set(u16::MAX)),
+		);
+		assert_eq!(system_config.activate_mins, Number::Time.range().max);
+		assert_eq!(system_config.min_light, Number::Light.range().max);
+		assert_eq!(system_config.min_moisture, Number::Moisture.range().max);
+		assert_eq!(system_config.flow_percent, Number::Flow.range().max);
+
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Light, ValueAction::Set(525)),
+		);
+		assert_eq!(system_config.min_light, 525);
+	}
+
+	#[test]
+	fn reset_restores_defaults() {
+		let mut system_config = SystemConfig::new();
+		for number in Number::ALL {
+			let range = number.range();
+			let value = if number.default_value() == range.max {
+				range.min
+			} else {
+				range.max
+			};
+			apply(
+				&mut system_config,
+				UpdateSystemValue::Number(*number, ValueAction::Set(value)),
+			);
+			assert_ne!(number.get(&system_config), number.default_value());
+		}
+		apply(&mut system_config, UpdateSystemValue::Activate);
+		apply(&mut system_config, UpdateSystemValue::Sound);
+		apply(&mut system_config, UpdateSystemValue::RemoteControl);
+		apply(&mut system_config, UpdateSystemValue::BtControl);
+		apply(&mut system_config, UpdateSystemValue::RelayPolarity);
+		apply(&mut system_config, UpdateSystemValue::ValveType);
+		apply(&mut system_config, UpdateSystemValue::NightDim);
+		apply(&mut system_config, UpdateSystemValue::Units);
+		apply(&mut system_config, UpdateSystemValue::Trigger);
+		apply(&mut system_config, UpdateSystemValue::EtScaling);
+		apply(&mut system_config, UpdateSystemValue::Profile);
+		apply(&mut system_config, UpdateSystemValue::Dose);
+
+		apply(&mut system_config, UpdateSystemValue::Reset);
+
+		for number in Number::ALL {
+			assert_eq!(number.get(&system_config), number.default_value());
+		}
+		assert!(system_config.activation_state.is_waiting());
+		assert_eq!(system_config.sound, DEFAULT_SOUND);
+		assert_eq!(system_config.remote_control, DEFAULT_REMOTE_CONTROL);
+		assert_eq!(system_config.bt_code, None);
+		// The relay wiring is kept.
+		assert_eq!(system_config.relay_active_low, !DEFAULT_RELAY_ACTIVE_LOW);
+		assert_eq!(
+			system_config.valve_normally_open,
+			!DEFAULT_VALVE_NORMALLY_OPEN
+		);
+		assert_eq!(system_config.night_dim, DEFAULT_NIGHT_DIM);
+		assert_eq!(system_config.units, DEFAULT_UNITS);
+		assert_eq!(system_config.trigger, DEFAULT_TRIGGER);
+		assert_eq!(system_config.et_scaling, DEFAULT_ET_SCALING);
+		assert_eq!(system_config.profile, DEFAULT_PROFILE);
+		assert_eq!(system_config.dose, DEFAULT_DOSE);
+	}
+
+	#[test]
+	fn temperatures_are_shown_in_the_chosen_units() {
+		let mut system_config = SystemConfig::new();
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::MistTemp, ValueAction::Set(30)),
+		);
+		let value = |system_config: &SystemConfig, number: Number| {
+			Buffer::<16>::from_display(&number.value(system_config))
+		};
+		assert!(value(&system_config, Number::MistTemp)
+			.as_str()
+			.ends_with(" 30C"));
+		assert!(value(&system_config, Number::MatBand)
+			.as_str()
+			.ends_with(" 1.0"));
+
+		apply(&mut system_config, UpdateSystemValue::Units);
+		assert!(value(&system_config, Number::MistTemp)
+			.as_str()
+			.ends_with(" 86F"));
+		assert!(value(&system_config, Number::MatBand)
+			.as_str()
+			.ends_with(" 1.8"));
+		assert!(value(&system_config, Number::FanTemp)
+			.as_str()
+			.ends_with(" -"));
+	}
+
+	#[test]
+	fn number_defaults_are_within_range() {
+		for number in Number::ALL {
+			let range = number.range();
+			assert!(range.min <= number.default_value());
+			assert!(number.default_value() <= range.max);
+			assert!(range.step > 0 && range.step <= range.max - range.min);
+			assert_eq!(number.get(&SystemConfig::new()), number.default_value());
 		}
+	}
+
+	#[test]
+	fn et_duration_is_limited_to_range() {
+		let system_config = SystemConfig::new();
+		let mins = |et_percent| {
+			system_config.et_duration(et_percent).as_millis()
+				/ Duration::from_config_mins(1).as_millis()
+		};
+
+		assert_eq!(mins(100), Number::Time.default_value() as u32);
+		assert_eq!(mins(250), 25);
+		assert_eq!(mins(25), Number::Time.range().min as u32);
+		assert_eq!(mins(1_000), Number::Time.range().max as u32);
+	}
+
+	#[test]
+	fn vacation_reduces_durations_and_lengthens_intervals() {
+		let mut system_config = SystemConfig::new();
+		system_config.profile = Profile::Drip;
+		let (watering, mist, interval) = (
+			system_config.watering_duration().as_millis(),
+			system_config.mist_duration().as_millis(),
+			system_config.min_interval().as_millis(),
+		);
+
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::VacationDays, ValueAction::Set(5)),
+		);
+		assert!(system_config.is_on_vacation());
+		assert_eq!(system_config.watering_duration().as_millis(), watering / 2);
+		assert_eq!(system_config.mist_duration().as_millis(), mist / 2);
+		assert_eq!(system_config.min_interval().as_millis(), interval * 2);
+	}
+
+	#[test]
+	fn band_control_caps_pulses_and_gives_way_to_misting() {
+		let mut system_config = SystemConfig::new();
+		system_config.profile = Profile::Drip;
+		system_config.band_low = 300;
+		assert!(system_config.is_band_control());
+		assert_eq!(
+			system_config.watering_duration().as_millis(),
+			Duration::from_config_mins(system_config.band_cap_mins).as_millis()
+		);
+		assert!(system_config.should_activate(900, 275));
+		assert!(!system_config.should_activate(0, 300));
+
+		system_config.mist_celsius = 30;
+		assert!(!system_config.is_band_control());
+		assert_eq!(
+			system_config.watering_duration().as_millis(),
+			system_config.activation_duration().as_millis() * 3
+		);
+	}
+
+	#[test]
+	fn trigger_cycles_through_policies() {
+		let mut system_config = SystemConfig::new();
+		let mut seen = [DEFAULT_TRIGGER; 4];
+		for trigger in seen.iter_mut().skip(1) {
+			apply(&mut system_config, UpdateSystemValue::Trigger);
+			*trigger = system_config.trigger;
+		}
+		assert_eq!(
+			seen,
+			[
+				Trigger::MoistureAndLight,
+				Trigger::MoistureOnly,
+				Trigger::LightOnly,
+				Trigger::MoistureOrLight,
+			]
+		);
+
+		apply(&mut system_config, UpdateSystemValue::Trigger);
+		assert_eq!(system_config.trigger, Trigger::MoistureAndLight);
+	}
+
+	#[test]
+	fn bt_control_has_a_new_code_each_time() {
+		let _timers = TimersGuard::take();
+		let mut system_config = SystemConfig::new();
+		let mut codes = [0; 2];
+		for code in codes.iter_mut() {
+			apply(&mut system_config, UpdateSystemValue::BtControl);
+			*code = system_config.bt_code.unwrap();
+			assert!((1_000..=9_999).contains(code));
+
+			apply(&mut system_config, UpdateSystemValue::BtControl);
+			assert_eq!(system_config.bt_code, None);
+			timer::advance(1_234);
+		}
+		assert_ne!(codes[0], codes[1]);
+	}
+
+	#[test]
+	fn values_refresh_from_the_same_setting() {
+		let mut system_config = SystemConfig::new();
+		apply(
+			&mut system_config,
+			UpdateSystemValue::Number(Number::Time, ValueAction::Set(25)),
+		);
+		assert!(matches!(
+			SystemValue::Number(Number::Time, 0, Units::Metric).current(&system_config),
+			SystemValue::Number(Number::Time, 25, Units::Metric)
+		));
+		assert!(matches!(
+			SystemValue::Suspend(SystemState::Activated).current(&system_config),
+			SystemValue::Suspend(SystemState::Waiting)
+		));
+		system_config.activation_state = SystemState::Winterized;
+		assert!(matches!(
+			SystemValue::Winterize(SystemState::Waiting).current(&system_config),
+			SystemValue::Winterize(SystemState::Winterized)
+		));
 
-		update
+		let faulted = SystemValue::Activate(SystemState::Fault(FaultKind::Valve));
+		assert_eq!(
+			UpdateSystemValue::from_value(&faulted, ValueAction::Increment),
+			UpdateSystemValue::Acknowledge
+		);
 	}
 }
@@ -1,19 +1,58 @@
-use arduino_hal::{
-	clock::MHz16,
-	hal::{port::PC2, Adc},
-	port::{mode::Analog, Pin},
-};
 use core::convert::TryFrom;
 
-/// The lower and upper bounds of the analog read for button 1 (Select)
-const BUTTON_1_THRESHOLD: (u16, u16) = (195, 220);
-/// The lower and upper bounds of the analog read for button 2 (Left)
-const BUTTON_2_THRESHOLD: (u16, u16) = (395, 415);
-/// The lower and upper bounds of the analog read for button 3 (Right)
-const BUTTON_3_THRESHOLD: (u16, u16) = (990, 1023);
+#[cfg(feature = "atmega328p")]
+use crate::{
+	eeprom::{Eeprom, BUTTON_THRESHOLDS_ADDRESS},
+	hal::{Adc, AnalogReader, Buttons},
+};
+use crate::{hal::Clock, queue::Queue, timer::TIMER};
+
+#[cfg(all(feature = "atmega328p", feature = "encoder"))]
+pub mod encoder;
+
+/// Number of buttons which can be connected to the control pad
+pub const BUTTON_COUNT: usize = 5;
+
+/// The lower and upper bounds of the analog read for each button, in [`ButtonType::ALL`] order
+///
+/// Buttons which aren't connected have bounds of `(0, 0)`, which never match a reading.
+const DEFAULT_THRESHOLDS: [(u16, u16); BUTTON_COUNT] = [
+	// Button 1 (Select)
+	(195, 220),
+	// Button 2 (Left)
+	(395, 415),
+	// Button 3 (Right)
+	(990, 1023),
+	// Button 4 (Up)
+	(0, 0),
+	// Button 5 (Down)
+	(0, 0),
+];
+/// Largest value of an analog reading
+const ANALOG_MAX: u16 = 1023;
+/// Amount either side of a calibrated reading which is still considered the same button
+const CALIBRATION_MARGIN: u16 = 20;
+/// Smallest difference between calibrated readings for them to be considered different buttons
+const CALIBRATION_MIN_SEPARATION: u16 = 10;
+
+/// How long in milliseconds a reading must be stable before a button is considered pressed or
+/// released
+const DEBOUNCE_MS: u32 = 30;
+
+/// How long in milliseconds a button must be held before its action repeats
+const REPEAT_INTERVAL_MS: u32 = 250;
+/// How long in milliseconds between repeats once repeating has accelerated
+const REPEAT_FAST_INTERVAL_MS: u32 = 75;
+/// Number of repeats (roughly 2 seconds worth) after which repeating accelerates
+const REPEAT_ACCELERATE_AFTER: u8 = 8;
+/// How long in milliseconds a button must be held before it is considered a long press
+const LONG_PRESS_MS: u32 = 1_000;
+
+/// Amount of button events which can be queued between ticks
+pub const EVENT_QUEUE_SIZE: usize = 8;
 
 /// Variants representing a button
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone, Copy)]
 pub enum ButtonType {
 	/// Button 1
 	Select,
@@ -21,24 +60,168 @@ pub enum ButtonType {
 	Left,
 	/// Button 3
 	Right,
+	/// Button 4 (optional)
+	Up,
+	/// Button 5 (optional)
+	Down,
+}
+
+impl ButtonType {
+	/// Every button, in the same order as the bounds in [`ButtonThresholds`]
+	pub const ALL: [ButtonType; BUTTON_COUNT] =
+		[Self::Select, Self::Left, Self::Right, Self::Up, Self::Down];
+
+	/// Name of the button
+	pub fn label(&self) -> &'static str {
+		match self {
+			Self::Select => "Select",
+			Self::Left => "Left",
+			Self::Right => "Right",
+			Self::Up => "Up",
+			Self::Down => "Down",
+		}
+	}
+
+	/// Whether the control pad works without the button being connected
+	pub fn is_optional(&self) -> bool {
+		matches!(self, Self::Up | Self::Down)
+	}
+}
+
+/// The lower and upper bounds of the analog read for each button, in [`ButtonType::ALL`] order
+#[derive(Clone, Copy)]
+pub struct ButtonThresholds([(u16, u16); BUTTON_COUNT]);
+
+impl ButtonThresholds {
+	/// Thresholds used when the buttons haven't been calibrated
+	pub const DEFAULT: Self = Self(DEFAULT_THRESHOLDS);
+
+	/// Size of the serialized thresholds in bytes
+	pub const SIZE: usize = BUTTON_COUNT * 4;
+
+	/// Work out thresholds from a reading taken for each button
+	///
+	/// Buttons without a reading are treated as not connected. Returns `None` if any two readings
+	/// are too close together to tell the buttons apart.
+	pub fn from_samples(samples: &[Option<u16>; BUTTON_COUNT]) -> Option<Self> {
+		let mut thresholds = [(0, 0); BUTTON_COUNT];
+		for (idx, sample) in samples.iter().enumerate() {
+			let sample = match sample {
+				Some(sample) => *sample,
+				None => continue,
+			};
+
+			// Limit the margin to half of the distance to the closest reading so that the bounds
+			// for each button never overlap.
+			let mut margin = CALIBRATION_MARGIN;
+			for (other_idx, other) in samples.iter().enumerate() {
+				let other = match other {
+					Some(other) if other_idx != idx => *other,
+					_ => continue,
+				};
+
+				let distance = if sample > other {
+					sample - other
+				} else {
+					other - sample
+				};
+				if distance < CALIBRATION_MIN_SEPARATION {
+					return None;
+				}
+				margin = margin.min(distance / 2);
+			}
+
+			thresholds[idx] = (
+				sample.saturating_sub(margin),
+				(sample + margin).min(ANALOG_MAX + 1),
+			);
+		}
+
+		Some(Self(thresholds))
+	}
+
+	/// Load calibrated thresholds from the EEPROM
+	///
+	/// Returns `None` if the buttons have never been calibrated.
+	#[cfg(feature = "atmega328p")]
+	pub fn load(eeprom: &Eeprom) -> Option<Self> {
+		let mut buf = [0; Self::SIZE];
+		if !eeprom.read_record(BUTTON_THRESHOLDS_ADDRESS, &mut buf) {
+			return None;
+		}
+
+		let mut thresholds = [(0, 0); BUTTON_COUNT];
+		for (idx, bounds) in thresholds.iter_mut().enumerate() {
+			let offset = idx * 4;
+			*bounds = (
+				u16::from_le_bytes([buf[offset], buf[offset + 1]]),
+				u16::from_le_bytes([buf[offset + 2], buf[offset + 3]]),
+			);
+		}
+
+		Some(Self(thresholds))
+	}
+
+	/// Store the thresholds in the EEPROM
+	#[cfg(feature = "atmega328p")]
+	pub fn save(&self, eeprom: &mut Eeprom) {
+		let mut buf = [0; Self::SIZE];
+		for (idx, (lower, upper)) in self.0.iter().enumerate() {
+			let offset = idx * 4;
+			buf[offset..offset + 2].copy_from_slice(&lower.to_le_bytes());
+			buf[offset + 2..offset + 4].copy_from_slice(&upper.to_le_bytes());
+		}
+
+		eeprom.write_record(BUTTON_THRESHOLDS_ADDRESS, &buf);
+	}
 }
 
 /// Variants representing the current stage of a button press
+#[derive(Clone, Copy)]
 pub enum ButtonStage {
 	/// Button has been pressed down
 	Down,
 	/// Button is being held down
 	Hold,
+	/// Button is being held down, and its action should be repeated
+	///
+	/// Only lasts for a single update before moving back to [`ButtonStage::Hold`].
+	Repeat,
+	/// Button has been held down for longer than [`LONG_PRESS_MS`]
+	///
+	/// Only lasts for a single update before moving back to [`ButtonStage::Hold`]. Releasing the
+	/// button after a long press does not produce a [`ButtonStage::Release`].
+	LongPress,
 	/// Button was released
 	Release,
 }
 
+/// A change in the stage of a button press
+#[derive(Clone, Copy)]
+pub struct ButtonEvent {
+	/// Stage the button moved into
+	pub stage: ButtonStage,
+	/// Button type
+	pub button: ButtonType,
+}
+
+/// Queue of button events which haven't been handled yet
+pub type ButtonEvents = Queue<ButtonEvent, EVENT_QUEUE_SIZE>;
+
 /// Represents the current state of a button
 pub struct ButtonState {
 	/// Button stage
-	pub stage: ButtonStage,
+	stage: ButtonStage,
 	/// Button type
-	pub button: ButtonType,
+	button: ButtonType,
+	/// [`TIMER`] clock value of when the button started being held
+	held_since_ms: u32,
+	/// [`TIMER`] clock value of when the button started being held, or last repeated
+	last_repeat_ms: u32,
+	/// Number of times the button has repeated while being held
+	repeats: u8,
+	/// Whether the button has already been long pressed
+	long_pressed: bool,
 }
 
 impl ButtonState {
@@ -47,86 +230,238 @@ impl ButtonState {
 		Self {
 			stage: ButtonStage::Down,
 			button,
+			held_since_ms: 0,
+			last_repeat_ms: 0,
+			repeats: 0,
+			long_pressed: false,
+		}
+	}
+
+	/// The event for the current stage of the button
+	///
+	/// Every stage other than [`ButtonStage::Hold`] only lasts for a single update, so is an
+	/// event.
+	fn event(&self) -> Option<ButtonEvent> {
+		match self.stage {
+			ButtonStage::Hold => None,
+			stage => Some(ButtonEvent {
+				stage,
+				button: self.button,
+			}),
+		}
+	}
+
+	/// Start holding the button down
+	fn start_hold(&mut self) {
+		let now = TIMER.millis();
+		self.stage = ButtonStage::Hold;
+		self.held_since_ms = now;
+		self.last_repeat_ms = now;
+	}
+
+	/// Move the button into either the [`ButtonStage::LongPress`], [`ButtonStage::Repeat`] or
+	/// [`ButtonStage::Hold`] stage depending on how long it has been held for
+	fn update_hold(&mut self) {
+		let now = TIMER.millis();
+		if !self.long_pressed && now.wrapping_sub(self.held_since_ms) >= LONG_PRESS_MS {
+			self.stage = ButtonStage::LongPress;
+			self.long_pressed = true;
+			return;
+		}
+
+		let interval = if self.repeats >= REPEAT_ACCELERATE_AFTER {
+			REPEAT_FAST_INTERVAL_MS
+		} else {
+			REPEAT_INTERVAL_MS
+		};
+
+		if now.wrapping_sub(self.last_repeat_ms) >= interval {
+			self.stage = ButtonStage::Repeat;
+			self.last_repeat_ms = now;
+			self.repeats = self.repeats.saturating_add(1);
+		} else {
+			self.stage = ButtonStage::Hold;
 		}
 	}
 }
 
-impl TryFrom<u16> for ButtonType {
+impl TryFrom<(u16, &ButtonThresholds)> for ButtonType {
 	type Error = ();
 
 	/// Attempt to convert the analog reading into a [`ButtonType`] based on the lower and upper
 	/// bounds for each button
-	fn try_from(value: u16) -> Result<Self, Self::Error> {
-		match value {
-			value if value >= BUTTON_1_THRESHOLD.0 && value < BUTTON_1_THRESHOLD.1 => {
-				Ok(Self::Select)
+	fn try_from((value, thresholds): (u16, &ButtonThresholds)) -> Result<Self, Self::Error> {
+		thresholds
+			.0
+			.iter()
+			.zip(Self::ALL.iter())
+			.find(|((lower, upper), _button)| value >= *lower && value < *upper)
+			.map(|(_bounds, button)| *button)
+			.ok_or(())
+	}
+}
+
+/// Debounces button readings
+///
+/// Readings bounce when a button is pressed or released, so a reading must be stable for
+/// [`DEBOUNCE_MS`] before it replaces the previous debounced reading.
+pub struct Debouncer {
+	/// Last raw button reading
+	reading: Option<ButtonType>,
+	/// [`TIMER`] clock value of when the raw button reading last changed
+	reading_since_ms: u32,
+	/// Button reading which has been stable for at least [`DEBOUNCE_MS`]
+	debounced: Option<ButtonType>,
+}
+
+impl Debouncer {
+	/// Create a new [`Debouncer`] with no button being pressed
+	pub fn new() -> Self {
+		Self {
+			reading: None,
+			reading_since_ms: 0,
+			debounced: None,
+		}
+	}
+
+	/// Debounce a raw button reading
+	pub fn update(&mut self, button: Option<ButtonType>) -> Option<ButtonType> {
+		let now = TIMER.millis();
+		if button != self.reading {
+			self.reading = button;
+			self.reading_since_ms = now;
+		} else if now.wrapping_sub(self.reading_since_ms) >= DEBOUNCE_MS {
+			self.debounced = button;
+		}
+
+		self.debounced
+	}
+}
+
+/// Update the state of a button from a debounced reading, and return the resulting event if any
+pub fn update_state(
+	state: &mut Option<ButtonState>,
+	button: Option<ButtonType>,
+) -> Option<ButtonEvent> {
+	// Compare the current state with the new state.
+	//
+	// This ignores a possible state where one button can be pressed in the next tick from
+	// releasing a different button. I think thats a rare (nearly impossible physically) edge
+	// case and its quicker to write a comment about it, than handle it.
+	match (&mut *state, button) {
+		(None, Some(button)) => {
+			// Set the button as pressed.
+			*state = Some(ButtonState::new(button));
+		}
+		(Some(button_state), button) => match (&button_state.stage, button) {
+			(ButtonStage::Down, Some(button)) if button == button_state.button => {
+				// If the current stage is `Down`, and the same button is being pressed, then
+				// move the state into `Hold`.
+				button_state.start_hold();
 			}
-			value if value >= BUTTON_2_THRESHOLD.0 && value < BUTTON_2_THRESHOLD.1 => {
-				Ok(Self::Left)
+			(ButtonStage::Hold, Some(button))
+			| (ButtonStage::Repeat, Some(button))
+			| (ButtonStage::LongPress, Some(button))
+				if button == button_state.button =>
+			{
+				// If the button is still being held, then move the state into `LongPress` or
+				// `Repeat` if it has been held long enough, otherwise back into `Hold`.
+				button_state.update_hold();
 			}
-			value if value >= BUTTON_3_THRESHOLD.0 && value < BUTTON_3_THRESHOLD.1 => {
-				Ok(Self::Right)
+			(_, None) if button_state.long_pressed => {
+				// If the button was long pressed and is no longer being pressed, then update
+				// the state so that no button is being pressed without releasing it.
+				*state = None;
 			}
-			_ => Err(()),
-		}
-	}
+			(ButtonStage::Down, _) | (ButtonStage::Hold, None) | (ButtonStage::Repeat, None) => {
+				// If no button is being pressed any longer (or a different button is pressed
+				// straight after this one), move the current stage into `Release`.
+				button_state.stage = ButtonStage::Release;
+			}
+			(ButtonStage::Release, _) => {
+				// If the current stage is `Release`, then update the state so that no button
+				// is being pressed.
+				*state = None;
+			}
+			_ => {}
+		},
+		_ => {}
+	};
+
+	state.as_ref().and_then(ButtonState::event)
 }
 
+/// Input used to control the menu, selected with the `encoder` feature
+#[cfg(all(feature = "atmega328p", not(feature = "encoder")))]
+pub type ControlInput = ControlPad;
+/// Input used to control the menu, selected with the `encoder` feature
+#[cfg(all(feature = "atmega328p", feature = "encoder"))]
+pub type ControlInput = encoder::Encoder;
+
 /// Current state of the "control pad", i.e. buttons
+#[cfg(feature = "atmega328p")]
 pub struct ControlPad {
 	/// Holds the pin for taking analog readings
-	buttons_input: Pin<Analog, PC2>,
+	buttons_input: Buttons,
+	/// Bounds of the analog reading for each button
+	thresholds: ButtonThresholds,
 	/// Whether a button is in a state, and what state it is in
 	///
 	/// `None` means that no button is being pressed.
-	pub state: Option<ButtonState>,
+	state: Option<ButtonState>,
+	/// Debounces the resistor ladder readings
+	debouncer: Debouncer,
+	/// Button events which haven't been handled yet
+	pub events: ButtonEvents,
 }
 
+#[cfg(feature = "atmega328p")]
 impl ControlPad {
-	/// Create a new `ControlPad` using the A2 pin
-	pub fn new(buttons_input: Pin<Analog, PC2>) -> Self {
+	/// Create a new `ControlPad` using the board's buttons pin (A2)
+	pub fn new(buttons_input: Buttons) -> Self {
 		Self {
 			buttons_input,
+			thresholds: ButtonThresholds::DEFAULT,
 			state: None,
+			debouncer: Debouncer::new(),
+			events: ButtonEvents::new(),
 		}
 	}
 
-	/// Takes an analog reading and updates the control pad's state
-	pub fn update(&mut self, adc: &mut Adc<MHz16>) {
-		// Take an analog reading.
-		let value = self.buttons_input.analog_read(adc);
+	/// Use calibrated thresholds from the EEPROM, if the buttons have been calibrated
+	pub fn load_thresholds(&mut self, eeprom: &Eeprom) {
+		if let Some(thresholds) = ButtonThresholds::load(eeprom) {
+			self.thresholds = thresholds;
+		}
+	}
+
+	/// Use new thresholds
+	pub fn set_thresholds(&mut self, thresholds: ButtonThresholds) {
+		self.thresholds = thresholds;
+	}
+
+	/// Take a raw analog reading
+	pub fn read_raw(&self, adc: &mut Adc) -> u16 {
+		adc.read(&self.buttons_input)
+	}
+
+	/// Take an analog reading and convert it to the button being pressed, if any
+	///
+	/// The reading isn't debounced.
+	pub fn read_button(&self, adc: &mut Adc) -> Option<ButtonType> {
 		// Convert the `Result<ButtonType, ()>` to an `Option<ButtonType>`.
-		let button = ButtonType::try_from(value).ok();
-
-		// Compare the current state with the new state.
-		//
-		// This ignores a possible state where one button can be pressed in the next tick from
-		// releasing a different button. I think thats a rare (nearly impossible physically) edge
-		// case and its quicker to write a comment about it, than handle it.
-		match (&mut self.state, button) {
-			(None, Some(button)) => {
-				// Set the button as pressed.
-				self.state = Some(ButtonState::new(button));
-			}
-			(Some(button_state), button) => match (&button_state.stage, button) {
-				(ButtonStage::Down, Some(button)) if button == button_state.button => {
-					// If the current stage is `Down`, and the same button is being pressed, then
-					// move the state into `Hold`.
-					button_state.stage = ButtonStage::Hold;
-				}
-				(ButtonStage::Hold, None) => {
-					// If the current stage is `Hold` and no button is being pressed any longer,
-					// move the current stage into `Release`.
-					button_state.stage = ButtonStage::Release;
-				}
-				(ButtonStage::Release, _) => {
-					// If the current stage is `Release`, then update the state so that no button
-					// is being pressed.
-					self.state = None;
-				}
-				_ => {}
-			},
-			_ => {}
-		};
+		ButtonType::try_from((self.read_raw(adc), &self.thresholds)).ok()
+	}
+
+	/// Takes an analog reading and updates the control pad's state
+	pub fn update(&mut self, adc: &mut Adc) {
+		// Take an analog reading, and debounce it.
+		let button = self.read_button(adc);
+		let button = self.debouncer.update(button);
+
+		// Queue the event, if any. If the queue is full, the event is dropped.
+		if let Some(event) = update_state(&mut self.state, button) {
+			self.events.push(event);
+		}
 	}
 }
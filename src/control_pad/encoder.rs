@@ -0,0 +1,155 @@
+//! Quadrature rotary encoder with a push switch, as an alternative to the button pad
+//!
+//! Turning the encoder one detent clockwise acts as pressing and releasing [`ButtonType::Right`],
+//! and anti-clockwise as [`ButtonType::Left`]. Pushing the switch acts as [`ButtonType::Select`].
+//!
+//! The encoder's A and B outputs are connected to D4 and D5, and are decoded in the pin change
+//! interrupt so that no steps are missed while the display is being rendered. The switch is
+//! connected to D6.
+
+use arduino_hal::{
+	hal::port::{PD4, PD5, PD6},
+	pac::EXINT,
+	port::{
+		mode::{Input, PullUp},
+		Pin,
+	},
+};
+use avr_device::interrupt::Mutex;
+use core::cell::Cell;
+
+use super::{update_state, ButtonEvent, ButtonEvents, ButtonStage, ButtonType, Debouncer};
+
+/// Amount of steps the encoder moves through for a single detent
+const STEPS_PER_DETENT: i8 = 4;
+
+/// PCINT20 (D4) and PCINT21 (D5)
+const ENCODER_PCINT_MASK: u8 = 0b0011_0000;
+/// Bit offset of D4 in PORTD
+const ENCODER_PIN_OFFSET: u8 = 4;
+/// Pin change interrupt enable bit for PCINT[23:16]
+const PCIE2: u8 = 0b100;
+
+/// Direction of each step indexed by `(previous AB << 2) | current AB`
+///
+/// Invalid transitions (both A and B changing) are ignored.
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Previous state of the outputs, with B in bit 1 and A in bit 0
+static ENCODER_AB: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
+/// Steps which have been decoded, but not yet turned into detents
+static ENCODER_STEPS: Mutex<Cell<i8>> = Mutex::new(Cell::new(0));
+
+/// Rotary encoder
+pub struct Encoder {
+	/// Encoder outputs, held so that the pins can't be used for anything else
+	_outputs: (Pin<Input<PullUp>, PD4>, Pin<Input<PullUp>, PD5>),
+	/// Push switch
+	switch: Pin<Input<PullUp>, PD6>,
+	/// Whether the push switch is in a state, and what state it is in
+	///
+	/// `None` means that the switch isn't being pushed.
+	state: Option<ButtonState>,
+	/// Debounces the push switch
+	debouncer: Debouncer,
+	/// Detents which have been turned but not yet queued as button events
+	///
+	/// Positive values are clockwise.
+	pending_detents: i8,
+	/// Button events which haven't been handled yet
+	pub events: ButtonEvents,
+}
+
+impl Encoder {
+	/// Create a new [`Encoder`], and enable the pin change interrupt for its outputs
+	pub fn new(
+		output_a: Pin<Input<PullUp>, PD4>,
+		output_b: Pin<Input<PullUp>, PD5>,
+		switch: Pin<Input<PullUp>, PD6>,
+		exint: &EXINT,
+	) -> Self {
+		// Seed the previous state in the same order as the interrupt reads it, where output A (PD4)
+		// is bit 0.
+		let ab = ((output_b.is_high() as u8) << 1) | output_a.is_high() as u8;
+		avr_device::interrupt::free(|cs| ENCODER_AB.borrow(cs).set(ab));
+
+		exint
+			.pcmsk2
+			.modify(|r, w| unsafe { w.bits(r.bits() | ENCODER_PCINT_MASK) });
+		exint
+			.pcicr
+			.modify(|r, w| unsafe { w.bits(r.bits() | PCIE2) });
+
+		Self {
+			_outputs: (output_a, output_b),
+			switch,
+			state: None,
+			debouncer: Debouncer::new(),
+			pending_detents: 0,
+			events: ButtonEvents::new(),
+		}
+	}
+
+	/// Take the detents which have been decoded since the last call
+	fn take_detents() -> i8 {
+		avr_device::interrupt::free(|cs| {
+			let steps_cell = ENCODER_STEPS.borrow(cs);
+			let steps = steps_cell.get();
+			let detents = steps / STEPS_PER_DETENT;
+			steps_cell.set(steps - detents * STEPS_PER_DETENT);
+			detents
+		})
+	}
+
+	/// Updates the encoder's state, and queues any button events
+	///
+	/// Takes the ADC so that it can be used in place of [`super::ControlPad`], but doesn't use
+	/// it.
+	pub fn update(&mut self, _adc: &mut crate::hal::Adc) {
+		let button = if self.switch.is_low() {
+			Some(ButtonType::Select)
+		} else {
+			None
+		};
+		let button = self.debouncer.update(button);
+		if let Some(event) = update_state(&mut self.state, button) {
+			self.events.push(event);
+		}
+
+		// Each detent is queued as a press and release of either the Left or Right button. Any
+		// detents which don't fit in the queue are kept until the next update.
+		self.pending_detents = self.pending_detents.saturating_add(Self::take_detents());
+		while self.pending_detents != 0 && self.events.remaining() >= 2 {
+			let button = if self.pending_detents > 0 {
+				self.pending_detents -= 1;
+				ButtonType::Right
+			} else {
+				self.pending_detents += 1;
+				ButtonType::Left
+			};
+
+			for stage in [ButtonStage::Down, ButtonStage::Release].iter() {
+				self.events.push(ButtonEvent {
+					stage: *stage,
+					button,
+				});
+			}
+		}
+	}
+}
+
+#[avr_device::interrupt(atmega328p)]
+#[allow(non_snake_case)]
+fn PCINT2() {
+	avr_device::interrupt::free(|cs| {
+		let portd = unsafe { &*arduino_hal::pac::PORTD::ptr() };
+		let ab = (portd.pind.read().bits() >> ENCODER_PIN_OFFSET) & 0b11;
+
+		let ab_cell = ENCODER_AB.borrow(cs);
+		let step = TRANSITIONS[((ab_cell.get() << 2) | ab) as usize];
+		ab_cell.set(ab);
+
+		let steps_cell = ENCODER_STEPS.borrow(cs);
+		steps_cell.set(steps_cell.get().saturating_add(step));
+	})
+}
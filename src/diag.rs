@@ -0,0 +1,218 @@
+//! Diagnostics screen
+//!
+//! Shows the raw readings of the analog inputs, refreshed continuously, to help with wiring the
+//! sensors and the resistor ladder, and with choosing thresholds. Every button press is shown by
+//! its reading rather than acted on, so the screen is left by holding Select down. The SRAM usage
+//! is shown below the readings (see [`memory`]).
+//!
+//! With shift registers (see `crate::shiftreg`), Left and Right also step through their outputs,
+//! energizing the relay on each one in turn to test the wiring. The output being tested is shown
+//! next to the title.
+
+pub mod memory;
+
+use crate::{
+	display::{self, BODY_START_ROW, ROW_LENGTH},
+	fmt::Buffer,
+	hal::{Clock, DisplayBus},
+	lang,
+	timer::{Duration, TIMER},
+};
+
+use self::memory::Usage;
+
+/// How often the readings are refreshed
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+/// Longest reading, such as `1023` (or `2048` bytes of SRAM)
+const READING_LENGTH: usize = 4;
+
+/// Raw readings of the analog inputs
+pub struct Readings {
+	/// Light sensor on A0
+	pub light: u16,
+	/// Moisture sensor on A1
+	pub moisture: u16,
+	/// Resistor ladder on A2, or `None` if a rotary encoder is used instead
+	pub buttons: Option<u16>,
+	/// SRAM usage
+	pub memory: Usage,
+}
+
+/// Diagnostics screen
+pub struct Diagnostics {
+	/// [`TIMER`] clock value of the last time the readings were rendered
+	last_refresh_ms: u32,
+	/// Output being tested, if there is one
+	test_channel: Option<u8>,
+}
+
+impl Diagnostics {
+	/// Create a new [`Diagnostics`] screen
+	pub fn new() -> Self {
+		Self {
+			last_refresh_ms: 0,
+			test_channel: None,
+		}
+	}
+
+	/// Render the diagnostics screen in the body of the display
+	pub fn render(&mut self, readings: &Readings, display: &mut impl DisplayBus) {
+		display.clear_body();
+		self.render_title(display);
+		self.render_readings(readings, display);
+	}
+
+	/// Render the title, and the output being tested next to it
+	fn render_title(&self, display: &mut impl DisplayBus) {
+		display.clear_row(BODY_START_ROW);
+		let _ = ufmt::uwrite!(display, "{}", lang::DIAGNOSTICS);
+		if let Some(channel) = self.test_channel {
+			let mut label = Buffer::<3>::new();
+			let _ = ufmt::uwrite!(label, "#{}", channel + 1);
+			display.set_position(ROW_LENGTH - label.as_str().len() as u8, BODY_START_ROW);
+			let _ = display.write_str(label.as_str());
+		}
+	}
+
+	/// Output being tested, if there is one
+	pub fn test_channel(&self) -> Option<u8> {
+		self.test_channel
+	}
+
+	/// Test the next (or previous) of `channels` outputs, stopping after the last (or before the
+	/// first), and return the output now being tested
+	pub fn step_test_channel(
+		&mut self,
+		forwards: bool,
+		channels: u8,
+		display: &mut impl DisplayBus,
+	) -> Option<u8> {
+		self.test_channel = match (self.test_channel, forwards) {
+			(None, true) => Some(0),
+			(None, false) => channels.checked_sub(1),
+			(Some(channel), true) => Some(channel + 1),
+			(Some(channel), false) => channel.checked_sub(1),
+		}
+		.filter(|channel| *channel < channels);
+		self.render_title(display);
+		self.test_channel
+	}
+
+	/// Stop testing an output
+	pub fn stop_test(&mut self) {
+		self.test_channel = None;
+	}
+
+	/// Whether the readings are due to be refreshed
+	pub fn is_due(&self) -> bool {
+		TIMER.has_elapsed(self.last_refresh_ms, REFRESH_INTERVAL)
+	}
+
+	/// Render the readings on the diagnostics screen
+	///
+	/// Faster than [`Diagnostics::render`], so should be used whenever the readings are refreshed.
+	pub fn render_readings(&mut self, readings: &Readings, display: &mut impl DisplayBus) {
+		self.last_refresh_ms = TIMER.millis();
+
+		let rows = [
+			("A0 light", Some(readings.light)),
+			("A1 moisture", Some(readings.moisture)),
+			("A2 buttons", readings.buttons),
+			("Free RAM", Some(readings.memory.free)),
+			("Stack peak", Some(readings.memory.stack_peak)),
+		];
+		for (idx, (label, reading)) in rows.iter().enumerate() {
+			if let Some(reading) = reading {
+				let value = Buffer::<READING_LENGTH>::from_display(reading);
+				display.set_position(0, BODY_START_ROW + 1 + idx as u8);
+				let _ = ufmt::uwrite!(
+					display,
+					"{}",
+					display::align(label, value.as_str(), ROW_LENGTH)
+				);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		hal::mock::MockDisplay,
+		timer::{self, tests::TimersGuard},
+	};
+
+	#[test]
+	fn renders_readings() {
+		let mut display = MockDisplay::new();
+		let mut diagnostics = Diagnostics::new();
+		let mut readings = Readings {
+			light: 512,
+			moisture: 1023,
+			buttons: Some(0),
+			memory: Usage {
+				free: 412,
+				stack_peak: 380,
+			},
+		};
+		diagnostics.render(&readings, &mut display);
+
+		assert_eq!(display.row(BODY_START_ROW).trim_end(), "Diagnostics");
+		assert_eq!(display.row(BODY_START_ROW + 1), "A0 light:    512");
+		assert_eq!(display.row(BODY_START_ROW + 2), "A1 moisture:1023");
+		assert_eq!(display.row(BODY_START_ROW + 3), "A2 buttons:    0");
+		assert_eq!(display.row(BODY_START_ROW + 4), "Free RAM:    412");
+		assert_eq!(display.row(BODY_START_ROW + 5), "Stack peak:  380");
+
+		readings.light = 7;
+		readings.buttons = None;
+		diagnostics.render(&readings, &mut display);
+		assert_eq!(display.row(BODY_START_ROW + 1), "A0 light:      7");
+		assert_eq!(display.row(BODY_START_ROW + 3).trim_end(), "");
+	}
+
+	#[test]
+	fn steps_through_test_channels() {
+		let mut display = MockDisplay::new();
+		let mut diagnostics = Diagnostics::new();
+		assert_eq!(
+			diagnostics.step_test_channel(true, 8, &mut display),
+			Some(0)
+		);
+		assert_eq!(display.row(BODY_START_ROW), "Diagnostics   #1");
+		assert_eq!(diagnostics.step_test_channel(false, 8, &mut display), None);
+		assert_eq!(display.row(BODY_START_ROW).trim_end(), "Diagnostics");
+		assert_eq!(
+			diagnostics.step_test_channel(false, 8, &mut display),
+			Some(7)
+		);
+		assert_eq!(diagnostics.step_test_channel(true, 8, &mut display), None);
+
+		// Without any outputs to test, nothing is tested.
+		assert_eq!(diagnostics.step_test_channel(true, 0, &mut display), None);
+		assert_eq!(diagnostics.step_test_channel(false, 0, &mut display), None);
+	}
+
+	#[test]
+	fn refreshes_every_interval() {
+		let _timers = TimersGuard::take();
+		let mut display = MockDisplay::new();
+		let mut diagnostics = Diagnostics::new();
+		let readings = Readings {
+			light: 0,
+			moisture: 0,
+			buttons: None,
+			memory: Usage {
+				free: 0,
+				stack_peak: 0,
+			},
+		};
+
+		diagnostics.render(&readings, &mut display);
+		assert!(!diagnostics.is_due());
+
+		timer::advance(REFRESH_INTERVAL.as_millis());
+		assert!(diagnostics.is_due());
+	}
+}
@@ -0,0 +1,92 @@
+//! Free SRAM and stack usage
+//!
+//! The ATmega328P only has 2KB of SRAM, shared by the static data (including the buffers for the
+//! serial output, the event log and the display) and the stack, which grows down towards it. There
+//! is no heap, so everything between the end of the static data and the stack pointer is free.
+//!
+//! To find out how deep the stack has grown, the free SRAM is painted with a pattern when the
+//! system starts up. The stack overwrites the pattern as it grows, so the bytes which are still
+//! painted are the ones the stack has never reached.
+
+/// Byte written over the free SRAM by [`paint`]
+const PAINT: u8 = 0xc5;
+/// Address of the last byte of SRAM, where the stack starts
+#[cfg(feature = "atmega328p")]
+const RAM_END: usize = 0x08ff;
+/// Bytes below the stack pointer which [`paint`] leaves alone, for its own stack frame
+#[cfg(feature = "atmega328p")]
+const PAINT_MARGIN: usize = 32;
+
+#[cfg(feature = "atmega328p")]
+extern "C" {
+	/// End of the static data, set by the linker
+	static __heap_start: u8;
+}
+
+/// SRAM usage, in bytes
+#[derive(Clone, Copy)]
+pub struct Usage {
+	/// Bytes between the end of the static data and the stack pointer
+	pub free: u16,
+	/// Deepest the stack has been since the system started up
+	pub stack_peak: u16,
+}
+
+/// Amount of bytes at the start of `bytes` which are still painted
+fn painted_len(bytes: impl Iterator<Item = u8>) -> usize {
+	bytes.take_while(|byte| *byte == PAINT).count()
+}
+
+/// Address of the end of the static data
+#[cfg(feature = "atmega328p")]
+fn heap_start() -> usize {
+	unsafe { &__heap_start as *const u8 as usize }
+}
+
+/// Address near the top of the stack, in the caller's stack frame
+#[cfg(feature = "atmega328p")]
+#[inline(always)]
+fn stack_pointer() -> usize {
+	let marker = 0u8;
+	&marker as *const u8 as usize
+}
+
+/// Paint the free SRAM, so that [`usage`] can tell how deep the stack has grown
+///
+/// Should be called as early as possible when the system starts up.
+#[cfg(feature = "atmega328p")]
+#[inline(never)]
+pub fn paint() {
+	let end = stack_pointer().saturating_sub(PAINT_MARGIN);
+	for address in heap_start()..end {
+		unsafe { core::ptr::write_volatile(address as *mut u8, PAINT) };
+	}
+}
+
+/// Measure the free SRAM, and the deepest the stack has been since [`paint`] was called
+#[cfg(feature = "atmega328p")]
+pub fn usage() -> Usage {
+	let start = heap_start();
+	let end = stack_pointer();
+	let untouched = painted_len(
+		(start..end).map(|address| unsafe { core::ptr::read_volatile(address as *const u8) }),
+	);
+
+	Usage {
+		free: (end - start) as u16,
+		stack_peak: (RAM_END + 1 - start - untouched) as u16,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn counts_painted_bytes_up_to_the_stack() {
+		let ram = [PAINT, PAINT, PAINT, 0x00, PAINT, 0x12];
+		assert_eq!(painted_len(ram.iter().copied()), 3);
+		assert_eq!(painted_len(ram[3..].iter().copied()), 0);
+		assert_eq!(painted_len([PAINT; 4].iter().copied()), 4);
+	}
+}
@@ -1,13 +1,16 @@
-use arduino_hal::I2c;
-use core::{convert::Infallible, fmt::Write, str};
+//! Layout of the display
+//!
+//! The SSD1306 OLED driver in `display/oled.rs` is only built for the ATmega328P.
 
-use ssd1306::{mode::TerminalMode, prelude::*, I2CDisplayInterface, Ssd1306};
-use ufmt::uWrite;
+use ufmt::{uDisplay, uWrite};
 
-///
-pub struct Display {
-	inner: Ssd1306<I2CInterface<I2c>, DisplaySize128x64, TerminalMode>,
-}
+pub mod dimmer;
+#[cfg(feature = "atmega328p")]
+mod oled;
+pub mod render;
+pub mod statusbar;
+#[cfg(feature = "atmega328p")]
+pub use oled::{Display, I2C_SPEED, I2C_STANDARD_SPEED};
 
 /// The first 2 rows are yellow (header) rows, the rest are blue
 pub const BODY_START_ROW: u8 = 2;
@@ -21,38 +24,95 @@ pub const ROW_LENGTH: u8 = 16;
 /// Slice of whitespace to clear a row in the display
 pub const CLEAR_ROW: &str = "                ";
 
-impl Display {
-	pub fn new(i2c: I2c) -> Self {
-		let interface = I2CDisplayInterface::new(i2c);
+/// Separator between a label and its value
+const LABEL_SEPARATOR: &str = ":";
+/// Shown at the end of a label or value which has been cut short
+const ELLIPSIS: &str = "...";
 
-		let mut display = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
-			.into_terminal_mode();
-		let _ = display.init();
+/// Label and value pair, rendered with the value aligned to the right of `width` characters
+pub struct Aligned<'a> {
+	label: &'a str,
+	value: &'a str,
+	width: u8,
+}
 
-		Self { inner: display }
+/// Render `label` and `value` across `width` characters, with the value aligned to the right
+///
+/// If they don't fit, the label is cut short with an ellipsis so that the value can still be
+/// shown. If the value doesn't fit by itself, only the start of the value is shown.
+pub fn align<'a>(label: &'a str, value: &'a str, width: u8) -> Aligned<'a> {
+	Aligned {
+		label,
+		value,
+		width,
 	}
+}
 
-	pub fn init(&mut self) {
-		let _ = self.inner.clear();
-	}
+impl uDisplay for Aligned<'_> {
+	fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		let width = self.width as usize;
+		let (label, value) = (self.label, self.value);
+		let used = label.len() + LABEL_SEPARATOR.len() + value.len();
 
-	pub fn clear_body(&mut self) {
-		for row in 0..BODY_ROW_COUNT {
-			let _ = self.inner.set_position(0, BODY_START_ROW + row as u8);
-			let _ = ufmt::uwrite!(self, "{}", CLEAR_ROW);
+		// If both fit, pad the space between them.
+		if used <= width {
+			ufmt::uwrite!(f, "{}{}", label, LABEL_SEPARATOR)?;
+			for _ in used..width {
+				f.write_str(" ")?;
+			}
+			return f.write_str(value);
 		}
-	}
 
-	pub fn set_position(&mut self, column: u8, row: u8) {
-		let _ = self.inner.set_position(column, row);
+		// Otherwise, if the value fits with an ellipsis in place of the end of the label, cut the
+		// label short.
+		let fixed = ELLIPSIS.len() + LABEL_SEPARATOR.len() + value.len();
+		if fixed <= width {
+			let label = label.get(..width - fixed).unwrap_or("");
+			return ufmt::uwrite!(f, "{}{}{}{}", label, ELLIPSIS, LABEL_SEPARATOR, value);
+		}
+
+		// Otherwise, show as much of the value as fits.
+		let value = value
+			.get(..width.saturating_sub(ELLIPSIS.len()))
+			.unwrap_or("");
+		ufmt::uwrite!(f, "{}{}", value, ELLIPSIS)
 	}
 }
 
-impl uWrite for Display {
-	type Error = Infallible;
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fmt::Buffer;
+
+	/// Render a label and value pair into a string
+	fn render(label: &str, value: &str, width: u8) -> Buffer<32> {
+		Buffer::from_display(&align(label, value, width))
+	}
+
+	#[test]
+	fn pads_between_label_and_value() {
+		assert_eq!(render("Light", "100", 14).as_str(), "Light:     100");
+		assert_eq!(render("Moisture", "10500", 14).as_str(), "Moisture:10500");
+	}
+
+	#[test]
+	fn cuts_label_short_when_too_wide() {
+		assert_eq!(render("Moisture", "105000", 14).as_str(), "Mois...:105000");
+		assert_eq!(render("Moisture", "1050000", 14).as_str(), "Moi...:1050000");
+		assert_eq!(
+			render("Moisture", "1234567890", 14).as_str(),
+			"...:1234567890"
+		);
+	}
 
-	fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
-		let _ = self.inner.write_str(s);
-		Ok(())
+	#[test]
+	fn cuts_value_short_when_it_doesnt_fit() {
+		assert_eq!(
+			render("Time", "12345678901234", 14).as_str(),
+			"12345678901..."
+		);
 	}
 }
@@ -0,0 +1,133 @@
+//! Contrast of the display
+//!
+//! The display is shown at the configured brightness, unless night dimming is enabled and the light
+//! sensor reads that it is dark, in which case it is dimmed so that it doesn't glare. The light is
+//! sampled every so often rather than on every tick, and has to rise well above the night threshold
+//! before the display is brightened again, so that it doesn't flicker at dusk.
+
+use crate::{
+	config::SystemConfig,
+	hal::Clock,
+	timer::{Duration, TIMER},
+};
+
+/// How often the light sensor is sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// Light reading below which it is considered to be night
+const NIGHT_LIGHT: u16 = 50;
+/// Light reading which it has to rise to before it is no longer considered to be night
+///
+/// Higher than [`NIGHT_LIGHT`] so that the display doesn't flip between brightnesses while the
+/// light is close to the threshold.
+const DAY_LIGHT: u16 = 100;
+/// Brightness of the display at night, as a percentage of its full contrast
+///
+/// The configured brightness is used instead if it is lower.
+const NIGHT_BRIGHTNESS_PERCENT: u16 = 10;
+
+/// Chooses the contrast of the display
+pub struct Dimmer {
+	/// Whether it is night
+	night: bool,
+	/// [`TIMER`] clock value of the last time the light was sampled
+	last_sample_ms: Option<u32>,
+	/// Brightness which the display was last set to, if it has been set
+	applied: Option<u8>,
+}
+
+impl Dimmer {
+	/// Create a new [`Dimmer`], which hasn't set the brightness of the display yet
+	pub fn new() -> Self {
+		Self {
+			night: false,
+			last_sample_ms: None,
+			applied: None,
+		}
+	}
+
+	/// Sample the light if it is due to be sampled, and choose the brightness of the display
+	///
+	/// Returns the brightness as a percentage if the display should be changed to it.
+	pub fn update(
+		&mut self,
+		system_config: &SystemConfig,
+		read_light: impl FnOnce() -> u16,
+	) -> Option<u8> {
+		let due = match self.last_sample_ms {
+			Some(last_sample_ms) => TIMER.has_elapsed(last_sample_ms, SAMPLE_INTERVAL),
+			None => true,
+		};
+		if system_config.night_dim && due {
+			self.last_sample_ms = Some(TIMER.millis());
+			let light = read_light();
+			if light < NIGHT_LIGHT {
+				self.night = true;
+			} else if light >= DAY_LIGHT {
+				self.night = false;
+			}
+		}
+
+		let mut brightness = system_config.brightness_percent;
+		if system_config.night_dim && self.night {
+			brightness = brightness.min(NIGHT_BRIGHTNESS_PERCENT);
+		}
+		let brightness = brightness.min(100) as u8;
+
+		if self.applied == Some(brightness) {
+			return None;
+		}
+		self.applied = Some(brightness);
+		Some(brightness)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::timer::{self, tests::TimersGuard};
+
+	#[test]
+	fn uses_configured_brightness_during_the_day() {
+		let _timers = TimersGuard::take();
+		let mut system_config = SystemConfig::new();
+		let mut dimmer = Dimmer::new();
+
+		assert_eq!(dimmer.update(&system_config, || 500), Some(100));
+		assert_eq!(dimmer.update(&system_config, || 500), None);
+
+		system_config.brightness_percent = 60;
+		assert_eq!(dimmer.update(&system_config, || 500), Some(60));
+	}
+
+	#[test]
+	fn dims_at_night_until_it_is_light_again() {
+		let _timers = TimersGuard::take();
+		let system_config = SystemConfig::new();
+		let mut dimmer = Dimmer::new();
+
+		assert_eq!(dimmer.update(&system_config, || 10), Some(10));
+
+		// The light isn't sampled again until the interval has passed.
+		assert_eq!(dimmer.update(&system_config, || 500), None);
+
+		// Light between the thresholds isn't enough to brighten the display again.
+		timer::advance(SAMPLE_INTERVAL.as_millis());
+		assert_eq!(dimmer.update(&system_config, || 75), None);
+
+		timer::advance(SAMPLE_INTERVAL.as_millis());
+		assert_eq!(dimmer.update(&system_config, || DAY_LIGHT), Some(100));
+	}
+
+	#[test]
+	fn night_dimming_can_be_disabled() {
+		let _timers = TimersGuard::take();
+		let mut system_config = SystemConfig::new();
+		system_config.night_dim = false;
+		let mut dimmer = Dimmer::new();
+
+		assert_eq!(
+			dimmer.update(&system_config, || panic!("light was sampled")),
+			Some(100)
+		);
+	}
+}
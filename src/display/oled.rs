@@ -0,0 +1,183 @@
+use arduino_hal::{
+	clock::{Clock as _, MHz16},
+	i2c::Direction,
+	pac::TWI,
+	I2c,
+};
+use core::{convert::Infallible, str};
+
+use ssd1306::{mode::TerminalMode, prelude::*, I2CDisplayInterface, Ssd1306};
+use ufmt::uWrite;
+
+use super::{
+	render::{Output, RenderQueue, CHAR_BYTES, POSITION_BYTES},
+	BODY_ROW_COUNT, BODY_START_ROW, CLEAR_ROW,
+};
+
+/// I2C address of the display
+const DISPLAY_ADDRESS: u8 = 0x3c;
+/// Standard-mode I2C clock in Hz, which every display supports
+pub const I2C_STANDARD_SPEED: u32 = 100_000;
+/// Fast-mode I2C clock in Hz
+const I2C_FAST_SPEED: u32 = 400_000;
+/// I2C clock in Hz which the bus is created with
+///
+/// Fast mode is tried first unless the `i2c-standard-mode` feature is enabled, as a full screen
+/// takes visibly long to render in standard mode.
+pub const I2C_SPEED: u32 = if cfg!(feature = "i2c-standard-mode") {
+	I2C_STANDARD_SPEED
+} else {
+	I2C_FAST_SPEED
+};
+/// Most bytes sent to the display on each tick
+///
+/// Even at 100kHz, this takes a few milliseconds, which leaves most of the tick for the rest of the
+/// system. A whole screen is rendered in about a dozen ticks.
+const MAX_BYTES_PER_TICK: usize = POSITION_BYTES + 8 * CHAR_BYTES;
+/// Pre-charge period used with every contrast, the same as the driver's preset brightnesses
+const CONTRAST_PRECHARGE: u8 = 0x2;
+
+/// SSD1306 OLED in terminal mode
+///
+/// Everything written is queued, and sent to the display a little at a time by
+/// [`Display::render_queued`], or all at once by [`Display::flush`].
+pub struct Display {
+	inner: Ssd1306<I2CInterface<I2c>, DisplaySize128x64, TerminalMode>,
+	/// Output which hasn't been sent yet
+	queue: RenderQueue,
+	/// Clock speed of the I2C bus in Hz
+	i2c_speed: u32,
+	/// Whether the display acknowledged its address when it was created
+	responded: bool,
+}
+
+impl Display {
+	/// Create a new [`Display`] on `i2c`, which was created with a clock of `i2c_speed`
+	///
+	/// Not every display supports fast mode, so if the display doesn't respond, the bus is slowed
+	/// down to standard mode.
+	pub fn new(mut i2c: I2c, mut i2c_speed: u32) -> Self {
+		let mut responded = ping(&mut i2c);
+		if !responded && i2c_speed > I2C_STANDARD_SPEED {
+			set_i2c_speed(I2C_STANDARD_SPEED);
+			i2c_speed = I2C_STANDARD_SPEED;
+			responded = ping(&mut i2c);
+		}
+
+		let interface = I2CDisplayInterface::new(i2c);
+
+		let mut display = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
+			.into_terminal_mode();
+		let _ = display.init();
+
+		Self {
+			inner: display,
+			queue: RenderQueue::new(),
+			i2c_speed,
+			responded,
+		}
+	}
+
+	/// Whether the display acknowledged its address when it was created
+	///
+	/// If it didn't, it is probably not connected, so nothing written to it will be shown.
+	pub fn responded(&self) -> bool {
+		self.responded
+	}
+
+	/// Clock speed of the I2C bus in Hz, after falling back to standard mode if necessary
+	pub fn i2c_speed(&self) -> u32 {
+		self.i2c_speed
+	}
+
+	pub fn init(&mut self) {
+		let _ = self.inner.clear();
+		self.queue = RenderQueue::new();
+	}
+
+	pub fn clear_body(&mut self) {
+		for row in 0..BODY_ROW_COUNT {
+			self.clear_row(BODY_START_ROW + row);
+		}
+	}
+
+	/// Clear a single row, leaving the cursor at the start of the row
+	pub fn clear_row(&mut self, row: u8) {
+		self.queue.set_position(0, row);
+		self.queue.write_str(CLEAR_ROW);
+		self.queue.set_position(0, row);
+	}
+
+	pub fn set_position(&mut self, column: u8, row: u8) {
+		self.queue.set_position(column, row);
+	}
+
+	/// Whether there is output which hasn't been sent to the display yet
+	pub fn has_queued(&self) -> bool {
+		!self.queue.is_empty()
+	}
+
+	/// Send some of the queued output to the display, without holding up the tick for long
+	///
+	/// Should be called on every tick while [`Display::has_queued`].
+	pub fn render_queued(&mut self) {
+		self.send(MAX_BYTES_PER_TICK);
+	}
+
+	/// Send all of the queued output to the display
+	///
+	/// Used before waiting with nothing else running, such as while a screen is shown at startup.
+	pub fn flush(&mut self) {
+		self.send(usize::MAX);
+	}
+
+	/// Send queued output to the display, up to `budget` bytes
+	fn send(&mut self, budget: usize) {
+		let inner = &mut self.inner;
+		self.queue.send(budget, |output| match output {
+			Output::Position { column, row } => {
+				let _ = inner.set_position(column, row);
+			}
+			Output::Char(byte) => {
+				let _ = inner.print_char(byte as char);
+			}
+		});
+	}
+
+	/// Switch the display on, or off to save power while keeping what it shows
+	pub fn set_on(&mut self, on: bool) {
+		let _ = self.inner.set_display_on(on);
+	}
+
+	/// Set the contrast of the display, as a percentage of its full contrast
+	pub fn set_contrast(&mut self, percent: u8) {
+		let contrast = (percent.min(100) as u16 * 255 / 100) as u8;
+		let _ = self
+			.inner
+			.set_brightness(Brightness::custom(CONTRAST_PRECHARGE, contrast));
+	}
+}
+
+/// Whether the display acknowledges its address on `i2c`
+fn ping(i2c: &mut I2c) -> bool {
+	matches!(i2c.ping_device(DISPLAY_ADDRESS, Direction::Write), Ok(true))
+}
+
+/// Change the clock speed of the I2C bus
+///
+/// The HAL only sets the clock when the bus is created, so the bit rate register is written
+/// directly, in the same way (with the prescaler left at 1).
+fn set_i2c_speed(speed: u32) {
+	let twi = unsafe { &*TWI::ptr() };
+	let twbr = (MHz16::FREQ / speed - 16) / 2;
+	twi.twbr.write(|w| unsafe { w.bits(twbr as u8) });
+}
+
+impl uWrite for Display {
+	type Error = Infallible;
+
+	fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+		self.queue.write_str(s);
+		Ok(())
+	}
+}
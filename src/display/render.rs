@@ -0,0 +1,207 @@
+//! Queue of output to the display
+//!
+//! Writing to the display over I2C takes long enough that rendering a whole screen at once stalls
+//! the main loop, so button presses are missed and the valve safety checks are held up. Instead,
+//! everything written is kept in a copy of the display, and the characters which have changed are
+//! sent a few at a time on each tick.
+
+use super::{BODY_ROW_COUNT, BODY_START_ROW, ROW_LENGTH};
+
+/// Amount of rows on the display, including the header
+const ROW_COUNT: u8 = BODY_START_ROW + BODY_ROW_COUNT;
+/// Bytes sent to the display for each character, one for each column of its glyph
+pub const CHAR_BYTES: usize = 8;
+/// Bytes sent to the display to move its cursor (the column and page address commands)
+pub const POSITION_BYTES: usize = 6;
+/// Character shown in place of anything the font doesn't have
+const REPLACEMENT_CHAR: u8 = b'?';
+
+/// Output to send to the display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+	/// Move the cursor
+	Position { column: u8, row: u8 },
+	/// Write a character at the cursor, and move the cursor on by one
+	Char(u8),
+}
+
+/// Copy of the display, with the characters which haven't been sent yet
+pub struct RenderQueue {
+	/// Characters which will be on the display once everything has been sent
+	frame: [[u8; ROW_LENGTH as usize]; ROW_COUNT as usize],
+	/// Bit for each column of each row which hasn't been sent yet
+	pending: [u16; ROW_COUNT as usize],
+	/// Where the next character written will be put
+	cursor: (u8, u8),
+	/// Where the display will put the next character it is sent, if it is known
+	display_cursor: Option<(u8, u8)>,
+}
+
+impl RenderQueue {
+	/// Create a new [`RenderQueue`] for a display which has just been cleared
+	pub fn new() -> Self {
+		Self {
+			frame: [[b' '; ROW_LENGTH as usize]; ROW_COUNT as usize],
+			pending: [0; ROW_COUNT as usize],
+			cursor: (0, 0),
+			display_cursor: None,
+		}
+	}
+
+	/// Move the cursor to `column` of `row`
+	pub fn set_position(&mut self, column: u8, row: u8) {
+		self.cursor = (column.min(ROW_LENGTH - 1), row.min(ROW_COUNT - 1));
+	}
+
+	/// Write `s` at the cursor, in the same way as the display's terminal mode
+	///
+	/// Text which reaches the end of a row carries on at the start of the next row, and a newline
+	/// moves the cursor to the start of the next row.
+	pub fn write_str(&mut self, s: &str) {
+		for c in s.chars() {
+			let (column, row) = self.cursor;
+			match c {
+				'\n' => self.cursor = (0, (row + 1) % ROW_COUNT),
+				'\r' => self.cursor = (0, row),
+				_ => {
+					let byte = if c.is_ascii() {
+						c as u8
+					} else {
+						REPLACEMENT_CHAR
+					};
+					// Characters which are already there don't need to be sent again.
+					let cell = &mut self.frame[row as usize][column as usize];
+					if *cell != byte {
+						*cell = byte;
+						self.pending[row as usize] |= 1 << column;
+					}
+					self.cursor = next_cursor(self.cursor);
+				}
+			}
+		}
+	}
+
+	/// Whether everything written has been sent to the display
+	pub fn is_empty(&self) -> bool {
+		self.pending.iter().all(|&pending| pending == 0)
+	}
+
+	/// Send the characters which have changed to the display, up to `budget` bytes
+	///
+	/// The cursor of the display is only moved when the next character isn't straight after the
+	/// last one sent.
+	pub fn send(&mut self, budget: usize, mut output: impl FnMut(Output)) {
+		let mut sent = 0;
+		for row in 0..ROW_COUNT {
+			while self.pending[row as usize] != 0 {
+				let column = self.pending[row as usize].trailing_zeros() as u8;
+				let moved = self.display_cursor != Some((column, row));
+				let cost = if moved {
+					POSITION_BYTES + CHAR_BYTES
+				} else {
+					CHAR_BYTES
+				};
+				if sent + cost > budget {
+					return;
+				}
+
+				if moved {
+					output(Output::Position { column, row });
+				}
+				output(Output::Char(self.frame[row as usize][column as usize]));
+				self.pending[row as usize] &= !(1 << column);
+				self.display_cursor = Some(next_cursor((column, row)));
+				sent += cost;
+			}
+		}
+	}
+}
+
+/// Position after a character written at `cursor`, which wraps around at the end of a row and at
+/// the bottom of the display
+fn next_cursor((column, row): (u8, u8)) -> (u8, u8) {
+	if column + 1 < ROW_LENGTH {
+		(column + 1, row)
+	} else {
+		(0, (row + 1) % ROW_COUNT)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Send everything in `queue`, within `budget` bytes
+	fn send(queue: &mut RenderQueue, budget: usize) -> Vec<Output> {
+		let mut outputs = Vec::new();
+		queue.send(budget, |output| outputs.push(output));
+		outputs
+	}
+
+	#[test]
+	fn sends_changed_characters() {
+		let mut queue = RenderQueue::new();
+		queue.set_position(3, 2);
+		queue.write_str("ab");
+
+		assert_eq!(
+			send(&mut queue, usize::MAX),
+			[
+				Output::Position { column: 3, row: 2 },
+				Output::Char(b'a'),
+				Output::Char(b'b'),
+			]
+		);
+		assert!(queue.is_empty());
+
+		// Only the character which changed is sent, and the cursor is moved back to it.
+		queue.set_position(3, 2);
+		queue.write_str("ac");
+		assert_eq!(
+			send(&mut queue, usize::MAX),
+			[Output::Position { column: 4, row: 2 }, Output::Char(b'c')]
+		);
+	}
+
+	#[test]
+	fn sends_up_to_budget() {
+		let mut queue = RenderQueue::new();
+		queue.write_str("abcd");
+
+		assert_eq!(
+			send(&mut queue, POSITION_BYTES + 2 * CHAR_BYTES),
+			[
+				Output::Position { column: 0, row: 0 },
+				Output::Char(b'a'),
+				Output::Char(b'b'),
+			]
+		);
+		assert!(!queue.is_empty());
+		assert_eq!(
+			send(&mut queue, 2 * CHAR_BYTES),
+			[Output::Char(b'c'), Output::Char(b'd')]
+		);
+		assert!(queue.is_empty());
+	}
+
+	#[test]
+	fn wraps_like_the_terminal() {
+		let mut queue = RenderQueue::new();
+		queue.set_position(ROW_LENGTH - 1, 0);
+		queue.write_str("ab\nc");
+
+		assert_eq!(
+			send(&mut queue, usize::MAX),
+			[
+				Output::Position {
+					column: ROW_LENGTH - 1,
+					row: 0
+				},
+				Output::Char(b'a'),
+				Output::Char(b'b'),
+				Output::Position { column: 0, row: 2 },
+				Output::Char(b'c'),
+			]
+		);
+	}
+}
@@ -0,0 +1,185 @@
+//! Status icons in the header
+//!
+//! The right side of the second header row is reserved for a glyph for each part of the state of
+//! the system, so that it can be seen without scrolling the menu. Each glyph has its own column,
+//! from left to right:
+//!
+//! - `^`: The co-processor link is connected;
+//! - `~`: Watering, or `=` while suspended;
+//! - `!`: Fault (the valve fail-safe has tripped, or the battery is low), or the code of the fault
+//!   which has stopped the system (see `crate::activation::FaultKind`);
+//! - `*`: The moisture changed unusually (see `crate::trend::Anomaly`);
+//! - `S`: The valve is due to be serviced;
+//! - `#`: The keypad is locked.
+//!
+//! The display only has the ASCII characters of its terminal font, so these stand in for proper
+//! icons.
+
+use super::ROW_LENGTH;
+use crate::hal::DisplayBus;
+
+/// Header row the icons are shown on
+const STATUS_ROW: u8 = 1;
+/// Amount of icons, which are shown in the last columns of [`STATUS_ROW`]
+const ICON_COUNT: usize = 6;
+
+/// State of the system shown by the icons
+pub struct Status {
+	/// Whether the co-processor link is connected
+	pub link_up: bool,
+	/// Whether the system is watering
+	pub watering: bool,
+	/// Whether the system is suspended
+	pub suspended: bool,
+	/// Whether there is a fault
+	pub fault: bool,
+	/// Code of the fault which has stopped the system, if it has faulted
+	pub fault_code: Option<u8>,
+	/// Whether the moisture changed unusually
+	pub soil_alert: bool,
+	/// Whether the valve is due to be serviced
+	pub service_due: bool,
+	/// Whether the keypad is locked
+	pub locked: bool,
+}
+
+impl Status {
+	/// Glyph to show in each column, from left to right
+	fn glyphs(&self) -> [u8; ICON_COUNT] {
+		let state = if self.watering {
+			b'~'
+		} else if self.suspended {
+			b'='
+		} else {
+			b' '
+		};
+
+		[
+			if self.link_up { b'^' } else { b' ' },
+			state,
+			match (self.fault_code, self.fault) {
+				(Some(code), _) => b'0' + code,
+				(None, true) => b'!',
+				(None, false) => b' ',
+			},
+			if self.soil_alert { b'*' } else { b' ' },
+			if self.service_due { b'S' } else { b' ' },
+			if self.locked { b'#' } else { b' ' },
+		]
+	}
+}
+
+/// Status icons in the header
+pub struct StatusBar {
+	/// Glyphs which are currently shown, or `None` if the header hasn't been rendered yet
+	shown: Option<[u8; ICON_COUNT]>,
+}
+
+impl StatusBar {
+	/// Create a new [`StatusBar`], which hasn't been rendered yet
+	pub fn new() -> Self {
+		Self { shown: None }
+	}
+
+	/// Render every icon, such as after the header has been rendered
+	pub fn render(&mut self, status: &Status, display: &mut impl DisplayBus) {
+		self.shown = None;
+		self.update(status, display);
+	}
+
+	/// Render the icons which have changed since they were last rendered
+	///
+	/// Writing to the display is slow, so this should be called on every tick rather than
+	/// rendering the icons each time.
+	pub fn update(&mut self, status: &Status, display: &mut impl DisplayBus) {
+		let glyphs = status.glyphs();
+		for (idx, &glyph) in glyphs.iter().enumerate() {
+			let changed = match &self.shown {
+				Some(shown) => shown[idx] != glyph,
+				None => true,
+			};
+			if changed {
+				display.set_position(ROW_LENGTH - ICON_COUNT as u8 + idx as u8, STATUS_ROW);
+				let _ = display.write_char(glyph as char);
+			}
+		}
+
+		self.shown = Some(glyphs);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ufmt::uWrite;
+
+	use super::*;
+	use crate::hal::mock::MockDisplay;
+
+	/// Status with nothing to show
+	fn idle() -> Status {
+		Status {
+			link_up: false,
+			watering: false,
+			suspended: false,
+			fault: false,
+			fault_code: None,
+			soil_alert: false,
+			service_due: false,
+			locked: false,
+		}
+	}
+
+	#[test]
+	fn renders_icons_on_right_of_header() {
+		let mut display = MockDisplay::new();
+		let mut statusbar = StatusBar::new();
+		statusbar.render(
+			&Status {
+				link_up: true,
+				watering: true,
+				locked: true,
+				..idle()
+			},
+			&mut display,
+		);
+		assert_eq!(display.row(STATUS_ROW), "          ^~   #");
+
+		statusbar.update(
+			&Status {
+				suspended: true,
+				fault: true,
+				..idle()
+			},
+			&mut display,
+		);
+		assert_eq!(display.row(STATUS_ROW), "           =!   ");
+
+		statusbar.update(
+			&Status {
+				fault: true,
+				fault_code: Some(2),
+				soil_alert: true,
+				service_due: true,
+				..idle()
+			},
+			&mut display,
+		);
+		assert_eq!(display.row(STATUS_ROW), "            2*S ");
+	}
+
+	#[test]
+	fn only_renders_changed_icons() {
+		let mut display = MockDisplay::new();
+		let mut statusbar = StatusBar::new();
+		statusbar.render(&idle(), &mut display);
+
+		// Anything written over an unchanged icon would be left in place.
+		display.set_position(ROW_LENGTH - 1, STATUS_ROW);
+		let _ = display.write_char('x');
+		statusbar.update(&idle(), &mut display);
+		assert_eq!(display.row(STATUS_ROW), "               x");
+
+		statusbar.render(&idle(), &mut display);
+		assert_eq!(display.row(STATUS_ROW), "                ");
+	}
+}
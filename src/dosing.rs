@@ -0,0 +1,72 @@
+//! Fertilizer dosing pump
+//!
+//! With the `dosing-pump` feature, a second output (the `doser` pin of the pin map) drives a small
+//! dosing pump which injects fertilizer into the water line. It runs for the first few seconds of
+//! each activation, while the valve is open, so that the fertilizer is carried out to the plants by
+//! the rest of the water.
+//!
+//! Too much fertilizer burns the roots, so the time the pump runs for is counted by the stats (see
+//! `src/stats.rs`), and the pump isn't run again once it has reached the weekly cap.
+
+use crate::{
+	activation,
+	config::SystemConfig,
+	timer::{Duration, SoftTimer},
+};
+
+/// Whether the dosing pump should be running
+///
+/// `dosed_secs` is how long the pump has already run for this week.
+pub fn should_dose(system_config: &SystemConfig, dosed_secs: u32) -> bool {
+	if !system_config.dose || !system_config.activation_state.is_activated() {
+		return false;
+	}
+
+	// Only the time the valve has been open counts, so that the dose isn't pumped into a closed
+	// valve during a soak.
+	let watered = system_config
+		.profile
+		.watered(SoftTimer::Activation.elapsed());
+	watered < Duration::from_secs(system_config.dose_secs)
+		&& !activation::is_soaking(system_config)
+		&& dosed_secs < system_config.dose_cap_mins as u32 * 60
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		config::UpdateSystemValue,
+		timer::{self, tests::TimersGuard},
+	};
+
+	fn dosing_config() -> SystemConfig {
+		let mut system_config = SystemConfig::new();
+		system_config.dose = true;
+		activation::transition(&mut system_config, &UpdateSystemValue::Activate);
+		system_config
+	}
+
+	#[test]
+	fn doses_at_start_of_activation() {
+		let _timers = TimersGuard::take();
+		let mut system_config = dosing_config();
+		assert!(should_dose(&system_config, 0));
+		system_config.dose = false;
+		assert!(!should_dose(&system_config, 0));
+		system_config.dose = true;
+
+		timer::advance(Duration::from_secs(system_config.dose_secs).as_millis());
+		assert!(!should_dose(&system_config, 0));
+	}
+
+	#[test]
+	fn stops_at_weekly_cap() {
+		let _timers = TimersGuard::take();
+		let system_config = dosing_config();
+		let cap_secs = system_config.dose_cap_mins as u32 * 60;
+
+		assert!(should_dose(&system_config, cap_secs - 1));
+		assert!(!should_dose(&system_config, cap_secs));
+	}
+}
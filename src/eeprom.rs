@@ -0,0 +1,151 @@
+//! Read and write the internal EEPROM
+//!
+//! Values are stored as records, which are followed by a CRC-8 checksum so that unwritten or
+//! corrupted records can be detected when they are read back.
+
+use arduino_hal::pac::EEPROM;
+
+use crate::{
+	hal::Storage,
+	storage::{crc8, crc8_update},
+};
+
+/// Address of the button calibration record
+pub const BUTTON_THRESHOLDS_ADDRESS: u16 = 0;
+/// Address of the uptime and watering statistics record
+pub const STATS_ADDRESS: u16 = 32;
+/// Address of the activation state record, used to recover after a power loss
+pub const RECOVERY_ADDRESS: u16 = 48;
+/// Address of the circular region of event history records, unless they are kept in external
+/// storage (see [`crate::storage`])
+pub const HISTORY_ADDRESS: u16 = 64;
+/// Address of the weekly dosing record, after the event history
+pub const DOSING_ADDRESS: u16 = 872;
+/// Address of the menu selection record, after the weekly dosing record
+pub const RESUME_ADDRESS: u16 = 880;
+/// Address of the daily watering record, after the menu selection record
+pub const WEEK_ADDRESS: u16 = 888;
+/// Address of the valve cycle record, after the daily watering record
+pub const VALVE_CYCLES_ADDRESS: u16 = 920;
+/// Address of the zone name record, after where the pin map used to be
+pub const ZONE_NAME_ADDRESS: u16 = 940;
+/// Address of the pin map record, after the zone name record
+///
+/// It was moved from 864, after the event history, once the master valve no longer let it fit in
+/// front of the weekly dosing record, and again from 928 once the latch of the shift registers no
+/// longer let it fit in front of the zone name record.
+pub const PIN_MAP_ADDRESS: u16 = 952;
+/// Address of the relay wiring record, after the pin map record
+pub const RELAY_WIRING_ADDRESS: u16 = 964;
+
+/// Value of a byte which has been erased, or never written
+const ERASED: u8 = 0xff;
+
+/// Wrapper around the EEPROM peripheral
+pub struct Eeprom {
+	inner: EEPROM,
+}
+
+impl Eeprom {
+	/// Create a new [`Eeprom`] from the EEPROM peripheral
+	pub fn new(eeprom: EEPROM) -> Self {
+		Self { inner: eeprom }
+	}
+
+	/// Wait for any previous write to complete
+	fn wait(&self) {
+		while self.inner.eecr.read().eepe().bit_is_set() {}
+	}
+
+	/// Read a single byte
+	pub fn read_byte(&self, address: u16) -> u8 {
+		self.wait();
+		self.inner.eear.write(|w| unsafe { w.bits(address) });
+		self.inner.eecr.write(|w| w.eere().set_bit());
+		self.inner.eedr.read().bits()
+	}
+
+	/// Write a single byte
+	///
+	/// The write is skipped if the byte already has the same value, to avoid wearing out the
+	/// EEPROM.
+	pub fn write_byte(&mut self, address: u16, value: u8) {
+		if self.read_byte(address) == value {
+			return;
+		}
+
+		self.wait();
+		self.inner.eear.write(|w| unsafe { w.bits(address) });
+		self.inner.eedr.write(|w| unsafe { w.bits(value) });
+		// EEPE must be set within 4 clock cycles of setting EEMPE, so make sure that an interrupt
+		// can't happen between the two.
+		avr_device::interrupt::free(|_cs| {
+			self.inner.eecr.write(|w| w.eempe().set_bit());
+			self.inner
+				.eecr
+				.write(|w| w.eempe().set_bit().eepe().set_bit());
+		});
+	}
+
+	/// Read bytes into `buf`, starting at `address`
+	pub fn read(&self, address: u16, buf: &mut [u8]) {
+		for (offset, byte) in buf.iter_mut().enumerate() {
+			*byte = self.read_byte(address + offset as u16);
+		}
+	}
+
+	/// Write `data`, starting at `address`
+	pub fn write(&mut self, address: u16, data: &[u8]) {
+		for (offset, byte) in data.iter().enumerate() {
+			self.write_byte(address + offset as u16, *byte);
+		}
+	}
+
+	/// Read a record into `buf`, starting at `address`
+	///
+	/// Returns `false` if the checksum following the record doesn't match its contents.
+	pub fn read_record(&self, address: u16, buf: &mut [u8]) -> bool {
+		self.read(address, buf);
+		self.read_byte(address + buf.len() as u16) == crc8(buf)
+	}
+
+	/// Write a record followed by its checksum, starting at `address`
+	pub fn write_record(&mut self, address: u16, data: &[u8]) {
+		self.write(address, data);
+		self.write_byte(address + data.len() as u16, crc8(data));
+	}
+
+	/// Whether the record of `len` bytes at `address` fails its checksum
+	///
+	/// A record which has never been written (or has been erased) isn't corrupted.
+	pub fn is_record_corrupted(&self, address: u16, len: usize) -> bool {
+		let mut crc = 0;
+		let mut erased = true;
+		for offset in 0..len as u16 {
+			let byte = self.read_byte(address + offset);
+			crc = crc8_update(crc, &[byte]);
+			erased &= byte == ERASED;
+		}
+
+		let checksum = self.read_byte(address + len as u16);
+		checksum != crc && !(erased && checksum == ERASED)
+	}
+
+	/// Erase the record of `len` bytes at `address`, and its checksum
+	pub fn erase_record(&mut self, address: u16, len: usize) {
+		for offset in 0..=len as u16 {
+			self.write_byte(address + offset, ERASED);
+		}
+	}
+}
+
+/// The event history can be kept in the internal EEPROM
+impl Storage for Eeprom {
+	fn read(&mut self, address: u16, buf: &mut [u8]) {
+		Eeprom::read(self, address, buf);
+	}
+
+	fn write(&mut self, address: u16, data: &[u8]) {
+		Eeprom::write(self, address, data);
+	}
+}
@@ -0,0 +1,200 @@
+//! Evening-only watering
+//!
+//! Without a real-time clock the system doesn't know what time it is, but the light sensor does
+//! know when the sun goes down. Dusk is when the light falls below the minimum light value after it
+//! has been above it for a couple of hours, and stays below it for a while, so that a passing
+//! cloud or a light switched on at night isn't mistaken for a day ending. While evening-only
+//! watering is enabled, the sensors can only activate the system within the configured amount of
+//! hours after dusk.
+//!
+//! The activation and manual override aren't affected, so the system can still be watered at any
+//! time from the menu or over serial.
+
+use crate::{
+	config::SystemConfig,
+	hal::Clock,
+	timer::{Duration, TIMER},
+};
+
+/// How often the light sensor is sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// How long in minutes the light has to be above the minimum light value for it to be day
+const DAY_MINS: u16 = 120;
+/// How long in minutes the light has to stay below the minimum light value, after a day, for it to
+/// be dusk
+const DUSK_MINS: u16 = 15;
+
+/// Detects dusk, and keeps track of whether the system is within the evening watering window
+pub struct Evening {
+	/// [`TIMER`] clock value of the last time the light was sampled
+	last_sample_ms: Option<u32>,
+	/// [`TIMER`] clock value of when the light rose above the minimum, if it is still above it
+	light_since_ms: Option<u32>,
+	/// [`TIMER`] clock value of when the light fell below the minimum after a day, if it is still
+	/// below it
+	dark_since_ms: Option<u32>,
+	/// [`TIMER`] clock value of the last dusk, until the watering window after it has passed
+	dusk_ms: Option<u32>,
+}
+
+impl Evening {
+	/// Create a new [`Evening`], which hasn't seen a day or a dusk yet
+	pub fn new() -> Self {
+		Self {
+			last_sample_ms: None,
+			light_since_ms: None,
+			dark_since_ms: None,
+			dusk_ms: None,
+		}
+	}
+
+	/// Sample the light if it is due to be sampled, and look for dusk
+	///
+	/// The light is sampled whether or not evening-only watering is enabled, so that enabling it
+	/// during the day still catches that evening's dusk. Returns whether dusk was just detected.
+	pub fn update(
+		&mut self,
+		system_config: &SystemConfig,
+		read_light: impl FnOnce() -> u16,
+	) -> bool {
+		let due = match self.last_sample_ms {
+			Some(last_sample_ms) => TIMER.has_elapsed(last_sample_ms, SAMPLE_INTERVAL),
+			None => true,
+		};
+		if !due {
+			return false;
+		}
+		let now = TIMER.millis();
+		self.last_sample_ms = Some(now);
+
+		// If the watering window has passed, forget the dusk.
+		if let Some(dusk_ms) = self.dusk_ms {
+			if TIMER.has_elapsed(dusk_ms, window(system_config)) {
+				self.dusk_ms = None;
+			}
+		}
+
+		if read_light() >= system_config.min_light {
+			// If it is light, the day carries on, even if it was dark for a moment.
+			self.dark_since_ms = None;
+			if self.light_since_ms.is_none() {
+				self.light_since_ms = Some(now);
+			}
+			return false;
+		}
+
+		match (self.light_since_ms, self.dark_since_ms) {
+			// If it just got dark after a day, it might be dusk.
+			(Some(light_since_ms), None)
+				if TIMER.has_elapsed(light_since_ms, Duration::from_config_mins(DAY_MINS)) =>
+			{
+				self.dark_since_ms = Some(now);
+			}
+			// If it just got dark after a short spell of light, it wasn't a day.
+			(Some(_), None) => self.light_since_ms = None,
+			_ => {}
+		}
+
+		// If it has been dark for long enough after a day, it got dark at dusk.
+		match self.dark_since_ms {
+			Some(dark_since_ms)
+				if TIMER.has_elapsed(dark_since_ms, Duration::from_config_mins(DUSK_MINS)) =>
+			{
+				self.dusk_ms = Some(dark_since_ms);
+				self.light_since_ms = None;
+				self.dark_since_ms = None;
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// Whether the sensors may activate the system
+	///
+	/// Always true while evening-only watering is disabled.
+	pub fn is_open(&self, system_config: &SystemConfig) -> bool {
+		if system_config.evening_hours == 0 {
+			return true;
+		}
+
+		match self.dusk_ms {
+			Some(dusk_ms) => !TIMER.has_elapsed(dusk_ms, window(system_config)),
+			None => false,
+		}
+	}
+}
+
+/// How long after dusk the sensors may activate the system
+fn window(system_config: &SystemConfig) -> Duration {
+	Duration::from_config_mins(system_config.evening_hours * 60)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::timer::{self, tests::TimersGuard};
+
+	/// Light reading above the default minimum light value
+	const LIGHT: u16 = 500;
+	/// Light reading below the default minimum light value
+	const DARK: u16 = 10;
+
+	/// Keep sampling `light` for `mins` minutes, returning whether dusk was detected
+	fn run(evening: &mut Evening, system_config: &SystemConfig, light: u16, mins: u16) -> bool {
+		let mut dusk = false;
+		for _ in 0..Duration::from_config_mins(mins).as_millis() / SAMPLE_INTERVAL.as_millis() {
+			dusk |= evening.update(system_config, || light);
+			timer::advance(SAMPLE_INTERVAL.as_millis());
+		}
+		dusk
+	}
+
+	fn evening_config(hours: u16) -> SystemConfig {
+		let mut system_config = SystemConfig::new();
+		system_config.evening_hours = hours;
+		system_config
+	}
+
+	#[test]
+	fn always_open_while_disabled() {
+		let _timers = TimersGuard::take();
+		let system_config = evening_config(0);
+		let evening = Evening::new();
+		assert!(evening.is_open(&system_config));
+	}
+
+	#[test]
+	fn opens_at_dusk_for_the_configured_hours() {
+		let _timers = TimersGuard::take();
+		let system_config = evening_config(2);
+		let mut evening = Evening::new();
+
+		assert!(!run(&mut evening, &system_config, LIGHT, DAY_MINS + 10));
+		assert!(!evening.is_open(&system_config));
+
+		assert!(run(&mut evening, &system_config, DARK, DUSK_MINS + 1));
+		assert!(evening.is_open(&system_config));
+
+		run(&mut evening, &system_config, DARK, 2 * 60 - DUSK_MINS);
+		assert!(!evening.is_open(&system_config));
+	}
+
+	#[test]
+	fn brief_darkness_isnt_dusk() {
+		let _timers = TimersGuard::take();
+		let system_config = evening_config(2);
+		let mut evening = Evening::new();
+
+		// A cloud passing during the day doesn't end it.
+		run(&mut evening, &system_config, LIGHT, DAY_MINS + 10);
+		assert!(!run(&mut evening, &system_config, DARK, DUSK_MINS - 1));
+		run(&mut evening, &system_config, LIGHT, 1);
+		assert!(!evening.is_open(&system_config));
+
+		// A light switched on at night doesn't make a day.
+		let mut evening = Evening::new();
+		run(&mut evening, &system_config, LIGHT, 30);
+		assert!(!run(&mut evening, &system_config, DARK, DUSK_MINS + 1));
+		assert!(!evening.is_open(&system_config));
+	}
+}
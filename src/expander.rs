@@ -0,0 +1,179 @@
+//! I2C GPIO expander
+//!
+//! Once the native pins have run out, the valve relay can be switched through an MCP23017 (or a
+//! PCF8574, with the `pcf8574` feature) on the display's I2C bus instead. The expander's output is
+//! driven like a pin, so the relay and valve polarity settings still apply.
+//!
+//! An expander has 8 outputs, which is enough for a valve in each of up to 8 zones. There is a
+//! single valve so far, so it is wired to [`VALVE_CHANNEL`], and the other outputs are kept low.
+
+use embedded_hal::blocking::i2c::Write;
+
+use crate::hal::ValveOutput;
+
+/// Address of the expander, with its address pins tied low
+const ADDRESS: u8 = 0x20;
+/// Output of the expander which switches the valve relay
+pub const VALVE_CHANNEL: u8 = 0;
+/// MCP23017 register of the directions of port A's pins, which are inputs while their bit is set
+const MCP23017_IODIRA: u8 = 0x00;
+/// MCP23017 register of the levels of port A's outputs
+const MCP23017_OLATA: u8 = 0x14;
+
+/// Expander chip the outputs are on
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Chip {
+	/// Outputs are set by writing their levels, and have no direction to configure
+	Pcf8574,
+	/// Outputs of port A, which start up as inputs
+	Mcp23017,
+}
+
+/// Expander chip the firmware is built for
+const CHIP: Chip = if cfg!(feature = "pcf8574") {
+	Chip::Pcf8574
+} else {
+	Chip::Mcp23017
+};
+
+/// Output of an expander on the I2C bus `I`
+pub struct ExpanderPin<I> {
+	bus: I,
+	chip: Chip,
+	/// Levels of the expander's outputs, one bit for each channel
+	outputs: u8,
+	/// Whether the expander was sent the current levels of the outputs
+	written: bool,
+}
+
+impl<I: Write> ExpanderPin<I> {
+	/// Create a new [`ExpanderPin`] for the valve, on an expander on `bus`
+	///
+	/// The outputs aren't written until the pin is first set.
+	pub fn new(bus: I) -> Self {
+		Self::with_chip(bus, CHIP)
+	}
+
+	/// Create a new [`ExpanderPin`] for the valve, on a `chip` on `bus`
+	fn with_chip(bus: I, chip: Chip) -> Self {
+		Self {
+			bus,
+			chip,
+			outputs: 0,
+			written: false,
+		}
+	}
+
+	/// Whether the expander acknowledged the last write of the outputs
+	pub fn is_responding(&self) -> bool {
+		self.written
+	}
+
+	/// Set the level of the valve's output, and send it to the expander if it changed
+	///
+	/// If the expander didn't acknowledge the outputs, they are sent again the next time, which is
+	/// on the next tick of the system.
+	fn set(&mut self, high: bool) {
+		let outputs = if high {
+			self.outputs | 1 << VALVE_CHANNEL
+		} else {
+			self.outputs & !(1 << VALVE_CHANNEL)
+		};
+		if outputs != self.outputs {
+			self.outputs = outputs;
+			self.written = false;
+		}
+		if !self.written {
+			self.written = self.write().is_ok();
+		}
+	}
+
+	/// Send the levels of the outputs to the expander
+	fn write(&mut self) -> Result<(), I::Error> {
+		match self.chip {
+			Chip::Pcf8574 => self.bus.write(ADDRESS, &[self.outputs]),
+			Chip::Mcp23017 => {
+				// Set the levels before making the pins outputs, so that they don't glitch. The
+				// directions are always written, in case the expander was reset since.
+				self.bus.write(ADDRESS, &[MCP23017_OLATA, self.outputs])?;
+				self.bus.write(ADDRESS, &[MCP23017_IODIRA, 0])
+			}
+		}
+	}
+}
+
+impl<I: Write> ValveOutput for ExpanderPin<I> {
+	fn set_high(&mut self) {
+		self.set(true);
+	}
+
+	fn set_low(&mut self) {
+		self.set(false);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// I2C bus which records each write, and acknowledges them while `responding`
+	struct MockBus {
+		writes: Vec<(u8, Vec<u8>)>,
+		responding: bool,
+	}
+
+	impl Write for MockBus {
+		type Error = ();
+
+		fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+			if !self.responding {
+				return Err(());
+			}
+			self.writes.push((address, bytes.to_vec()));
+			Ok(())
+		}
+	}
+
+	fn bus() -> MockBus {
+		MockBus {
+			writes: Vec::new(),
+			responding: true,
+		}
+	}
+
+	#[test]
+	fn writes_outputs_when_they_change() {
+		let mut pin = ExpanderPin::with_chip(bus(), Chip::Pcf8574);
+		pin.set_low();
+		pin.set_low();
+		pin.set_high();
+		pin.set_high();
+		assert_eq!(pin.bus.writes, vec![(ADDRESS, vec![0]), (ADDRESS, vec![1])]);
+	}
+
+	#[test]
+	fn mcp23017_outputs_are_configured() {
+		let mut pin = ExpanderPin::with_chip(bus(), Chip::Mcp23017);
+		pin.set_high();
+		assert_eq!(
+			pin.bus.writes,
+			vec![
+				(ADDRESS, vec![MCP23017_OLATA, 1]),
+				(ADDRESS, vec![MCP23017_IODIRA, 0])
+			]
+		);
+	}
+
+	#[test]
+	fn retries_until_acknowledged() {
+		let mut pin = ExpanderPin::with_chip(bus(), Chip::Pcf8574);
+		pin.bus.responding = false;
+		pin.set_high();
+		assert!(!pin.is_responding());
+
+		pin.bus.responding = true;
+		pin.set_high();
+		assert!(pin.is_responding());
+		assert_eq!(pin.bus.writes, vec![(ADDRESS, vec![1])]);
+	}
+}
@@ -0,0 +1,97 @@
+//! Ventilation fan
+//!
+//! With the `fan` feature, a relay (the `fan` pin of the pin map) runs a fan while the DHT22 reads
+//! that it is too hot or too humid, so that the system can also ventilate a small greenhouse. The
+//! fan starts once the temperature or the humidity rises above its threshold, and only stops once
+//! both have fallen a little below them, so that it doesn't chatter around a threshold.
+
+use crate::{climate::Reading, config::SystemConfig};
+
+/// How far in tenths of a degree the temperature has to fall below its threshold to stop the fan
+const TEMPERATURE_HYSTERESIS: i32 = 20;
+/// How far in tenths of a percent the humidity has to fall below its threshold to stop the fan
+const HUMIDITY_HYSTERESIS: i32 = 50;
+
+/// Keeps track of whether the fan is running
+pub struct Fan {
+	running: bool,
+}
+
+impl Fan {
+	/// Create a new [`Fan`], which isn't running
+	pub fn new() -> Self {
+		Self { running: false }
+	}
+
+	/// Whether the fan should run, given the latest `reading`
+	///
+	/// The fan is stopped without a reading, so that a faulty sensor doesn't leave it running.
+	pub fn update(&mut self, system_config: &SystemConfig, reading: Option<Reading>) -> bool {
+		self.running = match reading {
+			Some(reading) => exceeds(system_config, reading, self.running),
+			None => false,
+		};
+		self.running
+	}
+}
+
+/// Whether the temperature or the humidity is above its threshold, or within the hysteresis below
+/// it if the fan is already `running`
+fn exceeds(system_config: &SystemConfig, reading: Reading, running: bool) -> bool {
+	let (temperature_margin, humidity_margin) = if running {
+		(TEMPERATURE_HYSTERESIS, HUMIDITY_HYSTERESIS)
+	} else {
+		(0, 0)
+	};
+
+	let hot = system_config.fan_celsius != 0
+		&& reading.temperature as i32 > system_config.fan_celsius as i32 * 10 - temperature_margin;
+	let humid = system_config.fan_humidity != 0
+		&& reading.humidity as i32 > system_config.fan_humidity as i32 * 10 - humidity_margin;
+	hot || humid
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn reading(temperature: i16, humidity: u16) -> Option<Reading> {
+		Some(Reading {
+			temperature,
+			humidity,
+		})
+	}
+
+	#[test]
+	fn runs_with_hysteresis() {
+		let mut system_config = SystemConfig::new();
+		system_config.fan_celsius = 30;
+		let mut fan = Fan::new();
+
+		assert!(!fan.update(&system_config, reading(300, 900)));
+		assert!(fan.update(&system_config, reading(301, 500)));
+		assert!(fan.update(&system_config, reading(290, 500)));
+		assert!(!fan.update(&system_config, reading(280, 500)));
+		assert!(!fan.update(&system_config, reading(290, 500)));
+
+		// A failed read stops the fan.
+		assert!(fan.update(&system_config, reading(320, 500)));
+		assert!(!fan.update(&system_config, None));
+	}
+
+	#[test]
+	fn runs_while_either_is_exceeded() {
+		let mut system_config = SystemConfig::new();
+		system_config.fan_celsius = 30;
+		system_config.fan_humidity = 80;
+		let mut fan = Fan::new();
+
+		assert!(fan.update(&system_config, reading(200, 810)));
+		assert!(fan.update(&system_config, reading(310, 700)));
+		assert!(!fan.update(&system_config, reading(270, 740)));
+
+		system_config.fan_celsius = 0;
+		system_config.fan_humidity = 0;
+		assert!(!fan.update(&system_config, reading(400, 990)));
+	}
+}
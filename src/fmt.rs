@@ -0,0 +1,288 @@
+//! Formatting helpers for ufmt
+//!
+//! ufmt has no precision, width or padding, so fixed-point values and durations are formatted by
+//! these wrappers instead, which each implement `uDisplay`:
+//!
+//! - [`Tenths`]: A value in tenths with one decimal place, such as `215` as `21.5`;
+//! - [`Percent`], [`Celsius`]: Tenths with a unit, such as `63.2%` and `21.5C`;
+//! - [`Temperature`]: Tenths of a degree Celsius in the chosen [`Units`], such as `70.7F`;
+//! - [`MinsSecs`]: A duration as `MM:SS`;
+//! - [`DaysHoursMins`]: An amount of seconds as days, hours and minutes, such as `2d 3h 15m`;
+//! - [`Flag`]: `@` if set, otherwise `-`.
+//!
+//! Only the optional DHT22's readings are shown with a unit, so those wrappers are allowed to be
+//! unused.
+//!
+//! [`Buffer`] formats a value on the stack, for when its width is needed before it is written
+//! (for example, to align it to the right of the display).
+
+use core::str;
+
+use ufmt::{derive::uDebug, uDisplay, uWrite, Formatter};
+
+use crate::timer::Duration;
+
+/// Value in tenths, shown with one decimal place
+pub struct Tenths(pub i32);
+
+impl uDisplay for Tenths {
+	fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		// Split the value before formatting it, so that values between -1 and 0 keep their sign.
+		let sign = if self.0 < 0 { "-" } else { "" };
+		let tenths = self.0.wrapping_abs() as u32;
+		ufmt::uwrite!(f, "{}{}.{}", sign, tenths / 10, tenths % 10)
+	}
+}
+
+/// Percentage in tenths of a percent, such as a moisture level
+#[allow(dead_code)]
+pub struct Percent(pub u16);
+
+impl uDisplay for Percent {
+	fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		ufmt::uwrite!(f, "{}%", Tenths(self.0 as i32))
+	}
+}
+
+/// System of units which values are shown in
+#[derive(uDebug, Clone, Copy, PartialEq, Debug)]
+pub enum Units {
+	/// Degrees Celsius
+	Metric,
+	/// Degrees Fahrenheit
+	Imperial,
+}
+
+impl Units {
+	/// Temperature in tenths of a degree Celsius, converted to tenths of a degree in these units
+	pub fn tenths(self, tenths_celsius: i16) -> i32 {
+		match self {
+			Self::Metric => tenths_celsius as i32,
+			Self::Imperial => self.difference(tenths_celsius) + 320,
+		}
+	}
+
+	/// Difference between two temperatures in tenths of a degree Celsius, converted to tenths of a
+	/// degree in these units
+	pub fn difference(self, tenths_celsius: i16) -> i32 {
+		match self {
+			Self::Metric => tenths_celsius as i32,
+			// Round to the nearest tenth, away from zero.
+			Self::Imperial => {
+				let scaled = tenths_celsius as i32 * 9;
+				(scaled + scaled.signum() * 2) / 5
+			}
+		}
+	}
+
+	/// Symbol of the temperature unit, shown after a temperature
+	pub fn symbol(self) -> &'static str {
+		match self {
+			Self::Metric => "C",
+			Self::Imperial => "F",
+		}
+	}
+}
+
+/// Temperature in tenths of a degree Celsius, shown in the chosen units
+pub struct Temperature(pub i16, pub Units);
+
+impl uDisplay for Temperature {
+	fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		let Self(tenths_celsius, units) = *self;
+		ufmt::uwrite!(
+			f,
+			"{}{}",
+			Tenths(units.tenths(tenths_celsius)),
+			units.symbol()
+		)
+	}
+}
+
+/// Temperature in tenths of a degree Celsius
+#[allow(dead_code)]
+pub struct Celsius(pub i16);
+
+impl uDisplay for Celsius {
+	fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		ufmt::uwrite!(f, "{}C", Tenths(self.0 as i32))
+	}
+}
+
+/// Duration shown as minutes and seconds, rounded down to the second
+///
+/// The minutes aren't limited to two digits, so a duration of 100 minutes or more is wider.
+#[allow(dead_code)]
+pub struct MinsSecs(pub Duration);
+
+impl uDisplay for MinsSecs {
+	fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		let secs = self.0.as_millis() / 1_000;
+		let mins = secs / 60;
+		let padding = if mins < 10 { "0" } else { "" };
+		ufmt::uwrite!(f, "{}{}:{}{}", padding, mins, secs % 60 / 10, secs % 10)
+	}
+}
+
+/// Amount of seconds shown as days, hours and minutes, rounded down to the minute
+pub struct DaysHoursMins(pub u32);
+
+impl uDisplay for DaysHoursMins {
+	fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		let mins = self.0 / 60;
+		ufmt::uwrite!(
+			f,
+			"{}d {}h {}m",
+			mins / (24 * 60),
+			mins / 60 % 24,
+			mins % 60
+		)
+	}
+}
+
+/// Whether something is enabled, shown as a single character
+pub struct Flag(pub bool);
+
+impl uDisplay for Flag {
+	fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		f.write_str(if self.0 { "@" } else { "-" })
+	}
+}
+
+/// Text formatted into a fixed-size buffer
+pub struct Buffer<const N: usize> {
+	bytes: [u8; N],
+	len: usize,
+}
+
+impl<const N: usize> Buffer<N> {
+	/// Create a new, empty [`Buffer`]
+	pub fn new() -> Self {
+		Self {
+			bytes: [0; N],
+			len: 0,
+		}
+	}
+
+	/// Format `value` into a new [`Buffer`]
+	///
+	/// Anything which doesn't fit is left out.
+	pub fn from_display(value: &impl uDisplay) -> Self {
+		let mut buffer = Self::new();
+		let _ = ufmt::uwrite!(buffer, "{}", value);
+		buffer
+	}
+
+	/// Text in the buffer
+	pub fn as_str(&self) -> &str {
+		// Only whole strings are written, so the bytes are always valid.
+		str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+	}
+}
+
+impl<const N: usize> uWrite for Buffer<N> {
+	type Error = ();
+
+	/// Append `s`, or nothing at all if it doesn't fit
+	fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+		let end = self.len + s.len();
+		if end > N {
+			return Err(());
+		}
+
+		self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+		self.len = end;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Format `value` into a string
+	fn format(value: impl uDisplay) -> Buffer<16> {
+		Buffer::from_display(&value)
+	}
+
+	#[test]
+	fn formats_tenths() {
+		assert_eq!(format(Tenths(215)).as_str(), "21.5");
+		assert_eq!(format(Tenths(7)).as_str(), "0.7");
+		assert_eq!(format(Tenths(-5)).as_str(), "-0.5");
+		assert_eq!(format(Tenths(-123)).as_str(), "-12.3");
+	}
+
+	#[test]
+	fn formats_units() {
+		assert_eq!(format(Percent(632)).as_str(), "63.2%");
+		assert_eq!(format(Percent(1000)).as_str(), "100.0%");
+		assert_eq!(format(Celsius(215)).as_str(), "21.5C");
+		assert_eq!(format(Celsius(-40)).as_str(), "-4.0C");
+	}
+
+	#[test]
+	fn formats_temperatures_in_units() {
+		assert_eq!(format(Temperature(215, Units::Metric)).as_str(), "21.5C");
+		assert_eq!(format(Temperature(215, Units::Imperial)).as_str(), "70.7F");
+		assert_eq!(
+			format(Temperature(-400, Units::Imperial)).as_str(),
+			"-40.0F"
+		);
+		assert_eq!(Units::Imperial.difference(10), 18);
+		assert_eq!(Units::Imperial.difference(-5), -9);
+	}
+
+	#[test]
+	fn formats_mins_secs() {
+		assert_eq!(format(MinsSecs(Duration::from_secs(0))).as_str(), "00:00");
+		assert_eq!(format(MinsSecs(Duration::from_secs(65))).as_str(), "01:05");
+		assert_eq!(
+			format(MinsSecs(Duration::from_millis(599_999))).as_str(),
+			"09:59"
+		);
+		assert_eq!(format(MinsSecs(Duration::from_mins(90))).as_str(), "90:00");
+	}
+
+	#[test]
+	fn formats_days_hours_mins() {
+		assert_eq!(format(DaysHoursMins(59)).as_str(), "0d 0h 0m");
+		assert_eq!(format(DaysHoursMins(3_725)).as_str(), "0d 1h 2m");
+		assert_eq!(format(DaysHoursMins(188_100)).as_str(), "2d 4h 15m");
+	}
+
+	#[test]
+	fn formats_flag() {
+		assert_eq!(format(Flag(true)).as_str(), "@");
+		assert_eq!(format(Flag(false)).as_str(), "-");
+	}
+
+	#[test]
+	fn buffer_leaves_out_what_doesnt_fit() {
+		let mut buffer = Buffer::<5>::new();
+		assert!(ufmt::uwrite!(buffer, "{}", 1050u16).is_ok());
+		assert!(ufmt::uwrite!(buffer, "{}", 12u16).is_err());
+		assert_eq!(buffer.as_str(), "1050");
+	}
+}
@@ -0,0 +1,167 @@
+//! Weather-adjusted watering
+//!
+//! A bridge which fetches a weather forecast can push the probability of rain over the serial
+//! connection. There's no point watering just before a storm, so while the forecast is current the
+//! activations started by the sensors are shortened in proportion to the chance of rain, or skipped
+//! entirely if rain is likely. The forecast expires after a while, so that the garden is still
+//! watered if the bridge stops sending it.
+
+use crate::{
+	display::BODY_START_ROW,
+	hal::{Clock, DisplayBus},
+	lang,
+	timer::{Duration, TIMER},
+};
+
+/// Chance of rain in percent at which the sensors don't activate the system at all
+const SKIP_PERCENT: u8 = 70;
+/// How long a forecast is used for after it was received
+const EXPIRY: Duration = Duration::from_mins(12 * 60);
+/// Row of the info screen which the forecast is shown on
+const ROW: u8 = BODY_START_ROW + 1;
+
+/// Change to the activations started by the sensors
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Adjustment {
+	/// Water for the configured activation time
+	None,
+	/// Water for a percentage of the configured activation time
+	Shorten(u8),
+	/// Don't water
+	Skip,
+}
+
+/// Latest forecast received over the serial connection
+pub struct Forecast {
+	/// Chance of rain in percent
+	rain_percent: u8,
+	/// [`TIMER`] clock value of when the forecast was received, if one has been
+	received_ms: Option<u32>,
+}
+
+impl Forecast {
+	/// Create a new [`Forecast`], without a forecast
+	pub fn new() -> Self {
+		Self {
+			rain_percent: 0,
+			received_ms: None,
+		}
+	}
+
+	/// Use a new forecast, if the chance of rain is a valid percentage
+	pub fn set(&mut self, rain_percent: u8) -> bool {
+		if rain_percent > 100 {
+			return false;
+		}
+
+		self.rain_percent = rain_percent;
+		self.received_ms = Some(TIMER.millis());
+		true
+	}
+
+	/// Chance of rain in percent, if the forecast hasn't expired
+	pub fn rain_percent(&self) -> Option<u8> {
+		match self.received_ms {
+			Some(received_ms) if !TIMER.has_elapsed(received_ms, EXPIRY) => Some(self.rain_percent),
+			_ => None,
+		}
+	}
+
+	/// Change to make to the activations started by the sensors
+	pub fn adjustment(&self) -> Adjustment {
+		match self.rain_percent() {
+			Some(percent) if percent >= SKIP_PERCENT => Adjustment::Skip,
+			Some(percent) if percent > 0 => Adjustment::Shorten(100 - percent),
+			_ => Adjustment::None,
+		}
+	}
+
+	/// Shorten `duration` of an activation started by the sensors, if rain is forecast
+	pub fn shorten(&self, duration: Duration) -> Duration {
+		match self.adjustment() {
+			Adjustment::Shorten(percent) => {
+				Duration::from_millis(duration.as_millis() / 100 * percent as u32)
+			}
+			Adjustment::None | Adjustment::Skip => duration,
+		}
+	}
+
+	/// Render the forecast, and its adjustment, on the info screen
+	pub fn render(&self, display: &mut impl DisplayBus) {
+		display.clear_row(ROW);
+		if let Some(percent) = self.rain_percent() {
+			let _ = match self.adjustment() {
+				Adjustment::Skip => {
+					ufmt::uwrite!(display, "{} {}% {}", lang::RAIN, percent, lang::SKIP)
+				}
+				Adjustment::Shorten(run) => ufmt::uwrite!(
+					display,
+					"{} {}% {} {}%",
+					lang::RAIN,
+					percent,
+					lang::RUN,
+					run
+				),
+				Adjustment::None => ufmt::uwrite!(display, "{} {}%", lang::RAIN, percent),
+			};
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		hal::mock::MockDisplay,
+		timer::{self, tests::TimersGuard},
+	};
+
+	#[test]
+	fn adjusts_by_chance_of_rain() {
+		let _timers = TimersGuard::take();
+		let mut forecast = Forecast::new();
+		assert_eq!(forecast.adjustment(), Adjustment::None);
+
+		assert!(forecast.set(0));
+		assert_eq!(forecast.adjustment(), Adjustment::None);
+		assert!(forecast.set(40));
+		assert_eq!(forecast.adjustment(), Adjustment::Shorten(60));
+		assert_eq!(
+			forecast.shorten(Duration::from_mins(10)).as_millis(),
+			Duration::from_mins(6).as_millis()
+		);
+		assert!(forecast.set(SKIP_PERCENT));
+		assert_eq!(forecast.adjustment(), Adjustment::Skip);
+
+		assert!(!forecast.set(101));
+		assert_eq!(forecast.rain_percent(), Some(SKIP_PERCENT));
+	}
+
+	#[test]
+	fn expires() {
+		let _timers = TimersGuard::take();
+		let mut forecast = Forecast::new();
+		forecast.set(80);
+
+		timer::advance(EXPIRY.as_millis() - 1);
+		assert_eq!(forecast.adjustment(), Adjustment::Skip);
+		timer::advance(1);
+		assert_eq!(forecast.rain_percent(), None);
+		assert_eq!(forecast.adjustment(), Adjustment::None);
+	}
+
+	#[test]
+	fn renders_adjustment() {
+		let _timers = TimersGuard::take();
+		let mut display = MockDisplay::new();
+		let mut forecast = Forecast::new();
+
+		forecast.set(30);
+		forecast.render(&mut display);
+		assert_eq!(display.row(ROW).trim_end(), "Rain 30% Run 70%");
+
+		forecast.set(90);
+		forecast.render(&mut display);
+		assert_eq!(display.row(ROW).trim_end(), "Rain 90% Skip");
+	}
+}
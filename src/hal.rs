@@ -0,0 +1,122 @@
+//! Hardware abstraction
+//!
+//! The menu and the control logic only use the hardware through these traits, so that they can be
+//! built for a board other than the Arduino Nano (for example, an ATmega2560 or an RP2040 board
+//! with more pins). Each board provides its implementations, the type aliases used by the system,
+//! and the interrupt-safe `Mutex` and `free` used by the timers, in a submodule enabled by a cargo
+//! feature. The `sim` feature provides a host implementation for the simulation (see
+//! `src/sim.rs`) and the tests.
+
+use core::convert::Infallible;
+
+use embedded_hal::digital::v2::OutputPin;
+use ufmt::uWrite;
+
+use crate::{control_pad::ButtonEvent, storage::crc8, timer::Duration};
+
+#[cfg(feature = "atmega328p")]
+pub mod atmega328p;
+#[cfg(feature = "atmega328p")]
+pub use atmega328p::*;
+#[cfg(feature = "sim")]
+pub mod host;
+#[cfg(feature = "sim")]
+pub use host::*;
+#[cfg(test)]
+pub mod mock;
+
+/// Monotonic millisecond clock
+///
+/// The clock wraps around after ~49 days, so durations must always be measured with
+/// [`Clock::elapsed_since`] or [`Clock::has_elapsed`] rather than by comparing two values of
+/// [`Clock::millis`] directly.
+pub trait Clock {
+	/// Milliseconds since the clock was started
+	fn millis(&self) -> u32;
+
+	/// Milliseconds since `start`, which was a previous value of [`Clock::millis`]
+	///
+	/// Correct even if the clock has wrapped around since `start`.
+	fn elapsed_since(&self, start: u32) -> u32 {
+		self.millis().wrapping_sub(start)
+	}
+
+	/// Whether at least `duration` has passed since `start`, which was a previous value of
+	/// [`Clock::millis`]
+	fn has_elapsed(&self, start: u32, duration: Duration) -> bool {
+		self.elapsed_since(start) >= duration.as_millis()
+	}
+}
+
+/// Analog to digital converter which can read `Input`
+pub trait AnalogReader<Input> {
+	/// Read the value of `input`
+	fn read(&mut self, input: &Input) -> u16;
+}
+
+/// Digital output driving a valve relay or H-bridge input
+pub trait ValveOutput {
+	fn set_high(&mut self);
+
+	fn set_low(&mut self);
+}
+
+/// Any infallible embedded-hal output pin can drive a valve
+impl<P: OutputPin<Error = Infallible>> ValveOutput for P {
+	fn set_high(&mut self) {
+		let _ = OutputPin::set_high(self);
+	}
+
+	fn set_low(&mut self) {
+		let _ = OutputPin::set_low(self);
+	}
+}
+
+/// Character display with a header, and a body which the menu and screens are rendered to
+///
+/// See [`crate::display`] for the size of the display.
+pub trait DisplayBus: uWrite<Error = Infallible> {
+	/// Clear every row of the body
+	fn clear_body(&mut self);
+
+	/// Clear a single row, leaving the cursor at the start of the row
+	fn clear_row(&mut self, row: u8);
+
+	/// Move the cursor to `column` of `row`
+	fn set_position(&mut self, column: u8, row: u8);
+}
+
+/// Non-volatile storage of records, such as the internal EEPROM or an external I2C chip (see
+/// [`crate::storage`])
+///
+/// Each record is followed by a CRC-8 checksum, so that unwritten or corrupted records can be
+/// detected when they are read back.
+pub trait Storage {
+	/// Read bytes into `buf`, starting at `address`
+	fn read(&mut self, address: u16, buf: &mut [u8]);
+
+	/// Write `data`, starting at `address`
+	fn write(&mut self, address: u16, data: &[u8]);
+
+	/// Read a record into `buf`, starting at `address`
+	///
+	/// Returns `false` if the checksum following the record doesn't match its contents.
+	fn read_record(&mut self, address: u16, buf: &mut [u8]) -> bool {
+		let mut checksum = [0];
+		self.read(address, buf);
+		self.read(address + buf.len() as u16, &mut checksum);
+		checksum[0] == crc8(buf)
+	}
+
+	/// Write a record followed by its checksum, starting at `address`
+	fn write_record(&mut self, address: u16, data: &[u8]) {
+		self.write(address, data);
+		self.write(address + data.len() as u16, &[crc8(data)]);
+	}
+}
+
+/// Source of button events, such as the buttons or the rotary encoder
+pub trait ButtonInput {
+	/// Take the oldest button event which hasn't been handled yet
+	fn next_event(&mut self) -> Option<ButtonEvent>;
+}
@@ -0,0 +1,279 @@
+//! ATmega328P implementation, using arduino-hal
+//!
+//! [`super::Clock`] is implemented by [`crate::timer::Timer`], [`super::ValveOutput`] by any
+//! output pin (and by [`crate::expander::ExpanderPin`] and [`crate::shiftreg::ShiftRegister`]),
+//! and [`super::Storage`] by [`crate::eeprom::Eeprom`] and [`crate::storage::i2c::I2cStorage`].
+//! The pins are those of the board selected in [`crate::boards`], apart from the digital outputs in
+//! the pin map (see [`crate::pinmap`]).
+
+use arduino_hal::{
+	pac::{PORTB, PORTC, PORTD},
+	port::{mode::Analog, Pin},
+};
+use core::convert::Infallible;
+use embedded_hal::digital::v2::OutputPin;
+
+use super::{AnalogReader, ButtonInput, DisplayBus};
+#[cfg(not(feature = "external-storage"))]
+use crate::eeprom::Eeprom;
+#[cfg(feature = "expander-valve")]
+use crate::expander::ExpanderPin;
+#[cfg(feature = "shift-register-valve")]
+use crate::shiftreg::ShiftRegister;
+#[cfg(feature = "external-storage")]
+use crate::storage::i2c::I2cStorage;
+use crate::{
+	boards::{self, AnalogPin},
+	control_pad::{ButtonEvent, ControlInput},
+	display::Display,
+	pinmap::{DigitalPin, Port},
+	sampling::{self, Channel, MAILBOX},
+};
+
+pub use crate::timer::TIMER as CLOCK;
+pub use avr_device::interrupt::{free, Mutex};
+
+/// ADC multiplexer setting of the internal bandgap reference
+const BANDGAP_MUX: u8 = 0b1110;
+
+/// Light sensor
+pub type LightSensor = Pin<Analog, boards::LightSensor>;
+/// Moisture sensor
+pub type MoistureSensor = Pin<Analog, boards::MoistureSensor>;
+/// Resistor ladder of the buttons
+pub type Buttons = Pin<Analog, boards::Buttons>;
+/// Soil temperature probe of the heat mat
+#[cfg(feature = "heat-mat")]
+pub type SoilProbe = Pin<Analog, boards::SoilProbe>;
+/// Internal bandgap reference, which the supply voltage is measured against
+pub struct Bandgap;
+/// Valve relay, or H-bridge open input
+#[cfg(not(any(feature = "expander-valve", feature = "shift-register-valve")))]
+pub type ValvePin = MappedOutput;
+/// Valve relay, on an I2C GPIO expander
+#[cfg(feature = "expander-valve")]
+pub type ValvePin = ExpanderPin<arduino_hal::I2c>;
+/// Valve relay, on a chain of shift registers
+#[cfg(feature = "shift-register-valve")]
+pub type ValvePin = ShiftRegister<MappedOutput>;
+/// H-bridge close input of a latching valve
+#[cfg(feature = "latching-valve")]
+pub type ValveClosePin = MappedOutput;
+
+/// Digital output on a pin from the pin map
+///
+/// The pin is only known at runtime, so the port's registers are written directly rather than
+/// through the HAL's pin types.
+pub struct MappedOutput {
+	port: Port,
+	/// Bit of the pin in the port's registers
+	mask: u8,
+}
+
+impl MappedOutput {
+	/// Make `pin` an output, starting low
+	pub fn new(pin: DigitalPin) -> Self {
+		Self::new_at(pin, false)
+	}
+
+	/// Make `pin` an output, starting high if `high`
+	///
+	/// The level is written before the pin becomes an output, so that it never glitches to the
+	/// other level.
+	pub fn new_at(pin: DigitalPin, high: bool) -> Self {
+		let (port, bit) = pin.port_bit();
+		let output = Self {
+			port,
+			mask: 1 << bit,
+		};
+		output.write(false, high);
+		output.write(true, true);
+		output
+	}
+
+	pub fn set_high(&mut self) {
+		self.write(false, true);
+	}
+
+	pub fn set_low(&mut self) {
+		self.write(false, false);
+	}
+
+	/// Set or clear the pin's bit in the port's output register, or its data direction register if
+	/// `direction` is `true`
+	fn write(&self, direction: bool, set: bool) {
+		let mask = self.mask;
+		let update = |bits: u8| if set { bits | mask } else { bits & !mask };
+		// Other pins of the port are written by the HAL, so the read-modify-write can't be
+		// interrupted.
+		free(|_cs| unsafe {
+			match (self.port, direction) {
+				(Port::B, false) => (*PORTB::ptr())
+					.portb
+					.modify(|r, w| w.bits(update(r.bits()))),
+				(Port::B, true) => (*PORTB::ptr()).ddrb.modify(|r, w| w.bits(update(r.bits()))),
+				(Port::C, false) => (*PORTC::ptr())
+					.portc
+					.modify(|r, w| w.bits(update(r.bits()))),
+				(Port::C, true) => (*PORTC::ptr()).ddrc.modify(|r, w| w.bits(update(r.bits()))),
+				(Port::D, false) => (*PORTD::ptr())
+					.portd
+					.modify(|r, w| w.bits(update(r.bits()))),
+				(Port::D, true) => (*PORTD::ptr()).ddrd.modify(|r, w| w.bits(update(r.bits()))),
+			}
+		});
+	}
+}
+
+/// Storage of the event history, in the internal EEPROM
+#[cfg(not(feature = "external-storage"))]
+pub type LogStorage = Eeprom;
+/// Storage of the event history and the sensor samples, on an external EEPROM or FRAM chip
+#[cfg(feature = "external-storage")]
+pub type LogStorage = I2cStorage<arduino_hal::I2c>;
+
+/// Create a handle to the internal EEPROM for the event history
+///
+/// The system also has a handle to the EEPROM for its other records, so the peripherals are taken
+/// back for another one. Only one record is read or written at a time.
+#[cfg(not(feature = "external-storage"))]
+pub fn log_storage() -> LogStorage {
+	let dp = unsafe { arduino_hal::Peripherals::steal() };
+	Eeprom::new(dp.EEPROM)
+}
+
+/// Create the external storage chip on the display's I2C bus, with the bus running at `speed`
+///
+/// The display also has a handle to the bus, so the peripherals are taken back for another one.
+#[cfg(feature = "external-storage")]
+pub fn log_storage(speed: u32) -> LogStorage {
+	let dp = unsafe { arduino_hal::Peripherals::steal() };
+	let pins = arduino_hal::pins!(dp);
+	I2cStorage::new(arduino_hal::I2c::new(
+		dp.TWI,
+		pins.a4.into_pull_up_input(),
+		pins.a5.into_pull_up_input(),
+		speed,
+	))
+}
+
+/// Create the valve relay's output on the I2C GPIO expander, with the bus running at `speed`
+///
+/// The display also has a handle to the bus, so the peripherals are taken back for another one.
+/// Nothing uses the bus from an interrupt, so one transfer always finishes before the next starts.
+#[cfg(feature = "expander-valve")]
+pub fn expander_valve_pin(speed: u32) -> ValvePin {
+	let dp = unsafe { arduino_hal::Peripherals::steal() };
+	let pins = arduino_hal::pins!(dp);
+	ExpanderPin::new(arduino_hal::I2c::new(
+		dp.TWI,
+		pins.a4.into_pull_up_input(),
+		pins.a5.into_pull_up_input(),
+		speed,
+	))
+}
+
+impl OutputPin for MappedOutput {
+	type Error = Infallible;
+
+	fn set_high(&mut self) -> Result<(), Self::Error> {
+		MappedOutput::set_high(self);
+		Ok(())
+	}
+
+	fn set_low(&mut self) -> Result<(), Self::Error> {
+		MappedOutput::set_low(self);
+		Ok(())
+	}
+}
+
+/// Analog to digital converter which the sensors are read with
+///
+/// The inputs are converted one after another in the background (see [`crate::sampling`]), so
+/// reading one takes its latest conversion rather than waiting for a new one.
+pub struct Adc {
+	/// The HAL's ADC, kept so that nothing else starts a conversion
+	_adc: arduino_hal::Adc,
+}
+
+impl Adc {
+	/// Take over `adc` once the analog inputs have been setup with it, and start converting them
+	pub fn new(adc: arduino_hal::Adc) -> Self {
+		free(|cs| {
+			let mut mailbox = MAILBOX.borrow(cs).borrow_mut();
+			#[cfg(not(feature = "encoder"))]
+			mailbox.enable(Channel::Buttons, boards::Buttons::CHANNEL);
+			mailbox.enable(Channel::Light, boards::LightSensor::CHANNEL);
+			mailbox.enable(Channel::Moisture, boards::MoistureSensor::CHANNEL);
+			#[cfg(feature = "heat-mat")]
+			mailbox.enable(Channel::SoilProbe, boards::SoilProbe::CHANNEL);
+			mailbox.enable(Channel::Bandgap, BANDGAP_MUX);
+		});
+		sampling::start();
+
+		Self { _adc: adc }
+	}
+
+	/// Latest conversion of `channel`
+	///
+	/// Every channel is converted within a millisecond of the conversions starting, so this only
+	/// waits for the first conversion when the system starts up.
+	fn latest(&self, channel: Channel) -> u16 {
+		loop {
+			if let Some(reading) = sampling::latest(channel) {
+				return reading;
+			}
+		}
+	}
+}
+
+impl AnalogReader<LightSensor> for Adc {
+	fn read(&mut self, _input: &LightSensor) -> u16 {
+		self.latest(Channel::Light)
+	}
+}
+
+impl AnalogReader<MoistureSensor> for Adc {
+	fn read(&mut self, _input: &MoistureSensor) -> u16 {
+		self.latest(Channel::Moisture)
+	}
+}
+
+impl AnalogReader<Buttons> for Adc {
+	fn read(&mut self, _input: &Buttons) -> u16 {
+		self.latest(Channel::Buttons)
+	}
+}
+
+#[cfg(feature = "heat-mat")]
+impl AnalogReader<SoilProbe> for Adc {
+	fn read(&mut self, _input: &SoilProbe) -> u16 {
+		self.latest(Channel::SoilProbe)
+	}
+}
+
+impl AnalogReader<Bandgap> for Adc {
+	fn read(&mut self, _input: &Bandgap) -> u16 {
+		self.latest(Channel::Bandgap)
+	}
+}
+
+impl ButtonInput for ControlInput {
+	fn next_event(&mut self) -> Option<ButtonEvent> {
+		self.events.pop()
+	}
+}
+
+impl DisplayBus for Display {
+	fn clear_body(&mut self) {
+		Display::clear_body(self)
+	}
+
+	fn clear_row(&mut self, row: u8) {
+		Display::clear_row(self, row)
+	}
+
+	fn set_position(&mut self, column: u8, row: u8) {
+		Display::set_position(self, column, row)
+	}
+}
@@ -0,0 +1,173 @@
+//! Host implementation, used by the simulation and the tests
+//!
+//! The sensors, display and interrupts are replaced by values in memory.
+
+use core::{
+	convert::Infallible,
+	hint, str,
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use ufmt::uWrite;
+
+use super::{AnalogReader, DisplayBus};
+use crate::display::{BODY_ROW_COUNT, BODY_START_ROW, CLEAR_ROW, ROW_LENGTH};
+
+pub use crate::timer::TIMER as CLOCK;
+
+/// Amount of rows on the display, including the header
+const ROW_COUNT: usize = (BODY_START_ROW + BODY_ROW_COUNT) as usize;
+/// Reading of a sensor which hasn't been set, which is too bright and too wet to water, but isn't
+/// at the end of the ADC's range which a disconnected sensor reads at
+const DEFAULT_READING: u16 = 1000;
+
+/// Whether a thread is in a critical section
+static IN_CRITICAL_SECTION: AtomicBool = AtomicBool::new(false);
+
+/// Token for borrowing the contents of a [`Mutex`]
+///
+/// There are no interrupts to disable on the host, but the tests run on several threads, so only
+/// one of them can be in a critical section at a time.
+pub struct CriticalSection(());
+
+/// Run `f` in a critical section, once no other thread is in one
+pub fn free<F, R>(f: F) -> R
+where
+	F: FnOnce(&CriticalSection) -> R,
+{
+	while IN_CRITICAL_SECTION
+		.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+		.is_err()
+	{
+		hint::spin_loop();
+	}
+	let result = f(&CriticalSection(()));
+	IN_CRITICAL_SECTION.store(false, Ordering::Release);
+	result
+}
+
+/// Same interface as `avr_device::interrupt::Mutex`, for the timers
+pub struct Mutex<T>(T);
+
+impl<T> Mutex<T> {
+	pub const fn new(value: T) -> Self {
+		Self(value)
+	}
+
+	pub fn borrow<'cs>(&'cs self, _cs: &'cs CriticalSection) -> &'cs T {
+		&self.0
+	}
+}
+
+// The contents are only borrowed in a critical section, which one thread can be in at a time.
+unsafe impl<T> Sync for Mutex<T> {}
+
+/// Light sensor
+pub struct LightSensor;
+/// Moisture sensor
+pub struct MoistureSensor;
+
+/// Sensor readings, set by the simulation script
+pub struct Adc {
+	pub light: u16,
+	pub moisture: u16,
+}
+
+impl Adc {
+	/// Create a new [`Adc`], with readings which don't activate the system
+	pub fn new() -> Self {
+		Self {
+			light: DEFAULT_READING,
+			moisture: DEFAULT_READING,
+		}
+	}
+}
+
+impl AnalogReader<LightSensor> for Adc {
+	fn read(&mut self, _input: &LightSensor) -> u16 {
+		self.light
+	}
+}
+
+impl AnalogReader<MoistureSensor> for Adc {
+	fn read(&mut self, _input: &MoistureSensor) -> u16 {
+		self.moisture
+	}
+}
+
+/// Display which keeps what is written to it in memory, so that it can be printed
+///
+/// Behaves like the SSD1306 in terminal mode: text wraps onto the next row at the end of a row,
+/// and back to the top at the bottom of the display.
+pub struct VirtualDisplay {
+	rows: [[u8; ROW_LENGTH as usize]; ROW_COUNT],
+	column: u8,
+	row: u8,
+}
+
+impl VirtualDisplay {
+	/// Create a new, blank [`VirtualDisplay`]
+	pub fn new() -> Self {
+		Self {
+			rows: [[b' '; ROW_LENGTH as usize]; ROW_COUNT],
+			column: 0,
+			row: 0,
+		}
+	}
+
+	/// Text shown on a row of the display
+	pub fn row(&self, row: u8) -> &str {
+		// Only ASCII is written to the rows.
+		str::from_utf8(&self.rows[row as usize]).unwrap_or(CLEAR_ROW)
+	}
+
+	/// Text shown on every row of the display, from the top
+	pub fn rows(&self) -> impl Iterator<Item = &str> {
+		(0..ROW_COUNT as u8).map(move |row| self.row(row))
+	}
+
+	/// Move the cursor to the start of the next row
+	fn new_line(&mut self) {
+		self.column = 0;
+		self.row = (self.row + 1) % ROW_COUNT as u8;
+	}
+}
+
+impl uWrite for VirtualDisplay {
+	type Error = Infallible;
+
+	fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+		for byte in s.bytes() {
+			if byte == b'\n' {
+				self.new_line();
+				continue;
+			}
+
+			self.rows[self.row as usize][self.column as usize] = byte;
+			self.column += 1;
+			if self.column == ROW_LENGTH {
+				self.new_line();
+			}
+		}
+		Ok(())
+	}
+}
+
+impl DisplayBus for VirtualDisplay {
+	fn clear_body(&mut self) {
+		for row in 0..BODY_ROW_COUNT {
+			self.clear_row(BODY_START_ROW + row);
+		}
+	}
+
+	fn clear_row(&mut self, row: u8) {
+		self.set_position(0, row);
+		let _ = self.write_str(CLEAR_ROW);
+		self.set_position(0, row);
+	}
+
+	fn set_position(&mut self, column: u8, row: u8) {
+		self.column = column.min(ROW_LENGTH - 1);
+		self.row = row % ROW_COUNT as u8;
+	}
+}
@@ -0,0 +1,79 @@
+//! Mock hardware for host tests of the menu
+//!
+//! Button presses are queued up front, and the display keeps what is rendered to it, so that
+//! tests can press buttons and then check what is shown on each row. Storage is kept in memory,
+//! and starts out erased.
+
+use std::collections::VecDeque;
+
+use super::{ButtonInput, Storage};
+use crate::control_pad::{ButtonEvent, ButtonStage, ButtonType};
+
+/// Display which keeps the rendered rows in a 16×8 character buffer
+pub use super::host::VirtualDisplay as MockDisplay;
+
+/// Control pad which replays queued button presses
+pub struct MockControlPad {
+	events: VecDeque<ButtonEvent>,
+}
+
+impl MockControlPad {
+	/// Create a new [`MockControlPad`] with no button presses queued
+	pub fn new() -> Self {
+		Self {
+			events: VecDeque::new(),
+		}
+	}
+
+	/// Queue a press and release of `button`
+	pub fn press(&mut self, button: ButtonType) -> &mut Self {
+		self.push(button, ButtonStage::Down);
+		self.push(button, ButtonStage::Release)
+	}
+
+	/// Queue `button` being held down until it is long pressed
+	///
+	/// Releasing a button after a long press doesn't produce an event.
+	pub fn hold(&mut self, button: ButtonType) -> &mut Self {
+		self.push(button, ButtonStage::Down);
+		self.push(button, ButtonStage::LongPress)
+	}
+
+	/// Queue a single button event
+	fn push(&mut self, button: ButtonType, stage: ButtonStage) -> &mut Self {
+		self.events.push_back(ButtonEvent { stage, button });
+		self
+	}
+}
+
+impl ButtonInput for MockControlPad {
+	fn next_event(&mut self) -> Option<ButtonEvent> {
+		self.events.pop_front()
+	}
+}
+
+/// Storage kept in memory, which starts out erased
+pub struct MockStorage {
+	pub bytes: Vec<u8>,
+}
+
+impl MockStorage {
+	/// Create a new [`MockStorage`] of `size` erased bytes
+	pub fn new(size: usize) -> Self {
+		Self {
+			bytes: vec![0xff; size],
+		}
+	}
+}
+
+impl Storage for MockStorage {
+	fn read(&mut self, address: u16, buf: &mut [u8]) {
+		let address = address as usize;
+		buf.copy_from_slice(&self.bytes[address..address + buf.len()]);
+	}
+
+	fn write(&mut self, address: u16, data: &[u8]) {
+		let address = address as usize;
+		self.bytes[address..address + data.len()].copy_from_slice(data);
+	}
+}
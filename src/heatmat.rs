@@ -0,0 +1,238 @@
+//! Propagation heat mat thermostat
+//!
+//! With the `heat-mat` feature, a relay (the `heat-mat` pin of the pin map) switches a propagation
+//! heat mat to keep the soil at a target temperature, which is measured by a 10k NTC thermistor
+//! probe on A3. The mat is switched on once the soil falls below the bottom of the hysteresis band,
+//! and off once it is back up to the target, so that it isn't switched on and off every few
+//! seconds.
+//!
+//! A probe which has fallen out of the soil reads cold, and would leave the mat heating forever, so
+//! the mat is cut out once it has been on for longer than the configured limit. Like the valve
+//! fail-safe, it then stays off until the system is restarted.
+
+use crate::{
+	config::SystemConfig,
+	display::{self, BODY_START_ROW, ROW_LENGTH},
+	fmt::{Buffer, Temperature, Tenths},
+	hal::{Clock, DisplayBus},
+	lang,
+	timer::{Duration, TIMER},
+};
+
+/// How often the probe is read
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// Temperature in tenths of a degree of the first entry in [`PROBE_TABLE`]
+const PROBE_TABLE_START: i32 = -100;
+/// Difference in tenths of a degree between the entries in [`PROBE_TABLE`]
+const PROBE_TABLE_STEP: i32 = 50;
+/// Readings of the probe from -10C to 60C, in steps of 5C
+///
+/// The probe is a 10k NTC thermistor (B = 3950) between A3 and ground, with a 10k resistor from A3
+/// to 5V, so the reading falls as the temperature rises.
+const PROBE_TABLE: [u16; 15] = [
+	873, 834, 788, 738, 684, 627, 569, 512, 456, 403, 354, 310, 270, 235, 204,
+];
+/// Row of the info screen which the temperature is shown on
+const ROW: u8 = BODY_START_ROW;
+
+/// Thermostat of the heat mat
+pub struct HeatMat {
+	/// [`TIMER`] clock value of the last time the probe was read
+	last_sample_ms: Option<u32>,
+	/// Latest soil temperature in tenths of a degree, if the probe is connected
+	temperature: Option<i16>,
+	/// [`TIMER`] clock value of when the mat was switched on, if it is on
+	on_since_ms: Option<u32>,
+	/// Whether the mat has been cut out for being on for too long
+	cut_out: bool,
+}
+
+impl HeatMat {
+	/// Create a new [`HeatMat`], which is off
+	pub fn new() -> Self {
+		Self {
+			last_sample_ms: None,
+			temperature: None,
+			on_since_ms: None,
+			cut_out: false,
+		}
+	}
+
+	/// Read the probe if it is due to be read, and switch the mat on or off
+	///
+	/// Returns whether the mat was just cut out for being on for too long.
+	pub fn update(
+		&mut self,
+		system_config: &SystemConfig,
+		read_probe: impl FnOnce() -> u16,
+	) -> bool {
+		let due = match self.last_sample_ms {
+			Some(last_sample_ms) => TIMER.has_elapsed(last_sample_ms, SAMPLE_INTERVAL),
+			None => true,
+		};
+		if due {
+			self.last_sample_ms = Some(TIMER.millis());
+			self.temperature = probe_temperature(read_probe());
+		}
+
+		// If the mat has been on for too long, cut it out.
+		if let Some(on_since_ms) = self.on_since_ms {
+			if TIMER.has_elapsed(on_since_ms, max_on(system_config)) {
+				self.on_since_ms = None;
+				self.cut_out = true;
+				return true;
+			}
+		}
+
+		let target = system_config.mat_celsius as i16 * 10;
+		let on = match self.temperature {
+			_ if system_config.mat_celsius == 0 || self.cut_out => false,
+			// Without a reading, the soil could be at any temperature.
+			None => false,
+			Some(temperature) if self.on_since_ms.is_some() => temperature < target,
+			Some(temperature) => temperature < target - system_config.mat_band as i16,
+		};
+		match (on, self.on_since_ms) {
+			(true, None) => self.on_since_ms = Some(TIMER.millis()),
+			(false, Some(_)) => self.on_since_ms = None,
+			_ => {}
+		}
+		false
+	}
+
+	/// Whether the mat should be on
+	pub fn is_on(&self) -> bool {
+		self.on_since_ms.is_some()
+	}
+
+	/// Whether the mat has been cut out for being on for too long
+	pub fn is_cut_out(&self) -> bool {
+		self.cut_out
+	}
+
+	/// Latest soil temperature in tenths of a degree, if the probe is connected
+	pub fn temperature(&self) -> Option<i16> {
+		self.temperature
+	}
+
+	/// Render the soil temperature and the target after the title of the info screen
+	pub fn render(&self, system_config: &SystemConfig, display: &mut impl DisplayBus) {
+		if system_config.mat_celsius == 0 {
+			return;
+		}
+
+		let mut value = Buffer::<11>::new();
+		let _ = match (self.cut_out, self.temperature) {
+			(true, _) => ufmt::uwrite!(value, "{}", lang::MAT_CUT),
+			(false, Some(temperature)) => ufmt::uwrite!(
+				value,
+				"{}/{}",
+				Tenths(system_config.units.tenths(temperature)),
+				Temperature(system_config.mat_celsius as i16 * 10, system_config.units)
+			),
+			(false, None) => ufmt::uwrite!(value, "{}", lang::MAT_UNKNOWN),
+		};
+		display.clear_row(ROW);
+		let _ = ufmt::uwrite!(
+			display,
+			"{}",
+			display::align(lang::INFO, value.as_str(), ROW_LENGTH)
+		);
+	}
+}
+
+/// How long the mat can be on for before it is cut out
+fn max_on(system_config: &SystemConfig) -> Duration {
+	Duration::from_config_mins(system_config.mat_max_mins)
+}
+
+/// Temperature in tenths of a degree of a reading of the probe, if it is within the range of
+/// [`PROBE_TABLE`]
+///
+/// A reading outside of the range is most likely a disconnected or shorted probe.
+fn probe_temperature(reading: u16) -> Option<i16> {
+	PROBE_TABLE
+		.windows(2)
+		.enumerate()
+		.find(|(_, pair)| reading <= pair[0] && reading >= pair[1])
+		.map(|(idx, pair)| {
+			let (high, low) = (pair[0] as i32, pair[1] as i32);
+			let offset = (high - reading as i32) * PROBE_TABLE_STEP / (high - low);
+			(PROBE_TABLE_START + idx as i32 * PROBE_TABLE_STEP + offset) as i16
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		hal::mock::MockDisplay,
+		timer::{self, tests::TimersGuard},
+	};
+
+	/// Reading of the probe at 20C
+	const AT_20C: u16 = 569;
+	/// Reading of the probe at 25C
+	const AT_25C: u16 = 512;
+
+	fn mat_config() -> SystemConfig {
+		let mut system_config = SystemConfig::new();
+		system_config.mat_celsius = 24;
+		system_config.mat_band = 20;
+		system_config
+	}
+
+	/// Read `reading` from the probe once the next sample is due
+	fn sample(heat_mat: &mut HeatMat, system_config: &SystemConfig, reading: u16) -> bool {
+		let cut_out = heat_mat.update(system_config, || reading);
+		timer::advance(SAMPLE_INTERVAL.as_millis());
+		cut_out
+	}
+
+	#[test]
+	fn converts_probe_readings() {
+		assert_eq!(probe_temperature(873), Some(-100));
+		assert_eq!(probe_temperature(AT_20C), Some(200));
+		assert_eq!(probe_temperature(540), Some(225));
+		assert_eq!(probe_temperature(204), Some(600));
+		assert_eq!(probe_temperature(1023), None);
+		assert_eq!(probe_temperature(0), None);
+	}
+
+	#[test]
+	fn heats_within_band_and_cuts_out() {
+		let _timers = TimersGuard::take();
+		let system_config = mat_config();
+		let mut heat_mat = HeatMat::new();
+
+		// 23C is within the band, so the mat stays off until the soil cools to below 22C.
+		sample(&mut heat_mat, &system_config, 535);
+		assert!(!heat_mat.is_on());
+		sample(&mut heat_mat, &system_config, AT_20C);
+		assert!(heat_mat.is_on());
+		sample(&mut heat_mat, &system_config, 535);
+		assert!(heat_mat.is_on());
+		sample(&mut heat_mat, &system_config, AT_25C);
+		assert!(!heat_mat.is_on());
+
+		// A probe which never warms up cuts the mat out.
+		sample(&mut heat_mat, &system_config, AT_20C);
+		timer::advance(max_on(&system_config).as_millis());
+		assert!(sample(&mut heat_mat, &system_config, AT_20C));
+		assert!(!heat_mat.is_on());
+		assert!(!sample(&mut heat_mat, &system_config, AT_20C));
+		assert!(!heat_mat.is_on());
+	}
+
+	#[test]
+	fn renders_temperature_and_target() {
+		let _timers = TimersGuard::take();
+		let system_config = mat_config();
+		let mut display = MockDisplay::new();
+		let mut heat_mat = HeatMat::new();
+
+		sample(&mut heat_mat, &system_config, 540);
+		heat_mat.render(&system_config, &mut display);
+		assert_eq!(display.row(ROW), "Info: 22.5/24.0C");
+	}
+}
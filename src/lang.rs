@@ -0,0 +1,236 @@
+//! Text shown on the display, in the language chosen at compile time
+//!
+//! English is used unless one of the `lang-de`, `lang-es` or `lang-fr` features is enabled. Each
+//! string is declared in every language along with the most characters it can take up, and the
+//! translations are abbreviated where they would otherwise be too long for the display. The font
+//! of the display only has ASCII characters, so accents and umlauts are left out.
+//!
+//! Anything which is also sent over the USART (event names, reset causes, triggers, profiles and
+//! preset names) stays in English, so that scripts reading the serial output keep working. So do
+//! the calibration, self-test and diagnostics screens, which are only used while setting up.
+
+#[cfg(any(
+	all(feature = "lang-de", feature = "lang-es"),
+	all(feature = "lang-de", feature = "lang-fr"),
+	all(feature = "lang-es", feature = "lang-fr"),
+))]
+compile_error!("only one of the `lang-de`, `lang-es` and `lang-fr` features can be enabled");
+
+/// Amount of languages each string is declared in
+const LANGUAGE_COUNT: usize = 4;
+
+/// Index of the language in each string's translations: English, German, Spanish, then French
+const LANGUAGE: usize = if cfg!(feature = "lang-de") {
+	1
+} else if cfg!(feature = "lang-es") {
+	2
+} else if cfg!(feature = "lang-fr") {
+	3
+} else {
+	0
+};
+
+/// Longest label of a setting in the menu, which leaves room for its value
+const LABEL: usize = 9;
+/// Longest title of a screen, or label of a menu item without a value
+const TITLE: usize = 14;
+/// Longest line of text which takes up a whole row
+const LINE: usize = 16;
+
+/// Declare each string in English, German, Spanish and French, with the most characters it can
+/// take up
+macro_rules! strings {
+	($(
+		$(#[doc = $doc:literal])*
+		$name:ident($max:expr): [$en:literal, $de:literal, $es:literal, $fr:literal],
+	)*) => {
+		$(
+			$(#[doc = $doc])*
+			pub const $name: &str = [$en, $de, $es, $fr][LANGUAGE];
+		)*
+
+		/// Every string in every language, with the most characters it can take up
+		#[cfg(test)]
+		const TABLE: &[(&str, usize, [&str; LANGUAGE_COUNT])] =
+			&[$((stringify!($name), $max, [$en, $de, $es, $fr])),*];
+	};
+}
+
+strings! {
+	/// Activation time minutes setting
+	TIME(LABEL): ["Time", "Zeit", "Tiempo", "Duree"],
+	/// Minimum light setting
+	LIGHT(LABEL): ["Light", "Licht", "Luz", "Lumiere"],
+	/// Minimum moisture setting
+	MOISTURE(LABEL): ["Moisture", "Feuchte", "Humedad", "Humidite"],
+	/// Pump speed setting
+	FLOW(LABEL): ["Flow %", "Fluss %", "Flujo %", "Debit %"],
+	/// Display contrast setting
+	BRIGHTNESS(LABEL): ["Bright %", "Hell %", "Brillo %", "Lumin %"],
+	/// Evening-only watering setting
+	EVENING(LABEL): ["Evening h", "Abend h", "Noche h", "Soir h"],
+	/// Dosing time setting
+	DOSE_TIME(LABEL): ["Dose s", "Dosis s", "Dosis s", "Dose s"],
+	/// Weekly dosing cap setting
+	DOSE_CAP(LABEL): ["Dose/wk", "Dosis/Wo", "Dosis/sem", "Dose/sem"],
+	/// Misting temperature setting
+	MIST_TEMP(LABEL): ["Mist temp", "Nebel T", "Niebla T", "Brume T"],
+	/// Misting burst setting
+	MIST_TIME(LABEL): ["Mist s", "Nebel s", "Niebla s", "Brume s"],
+	/// Misting interval setting
+	MIST_INTERVAL(LABEL): ["Mist min", "Nebel min", "Niebla mn", "Brume min"],
+	/// Grow-light day length setting
+	DAY_HOURS(LABEL): ["Day h", "Tag h", "Dia h", "Jour h"],
+	/// Fan temperature setting
+	FAN_TEMP(LABEL): ["Fan temp", "Luefter T", "Vent T", "Vent T"],
+	/// Fan humidity setting
+	FAN_HUMIDITY(LABEL): ["Fan RH%", "Luefter %", "Vent HR%", "Vent HR%"],
+	/// Heat mat temperature setting
+	MAT_TEMP(LABEL): ["Mat temp", "Matte T", "Manta T", "Tapis T"],
+	/// Heat mat hysteresis band setting
+	MAT_BAND(LABEL): ["Mat band", "Matte +/-", "Manta +/-", "Tapis +/-"],
+	/// Heat mat cut-out setting
+	MAT_MAX(LABEL): ["Mat max m", "Matte max", "Manta max", "Tapis max"],
+	/// Suspends the system
+	SUSPEND(LABEL): ["Suspend", "Pause", "Pausar", "Pause"],
+	/// Ends the suspension
+	RESUME(LABEL): ["Resume", "Weiter", "Reanudar", "Reprendre"],
+	/// Winterizes the system
+	WINTERIZE(LABEL): ["Winterize", "Winter", "Invernar", "Hiverner"],
+	/// Re-enables the system after winterizing it
+	REENABLE(LABEL): ["Re-enable", "Reaktiv.", "Reactivar", "Reactiver"],
+	/// Acknowledges the fault which stopped the system
+	ACK_FAULT(LABEL): ["Ack fault", "Fehler ok", "Ok fallo", "Ok defaut"],
+	/// Activates the system
+	ACTIVATE(LABEL): ["Activate", "Giessen", "Regar", "Arroser"],
+	/// Cancels the activation
+	CANCEL(LABEL): ["Cancel", "Abbruch", "Cancelar", "Annuler"],
+	/// Buzzer setting
+	SOUND(LABEL): ["Sound", "Ton", "Sonido", "Son"],
+	/// Remote control setting
+	REMOTE(LABEL): ["Remote", "Fernbed.", "Remoto", "Distance"],
+	/// Bluetooth control setting, which shows the pairing code while enabled
+	BT_CONTROL(LABEL): ["BT ctrl", "BT-Steuer", "Ctrl BT", "Ctrl BT"],
+	/// Relay polarity setting
+	RELAY_LOW(LABEL): ["Relay low", "Relais lo", "Rele bajo", "Relai bas"],
+	/// Valve type setting
+	VALVE_NO(LABEL): ["Valve NO", "Ventil NO", "Valv. NA", "Vanne NO"],
+	/// Low battery threshold setting
+	LOW_BATTERY(LABEL): ["Low batt", "Akku min", "Bat min", "Batt min"],
+	/// Night dimming setting
+	NIGHT_DIM(LABEL): ["Night dim", "Nachtdimm", "Atenuar", "Att. nuit"],
+	/// Valve service interval setting
+	SERVICE(LABEL): ["Service k", "Wartung k", "Revis. k", "Entret. k"],
+	/// Vacation days setting, and shown in the header while on vacation, followed by the days left
+	VACATION(LABEL): ["Vacation", "Urlaub", "Vacacion", "Vacances"],
+	/// Vacation interval factor setting
+	VACATION_INTERVAL(LABEL): ["Vac int x", "Url int x", "Vac int x", "Vac int x"],
+	/// Vacation time percentage setting
+	VACATION_TIME(LABEL): ["Vac time%", "Url Zeit%", "Vac tiem%", "Vac dur %"],
+	/// Master valve pre-open delay setting
+	MASTER_LEAD(LABEL): ["Mstr pre", "HV vor", "VM antes", "VP avant"],
+	/// Master valve post-close delay setting
+	MASTER_LAG(LABEL): ["Mstr post", "HV nach", "VM tras", "VP apres"],
+	/// Pump start and stop delay setting
+	PUMP_DELAY(LABEL): ["Pump wait", "Pumpe Vz", "Bomba ret", "Pompe ret"],
+	/// Telemetry interval setting
+	TELEMETRY(LABEL): ["Telem s", "Telem s", "Telem s", "Telem s"],
+	/// Bottom of the moisture band setting
+	BAND_LOW(LABEL): ["Band lo", "Band min", "Banda min", "Bande min"],
+	/// Top of the moisture band setting
+	BAND_HIGH(LABEL): ["Band hi", "Band max", "Banda max", "Bande max"],
+	/// Band control pulse cap setting
+	BAND_CAP(LABEL): ["Band cap", "Band Lim.", "Banda lim", "Bande lim"],
+	/// Units setting
+	UNITS(LABEL): ["Units", "Einheit", "Unidades", "Unites"],
+	/// Trigger setting
+	TRIGGER(LABEL): ["Trigger", "Ausloeser", "Disparo", "Declench."],
+	/// Evapotranspiration scaling setting
+	ET_TIME(LABEL): ["ET time", "ET Zeit", "ET tiempo", "ET duree"],
+	/// Output profile setting
+	PROFILE(LABEL): ["Profile", "Profil", "Perfil", "Profil"],
+	/// Dosing pump setting
+	DOSE(LABEL): ["Dose", "Dosis", "Dosis", "Dose"],
+	/// Info screen, which shares its title row with the heat mat temperature
+	INFO(5): ["Info", "Info", "Info", "Info"],
+	/// About screen
+	ABOUT(TITLE): ["About", "Ueber", "Acerca de", "A propos"],
+	/// Event log screen, followed by the page number
+	LOG(10): ["Log", "Protokoll", "Registro", "Journal"],
+	/// Watering of each of the last 7 days, followed by the most minutes watered on any of them
+	WEEK(10): ["Week", "Woche", "Semana", "Semaine"],
+	/// Soil moisture trend, followed by the latest sample
+	SOIL(10): ["Soil", "Boden", "Suelo", "Sol"],
+	/// Event history screen
+	HISTORY(TITLE): ["History", "Verlauf", "Historial", "Historique"],
+	/// Diagnostics screen
+	DIAGNOSTICS(TITLE): ["Diagnostics", "Diagnose", "Diagnostico", "Diagnostic"],
+	/// Plant presets screen
+	PRESETS(TITLE): ["Presets", "Vorlagen", "Ajustes", "Prereglages"],
+	/// Name screen, and its menu item
+	NAME(TITLE): ["Name", "Name", "Nombre", "Nom"],
+	/// Starts counting the valve cycles again after servicing
+	SERVICED(TITLE): ["Serviced", "Gewartet", "Revisado", "Entretenu"],
+	/// Resets the configuration
+	RESET(TITLE): ["Reset", "Zuruecksetzen", "Restablecer", "Reinitialiser"],
+	/// Time since the system started, followed by the time
+	UPTIME(4): ["Up", "Lauf", "Act.", "Act."],
+	/// Amount of times the system has been activated, followed by the amount
+	RUNS(8): ["Runs", "Laeufe", "Riegos", "Arrosage"],
+	/// Total time spent watering, followed by the time
+	WATERED(9): ["Watered", "Gegossen", "Regado", "Arrose"],
+	/// Cause of the last reset, followed by the cause
+	RESET_CAUSE(5): ["Reset", "Start", "Rein.", "Reset"],
+	/// Build date of the firmware, followed by the date
+	BUILT(5): ["Built", "Bau", "Comp.", "Cree"],
+	/// Shown on the log screen while it is empty
+	NO_EVENTS(LINE): ["No events", "Keine Ereignisse", "Sin eventos", "Aucun evenement"],
+	/// Title of the safe mode screen
+	SAFE_MODE(LINE): ["SAFE MODE", "SICHERER MODUS", "MODO SEGURO", "MODE SECURITE"],
+	/// Shown on the safe mode screen
+	VALVE_CLOSED(LINE): ["Valve closed", "Ventil zu", "Valvula cerrada", "Vanne fermee"],
+	/// Shown in place of the name in the header while the system is winterized
+	WINTER(TITLE): ["WINTER", "WINTER", "INVIERNO", "HIVER"],
+	/// Title of the fault screen
+	FAULT(LINE): ["Fault", "Fehler", "Fallo", "Defaut"],
+	/// Shown when the watchdog reset the system
+	WATCHDOG_RESET(LINE): ["Watchdog reset", "Watchdog-Reset", "Reinicio WDT", "Reinit. WDT"],
+	/// Supply voltage on the info screen, followed by the voltage
+	BATTERY(4): ["Batt", "Akku", "Bat", "Batt"],
+	/// Shown after the supply voltage while the battery is low
+	LOW(4): ["Low", "Tief", "Baja", "Bas"],
+	/// Chance of rain on the info screen, followed by the percentage
+	RAIN(5): ["Rain", "Regen", "Lluv.", "Pluie"],
+	/// Shown in the header after the days of vacation left
+	DAYS_LEFT(7): ["d left", "T Rest", "d rest.", "j rest."],
+	/// Shown after the chance of rain when the next watering is skipped
+	SKIP(4): ["Skip", "Aus", "Omit", "Saut"],
+	/// Shown after the chance of rain when the next watering is shortened, followed by the
+	/// percentage it runs for
+	RUN(4): ["Run", "Lauf", "Dur.", "Dur."],
+	/// Shown on the soil screen while watering is held off after rain, followed by the hours left
+	RAIN_DELAY(11): ["Rain delay", "Regenpause", "Pausa lluv", "Pause pluie"],
+	/// Shown on the soil screen when the moisture fell much faster than usual
+	DRYING_FAST(LINE): ["Drying too fast", "Trocknet schnell", "Seca muy rapido", "Seche trop vite"],
+	/// Shown on the soil screen when the moisture didn't rise after watering
+	NO_RISE(LINE): ["No rise on water", "Kein Anstieg", "Sin subida", "Pas de hausse"],
+	/// Shown when the heat mat has been cut out
+	MAT_CUT(10): ["Mat cut", "Matte aus", "Manta off", "Tapis off"],
+	/// Shown when the soil temperature can't be read
+	MAT_UNKNOWN(10): ["Mat ?", "Matte ?", "Manta ?", "Tapis ?"],
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_translation_fits() {
+		for (name, max, translations) in TABLE.iter() {
+			for translation in translations.iter() {
+				assert!(translation.len() <= *max, "{} is too long", name);
+				assert!(translation.is_ascii(), "{} isn't ASCII", name);
+			}
+		}
+	}
+}
@@ -0,0 +1,387 @@
+//! Framed request/response protocol for an attached co-processor, such as an ESP8266 or ESP32
+//! bridge
+//!
+//! Frames share the USART with the command-line interface and the log output. Every frame starts
+//! with [`SOF`], which never appears in a command or a log line, so anything received outside of a
+//! frame is passed on to the command-line interface:
+//!
+//! ```text
+//! SOF | LEN | TYPE | DATA... | CRC
+//! ```
+//!
+//! `LEN` is the amount of bytes in `TYPE` and `DATA`, and `CRC` is the CRC-8 checksum of `LEN`,
+//! `TYPE` and `DATA`. Multi-byte values are big-endian.
+//!
+//! Requests (bridge to system):
+//!
+//! - `0x01` Get status: Replied to with a status frame;
+//! - `0x02` Set config (`key: u8`, `value: u16`): Set a configuration value, where `key` is 1 for
+//!   the activation time, 2 for light, 3 for moisture, and 4 for flow. Replied to with an ack;
+//! - `0x03` Activate: Replied to with an ack;
+//! - `0x04` Suspend: Replied to with an ack;
+//! - `0x05` Get config: Replied to with a config frame;
+//! - `0x06` Connected: The bridge has connected to the broker. Replied to with an ack, followed by
+//!   the announcements described in [`topics`];
+//! - `0x07` Remote control (`enabled: u8`): Enable or disable remote control. Replied to with an
+//!   ack, but remote control can only be enabled from the menu;
+//! - `0x08` Forecast (`rain: u8`): Chance of rain in percent, from 0 to 100, which shortens or
+//!   skips the activations started by the sensors (see [`crate::forecast`]). Replied to with an
+//!   ack;
+//! - `0x10` Publish (`topic_len: u8`, `topic`, `payload`): Message published to one of the topics
+//!   in [`topics`]. Replied to with an ack.
+//!
+//! Responses and events (system to bridge):
+//!
+//! - `0x80` Ack (`status: u8`): See [`Ack`];
+//! - `0x81` Status (`state: u8`, `valve: u8`, `light: u16`, `moisture: u16`, `elapsed: u32`),
+//!   where `state` is 0 for waiting, 2 for activated, 4 for suspended, 5 for faulted and 6 for
+//!   winterized (1 and 3 are no longer sent), and `elapsed` is in seconds;
+//! - `0x85` Config (`time: u16`, `light: u16`, `moisture: u16`, `flow: u16`, `remote: u8`);
+//! - `0x90` Publish (`retain: u8`, `topic_len: u8`, `topic`, `payload`): Message to publish to the
+//!   broker, see [`topics`];
+//! - `0xC0` Event (`event: u8`): Sent without a request, see [`Event`].
+//!
+//! While remote control is disabled, requests which could open the valve are replied to with
+//! [`Ack::Denied`].
+
+use crate::{
+	activation::SystemState,
+	config::{Number, SystemConfig, UpdateSystemValue, ValueAction},
+	hal::Clock,
+	serial::write_bytes,
+	storage::{crc8, crc8_update},
+	telemetry::Record,
+	timer::{Duration, TIMER},
+};
+
+pub mod topics;
+
+use topics::Message;
+
+/// Start of frame marker
+pub const SOF: u8 = 0x7E;
+/// Largest amount of bytes in `TYPE` and `DATA` of a received frame
+const MAX_PAYLOAD: usize = 32;
+/// Longest gap between two bytes of the same frame
+///
+/// If the rest of a frame isn't received in time, it is dropped so that the link doesn't get stuck
+/// waiting for it.
+const FRAME_TIMEOUT: Duration = Duration::from_millis(100);
+
+const GET_STATUS: u8 = 0x01;
+const SET_CONFIG: u8 = 0x02;
+const ACTIVATE: u8 = 0x03;
+const SUSPEND: u8 = 0x04;
+const GET_CONFIG: u8 = 0x05;
+const CONNECTED: u8 = 0x06;
+const REMOTE_CONTROL: u8 = 0x07;
+const FORECAST: u8 = 0x08;
+const SUBSCRIBED: u8 = 0x10;
+const ACK: u8 = 0x80;
+const STATUS: u8 = 0x81;
+const CONFIG: u8 = 0x85;
+const PUBLISH: u8 = 0x90;
+const EVENT: u8 = 0xC0;
+
+/// Request received from the bridge
+pub enum Request {
+	/// Reply with the state of the system
+	GetStatus,
+	/// Set a configuration value
+	Set(UpdateSystemValue),
+	/// Activate the system, if it isn't already
+	Activate,
+	/// Suspend the system, if it isn't already
+	Suspend,
+	/// Reply with the configuration
+	GetConfig,
+	/// Announce the system to the broker
+	Connected,
+	/// Enable or disable remote control
+	RemoteControl(bool),
+	/// Chance of rain in percent
+	Forecast(u8),
+	/// Message published to one of the subscribed topics
+	Publish(Message),
+}
+
+/// Status of a request, sent back in an ack frame
+#[derive(Clone, Copy)]
+pub enum Ack {
+	/// The request was accepted
+	Ok = 0,
+	/// The frame's checksum didn't match
+	BadChecksum = 1,
+	/// The request type, or its data, isn't valid
+	Invalid = 2,
+	/// The request isn't allowed while remote control is disabled
+	Denied = 3,
+}
+
+/// Something which happened, sent to the bridge without a request
+#[derive(Clone, Copy)]
+pub enum Event {
+	/// The system started watering
+	ActivationStarted = 1,
+	/// The system stopped watering
+	ActivationStopped = 2,
+	/// A fault stopped the system (see [`crate::activation::FaultKind`])
+	Fault = 3,
+	/// The battery is low
+	LowBattery = 4,
+	/// The soil dried out much faster than usual, see [`crate::trend::Anomaly`]
+	FastDryOut = 5,
+	/// The moisture didn't rise after watering
+	NoRise = 6,
+}
+
+/// Byte received over the USART, once it has been through the link
+pub enum Input {
+	/// Byte which isn't part of a frame
+	Text(u8),
+	/// Complete, valid request
+	Request(Request),
+}
+
+/// Part of a frame which is expected next
+#[derive(Clone, Copy)]
+enum Receiving {
+	/// Waiting for [`SOF`]
+	Idle,
+	/// Waiting for `LEN`
+	Length,
+	/// Waiting for `TYPE` and `DATA`
+	Payload,
+	/// Waiting for `CRC`
+	Checksum,
+}
+
+/// Receives frames from, and sends frames to, the bridge
+pub struct Link {
+	/// Part of the frame which is expected next
+	receiving: Receiving,
+	/// `TYPE` and `DATA` received for the current frame
+	payload: [u8; MAX_PAYLOAD],
+	/// Amount of bytes in `TYPE` and `DATA` for the current frame
+	expected_len: usize,
+	/// Amount of bytes received for `TYPE` and `DATA`
+	len: usize,
+	/// [`TIMER`] clock value of when the last byte was received
+	last_received_ms: u32,
+}
+
+impl Link {
+	/// Create a new [`Link`]
+	pub fn new() -> Self {
+		Self {
+			receiving: Receiving::Idle,
+			payload: [0; MAX_PAYLOAD],
+			expected_len: 0,
+			len: 0,
+			last_received_ms: 0,
+		}
+	}
+
+	/// Pass a received byte through the link
+	///
+	/// Returns the byte if it isn't part of a frame, the request once a full frame has been
+	/// received, or `None` otherwise.
+	pub fn receive(&mut self, byte: u8) -> Option<Input> {
+		// If the rest of the frame took too long, drop it.
+		if !matches!(self.receiving, Receiving::Idle)
+			&& TIMER.has_elapsed(self.last_received_ms, FRAME_TIMEOUT)
+		{
+			self.receiving = Receiving::Idle;
+		}
+		self.last_received_ms = TIMER.millis();
+
+		match self.receiving {
+			// If the byte starts a frame, wait for its length. Otherwise, pass it on.
+			Receiving::Idle => {
+				if byte != SOF {
+					return Some(Input::Text(byte));
+				}
+				self.receiving = Receiving::Length;
+			}
+			// If the length is valid, wait for the payload. Otherwise, drop the frame.
+			Receiving::Length => {
+				let len = byte as usize;
+				if len == 0 || len > MAX_PAYLOAD {
+					self.receiving = Receiving::Idle;
+				} else {
+					self.expected_len = len;
+					self.len = 0;
+					self.receiving = Receiving::Payload;
+				}
+			}
+			// Collect the payload, and wait for the checksum once all of it has been received.
+			Receiving::Payload => {
+				self.payload[self.len] = byte;
+				self.len += 1;
+				if self.len == self.expected_len {
+					self.receiving = Receiving::Checksum;
+				}
+			}
+			// The frame is complete, so check it and parse the request.
+			Receiving::Checksum => {
+				self.receiving = Receiving::Idle;
+				if byte != self.checksum() {
+					self.send_ack(Ack::BadChecksum);
+					return None;
+				}
+
+				let request = self.parse();
+				if request.is_none() {
+					self.send_ack(Ack::Invalid);
+				}
+				return request.map(Input::Request);
+			}
+		}
+
+		None
+	}
+
+	/// Reply to a request with its status
+	pub fn send_ack(&self, ack: Ack) {
+		send_frame(&[ACK, ack as u8]);
+	}
+
+	/// Reply with the state of the system
+	pub fn send_status(&self, record: &Record) {
+		let state = match record.state {
+			SystemState::Waiting => 0,
+			SystemState::Activated => 2,
+			SystemState::Suspended => 4,
+			SystemState::Fault(_) => 5,
+			SystemState::Winterized => 6,
+		};
+		let [light_high, light_low] = record.light.to_be_bytes();
+		let [moisture_high, moisture_low] = record.moisture.to_be_bytes();
+		let [elapsed_0, elapsed_1, elapsed_2, elapsed_3] =
+			(record.elapsed.as_millis() / 1_000).to_be_bytes();
+
+		send_frame(&[
+			STATUS,
+			state,
+			record.valve_open as u8,
+			light_high,
+			light_low,
+			moisture_high,
+			moisture_low,
+			elapsed_0,
+			elapsed_1,
+			elapsed_2,
+			elapsed_3,
+		]);
+	}
+
+	/// Reply with the configuration
+	pub fn send_config(&self, config: &SystemConfig) {
+		let [time_high, time_low] = config.activate_mins.to_be_bytes();
+		let [light_high, light_low] = config.min_light.to_be_bytes();
+		let [moisture_high, moisture_low] = config.min_moisture.to_be_bytes();
+		let [flow_high, flow_low] = config.flow_percent.to_be_bytes();
+
+		send_frame(&[
+			CONFIG,
+			time_high,
+			time_low,
+			light_high,
+			light_low,
+			moisture_high,
+			moisture_low,
+			flow_high,
+			flow_low,
+			config.remote_control as u8,
+		]);
+	}
+
+	/// Let the bridge know that something happened
+	pub fn send_event(&self, event: Event) {
+		send_frame(&[EVENT, event as u8]);
+	}
+
+	/// Ask the bridge to publish a message to the broker
+	///
+	/// The topic and payload are each given in parts, which are sent one after the other. The
+	/// message is dropped if it doesn't fit in a frame.
+	pub fn publish(&self, topic: &[&str], payload: &[&str], retain: bool) {
+		let topic_len: usize = topic.iter().map(|part| part.len()).sum();
+		let payload_len: usize = payload.iter().map(|part| part.len()).sum();
+		let len = 3 + topic_len + payload_len;
+		if topic_len > u8::MAX as usize || len > u8::MAX as usize {
+			return;
+		}
+
+		let mut frame = FrameWriter::start(len as u8);
+		frame.write(&[PUBLISH, retain as u8, topic_len as u8]);
+		for part in topic.iter().chain(payload) {
+			frame.write(part.as_bytes());
+		}
+		frame.finish();
+	}
+
+	/// Checksum of the current frame
+	fn checksum(&self) -> u8 {
+		crc8_update(crc8(&[self.len as u8]), &self.payload[..self.len])
+	}
+
+	/// Parse the current frame into a request
+	fn parse(&self) -> Option<Request> {
+		match &self.payload[..self.len] {
+			[GET_STATUS] => Some(Request::GetStatus),
+			[SET_CONFIG, key, value_high, value_low] => {
+				let action = ValueAction::Set(u16::from_be_bytes([*value_high, *value_low]));
+				match *key {
+					1 => Some(UpdateSystemValue::Number(Number::Time, action)),
+					2 => Some(UpdateSystemValue::Number(Number::Light, action)),
+					3 => Some(UpdateSystemValue::Number(Number::Moisture, action)),
+					4 => Some(UpdateSystemValue::Number(Number::Flow, action)),
+					_ => None,
+				}
+				.map(Request::Set)
+			}
+			[ACTIVATE] => Some(Request::Activate),
+			[SUSPEND] => Some(Request::Suspend),
+			[GET_CONFIG] => Some(Request::GetConfig),
+			[CONNECTED] => Some(Request::Connected),
+			[REMOTE_CONTROL, enabled] => Some(Request::RemoteControl(*enabled != 0)),
+			[FORECAST, rain] if *rain <= 100 => Some(Request::Forecast(*rain)),
+			[SUBSCRIBED, data @ ..] => topics::parse(data).map(Request::Publish),
+			_ => None,
+		}
+	}
+}
+
+/// Send a frame with `payload` as its `TYPE` and `DATA`
+fn send_frame(payload: &[u8]) {
+	let mut frame = FrameWriter::start(payload.len() as u8);
+	frame.write(payload);
+	frame.finish();
+}
+
+/// Frame which is being sent, working out its checksum as it is written
+struct FrameWriter {
+	checksum: u8,
+}
+
+impl FrameWriter {
+	/// Send the start of a frame with `len` bytes in `TYPE` and `DATA`
+	fn start(len: u8) -> Self {
+		write_bytes(&[SOF, len]);
+		Self {
+			checksum: crc8(&[len]),
+		}
+	}
+
+	/// Send part of `TYPE` and `DATA`
+	fn write(&mut self, bytes: &[u8]) {
+		self.checksum = crc8_update(self.checksum, bytes);
+		write_bytes(bytes);
+	}
+
+	/// Send the checksum, which ends the frame
+	fn finish(self) {
+		write_bytes(&[self.checksum]);
+	}
+}
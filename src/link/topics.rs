@@ -0,0 +1,253 @@
+//! Publish/subscribe topics, so that the bridge can forward messages straight to an MQTT broker
+//!
+//! The system publishes its state to:
+//!
+//! - `garden/moisture`, `garden/light`: Sensor readings;
+//! - `garden/valve`: `ON` while watering, otherwise `OFF`;
+//! - `garden/state`: Activation state;
+//! - `garden/name` (retained): Name of the zone (see [`crate::zonename`]);
+//! - `garden/fault` (retained): `none`, `sensor`, `valve`, `watchdog`, `battery` or `safe-mode`;
+//! - `garden/status` (retained): `online`. The bridge should set `offline` as its last will on this
+//!   topic;
+//! - `homeassistant/<component>/garden/<object>/config` (retained): Home Assistant discovery.
+//!
+//! And subscribes to:
+//!
+//! - `garden/valve/set`: `ON` to start watering, or `OFF` to stop;
+//! - `garden/config/<time|light|moisture|flow>`: Set a configuration value;
+//! - `garden/config/remote`: `OFF` to disable remote control. It can only be enabled from the menu.
+//!
+//! Nothing is published until the bridge has connected, so that frames aren't mixed in with the
+//! log output when there isn't a bridge.
+
+use core::str;
+
+use super::Link;
+use crate::{
+	activation::SystemState,
+	config::{Number, UpdateSystemValue, ValueAction},
+	telemetry::Record,
+};
+
+const MOISTURE_TOPIC: &str = "garden/moisture";
+const LIGHT_TOPIC: &str = "garden/light";
+const VALVE_TOPIC: &str = "garden/valve";
+const STATE_TOPIC: &str = "garden/state";
+const NAME_TOPIC: &str = "garden/name";
+const FAULT_TOPIC: &str = "garden/fault";
+const AVAILABILITY_TOPIC: &str = "garden/status";
+const VALVE_SET_TOPIC: &str = "garden/valve/set";
+const CONFIG_TIME_TOPIC: &str = "garden/config/time";
+const CONFIG_LIGHT_TOPIC: &str = "garden/config/light";
+const CONFIG_MOISTURE_TOPIC: &str = "garden/config/moisture";
+const CONFIG_FLOW_TOPIC: &str = "garden/config/flow";
+const CONFIG_REMOTE_TOPIC: &str = "garden/config/remote";
+
+/// Message published by the bridge to one of the subscribed topics
+pub enum Message {
+	/// Start or stop watering
+	Valve(bool),
+	/// Set a configuration value
+	Set(UpdateSystemValue),
+	/// Enable or disable remote control
+	RemoteControl(bool),
+}
+
+/// Reason the system can't water, announced on `garden/fault`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+	/// Nothing is wrong
+	None,
+	/// The sensor readings stopped the system
+	Sensor,
+	/// The valve fail-safe stopped the system
+	Valve,
+	/// A watchdog reset stopped the system
+	Watchdog,
+	/// The battery is low
+	Battery,
+	/// The system started up in safe mode
+	SafeMode,
+}
+
+impl Fault {
+	fn as_str(self) -> &'static str {
+		match self {
+			Self::None => "none",
+			Self::Sensor => "sensor",
+			Self::Valve => "valve",
+			Self::Watchdog => "watchdog",
+			Self::Battery => "battery",
+			Self::SafeMode => "safe-mode",
+		}
+	}
+}
+
+/// Publishes the state of the system to topics
+pub struct Topics {
+	/// Whether the bridge has connected, and messages can be published
+	connected: bool,
+	/// Fault which was last published
+	fault: Fault,
+}
+
+impl Topics {
+	/// Create a new [`Topics`]
+	pub fn new() -> Self {
+		Self {
+			connected: false,
+			fault: Fault::None,
+		}
+	}
+
+	/// Announce the system once the bridge has connected to the broker
+	///
+	/// Publishes the discovery configuration, availability, fault, zone name and state of the
+	/// system, since the broker may not have seen any of them before.
+	pub fn announce(&mut self, link: &Link, record: &Record, fault: Fault, name: &str) {
+		self.connected = true;
+
+		discover(link, "sensor", "moisture", "");
+		discover(link, "sensor", "light", "");
+		discover(link, "switch", "valve", ",\"cmd_t\":\"~/valve/set\"");
+		discover(
+			link,
+			"binary_sensor",
+			"fault",
+			",\"dev_cla\":\"problem\",\"val_tpl\":\"{{'OFF' if value=='none' else 'ON'}}\"",
+		);
+
+		link.publish(&[AVAILABILITY_TOPIC], &["online"], true);
+		self.fault = fault;
+		link.publish(&[FAULT_TOPIC], &[fault.as_str()], true);
+		self.publish_name(link, name);
+		self.publish_state(link, record);
+	}
+
+	/// Whether the bridge has connected
+	pub fn is_connected(&self) -> bool {
+		self.connected
+	}
+
+	/// Publish the sensor readings, valve and activation state
+	pub fn publish_state(&self, link: &Link, record: &Record) {
+		if !self.connected {
+			return;
+		}
+
+		let mut buf = [0; 5];
+		link.publish(
+			&[MOISTURE_TOPIC],
+			&[digits(record.moisture, &mut buf)],
+			false,
+		);
+		link.publish(&[LIGHT_TOPIC], &[digits(record.light, &mut buf)], false);
+		self.publish_valve(link, record.valve_open);
+
+		let state = match record.state {
+			SystemState::Waiting => "waiting",
+			SystemState::Activated => "activated",
+			SystemState::Suspended => "suspended",
+			SystemState::Fault(_) => "fault",
+			SystemState::Winterized => "winterized",
+		};
+		link.publish(&[STATE_TOPIC], &[state], false);
+	}
+
+	/// Publish the name of the zone
+	///
+	/// The name is retained, so that anything subscribing later still sees it.
+	pub fn publish_name(&self, link: &Link, name: &str) {
+		if self.connected {
+			link.publish(&[NAME_TOPIC], &[name], true);
+		}
+	}
+
+	/// Publish whether the system is watering
+	pub fn publish_valve(&self, link: &Link, open: bool) {
+		if self.connected {
+			link.publish(&[VALVE_TOPIC], &[if open { "ON" } else { "OFF" }], false);
+		}
+	}
+
+	/// Publish the fault if it has changed since it was last published
+	///
+	/// The fault is retained, so that anything subscribing later still sees it.
+	pub fn update_fault(&mut self, link: &Link, fault: Fault) {
+		if self.connected && fault != self.fault {
+			self.fault = fault;
+			link.publish(&[FAULT_TOPIC], &[fault.as_str()], true);
+		}
+	}
+}
+
+/// Parse a message published by the bridge, from its topic length, topic and payload
+pub fn parse(data: &[u8]) -> Option<Message> {
+	let (&topic_len, rest) = data.split_first()?;
+	if rest.len() < topic_len as usize {
+		return None;
+	}
+
+	let (topic, payload) = rest.split_at(topic_len as usize);
+	let topic = str::from_utf8(topic).ok()?;
+	let payload = str::from_utf8(payload).ok()?.trim();
+	let set = |number| {
+		let value = payload.parse::<u16>().ok()?;
+		Some(Message::Set(UpdateSystemValue::Number(
+			number,
+			ValueAction::Set(value),
+		)))
+	};
+	let switch = || match payload {
+		"ON" => Some(true),
+		"OFF" => Some(false),
+		_ => None,
+	};
+	match topic {
+		VALVE_SET_TOPIC => switch().map(Message::Valve),
+		CONFIG_TIME_TOPIC => set(Number::Time),
+		CONFIG_LIGHT_TOPIC => set(Number::Light),
+		CONFIG_MOISTURE_TOPIC => set(Number::Moisture),
+		CONFIG_FLOW_TOPIC => set(Number::Flow),
+		CONFIG_REMOTE_TOPIC => switch().map(Message::RemoteControl),
+		_ => None,
+	}
+}
+
+/// Publish the Home Assistant discovery configuration of an entity
+///
+/// The configuration is built from parts, rather than being stored whole for each entity, since
+/// string literals take up RAM.
+fn discover(link: &Link, component: &str, object: &str, extra: &str) {
+	link.publish(
+		&["homeassistant/", component, "/garden/", object, "/config"],
+		&[
+			"{\"~\":\"garden\",\"name\":\"Garden ",
+			object,
+			"\",\"stat_t\":\"~/",
+			object,
+			"\",\"uniq_id\":\"garden_",
+			object,
+			"\",\"avty_t\":\"~/status\"",
+			extra,
+			"}",
+		],
+		true,
+	);
+}
+
+/// Decimal digits of `value`
+fn digits(mut value: u16, buf: &mut [u8; 5]) -> &str {
+	let mut start = buf.len();
+	loop {
+		start -= 1;
+		buf[start] = b'0' + (value % 10) as u8;
+		value /= 10;
+		if value == 0 {
+			break;
+		}
+	}
+
+	// Only ASCII digits are written to the buffer.
+	str::from_utf8(&buf[start..]).unwrap_or("")
+}
@@ -0,0 +1,77 @@
+//! Keypad lock
+//!
+//! While the keypad is locked, every button press is ignored except for the unlock sequence, so
+//! that curious hands can't change the configuration or start watering.
+
+use crate::control_pad::{ButtonEvent, ButtonStage, ButtonType};
+
+/// Buttons which must be pressed (and released) in order to unlock the keypad
+const UNLOCK_SEQUENCE: [ButtonType; 4] = [
+	ButtonType::Left,
+	ButtonType::Right,
+	ButtonType::Left,
+	ButtonType::Select,
+];
+
+/// Keeps track of whether the keypad is locked, and how much of the unlock sequence has been
+/// entered
+pub struct KeypadLock {
+	/// Whether the keypad is locked
+	locked: bool,
+	/// Amount of buttons in [`UNLOCK_SEQUENCE`] which have been pressed in order
+	progress: usize,
+}
+
+impl KeypadLock {
+	/// Create a new, unlocked, [`KeypadLock`]
+	pub fn new() -> Self {
+		Self {
+			locked: false,
+			progress: 0,
+		}
+	}
+
+	/// Whether the keypad is locked
+	pub fn is_locked(&self) -> bool {
+		self.locked
+	}
+
+	/// Handle a button event
+	///
+	/// Holding down the Left button while a menu item isn't being edited locks the keypad.
+	///
+	/// Returns `true` if the event was used by the lock (or ignored because the keypad is locked),
+	/// and shouldn't be handled by anything else.
+	pub fn on_press(&mut self, event: &ButtonEvent, editing: bool) -> bool {
+		if !self.locked {
+			let should_lock = matches!(
+				(&event.stage, &event.button),
+				(ButtonStage::LongPress, ButtonType::Left)
+			) && !editing;
+			if should_lock {
+				self.locked = true;
+				self.progress = 0;
+			}
+			return should_lock;
+		}
+
+		// Only released buttons count towards the unlock sequence.
+		if let ButtonStage::Release = event.stage {
+			if event.button == UNLOCK_SEQUENCE[self.progress] {
+				self.progress += 1;
+			} else if event.button == UNLOCK_SEQUENCE[0] {
+				// Start the sequence over, from the first button.
+				self.progress = 1;
+			} else {
+				self.progress = 0;
+			}
+
+			if self.progress == UNLOCK_SEQUENCE.len() {
+				self.locked = false;
+				self.progress = 0;
+			}
+		}
+
+		true
+	}
+}
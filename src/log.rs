@@ -0,0 +1,263 @@
+//! Event log
+//!
+//! The most recent events are kept in RAM with the time since the system started, so that what
+//! the system did overnight can be checked afterwards. The log can be viewed page by page on the
+//! display, or dumped over the USART with `log dump`. Events are also kept in the EEPROM (or the
+//! external chip), see [`history`], along with the sensor readings on an external chip, see
+//! `samples`. Changes to the configuration are also kept in more detail, see [`changes`].
+
+use crate::{
+	display::{BODY_ROW_COUNT, BODY_START_ROW},
+	hal::{Clock, DisplayBus, CLOCK},
+	lang,
+	queue::Queue,
+};
+
+pub mod changes;
+pub mod history;
+#[cfg(feature = "external-storage")]
+pub mod samples;
+
+/// Amount of events which are kept, before the oldest are dropped
+const LOG_SIZE: usize = 24;
+/// Amount of events shown on each page of the log screen, below its title
+const PAGE_SIZE: u8 = BODY_ROW_COUNT - 1;
+
+/// Something which happened to the system
+#[derive(Clone, Copy)]
+pub enum LogEvent {
+	/// The system started up
+	Startup = 0,
+	/// The system was reset by the watchdog
+	WatchdogReset = 1,
+	/// The system started watering
+	ActivationStarted = 2,
+	/// The system stopped watering
+	ActivationStopped = 3,
+	/// The system was suspended
+	Suspended = 4,
+	/// The suspension ended, or was cancelled
+	Resumed = 5,
+	/// The valve fail-safe tripped
+	ValveFault = 6,
+	/// The battery is low
+	LowBattery = 7,
+	/// A configuration value was changed
+	ConfigChanged = 8,
+	/// The system started up in safe mode
+	SafeMode = 9,
+	/// The sensor readings stopped the system
+	SensorFault = 10,
+	/// The fault which stopped the system was acknowledged
+	FaultCleared = 11,
+	/// The system was reset by the power being switched on
+	PowerOnReset = 12,
+	/// The system was reset by the supply voltage dropping too low
+	BrownOutReset = 13,
+	/// The system was reset by the reset pin, such as by the reset button
+	ExternalReset = 14,
+	/// The soil dried out much faster than usual
+	FastDryOut = 15,
+	/// The moisture didn't rise after watering
+	NoRise = 16,
+	/// The valve has been opened enough times to be due a service
+	ServiceDue = 17,
+	/// The valve was serviced
+	Serviced = 18,
+	/// The system was winterized
+	Winterized = 19,
+	/// The system was re-enabled after being winterized
+	Reenabled = 20,
+	/// It rained, so watering is held off for a while
+	RainDelay = 21,
+}
+
+impl LogEvent {
+	/// Code of the event, for storing it in the EEPROM
+	fn code(self) -> u8 {
+		self as u8
+	}
+
+	/// Event with the given code, if it is valid
+	fn from_code(code: u8) -> Option<Self> {
+		match code {
+			0 => Some(Self::Startup),
+			1 => Some(Self::WatchdogReset),
+			2 => Some(Self::ActivationStarted),
+			3 => Some(Self::ActivationStopped),
+			4 => Some(Self::Suspended),
+			5 => Some(Self::Resumed),
+			6 => Some(Self::ValveFault),
+			7 => Some(Self::LowBattery),
+			8 => Some(Self::ConfigChanged),
+			9 => Some(Self::SafeMode),
+			10 => Some(Self::SensorFault),
+			11 => Some(Self::FaultCleared),
+			12 => Some(Self::PowerOnReset),
+			13 => Some(Self::BrownOutReset),
+			14 => Some(Self::ExternalReset),
+			15 => Some(Self::FastDryOut),
+			16 => Some(Self::NoRise),
+			17 => Some(Self::ServiceDue),
+			18 => Some(Self::Serviced),
+			19 => Some(Self::Winterized),
+			20 => Some(Self::Reenabled),
+			21 => Some(Self::RainDelay),
+			_ => None,
+		}
+	}
+
+	/// Short description of the event, which fits on a row of the display after its time
+	fn label(self) -> &'static str {
+		match self {
+			Self::Startup => "Startup",
+			Self::WatchdogReset => "WDT reset",
+			Self::ActivationStarted => "Water on",
+			Self::ActivationStopped => "Water off",
+			Self::Suspended => "Suspended",
+			Self::Resumed => "Resumed",
+			Self::ValveFault => "Fail-safe",
+			Self::LowBattery => "Low batt",
+			Self::ConfigChanged => "Config",
+			Self::SafeMode => "Safe mode",
+			Self::SensorFault => "Sensor",
+			Self::FaultCleared => "Fault ack",
+			Self::PowerOnReset => "Power-on",
+			Self::BrownOutReset => "Brown-out",
+			Self::ExternalReset => "Ext reset",
+			Self::FastDryOut => "Fast dry",
+			Self::NoRise => "No rise",
+			Self::ServiceDue => "Service",
+			Self::Serviced => "Serviced",
+			Self::Winterized => "Winter",
+			Self::Reenabled => "Re-enable",
+			Self::RainDelay => "Rain",
+		}
+	}
+}
+
+/// Event with the time it happened at
+#[derive(Clone, Copy)]
+struct Entry {
+	/// Seconds since the system started
+	secs: u32,
+	event: LogEvent,
+}
+
+/// Ring buffer of the most recent events
+pub struct EventLog {
+	entries: Queue<Entry, LOG_SIZE>,
+	/// Page being shown on the log screen, where the first page has the newest events
+	page: u8,
+}
+
+impl EventLog {
+	/// Create a new, empty [`EventLog`]
+	pub fn new() -> Self {
+		Self {
+			entries: Queue::new(),
+			page: 0,
+		}
+	}
+
+	/// Add an event to the log, dropping the oldest event if the log is full
+	pub fn record(&mut self, event: LogEvent) {
+		let entry = Entry {
+			secs: CLOCK.millis() / 1_000,
+			event,
+		};
+		if !self.entries.push(entry) {
+			self.entries.pop();
+			self.entries.push(entry);
+		}
+	}
+
+	/// Print every event over the USART, oldest first
+	pub fn dump(&self) {
+		for idx in 0..self.len() {
+			if let Some(entry) = self.entries.get(idx) {
+				log!("{} {}", entry.secs, entry.event.label());
+			}
+		}
+		log!("ok");
+	}
+
+	/// Show the first page of the log screen
+	pub fn render(&mut self, display: &mut impl DisplayBus) {
+		self.page = 0;
+		self.render_page(display);
+	}
+
+	/// Show the next (older) page, if there is one
+	pub fn next_page(&mut self, display: &mut impl DisplayBus) {
+		if self.page + 1 < self.page_count() {
+			self.page += 1;
+			self.render_page(display);
+		}
+	}
+
+	/// Show the previous (newer) page, if there is one
+	pub fn previous_page(&mut self, display: &mut impl DisplayBus) {
+		if self.page > 0 {
+			self.page -= 1;
+			self.render_page(display);
+		}
+	}
+
+	/// Render the current page of the log screen
+	pub fn render_page(&self, display: &mut impl DisplayBus) {
+		display.clear_body();
+		display.set_position(0, BODY_START_ROW);
+		let _ = ufmt::uwrite!(
+			display,
+			"{} {}/{}",
+			lang::LOG,
+			self.page + 1,
+			self.page_count()
+		);
+
+		let len = self.len();
+		if len == 0 {
+			display.set_position(0, BODY_START_ROW + 1);
+			let _ = ufmt::uwrite!(display, "{}", lang::NO_EVENTS);
+			return;
+		}
+
+		// Newest events are shown first.
+		let first = self.page as usize * PAGE_SIZE as usize;
+		for row in 0..PAGE_SIZE {
+			let idx = first + row as usize;
+			if idx >= len {
+				break;
+			}
+
+			if let Some(entry) = self.entries.get(len - 1 - idx) {
+				let mins = entry.secs / 60;
+				display.set_position(0, BODY_START_ROW + 1 + row);
+				let _ = ufmt::uwrite!(
+					display,
+					"{}:{}{} {}",
+					mins / 60,
+					mins % 60 / 10,
+					mins % 10,
+					entry.event.label()
+				);
+			}
+		}
+	}
+
+	/// Amount of events in the log
+	fn len(&self) -> usize {
+		LOG_SIZE - self.entries.remaining()
+	}
+
+	/// Amount of pages on the log screen
+	fn page_count(&self) -> u8 {
+		let pages = (self.len() + PAGE_SIZE as usize - 1) / PAGE_SIZE as usize;
+		if pages == 0 {
+			1
+		} else {
+			pages as u8
+		}
+	}
+}
@@ -0,0 +1,201 @@
+//! Audit trail of configuration changes
+//!
+//! Each change to a setting is kept with the time it was made, the value before and after it, and
+//! where the change came from, so that a changed threshold can be traced back to the buttons, a
+//! serial command or the co-processor link. `log changes [n]` prints the last changes. A value
+//! edited in the menu is kept as a single change once the editing is finished, rather than one for
+//! each press. Changes to the activation state aren't kept, as they are in the event log already.
+
+use crate::{
+	config::{SystemValue, UpdateSystemValue, VALUE_LENGTH},
+	fmt::{Buffer, Flag},
+	hal::{Clock, CLOCK},
+	lang,
+	presets::PRESETS,
+	queue::Queue,
+};
+
+/// Amount of changes which are kept, before the oldest are dropped
+const CHANGE_LOG_SIZE: usize = 8;
+
+/// Where a change came from
+#[derive(Clone, Copy, PartialEq)]
+pub enum Source {
+	/// The buttons or the rotary encoder
+	Buttons,
+	/// A command received over the USART
+	Serial,
+	/// A request received from the co-processor link
+	Link,
+	/// The system itself, such as counting down the vacation days
+	System,
+}
+
+impl Source {
+	/// Name of the source, as printed by `log changes`
+	fn label(self) -> &'static str {
+		match self {
+			Self::Buttons => "buttons",
+			Self::Serial => "serial",
+			Self::Link => "link",
+			Self::System => "system",
+		}
+	}
+}
+
+/// Change to a setting, with the time it was made at
+#[derive(Clone, Copy)]
+struct Change {
+	/// Seconds since the system started
+	secs: u32,
+	source: Source,
+	update: UpdateSystemValue,
+	/// Value before the change, if the update changes a single setting
+	old: Option<SystemValue>,
+	/// Value after the change, if the update changes a single setting
+	new: Option<SystemValue>,
+}
+
+impl Change {
+	/// Whether the change is to the same setting as `other`
+	fn is_same_setting(&self, other: &Self) -> bool {
+		match (self.update, other.update) {
+			(UpdateSystemValue::Number(number, _), UpdateSystemValue::Number(other_number, _)) => {
+				number == other_number
+			}
+			(update, other_update) => update == other_update,
+		}
+	}
+}
+
+/// Ring buffer of the most recent configuration changes
+pub struct ChangeLog {
+	changes: Queue<Change, CHANGE_LOG_SIZE>,
+	/// Change to the value being edited in the menu, which is kept once the editing is finished
+	editing: Option<Change>,
+}
+
+impl ChangeLog {
+	/// Create a new, empty [`ChangeLog`]
+	pub fn new() -> Self {
+		Self {
+			changes: Queue::new(),
+			editing: None,
+		}
+	}
+
+	/// Keep the change made by `update` from `old` to `new`, unless it didn't change a setting
+	///
+	/// While a value is `editing` in the menu, the change is held until [`ChangeLog::finish_editing`]
+	/// and later presses only change its new value.
+	pub fn record(
+		&mut self,
+		update: UpdateSystemValue,
+		old: Option<SystemValue>,
+		new: Option<SystemValue>,
+		source: Source,
+		editing: bool,
+	) {
+		match update {
+			UpdateSystemValue::Activate
+			| UpdateSystemValue::Suspend
+			| UpdateSystemValue::Winterize
+			| UpdateSystemValue::ActivationState
+			| UpdateSystemValue::Fault(_)
+			| UpdateSystemValue::Acknowledge
+			| UpdateSystemValue::Serviced => return,
+			_ => {}
+		}
+
+		let change = Change {
+			secs: CLOCK.millis() / 1_000,
+			source,
+			update,
+			old,
+			new,
+		};
+		if !editing {
+			self.finish_editing();
+			self.push(change);
+			return;
+		}
+
+		// If the same value is still being edited, only its new value changes.
+		if let Some(edit) = &mut self.editing {
+			if edit.is_same_setting(&change) {
+				edit.new = change.new;
+				return;
+			}
+		}
+		self.finish_editing();
+		self.editing = Some(change);
+	}
+
+	/// Keep the change to the value which was being edited, if there is one
+	pub fn finish_editing(&mut self) {
+		if let Some(change) = self.editing.take() {
+			self.push(change);
+		}
+	}
+
+	/// Print the last `count` changes over the USART, oldest first
+	pub fn dump(&self, count: usize) {
+		let len = CHANGE_LOG_SIZE - self.changes.remaining();
+		for idx in len.saturating_sub(count)..len {
+			let change = match self.changes.get(idx) {
+				Some(change) => change,
+				None => continue,
+			};
+
+			match (change.old, change.new, change.update) {
+				(Some(old), Some(new), _) => {
+					let (label, old) = value(&old);
+					log!(
+						"{} {} {}: {} -> {}",
+						change.secs,
+						change.source.label(),
+						label,
+						old.as_str(),
+						value(&new).1.as_str()
+					);
+				}
+				(_, _, UpdateSystemValue::Preset(idx)) => log!(
+					"{} {} {}: {}",
+					change.secs,
+					change.source.label(),
+					lang::PRESETS,
+					PRESETS.get(idx as usize).map_or("?", |preset| preset.name)
+				),
+				_ => log!("{} {} {}", change.secs, change.source.label(), lang::RESET),
+			}
+		}
+		log!("ok");
+	}
+
+	/// Add a change, dropping the oldest change if the log is full
+	///
+	/// Changes which left the setting as it was, such as incrementing a value which is already at
+	/// its maximum, are left out.
+	fn push(&mut self, change: Change) {
+		if change.old.is_some() && change.old == change.new {
+			return;
+		}
+		if !self.changes.push(change) {
+			self.changes.pop();
+			self.changes.push(change);
+		}
+	}
+}
+
+/// Label of a setting, and its value as printed by `log changes`
+///
+/// The Bluetooth pairing code is left out, so that it isn't printed to whoever is connected.
+fn value(value: &SystemValue) -> (&'static str, Buffer<VALUE_LENGTH>) {
+	match value {
+		SystemValue::BtControl(code) => (
+			lang::BT_CONTROL,
+			Buffer::from_display(&Flag(code.is_some())),
+		),
+		_ => value.label_and_value(),
+	}
+}
@@ -0,0 +1,117 @@
+//! Event history in non-volatile storage
+//!
+//! Every event in the log is also appended to a circular region of the internal EEPROM (or of the
+//! external chip, see [`crate::storage`]), so that the most recent events survive a power cycle.
+//! Records hold the total uptime from [`crate::stats::Stats`] at the time of the event.
+
+use super::LogEvent;
+#[cfg(not(feature = "external-storage"))]
+use crate::eeprom::HISTORY_ADDRESS;
+use crate::{
+	display::{BODY_ROW_COUNT, BODY_START_ROW},
+	hal::{DisplayBus, Storage},
+	lang,
+	storage::{Region, Ring},
+	watchdog::Watchdog,
+};
+
+/// Records in the internal EEPROM, 8 bytes each
+#[cfg(not(feature = "external-storage"))]
+const HISTORY_REGION: Region = Region {
+	address: HISTORY_ADDRESS,
+	slots: 100,
+};
+/// Records in the first 4KiB of the external chip, 8 bytes each
+#[cfg(feature = "external-storage")]
+const HISTORY_REGION: Region = Region {
+	address: 0,
+	slots: 512,
+};
+
+/// Record read back from storage
+struct Record {
+	/// Sequence number, which goes up by one for each record
+	seq: u16,
+	/// Total uptime in seconds when the event happened
+	secs: u32,
+	event: LogEvent,
+}
+
+/// Events kept in storage
+pub struct History {
+	ring: Ring,
+}
+
+impl History {
+	/// Size in bytes of a record, not including its sequence number or checksum
+	const SIZE: usize = 5;
+
+	/// Find where the next record should be written
+	pub fn load(storage: &mut impl Storage) -> Self {
+		Self {
+			ring: Ring::load(storage, HISTORY_REGION, Self::SIZE),
+		}
+	}
+
+	/// Append an event to the history, overwriting the oldest record if the history is full
+	pub fn append(&mut self, event: LogEvent, secs: u32, storage: &mut impl Storage) {
+		let mut buf = [0; Self::SIZE];
+		buf[0..4].copy_from_slice(&secs.to_le_bytes());
+		buf[4] = event.code();
+		self.ring.append(storage, &buf);
+	}
+
+	/// Print every record over the USART, oldest first
+	///
+	/// The watchdog is fed after each record, as printing them all can take longer than its
+	/// timeout.
+	pub fn export(&self, storage: &mut impl Storage, watchdog: &Watchdog) {
+		for age in (0..self.ring.slots()).rev() {
+			watchdog.feed();
+			if let Some(record) = self.nth_newest(storage, age) {
+				log!("{} {} {}", record.seq, record.secs, record.event.label());
+			}
+		}
+		log!("ok");
+	}
+
+	/// Render the newest records on the history screen, with how long ago each one happened
+	pub fn render(
+		&self,
+		storage: &mut impl Storage,
+		uptime_secs: u32,
+		display: &mut impl DisplayBus,
+	) {
+		display.clear_body();
+		display.set_position(0, BODY_START_ROW);
+		let _ = ufmt::uwrite!(display, "{}", lang::HISTORY);
+
+		for age in 0..(BODY_ROW_COUNT - 1) {
+			let record = match self.nth_newest(storage, age as u16) {
+				Some(record) => record,
+				None => break,
+			};
+
+			let mins = uptime_secs.saturating_sub(record.secs) / 60;
+			display.set_position(0, BODY_START_ROW + 1 + age);
+			let _ = if mins < 60 {
+				ufmt::uwrite!(display, "{}m {}", mins, record.event.label())
+			} else if mins < 24 * 60 {
+				ufmt::uwrite!(display, "{}h {}", mins / 60, record.event.label())
+			} else {
+				ufmt::uwrite!(display, "{}d {}", mins / (24 * 60), record.event.label())
+			};
+		}
+	}
+
+	/// Record `age` places before the newest, if it exists and is part of the current history
+	fn nth_newest(&self, storage: &mut impl Storage, age: u16) -> Option<Record> {
+		let mut buf = [0; Self::SIZE];
+		let seq = self.ring.nth_newest(storage, age, &mut buf)?;
+		Some(Record {
+			seq,
+			secs: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+			event: LogEvent::from_code(buf[4])?,
+		})
+	}
+}
@@ -0,0 +1,85 @@
+//! Sensor samples in external storage
+//!
+//! With the `external-storage` feature, the light and moisture readings are appended to the
+//! external chip (see [`crate::storage`]) every [`SAMPLE_INTERVAL_SECS`], along with the total
+//! uptime from [`crate::stats::Stats`]. The rest of the chip after the event history holds 2,606
+//! samples, which is around 43 hours of readings. `log samples` prints them as a sensor trace, so
+//! that they can be replayed (see [`crate::trace`]).
+
+use crate::{
+	hal::Storage,
+	storage::{Region, Ring},
+	watchdog::Watchdog,
+};
+
+/// Seconds of uptime between samples
+const SAMPLE_INTERVAL_SECS: u32 = 60;
+/// Records after the event history, 11 bytes each, up to the end of a 32KiB chip
+const SAMPLES_REGION: Region = Region {
+	address: 4_096,
+	slots: 2_606,
+};
+
+/// Light and moisture readings kept in storage
+pub struct SampleLog {
+	ring: Ring,
+	/// Total uptime in seconds of the newest sample, if there is one
+	last_secs: Option<u32>,
+}
+
+impl SampleLog {
+	/// Size in bytes of a record, not including its sequence number or checksum
+	const SIZE: usize = 8;
+
+	/// Find where the next sample should be written, and when the newest was taken
+	pub fn load(storage: &mut impl Storage) -> Self {
+		let ring = Ring::load(storage, SAMPLES_REGION, Self::SIZE);
+		let mut buf = [0; Self::SIZE];
+		let last_secs = ring
+			.nth_newest(storage, 0, &mut buf)
+			.map(|_| u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]));
+		Self { ring, last_secs }
+	}
+
+	/// Append a sample of the readings from `read_sensors`, if [`SAMPLE_INTERVAL_SECS`] have
+	/// passed since the last one
+	pub fn update(
+		&mut self,
+		secs: u32,
+		read_sensors: impl FnOnce() -> (u16, u16),
+		storage: &mut impl Storage,
+	) {
+		if let Some(last_secs) = self.last_secs {
+			if secs.wrapping_sub(last_secs) < SAMPLE_INTERVAL_SECS {
+				return;
+			}
+		}
+
+		let (light, moisture) = read_sensors();
+		let mut buf = [0; Self::SIZE];
+		buf[0..4].copy_from_slice(&secs.to_le_bytes());
+		buf[4..6].copy_from_slice(&light.to_le_bytes());
+		buf[6..8].copy_from_slice(&moisture.to_le_bytes());
+		self.ring.append(storage, &buf);
+		self.last_secs = Some(secs);
+	}
+
+	/// Print every sample over the USART as a line of a sensor trace, oldest first
+	///
+	/// The watchdog is fed after each sample, as printing them all takes several seconds.
+	pub fn export(&self, storage: &mut impl Storage, watchdog: &Watchdog) {
+		let mut buf = [0; Self::SIZE];
+		for age in (0..self.ring.slots()).rev() {
+			watchdog.feed();
+			if self.ring.nth_newest(storage, age, &mut buf).is_some() {
+				log!(
+					"{} {} {}",
+					u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+					u16::from_le_bytes([buf[4], buf[5]]),
+					u16::from_le_bytes([buf[6], buf[7]])
+				);
+			}
+		}
+		log!("ok");
+	}
+}
@@ -1,30 +1,185 @@
-#![feature(llvm_asm)]
-#![feature(abi_avr_interrupt)]
-#![no_std]
-#![no_main]
+#![cfg_attr(feature = "atmega328p", feature(llvm_asm))]
+#![cfg_attr(feature = "atmega328p", feature(abi_avr_interrupt))]
+#![cfg_attr(not(feature = "sim"), no_std)]
+#![cfg_attr(not(feature = "sim"), no_main)]
+// Only the control logic is built for the simulation, so the rest of it goes unused.
+#![cfg_attr(feature = "sim", allow(dead_code))]
 
-#[macro_use]
-mod serial;
+#[cfg(all(feature = "atmega328p", feature = "sim"))]
+compile_error!("the simulation is built with `--no-default-features --features sim`");
+#[cfg(all(feature = "expander-valve", feature = "latching-valve"))]
+compile_error!("a latching valve can't be driven through the GPIO expander");
+#[cfg(all(feature = "shift-register-valve", feature = "latching-valve"))]
+compile_error!("a latching valve can't be driven through the shift registers");
+#[cfg(all(feature = "rain-gauge", feature = "heat-mat"))]
+compile_error!("the 433MHz receiver and the heat mat's soil probe both need A3");
+#[cfg(all(feature = "encoder", feature = "master-valve", feature = "heat-mat"))]
+compile_error!(
+	"with the rotary encoder, the master valve and the heat mat's soil probe both need A3"
+);
+#[cfg(all(feature = "encoder", feature = "master-valve", feature = "rain-gauge"))]
+compile_error!("with the rotary encoder, the master valve and the 433MHz receiver both need A3");
+#[cfg(all(feature = "shift-register-valve", feature = "expander-valve"))]
+compile_error!(
+	"only one of the `expander-valve` and `shift-register-valve` features can be enabled"
+);
 
+// The control logic, which is built for every target.
+mod about;
+mod activation;
+mod band;
+mod bus;
+mod climate;
 mod config;
 mod control_pad;
+mod diag;
 mod display;
+mod dosing;
+mod evening;
+#[cfg(any(feature = "expander-valve", test))]
+mod expander;
+mod fan;
+mod fmt;
+mod forecast;
+mod hal;
+mod heatmat;
+mod lang;
+mod lock;
 mod menu;
+mod photoperiod;
+mod pinmap;
+mod presets;
+mod profile;
+mod queue;
+mod raindelay;
+#[cfg(any(feature = "rain-gauge", test))]
+mod raingauge;
+mod safemode;
+mod sampling;
+mod scheduler;
+mod selftest;
+mod sequence;
+#[cfg(any(feature = "shift-register-valve", test))]
+mod shiftreg;
+mod storage;
+mod timer;
+mod trace;
+mod trend;
+mod vacation;
+mod week;
+mod zonename;
+
+// The rest of the firmware, which uses the ATmega328P directly.
+#[cfg(feature = "atmega328p")]
+#[macro_use]
+mod serial;
+
+#[cfg(feature = "atmega328p")]
+mod battery;
+#[cfg(feature = "atmega328p")]
+#[macro_use]
+mod boards;
+#[cfg(feature = "atmega328p")]
+mod buzzer;
+#[cfg(all(feature = "atmega328p", not(feature = "encoder")))]
+mod calibration;
+#[cfg(feature = "atmega328p")]
+mod eeprom;
+#[cfg(feature = "atmega328p")]
+mod link;
+#[cfg(feature = "atmega328p")]
+mod log;
+#[cfg(feature = "atmega328p")]
+mod power;
+#[cfg(all(feature = "atmega328p", feature = "pwm-pump"))]
+mod pump;
+#[cfg(feature = "atmega328p")]
+mod recovery;
+#[cfg(feature = "atmega328p")]
+mod resume;
+#[cfg(feature = "atmega328p")]
+mod stats;
+#[cfg(feature = "atmega328p")]
+mod status_led;
+#[cfg(feature = "atmega328p")]
 mod system;
+#[cfg(feature = "atmega328p")]
+mod telemetry;
+#[cfg(feature = "atmega328p")]
+mod valve;
+#[cfg(feature = "atmega328p")]
+mod watchdog;
 
-mod timer;
+#[cfg(feature = "sim")]
+mod sim;
 
+#[cfg(feature = "atmega328p")]
 use arduino_hal::{Peripherals, Pins};
+#[cfg(feature = "atmega328p")]
+use buzzer::Buzzer;
+#[cfg(all(feature = "atmega328p", feature = "encoder"))]
+use control_pad::encoder::Encoder;
+#[cfg(all(feature = "atmega328p", not(feature = "encoder")))]
 use control_pad::ControlPad;
+#[cfg(feature = "atmega328p")]
 use core::panic::PanicInfo;
-use display::Display;
+#[cfg(feature = "atmega328p")]
+use display::{Display, BODY_START_ROW, I2C_SPEED, I2C_STANDARD_SPEED};
+#[cfg(feature = "atmega328p")]
+use eeprom::Eeprom;
+#[cfg(feature = "atmega328p")]
+use hal::MappedOutput;
+#[cfg(feature = "atmega328p")]
+use pinmap::{PinMap, Role};
+#[cfg(feature = "atmega328p")]
+use power::Power;
+#[cfg(all(feature = "atmega328p", feature = "pwm-pump"))]
+use pump::Pump;
+#[cfg(all(feature = "atmega328p", feature = "rain-gauge"))]
+use raingauge::receiver::Receiver;
+#[cfg(feature = "atmega328p")]
 use serial::set_serial;
+#[cfg(all(feature = "atmega328p", feature = "shift-register-valve"))]
+use shiftreg::ShiftRegister;
+#[cfg(feature = "atmega328p")]
+use status_led::StatusLed;
+#[cfg(feature = "atmega328p")]
 use system::{System, SystemPeripherals};
+#[cfg(feature = "atmega328p")]
 use timer::Timer;
+#[cfg(all(feature = "atmega328p", feature = "latching-valve"))]
+use valve::LatchingValve;
+#[cfg(all(feature = "atmega328p", not(feature = "latching-valve")))]
+use valve::RelayValve;
+#[cfg(all(
+	feature = "atmega328p",
+	any(
+		not(feature = "latching-valve"),
+		feature = "dosing-pump",
+		feature = "grow-light",
+		feature = "fan",
+		feature = "heat-mat",
+		feature = "master-valve"
+	)
+))]
+use valve::RelayWiring;
+#[cfg(feature = "atmega328p")]
+use watchdog::Watchdog;
 
+#[cfg(feature = "sim")]
+fn main() {
+	sim::run();
+}
+
+#[cfg(feature = "atmega328p")]
 #[arduino_hal::entry]
 fn main() -> ! {
 	let dp: Peripherals = arduino_hal::Peripherals::take().unwrap();
+	// The watchdog is still running if it caused the last reset, so it needs to be disabled
+	// before doing anything else.
+	let watchdog = Watchdog::new(dp.WDT, &dp.CPU);
+	// Paint the free SRAM before the stack grows any deeper, to measure how deep it grows later.
+	diag::memory::paint();
 	let pins: Pins = arduino_hal::pins!(dp);
 
 	// Initialize the serial interface for writing output when needed.
@@ -36,35 +191,240 @@ fn main() -> ! {
 	// Turn on interrupts for this device.
 	unsafe { avr_device::interrupt::enable() };
 
-	// Get all the peripherals attached to the device.
+	// Get all the peripherals attached to the device. The pins of those which can be wired to any
+	// pin are set by the board (see src/boards.rs).
 	let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
-	let light_sensor = pins.a0.into_analog_input(&mut adc);
-	let moisture_sensor = pins.a1.into_analog_input(&mut adc);
-	let buttons = pins.a2.into_analog_input(&mut adc);
-	let valve = pins.d3.into_output();
+	let board = board_pins!(pins, &mut adc);
+	// Once the analog inputs are setup, they are converted in the background.
+	let adc = hal::Adc::new(adc);
+	info!("board={}", boards::NAME);
+	let eeprom = Eeprom::new(dp.EEPROM);
+	// The valve and the status LED are on the pins in the pin map, which can be changed at
+	// runtime.
+	let (pin_map, pin_map_error) = PinMap::load(&eeprom, boards::PIN_MAP);
+	if let Some(err) = pin_map_error {
+		warn!("pin map: {}, using the default", err);
+	}
+	// The valve is switched by a relay, or by an H-bridge if it is latching. The relay can also be
+	// on a GPIO expander on the display's I2C bus, or on a chain of shift registers, if the native
+	// pins have run out. The relay, and the relays of the other outputs, are switched off as they
+	// were last wired in the menu.
+	#[cfg(any(
+		not(feature = "latching-valve"),
+		feature = "dosing-pump",
+		feature = "grow-light",
+		feature = "fan",
+		feature = "heat-mat",
+		feature = "master-valve"
+	))]
+	let wiring = RelayWiring::load(&eeprom);
+	#[cfg(not(any(
+		feature = "latching-valve",
+		feature = "expander-valve",
+		feature = "shift-register-valve"
+	)))]
+	let valve = RelayValve::new(MappedOutput::new(pin_map.pin(Role::Valve)), wiring);
+	#[cfg(feature = "expander-valve")]
+	let valve = RelayValve::new(hal::expander_valve_pin(I2C_SPEED), wiring);
+	#[cfg(feature = "expander-valve")]
+	if !valve.is_responding() {
+		warn!("expander: no response");
+	}
+	#[cfg(feature = "shift-register-valve")]
+	let valve = RelayValve::new(
+		ShiftRegister::new(
+			MappedOutput::new(pin_map.pin(Role::Valve)),
+			MappedOutput::new(pin_map.pin(Role::ValveClose)),
+			MappedOutput::new(pin_map.pin(Role::Latch)),
+		),
+		wiring,
+	);
+	#[cfg(feature = "latching-valve")]
+	let valve = LatchingValve::new(
+		MappedOutput::new(pin_map.pin(Role::Valve)),
+		MappedOutput::new(pin_map.pin(Role::ValveClose)),
+	);
 
 	// The OLED display is using the I2C interface, not SPI.
 	let i2c = arduino_hal::I2c::new(
 		dp.TWI,
 		pins.a4.into_pull_up_input(),
 		pins.a5.into_pull_up_input(),
-		100_000,
+		I2C_SPEED,
 	);
 
-	let display = Display::new(i2c);
-	let control_pad = ControlPad::new(buttons);
+	let display = Display::new(i2c, I2C_SPEED);
+	info!("i2c={}kHz", display.i2c_speed() / 1_000);
+
+	// The event history is kept in the internal EEPROM, or on an external chip on the display's
+	// bus.
+	#[cfg(not(feature = "external-storage"))]
+	let log_storage = hal::log_storage();
+	#[cfg(feature = "external-storage")]
+	let mut log_storage = hal::log_storage(display.i2c_speed());
+	#[cfg(feature = "external-storage")]
+	if !log_storage.is_responding() {
+		warn!("storage: no response");
+	}
+
+	// The menu is controlled by either the buttons on A2, or a rotary encoder on D4, D5 and D6.
+	#[cfg(not(feature = "encoder"))]
+	let control_pad = ControlPad::new(board.buttons);
+	#[cfg(feature = "encoder")]
+	let control_pad = Encoder::new(
+		pins.d4.into_pull_up_input(),
+		pins.d5.into_pull_up_input(),
+		pins.d6.into_pull_up_input(),
+		&dp.EXINT,
+	);
 
-	let peripherals = SystemPeripherals::new(valve, light_sensor, moisture_sensor);
-	let mut control = System::new(adc, peripherals, display, control_pad);
+	// A DC pump can optionally be driven with PWM on D11.
+	#[cfg(feature = "pwm-pump")]
+	let pump = Pump::new(dp.TC2, pins.d11.into_output());
+
+	// A 433MHz receiver for a wireless rain gauge can optionally be on A3.
+	#[cfg(feature = "rain-gauge")]
+	let rain_receiver = Receiver::new(pins.a3.into_floating_input(), &dp.EXINT);
+
+	let peripherals = SystemPeripherals::new(
+		valve,
+		#[cfg(feature = "pwm-pump")]
+		pump,
+		board.light_sensor,
+		board.moisture_sensor,
+		#[cfg(feature = "dosing-pump")]
+		wiring.relay_output(pin_map.pin(Role::Doser)),
+		#[cfg(feature = "grow-light")]
+		wiring.relay_output(pin_map.pin(Role::GrowLight)),
+		#[cfg(feature = "fan")]
+		wiring.relay_output(pin_map.pin(Role::Fan)),
+		#[cfg(feature = "heat-mat")]
+		board.soil_probe,
+		#[cfg(feature = "heat-mat")]
+		wiring.relay_output(pin_map.pin(Role::HeatMat)),
+		#[cfg(feature = "master-valve")]
+		wiring.relay_output(pin_map.pin(Role::Master)),
+		#[cfg(feature = "rain-gauge")]
+		rain_receiver,
+	);
+	let buzzer = Buzzer::new(dp.TC1, pins.d9.into_output());
+	let status_led = StatusLed::new(MappedOutput::new(pin_map.pin(Role::StatusLed)));
+
+	let mut control = System::new(
+		adc,
+		peripherals,
+		display,
+		control_pad,
+		eeprom,
+		log_storage,
+		watchdog,
+		buzzer,
+		status_led,
+	);
 	control.init();
 
+	let mut power = Power::new(dp.CPU);
 	loop {
-		// Run through control logic.
+		// Run each task which is due.
 		control.tick();
+		// Sleep until the next tick, which is further off while the battery is low.
+		power.wait_for_tick(control.is_low_power());
 	}
 }
 
+/// Make the system safe, show the fault, and let the watchdog restart the system
+///
+/// Nothing else runs once the system has panicked, so the peripherals are taken back rather than
+/// relying on the state of the rest of the system.
+#[cfg(feature = "atmega328p")]
 #[panic_handler]
-fn panic(_: &PanicInfo) -> ! {
+fn panic(info: &PanicInfo) -> ! {
+	avr_device::interrupt::disable();
+	let dp = unsafe { arduino_hal::Peripherals::steal() };
+	let pins = arduino_hal::pins!(dp);
+
+	// Close the valve (and stop the pump) before anything else. The relays are switched off as they
+	// were last wired in the menu, which is kept in the EEPROM along with the pin map.
+	let eeprom = Eeprom::new(dp.EEPROM);
+	let (pin_map, _) = PinMap::load(&eeprom, boards::PIN_MAP);
+	#[cfg(any(
+		not(feature = "latching-valve"),
+		feature = "dosing-pump",
+		feature = "grow-light",
+		feature = "fan",
+		feature = "heat-mat",
+		feature = "master-valve"
+	))]
+	let wiring = RelayWiring::load(&eeprom);
+	#[cfg(not(any(
+		feature = "latching-valve",
+		feature = "expander-valve",
+		feature = "shift-register-valve"
+	)))]
+	valve::close_after_panic(MappedOutput::new(pin_map.pin(Role::Valve)), wiring);
+	#[cfg(feature = "expander-valve")]
+	valve::close_after_panic(hal::expander_valve_pin(I2C_STANDARD_SPEED), wiring);
+	#[cfg(feature = "shift-register-valve")]
+	valve::close_after_panic(
+		ShiftRegister::new(
+			MappedOutput::new(pin_map.pin(Role::Valve)),
+			MappedOutput::new(pin_map.pin(Role::ValveClose)),
+			MappedOutput::new(pin_map.pin(Role::Latch)),
+		),
+		wiring,
+	);
+	#[cfg(feature = "latching-valve")]
+	valve::close_after_panic(
+		MappedOutput::new(pin_map.pin(Role::Valve)),
+		MappedOutput::new(pin_map.pin(Role::ValveClose)),
+	);
+	#[cfg(feature = "pwm-pump")]
+	Pump::new(dp.TC2, pins.d11.into_output());
+	#[cfg(feature = "dosing-pump")]
+	wiring.relay_output(pin_map.pin(Role::Doser));
+	#[cfg(feature = "grow-light")]
+	wiring.relay_output(pin_map.pin(Role::GrowLight));
+	#[cfg(feature = "fan")]
+	wiring.relay_output(pin_map.pin(Role::Fan));
+	#[cfg(feature = "heat-mat")]
+	wiring.relay_output(pin_map.pin(Role::HeatMat));
+	#[cfg(feature = "master-valve")]
+	wiring.relay_output(pin_map.pin(Role::Master));
+
+	// Restart the system after a delay. This also restarts it if showing the fault gets stuck.
+	let mut watchdog = Watchdog::new(dp.WDT, &dp.CPU);
+	watchdog.reset_after_delay();
+
+	// Interrupts are disabled, so the buffered serial output can't be used.
+	#[cfg(feature = "panic-message")]
+	{
+		let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+		if let Some(location) = info.location() {
+			let _ = ufmt::uwriteln!(
+				serial,
+				"E: panicked at {}:{}",
+				location.file(),
+				location.line()
+			);
+		}
+	}
+	#[cfg(not(feature = "panic-message"))]
+	let _ = info;
+
+	let i2c = arduino_hal::I2c::new(
+		dp.TWI,
+		pins.a4.into_pull_up_input(),
+		pins.a5.into_pull_up_input(),
+		I2C_STANDARD_SPEED,
+	);
+	// Standard mode works with every display, so there is nothing to fall back from.
+	let mut display = Display::new(i2c, I2C_STANDARD_SPEED);
+	display.init();
+	display.set_position(0, BODY_START_ROW);
+	let _ = ufmt::uwrite!(display, "FAULT -");
+	display.set_position(0, BODY_START_ROW + 1);
+	let _ = ufmt::uwrite!(display, "power cycle");
+	display.flush();
+
 	loop {}
 }
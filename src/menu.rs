@@ -1,19 +1,99 @@
+use core::mem::discriminant;
+
 use ufmt::{uDisplay, uWrite};
 
 use crate::{
-	config::{SystemConfig, SystemValue, UpdateSystemValue, ValueAction},
-	control_pad::{ButtonStage, ButtonState, ButtonType},
-	display::{Display, BODY_START_ROW},
+	bus::Bus,
+	config::{Number, SystemConfig, SystemValue, UpdateSystemValue, ValueAction},
+	control_pad::{ButtonEvent, ButtonStage, ButtonType},
+	display::{BODY_ROW_COUNT, BODY_START_ROW},
+	hal::{Clock, DisplayBus, CLOCK},
+	lang,
 };
 
 /// Amount of padding to add infront of a menu item
 pub const MENU_ITEM_PADDING: u8 = 2;
 
+/// Selection indicator shown when navigating the menu
+const SELECTOR: &str = ">";
+/// Selection indicator shown, blinking, when the selected item is being edited
+const EDIT_SELECTOR: &str = "*";
+/// How long in milliseconds the edit indicator is shown or hidden for while blinking
+const EDIT_BLINK_MS: u32 = 400;
+/// Amount of items in the menu, leaving out the settings of features which aren't enabled
+const ITEM_COUNT: usize = 36
+	+ 2 * cfg!(feature = "pwm-pump") as usize
+	+ cfg!(feature = "bluetooth") as usize
+	+ 2 * cfg!(feature = "master-valve") as usize
+	+ 4 * cfg!(feature = "dht22") as usize
+	+ 3 * cfg!(feature = "dosing-pump") as usize
+	+ cfg!(feature = "grow-light") as usize
+	+ 2 * cfg!(feature = "fan") as usize
+	+ 3 * cfg!(feature = "heat-mat") as usize;
+
+/// Action for the system to perform, which is outside of the menu
+#[derive(Clone, Copy)]
+pub enum MenuAction {
+	/// Show the info screen
+	ShowInfo,
+	/// Show the watering of each of the last few days
+	ShowWeek,
+	/// Show the trend of the soil moisture
+	ShowSoil,
+	/// Show the firmware information screen
+	ShowAbout,
+	/// Show the event log screen
+	ShowLog,
+	/// Show the event history screen
+	ShowHistory,
+	/// Show the diagnostics screen
+	ShowDiagnostics,
+	/// Show the plant presets screen
+	ShowPresets,
+	/// Show the screen for renaming the zone
+	ShowName,
+}
+
+impl MenuAction {
+	/// Label of the menu item which performs the action
+	fn label(self) -> &'static str {
+		match self {
+			Self::ShowInfo => lang::INFO,
+			Self::ShowWeek => lang::WEEK,
+			Self::ShowSoil => lang::SOIL,
+			Self::ShowAbout => lang::ABOUT,
+			Self::ShowLog => lang::LOG,
+			Self::ShowHistory => lang::HISTORY,
+			Self::ShowDiagnostics => lang::DIAGNOSTICS,
+			Self::ShowPresets => lang::PRESETS,
+			Self::ShowName => lang::NAME,
+		}
+	}
+}
+
 /// The menu. Keeps track of the currently selected item, and holds a list of menu items to display
 /// in order.
 pub struct Menu {
 	current_idx: u8,
-	items: [MenuItem; 6],
+	items: [MenuItem; ITEM_COUNT],
+	/// Index of the first item shown on the display
+	///
+	/// There are more items than rows on the display, so the menu scrolls to keep the current
+	/// selection visible.
+	scroll_idx: u8,
+	/// Whether the menu is being shown on the display
+	visible: bool,
+	/// Whether the currently selected item is being edited
+	///
+	/// Values can only be changed with Left/Right while editing, so that an accidental button
+	/// press doesn't silently change a threshold.
+	editing: bool,
+	/// Whether the blinking edit indicator is currently visible
+	blink_visible: bool,
+	/// [`CLOCK`] clock value of the last time the edit indicator was toggled
+	last_blink_ms: u32,
+	/// Whether only Diagnostics and Reset can be selected (see [`crate::safemode`])
+	safe_mode: bool,
 }
 
 impl Menu {
@@ -22,55 +102,302 @@ impl Menu {
 		Self {
 			current_idx: 0,
 			items: [
-				MenuItem::Time(SystemValue::Time(system_config.activate_mins)),
-				MenuItem::Light(SystemValue::Light(system_config.min_light)),
-				MenuItem::Moisture(SystemValue::Moisture(system_config.min_moisture)),
-				MenuItem::Activate(SystemValue::Activate(
-					system_config.activation_state.clone(),
+				MenuItem::Setting(Number::Time.value(system_config)),
+				MenuItem::Setting(Number::Light.value(system_config)),
+				MenuItem::Setting(Number::Moisture.value(system_config)),
+				#[cfg(feature = "pwm-pump")]
+				MenuItem::Setting(Number::Flow.value(system_config)),
+				MenuItem::Setting(SystemValue::Activate(system_config.activation_state)),
+				MenuItem::Setting(SystemValue::Suspend(system_config.activation_state)),
+				MenuItem::Setting(SystemValue::Sound(system_config.sound)),
+				MenuItem::Setting(SystemValue::RemoteControl(system_config.remote_control)),
+				#[cfg(feature = "bluetooth")]
+				MenuItem::Setting(SystemValue::BtControl(system_config.bt_code)),
+				MenuItem::Setting(SystemValue::RelayActiveLow(system_config.relay_active_low)),
+				MenuItem::Setting(SystemValue::ValveNormallyOpen(
+					system_config.valve_normally_open,
 				)),
-				MenuItem::Suspend(SystemValue::Suspend(system_config.activation_state.clone())),
+				#[cfg(feature = "master-valve")]
+				MenuItem::Setting(Number::MasterLead.value(system_config)),
+				#[cfg(feature = "master-valve")]
+				MenuItem::Setting(Number::MasterLag.value(system_config)),
+				#[cfg(feature = "pwm-pump")]
+				MenuItem::Setting(Number::PumpDelay.value(system_config)),
+				MenuItem::Setting(Number::ServiceCycles.value(system_config)),
+				MenuItem::Serviced,
+				MenuItem::Setting(SystemValue::Winterize(system_config.activation_state)),
+				MenuItem::Setting(Number::VacationDays.value(system_config)),
+				MenuItem::Setting(Number::VacationInterval.value(system_config)),
+				MenuItem::Setting(Number::VacationTime.value(system_config)),
+				MenuItem::Setting(SystemValue::Trigger(system_config.trigger)),
+				MenuItem::Setting(Number::Evening.value(system_config)),
+				#[cfg(feature = "dht22")]
+				MenuItem::Setting(SystemValue::EtScaling(system_config.et_scaling)),
+				MenuItem::Setting(SystemValue::Profile(system_config.profile)),
+				MenuItem::Setting(Number::BandLow.value(system_config)),
+				MenuItem::Setting(Number::BandHigh.value(system_config)),
+				MenuItem::Setting(Number::BandCap.value(system_config)),
+				#[cfg(feature = "dosing-pump")]
+				MenuItem::Setting(SystemValue::Dose(system_config.dose)),
+				#[cfg(feature = "dosing-pump")]
+				MenuItem::Setting(Number::DoseTime.value(system_config)),
+				#[cfg(feature = "dosing-pump")]
+				MenuItem::Setting(Number::DoseCap.value(system_config)),
+				#[cfg(feature = "dht22")]
+				MenuItem::Setting(Number::MistTemp.value(system_config)),
+				#[cfg(feature = "dht22")]
+				MenuItem::Setting(Number::MistTime.value(system_config)),
+				#[cfg(feature = "dht22")]
+				MenuItem::Setting(Number::MistInterval.value(system_config)),
+				#[cfg(feature = "grow-light")]
+				MenuItem::Setting(Number::DayHours.value(system_config)),
+				#[cfg(feature = "fan")]
+				MenuItem::Setting(Number::FanTemp.value(system_config)),
+				#[cfg(feature = "fan")]
+				MenuItem::Setting(Number::FanHumidity.value(system_config)),
+				#[cfg(feature = "heat-mat")]
+				MenuItem::Setting(Number::MatTemp.value(system_config)),
+				#[cfg(feature = "heat-mat")]
+				MenuItem::Setting(Number::MatBand.value(system_config)),
+				#[cfg(feature = "heat-mat")]
+				MenuItem::Setting(Number::MatMax.value(system_config)),
+				MenuItem::Setting(Number::LowBattery.value(system_config)),
+				MenuItem::Setting(Number::Telemetry.value(system_config)),
+				MenuItem::Setting(Number::Brightness.value(system_config)),
+				MenuItem::Setting(SystemValue::NightDim(system_config.night_dim)),
+				MenuItem::Setting(SystemValue::Units(system_config.units)),
+				MenuItem::Show(MenuAction::ShowPresets),
+				MenuItem::Show(MenuAction::ShowName),
+				MenuItem::Show(MenuAction::ShowInfo),
+				MenuItem::Show(MenuAction::ShowWeek),
+				MenuItem::Show(MenuAction::ShowSoil),
+				MenuItem::Show(MenuAction::ShowAbout),
+				MenuItem::Show(MenuAction::ShowLog),
+				MenuItem::Show(MenuAction::ShowHistory),
+				MenuItem::Show(MenuAction::ShowDiagnostics),
 				MenuItem::Reset,
 			],
+			scroll_idx: 0,
+			visible: true,
+			editing: false,
+			blink_visible: false,
+			last_blink_ms: 0,
+			safe_mode: false,
 		}
 	}
 
+	/// Only allow Diagnostics and Reset to be selected, and select the first of them
+	pub fn enter_safe_mode(&mut self, display: &mut impl DisplayBus) {
+		self.safe_mode = true;
+		self.editing = false;
+		let previous_idx = self.current_idx;
+		self.current_idx = self.first_selectable();
+		if self.visible {
+			self.move_selection(previous_idx, display);
+		}
+	}
+
+	/// Whether the item at `idx` can be selected
+	fn is_selectable(&self, idx: u8) -> bool {
+		!self.safe_mode
+			|| matches!(
+				self.items[idx as usize],
+				MenuItem::Show(MenuAction::ShowDiagnostics) | MenuItem::Reset
+			)
+	}
+
+	/// Index of the first item which can be selected
+	fn first_selectable(&self) -> u8 {
+		(0..self.items.len() as u8)
+			.find(|idx| self.is_selectable(*idx))
+			.unwrap_or(0)
+	}
+
 	/// Similar to [`Menu::new`], but resets the value for each menu item to the corresponding
 	/// value in [`SystemConfig`]
 	fn reset(&mut self, system_config: &SystemConfig) {
-		self.current_idx = 0;
-		self.items.iter_mut().for_each(|item| match item {
-			MenuItem::Time(value) => *value = SystemValue::Time(system_config.activate_mins),
-			MenuItem::Light(value) => *value = SystemValue::Light(system_config.min_light),
-			MenuItem::Moisture(value) => *value = SystemValue::Moisture(system_config.min_moisture),
-			MenuItem::Activate(value) => {
-				*value = SystemValue::Activate(system_config.activation_state.clone())
-			}
-			MenuItem::Suspend(value) => {
-				*value = SystemValue::Suspend(system_config.activation_state.clone())
+		self.current_idx = self.first_selectable();
+		self.scroll_idx = 0;
+		self.editing = false;
+		self.refresh(system_config);
+	}
+
+	/// Update the value of each menu item to the corresponding value in [`SystemConfig`]
+	fn refresh(&mut self, system_config: &SystemConfig) {
+		for item in self.items.iter_mut() {
+			if let MenuItem::Setting(value) = item {
+				*value = value.current(system_config);
 			}
-			_ => {}
-		})
+		}
 	}
 
 	/// Render the entire menu
 	///
 	/// The OLED (that I have) renders a full menu slowly so calling this should be limited to when
 	/// the program launches, and whenever the menu resets only.
-	pub fn render(&self, display: &mut Display) {
+	pub fn render(&self, display: &mut impl DisplayBus) {
 		display.clear_body();
 		for (idx, item) in self.items.iter().enumerate() {
-			Self::render_item(idx, item, display);
+			if let Some(row) = Self::row(self.scroll_idx, idx) {
+				Self::render_item(row, item, display);
+			}
+		}
+
+		self.render_selector(display, None);
+	}
+
+	/// Stop rendering the menu, so that something else can be shown on the display
+	pub fn hide(&mut self) {
+		self.visible = false;
+		self.editing = false;
+	}
+
+	/// Start rendering the menu again, and render the entire menu
+	pub fn show(&mut self, display: &mut impl DisplayBus) {
+		self.visible = true;
+		self.render(display);
+	}
+
+	/// Blink the edit indicator if the selected item is being edited
+	///
+	/// Should be called on every tick of the system.
+	pub fn tick(&mut self, display: &mut impl DisplayBus) {
+		if !self.editing || !self.visible {
+			return;
+		}
+
+		let now = CLOCK.millis();
+		if now.wrapping_sub(self.last_blink_ms) >= EDIT_BLINK_MS {
+			self.last_blink_ms = now;
+			self.blink_visible = !self.blink_visible;
+			self.render_selector(display, None);
+		}
+	}
+
+	/// Index of the currently selected item
+	pub fn selected(&self) -> u8 {
+		self.current_idx
+	}
+
+	/// Select the item at `idx`, and scroll the menu so that it is visible
+	///
+	/// Nothing is selected if there is no such item, or it can't be selected. The menu isn't
+	/// rendered, so this should be called before it is shown.
+	pub fn select(&mut self, idx: u8) {
+		if idx as usize >= self.items.len() || !self.is_selectable(idx) {
+			return;
 		}
 
-		Self::render_selector(display, None, self.current_idx);
+		self.current_idx = idx;
+		self.scroll_idx = idx.saturating_sub(BODY_ROW_COUNT - 1);
+	}
+
+	/// Whether the currently selected item is being edited
+	pub fn is_editing(&self) -> bool {
+		self.editing
+	}
+
+	/// Selection indicator to render for the current menu state
+	fn selector(&self) -> &'static str {
+		match (self.editing, self.blink_visible) {
+			(false, _) => SELECTOR,
+			(true, true) => EDIT_SELECTOR,
+			(true, false) => " ",
+		}
+	}
+
+	/// Start editing the currently selected item
+	fn start_editing(&mut self, display: &mut impl DisplayBus) {
+		self.editing = true;
+		self.blink_visible = true;
+		self.last_blink_ms = CLOCK.millis();
+		self.render_selector(display, None);
+	}
+
+	/// Stop editing the currently selected item
+	fn stop_editing(&mut self, display: &mut impl DisplayBus) {
+		self.editing = false;
+		self.render_selector(display, None);
+	}
+
+	/// Move the current selection to the next menu item, or the first if the current item is the
+	/// last menu item
+	///
+	/// Items which can't be selected are skipped.
+	fn select_next(&mut self, display: &mut impl DisplayBus) {
+		let previous_idx = self.current_idx;
+		loop {
+			if self.current_idx == (self.items.len() - 1) as u8 {
+				self.current_idx = 0;
+			} else {
+				self.current_idx += 1;
+			}
+			if self.is_selectable(self.current_idx) {
+				break;
+			}
+		}
+		self.move_selection(previous_idx, display);
+	}
+
+	/// Move the current selection to the previous menu item, or the last if the current item is
+	/// the first menu item
+	///
+	/// Items which can't be selected are skipped.
+	fn select_previous(&mut self, display: &mut impl DisplayBus) {
+		let previous_idx = self.current_idx;
+		loop {
+			if self.current_idx == 0 {
+				self.current_idx = (self.items.len() - 1) as u8;
+			} else {
+				self.current_idx -= 1;
+			}
+			if self.is_selectable(self.current_idx) {
+				break;
+			}
+		}
+		self.move_selection(previous_idx, display);
+	}
+
+	/// Scroll the menu if the current selection isn't visible, otherwise rerender the selector
+	fn move_selection(&mut self, previous_idx: u8, display: &mut impl DisplayBus) {
+		if self.current_idx < self.scroll_idx {
+			self.scroll_idx = self.current_idx;
+		} else if self.current_idx >= self.scroll_idx + BODY_ROW_COUNT {
+			self.scroll_idx = self.current_idx + 1 - BODY_ROW_COUNT;
+		} else {
+			// Rerender the selector.
+			self.render_selector(display, Some(previous_idx));
+			return;
+		}
+
+		// The menu has scrolled, so rerender all of it.
+		self.render(display);
+	}
+
+	/// Display row of the item at `idx`, if it is visible when the menu is scrolled to
+	/// `scroll_idx`
+	fn row(scroll_idx: u8, idx: usize) -> Option<u8> {
+		let idx = idx as u8;
+		if idx >= scroll_idx && idx < scroll_idx + BODY_ROW_COUNT {
+			Some(BODY_START_ROW + idx - scroll_idx)
+		} else {
+			None
+		}
+	}
+
+	/// Increment or decrement the value of the currently selected item
+	fn adjust(&self, action: ValueAction, bus: &mut Bus) {
+		if let MenuItem::Setting(value) = &self.items[self.current_idx as usize] {
+			bus.send(UpdateSystemValue::from_value(value, action));
+		}
 	}
 
 	/// Render a single menu item
 	///
 	/// Faster than [`Menu::render`] - Should be limit calls to only whenever a system value
 	/// changes.
-	fn render_item(idx: usize, item: &MenuItem, display: &mut Display) {
-		let _ = display.set_position(0, BODY_START_ROW + idx as u8);
+	fn render_item(row: u8, item: &MenuItem, display: &mut impl DisplayBus) {
+		let _ = display.set_position(0, row);
 
 		// Render the padding first.
 		for _ in 0..MENU_ITEM_PADDING {
@@ -84,15 +411,17 @@ impl Menu {
 	/// Render the selection indicator
 	///
 	/// First clears the previous selection, and then renders the new selection indicator.
-	fn render_selector(display: &mut Display, previous_idx: Option<u8>, current_idx: u8) {
+	fn render_selector(&self, display: &mut impl DisplayBus, previous_idx: Option<u8>) {
 		// Clear the previous selection
-		if let Some(previous_idx) = previous_idx {
-			let _ = display.set_position(0, BODY_START_ROW + previous_idx);
+		if let Some(row) = previous_idx.and_then(|idx| Self::row(self.scroll_idx, idx as usize)) {
+			let _ = display.set_position(0, row);
 			let _ = ufmt::uwrite!(display, " ");
 		}
 
-		let _ = display.set_position(0, BODY_START_ROW + current_idx);
-		let _ = ufmt::uwrite!(display, ">");
+		if let Some(row) = Self::row(self.scroll_idx, self.current_idx as usize) {
+			let _ = display.set_position(0, row);
+			let _ = ufmt::uwrite!(display, "{}", self.selector());
+		}
 	}
 
 	/// Update a menu item associated with a system value change from [`UpdateSystemValue`]
@@ -104,137 +433,172 @@ impl Menu {
 		&mut self,
 		update_value: UpdateSystemValue,
 		system_config: &SystemConfig,
-		display: &mut Display,
+		display: &mut impl DisplayBus,
 	) {
-		if let UpdateSystemValue::Reset = update_value {
+		// A preset changes several values at once, so every item is updated as if it was reset.
+		if matches!(
+			update_value,
+			UpdateSystemValue::Reset | UpdateSystemValue::Preset(_)
+		) {
 			self.reset(system_config);
-			self.render(display);
-		} else {
-			// Find the menu item associated with the UpdateSystemValue.
+			if self.visible {
+				self.render(display);
+			}
+		} else if let UpdateSystemValue::Units = update_value {
+			// Every temperature is shown in the units, so update every item but keep the selection.
+			self.refresh(system_config);
+			if self.visible {
+				self.render(display);
+			}
+		} else if let Some(system_value) = update_value.to_value(system_config) {
+			// Find the menu item showing the setting which was updated.
 			let item = self
 				.items
 				.iter_mut()
 				.enumerate()
-				.find(|(_idx, item)| match update_value {
-					UpdateSystemValue::Time(_) => matches!(item, MenuItem::Time(_)),
-					UpdateSystemValue::Light(_) => matches!(item, MenuItem::Light(_)),
-					UpdateSystemValue::Moisture(_) => matches!(item, MenuItem::Moisture(_)),
-					UpdateSystemValue::Suspend => matches!(item, MenuItem::Suspend(_)),
-					UpdateSystemValue::Activate => matches!(item, MenuItem::Activate(_)),
-					_ => false,
-				});
+				.find(|(_idx, item)| item.is_setting_of(&system_value));
 
 			if let Some((idx, item)) = item {
 				// If a menu item is found, update its value from the value in system_config.
-				let system_value = update_value.to_value(system_config);
-				item.set_value(system_value);
+				*item = MenuItem::Setting(system_value);
 
-				// Rerender the item.
-				Self::render_item(idx, item, display);
-				// Rerender the selector.
-				Self::render_selector(display, None, self.current_idx);
+				// Rerender the item if it is visible.
+				match Self::row(self.scroll_idx, idx) {
+					Some(row) if self.visible => {
+						Self::render_item(row, item, display);
+						// Rerender the selector.
+						self.render_selector(display, None);
+					}
+					_ => {}
+				}
 			}
 		}
 	}
 
 	/// Handle a button press event
+	///
+	/// Changes to the configuration are sent onto `bus` as commands, and the menu follows them once
+	/// they have been applied (see [`Menu::update`]).
+	///
+	/// Returns the action for the system to perform, if the button press selected something
+	/// outside of the menu.
 	pub fn on_press(
 		&mut self,
-		button_state: &ButtonState,
-		display: &mut Display,
-		system_config: &mut SystemConfig,
-	) {
-		match (&button_state.stage, &button_state.button) {
+		event: &ButtonEvent,
+		display: &mut impl DisplayBus,
+		bus: &mut Bus,
+	) -> Option<MenuAction> {
+		match (&event.stage, &event.button) {
 			(ButtonStage::Release, ButtonType::Select) => {
-				// If the select button has been pressed, move the current selection to the next
-				// menu item, or the first if the current item is the last menu item.
-				let previous_idx = self.current_idx;
-				if self.current_idx == (self.items.len() - 1) as u8 {
-					self.current_idx = 0;
-				} else {
-					self.current_idx += 1;
+				// If the current item is being edited, then the select button commits the value
+				// and returns to navigating the menu.
+				if self.editing {
+					self.stop_editing(display);
+					return None;
 				}
-				// Rerender the selector.
-				Self::render_selector(display, Some(previous_idx), self.current_idx as u8);
-			}
-			(ButtonStage::Release, ButtonType::Right) => {
-				// If the right button has been pressed, fetch the current selection and...
-				let item = &self.items[self.current_idx as usize];
-				match item {
-					MenuItem::Time(value) | MenuItem::Light(value) | MenuItem::Moisture(value) => {
-						// If the current item can be incremented (example: u16), then create a new
-						// UpdateSystemValue with the Increment action.
-						system_config.update_next_tick(UpdateSystemValue::from_value(
-							value,
-							ValueAction::Increment,
-						));
-					}
-					MenuItem::Suspend(_) => {
-						// If the current item is Suspend/Resume, create a Suspend
-						// UpdateSystemValue variant which will toggle the systems suspension
-						// state.
-						system_config.update_next_tick(UpdateSystemValue::Suspend);
-					}
-					MenuItem::Activate(_) => {
-						// If the current item is Activate/Cancel, create an Activate
-						// UpdateSystemValue variant which will toggle the systems activation
-						// state.
-						system_config.update_next_tick(UpdateSystemValue::Activate);
-					}
-					MenuItem::Reset => {
-						// If the item is Reset, create a Reset variant which will reset the values
-						// in system_config, and reset the menu state.
-						system_config.update_next_tick(UpdateSystemValue::Reset);
+
+				// Otherwise, fetch the update which the current selection makes, or...
+				let update = match &self.items[self.current_idx as usize] {
+					MenuItem::Setting(value) => {
+						UpdateSystemValue::from_value(value, ValueAction::Increment)
 					}
+					// If the item shows a screen, then the system should show it.
+					MenuItem::Show(action) => return Some(*action),
+					// If the item is Serviced, start counting the valve cycles again.
+					MenuItem::Serviced => UpdateSystemValue::Serviced,
+					// If the item is Reset, reset the values in system_config, and reset the
+					// menu state.
+					MenuItem::Reset => UpdateSystemValue::Reset,
+				};
+
+				if update.inner_as_ref().is_some() {
+					// If the setting can be incremented or decremented (example: u16), then
+					// start editing it.
+					self.start_editing(display);
+				} else {
+					// Otherwise, send the update straight away (example: Activate toggles the
+					// system's activation state, or acknowledges a fault).
+					bus.send(update);
 				}
 			}
-			(ButtonStage::Release, ButtonType::Left) => {
-				// If the left button has been pressed, fetch the current selection and...
-				let item = &self.items[self.current_idx as usize];
-				match item {
-					MenuItem::Time(value) | MenuItem::Light(value) | MenuItem::Moisture(value) => {
-						// If the current item can be decremented (example: u16), then create a new
-						// UpdateSystemValue with the Decrement action.
-						system_config.update_next_tick(UpdateSystemValue::from_value(
-							value,
-							ValueAction::Decrement,
-						));
-					}
-					_ => {}
-				}
+			(ButtonStage::Down, ButtonType::Right) | (ButtonStage::Repeat, ButtonType::Right)
+				if self.editing =>
+			{
+				// If the current item is being edited, create a new UpdateSystemValue with the
+				// Increment action. Holding the button down repeats the increment.
+				self.adjust(ValueAction::Increment, bus);
+			}
+			(ButtonStage::Down, ButtonType::Left) | (ButtonStage::Repeat, ButtonType::Left)
+				if self.editing =>
+			{
+				// If the current item is being edited, create a new UpdateSystemValue with the
+				// Decrement action. Holding the button down repeats the decrement.
+				self.adjust(ValueAction::Decrement, bus);
+			}
+			(ButtonStage::LongPress, ButtonType::Select) => {
+				// If the select button has been held down, stop editing and move the current
+				// selection back to the first menu item.
+				let previous_idx = self.current_idx;
+				self.editing = false;
+				self.current_idx = self.first_selectable();
+				self.move_selection(previous_idx, display);
+			}
+			(ButtonStage::Release, ButtonType::Right) if !self.editing => {
+				// If the right button has been pressed, move the current selection to the next
+				// menu item.
+				self.select_next(display);
+			}
+			(ButtonStage::Release, ButtonType::Left) if !self.editing => {
+				// If the left button has been pressed, move the current selection to the previous
+				// menu item.
+				self.select_previous(display);
+			}
+			(ButtonStage::Down, ButtonType::Down) | (ButtonStage::Repeat, ButtonType::Down) => {
+				// If the (optional) down button has been pressed, stop editing and move the
+				// current selection to the next menu item. Holding the button down keeps moving
+				// the selection.
+				self.editing = false;
+				self.select_next(display);
+			}
+			(ButtonStage::Down, ButtonType::Up) | (ButtonStage::Repeat, ButtonType::Up) => {
+				// If the (optional) up button has been pressed, stop editing and move the current
+				// selection to the previous menu item. Holding the button down keeps moving the
+				// selection.
+				self.editing = false;
+				self.select_previous(display);
 			}
 			_ => {}
 		}
+
+		None
 	}
 }
 
 /// Menu item
-///
-/// Each variant is a possible menu item.
-///
-/// This is not well modeled - it is possible to store an incorrect [`SystemValue`] variant inside
-/// a [`MenuItem`] variant.
 enum MenuItem {
-	Time(SystemValue),
-	Light(SystemValue),
-	Moisture(SystemValue),
-	Suspend(SystemValue),
-	Activate(SystemValue),
+	/// Setting, shown with its current value
+	///
+	/// Settings which are changed with a [`ValueAction`] are edited with Left and Right, and the
+	/// others are toggled by selecting them.
+	Setting(SystemValue),
+	/// Screen which selecting the item shows
+	Show(MenuAction),
+	/// Start counting the valve cycles again
+	Serviced,
+	/// Reset the configuration
 	Reset,
 }
 
 impl MenuItem {
-	/// Updates the inner value of a [`MenuItem`] variant
-	pub fn set_value(&mut self, system_value: Option<SystemValue>) {
-		if let Some(system_value) = system_value {
-			match self {
-				Self::Time(value) => *value = system_value,
-				Self::Light(value) => *value = system_value,
-				Self::Moisture(value) => *value = system_value,
-				Self::Suspend(value) => *value = system_value,
-				Self::Activate(value) => *value = system_value,
-				Self::Reset => {}
+	/// Whether the item shows the same setting as `system_value`
+	fn is_setting_of(&self, system_value: &SystemValue) -> bool {
+		match self {
+			// Every numeric setting is the same variant, so compare which setting it is.
+			Self::Setting(SystemValue::Number(number, _, _)) => {
+				matches!(system_value, SystemValue::Number(other, _, _) if other == number)
 			}
+			Self::Setting(value) => discriminant(value) == discriminant(system_value),
+			Self::Show(_) | Self::Serviced | Self::Reset => false,
 		}
 	}
 }
@@ -245,12 +609,231 @@ impl uDisplay for MenuItem {
 		W: uWrite + ?Sized,
 	{
 		match self {
-			Self::Time(value) => ufmt::uwrite!(f, "{}", value),
-			Self::Light(value) => ufmt::uwrite!(f, "{}", value),
-			Self::Moisture(value) => ufmt::uwrite!(f, "{}", value),
-			Self::Suspend(value) => ufmt::uwrite!(f, "{}", value),
-			Self::Activate(value) => ufmt::uwrite!(f, "{}", value),
-			Self::Reset => ufmt::uwrite!(f, "Reset"),
+			Self::Setting(value) => ufmt::uwrite!(f, "{}", value),
+			Self::Show(action) => ufmt::uwrite!(f, "{}", action.label()),
+			Self::Serviced => ufmt::uwrite!(f, "{}", lang::SERVICED),
+			Self::Reset => ufmt::uwrite!(f, "{}", lang::RESET),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		activation::FaultKind,
+		bus::Notification,
+		hal::{
+			mock::{MockControlPad, MockDisplay},
+			ButtonInput,
+		},
+		timer::tests::TimersGuard,
+	};
+
+	/// Row of Activate, which comes after Flow % if the pump is driven with PWM
+	const ACTIVATE_ROW: u8 = 3 + cfg!(feature = "pwm-pump") as u8;
+	/// First item past the rows of the display, selected once the menu scrolls down by one
+	const SCROLLED_ITEM: &str = if cfg!(feature = "pwm-pump") {
+		"> Sound:       @"
+	} else {
+		"> Remote:      -"
+	};
+
+	/// Menu rendered to the mock display, which handles button presses as the system does
+	struct TestMenu {
+		menu: Menu,
+		system_config: SystemConfig,
+		bus: Bus,
+		display: MockDisplay,
+		control_pad: MockControlPad,
+		/// Activating the system from the menu starts the soft timers
+		_timers: TimersGuard,
+	}
+
+	impl TestMenu {
+		fn new() -> Self {
+			let _timers = TimersGuard::take();
+			let system_config = SystemConfig::new();
+			let menu = Menu::new(&system_config);
+			let mut display = MockDisplay::new();
+			menu.render(&mut display);
+
+			Self {
+				menu,
+				system_config,
+				bus: Bus::new(),
+				display,
+				control_pad: MockControlPad::new(),
+				_timers,
+			}
+		}
+
+		/// Handle every queued button press, applying each command it sends
+		fn run(&mut self) {
+			while let Some(event) = self.control_pad.next_event() {
+				self.menu.on_press(&event, &mut self.display, &mut self.bus);
+				self.apply();
+			}
+		}
+
+		/// Apply the commands on the bus, and update the menu to match
+		fn apply(&mut self) {
+			self.bus.apply(&mut self.system_config);
+			while let Some(notification) = self.bus.next_notification() {
+				if let Notification::Changed(update_value, _) = notification {
+					self.menu
+						.update(update_value, &self.system_config, &mut self.display);
+				}
+			}
+		}
+
+		/// Text shown on the nth row of the menu
+		fn row(&self, idx: u8) -> &str {
+			self.display.row(BODY_START_ROW + idx)
+		}
+	}
+
+	#[test]
+	fn renders_first_items() {
+		let menu = TestMenu::new();
+
+		assert_eq!(menu.row(0), "> Time:       10");
+		assert_eq!(menu.row(1), "  Light:     100");
+		assert_eq!(menu.row(2), "  Moisture:  100");
+		#[cfg(feature = "pwm-pump")]
+		assert_eq!(menu.row(3), "  Flow %:    100");
+		assert_eq!(menu.row(ACTIVATE_ROW), "  Activate:    -");
+		assert_eq!(menu.row(ACTIVATE_ROW + 1), "  Suspend:     -");
+	}
+
+	#[test]
+	fn editing_increments_value() {
+		let mut menu = TestMenu::new();
+		menu.control_pad
+			.press(ButtonType::Right)
+			.press(ButtonType::Select)
+			.press(ButtonType::Right);
+		menu.run();
+
+		assert_eq!(menu.row(1), "* Light:     125");
+		assert_eq!(menu.system_config.min_light, 125);
+
+		menu.control_pad.press(ButtonType::Select);
+		menu.run();
+		assert_eq!(menu.row(1), "> Light:     125");
+	}
+
+	#[test]
+	fn left_and_right_move_selection_without_editing() {
+		let mut menu = TestMenu::new();
+		menu.control_pad
+			.press(ButtonType::Right)
+			.press(ButtonType::Right)
+			.press(ButtonType::Left);
+		menu.run();
+
+		assert_eq!(menu.row(0), "  Time:       10");
+		assert_eq!(menu.row(1), "> Light:     100");
+		assert_eq!(menu.system_config.min_light, 100);
+	}
+
+	#[test]
+	fn scrolls_to_keep_selection_visible() {
+		let mut menu = TestMenu::new();
+		for _ in 0..BODY_ROW_COUNT {
+			menu.control_pad.press(ButtonType::Right);
+		}
+		menu.run();
+
+		assert_eq!(menu.row(0), "  Light:     100");
+		assert_eq!(menu.row(5), SCROLLED_ITEM);
+	}
+
+	#[test]
+	fn restored_selection_is_scrolled_into_view() {
+		let mut menu = TestMenu::new();
+		menu.menu.select(BODY_ROW_COUNT);
+		menu.menu.select(u8::MAX);
+		menu.menu.show(&mut menu.display);
+
+		assert_eq!(menu.menu.selected(), BODY_ROW_COUNT);
+		assert_eq!(menu.row(0), "  Light:     100");
+		assert_eq!(menu.row(5), SCROLLED_ITEM);
+	}
+
+	#[test]
+	fn long_press_select_returns_to_first_item() {
+		let mut menu = TestMenu::new();
+		for _ in 0..BODY_ROW_COUNT {
+			menu.control_pad.press(ButtonType::Right);
+		}
+		menu.control_pad.hold(ButtonType::Select);
+		menu.run();
+
+		assert_eq!(menu.row(0), "> Time:       10");
+	}
+
+	#[test]
+	fn safe_mode_only_selects_diagnostics_and_reset() {
+		let mut menu = TestMenu::new();
+		menu.menu.enter_safe_mode(&mut menu.display);
+		assert_eq!(menu.row(5).trim_end(), "> Diagnostics");
+
+		// Moving past Reset wraps around to Diagnostics, skipping every other item.
+		menu.control_pad
+			.press(ButtonType::Right)
+			.press(ButtonType::Right);
+		menu.run();
+		assert_eq!(menu.row(4).trim_end(), "> Diagnostics");
+		assert_eq!(menu.row(5).trim_end(), "  Reset");
+
+		menu.control_pad.press(ButtonType::Left);
+		menu.run();
+		assert_eq!(menu.row(5).trim_end(), "> Reset");
+	}
+
+	#[test]
+	fn toggles_are_rendered() {
+		let mut menu = TestMenu::new();
+		for _ in 0..ACTIVATE_ROW {
+			menu.control_pad.press(ButtonType::Right);
+		}
+		menu.control_pad.press(ButtonType::Select);
+		menu.run();
+
+		assert_eq!(menu.row(ACTIVATE_ROW), "> Cancel:      @");
+		assert!(menu.system_config.activation_state.is_activated());
+	}
+
+	#[test]
+	fn fault_is_acknowledged_from_activate() {
+		let mut menu = TestMenu::new();
+		menu.bus.send(UpdateSystemValue::Fault(FaultKind::Valve));
+		menu.apply();
+		assert_eq!(menu.row(ACTIVATE_ROW), "  Ack fault:  E2");
+
+		for _ in 0..ACTIVATE_ROW {
+			menu.control_pad.press(ButtonType::Right);
 		}
+		menu.control_pad.press(ButtonType::Select);
+		menu.run();
+		assert_eq!(menu.row(ACTIVATE_ROW), "> Activate:    -");
+		assert!(menu.system_config.activation_state.is_waiting());
+	}
+
+	#[test]
+	fn reset_restores_values_and_selection() {
+		let mut menu = TestMenu::new();
+		menu.control_pad
+			.press(ButtonType::Select)
+			.press(ButtonType::Right)
+			.press(ButtonType::Select)
+			.press(ButtonType::Left)
+			.press(ButtonType::Select);
+		menu.run();
+
+		assert_eq!(menu.system_config.activate_mins, 10);
+		assert_eq!(menu.row(0), "> Time:       10");
+		assert_eq!(menu.row(1), "  Light:     100");
 	}
 }
@@ -0,0 +1,166 @@
+//! Grow-light photoperiod
+//!
+//! With the `grow-light` feature, a second relay (the `grow-light` pin of the pin map) switches
+//! grow lights, so that seedlings get a set amount of light each day. Without a real-time clock,
+//! the light sensor measures how long the day was, and at dusk (see `crate::evening`) the grow
+//! lights are switched on for as long as the day fell short of the configured hours.
+//!
+//! The grow lights may shine on the light sensor, so the light isn't counted while they are on,
+//! and a dusk which follows no counted daylight (such as the grow lights switching off) isn't
+//! topped up again.
+
+use crate::{
+	config::SystemConfig,
+	hal::Clock,
+	timer::{Duration, TIMER},
+};
+
+/// How often the light sensor is sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counts the daylight, and keeps track of whether the grow lights are topping up the day
+pub struct Photoperiod {
+	/// [`TIMER`] clock value of the last time the light was sampled
+	last_sample_ms: Option<u32>,
+	/// Amount of daylight in milliseconds since the last dusk
+	daylight_ms: u32,
+	/// [`TIMER`] clock value of when the grow lights were switched on, and how long for
+	top_up: Option<(u32, Duration)>,
+}
+
+impl Photoperiod {
+	/// Create a new [`Photoperiod`], which hasn't counted any daylight yet
+	pub fn new() -> Self {
+		Self {
+			last_sample_ms: None,
+			daylight_ms: 0,
+			top_up: None,
+		}
+	}
+
+	/// Sample the light if it is due to be sampled, and top up the day if `dusk` was just detected
+	///
+	/// Returns how long the grow lights were switched on for, if they just were.
+	pub fn update(
+		&mut self,
+		system_config: &SystemConfig,
+		dusk: bool,
+		read_light: impl FnOnce() -> u16,
+	) -> Option<Duration> {
+		let now = TIMER.millis();
+
+		// If the top-up has finished, the light can be counted again.
+		if let Some((on_ms, duration)) = self.top_up {
+			if TIMER.has_elapsed(on_ms, duration) {
+				self.top_up = None;
+			}
+		}
+
+		let due = match self.last_sample_ms {
+			Some(last_sample_ms) => TIMER.has_elapsed(last_sample_ms, SAMPLE_INTERVAL),
+			None => true,
+		};
+		if due {
+			self.last_sample_ms = Some(now);
+			if self.top_up.is_none() && read_light() >= system_config.min_light {
+				self.daylight_ms = self.daylight_ms.saturating_add(SAMPLE_INTERVAL.as_millis());
+			}
+		}
+
+		if !dusk {
+			return None;
+		}
+
+		// If there was daylight since the last dusk, switch on the grow lights for the rest of the
+		// configured day.
+		let daylight_ms = core::mem::replace(&mut self.daylight_ms, 0);
+		let target_ms = day_length(system_config).as_millis();
+		if system_config.day_hours == 0 || daylight_ms == 0 || daylight_ms >= target_ms {
+			return None;
+		}
+		let duration = Duration::from_millis(target_ms - daylight_ms);
+		self.top_up = Some((now, duration));
+		Some(duration)
+	}
+
+	/// Whether the grow lights should be on
+	///
+	/// Always false while the photoperiod is disabled.
+	pub fn is_on(&self, system_config: &SystemConfig) -> bool {
+		if system_config.day_hours == 0 {
+			return false;
+		}
+
+		match self.top_up {
+			Some((on_ms, duration)) => !TIMER.has_elapsed(on_ms, duration),
+			None => false,
+		}
+	}
+}
+
+/// Amount of light the plants should get each day
+fn day_length(system_config: &SystemConfig) -> Duration {
+	Duration::from_config_mins(system_config.day_hours * 60)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::timer::{self, tests::TimersGuard};
+
+	/// Light reading above the default minimum light value
+	const LIGHT: u16 = 500;
+	/// Light reading below the default minimum light value
+	const DARK: u16 = 10;
+
+	/// Keep sampling `light` for `mins` minutes
+	fn run(photoperiod: &mut Photoperiod, system_config: &SystemConfig, light: u16, mins: u16) {
+		for _ in 0..mins {
+			photoperiod.update(system_config, false, || light);
+			timer::advance(SAMPLE_INTERVAL.as_millis());
+		}
+	}
+
+	fn photoperiod_config(hours: u16) -> SystemConfig {
+		let mut system_config = SystemConfig::new();
+		system_config.day_hours = hours;
+		system_config
+	}
+
+	#[test]
+	fn tops_up_a_short_day() {
+		let _timers = TimersGuard::take();
+		let system_config = photoperiod_config(14);
+		let mut photoperiod = Photoperiod::new();
+
+		run(&mut photoperiod, &system_config, LIGHT, 10 * 60);
+		let duration = photoperiod.update(&system_config, true, || DARK);
+		assert_eq!(
+			duration.map(|duration| duration.as_millis()),
+			Some(Duration::from_mins(4 * 60).as_millis())
+		);
+		assert!(photoperiod.is_on(&system_config));
+
+		run(&mut photoperiod, &system_config, DARK, 4 * 60);
+		assert!(!photoperiod.is_on(&system_config));
+	}
+
+	#[test]
+	fn grow_lights_arent_daylight() {
+		let _timers = TimersGuard::take();
+		let system_config = photoperiod_config(14);
+		let mut photoperiod = Photoperiod::new();
+
+		run(&mut photoperiod, &system_config, LIGHT, 13 * 60);
+		assert!(photoperiod.update(&system_config, true, || DARK).is_some());
+
+		// The grow lights shine on the sensor, and switching them off looks like another dusk.
+		run(&mut photoperiod, &system_config, LIGHT, 60);
+		assert!(photoperiod.update(&system_config, true, || DARK).is_none());
+		assert!(!photoperiod.is_on(&system_config));
+
+		// A long enough day isn't topped up.
+		run(&mut photoperiod, &system_config, LIGHT, 15 * 60);
+		assert!(photoperiod.update(&system_config, true, || DARK).is_none());
+	}
+}
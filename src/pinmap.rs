@@ -0,0 +1,417 @@
+//! Pins of the digital outputs, chosen at runtime
+//!
+//! The valve, the status LED, the dosing pump, the master valve, the grow-light, fan and heat mat
+//! relays, and the latch of the shift registers can be moved to other pins without rebuilding the
+//! firmware, so that a single build works for boards which were wired slightly differently. The
+//! pin of each [`Role`] is set over the serial connection, saved in the EEPROM, and used the next
+//! time the system starts up. A saved pin map which isn't valid for the build (for example, if it
+//! uses a pin which the rotary encoder needs) is ignored in favour of the board's default pin map
+//! (see `crate::boards`).
+//!
+//! Only pins which are plain digital outputs can be mapped. The sensors and buttons need analog
+//! inputs, and the buzzer and pump are driven by the timers' output pins, so they stay on the pins
+//! set by the board.
+
+use core::str::FromStr;
+
+use ufmt::{uDisplay, uWrite, Formatter};
+
+#[cfg(feature = "atmega328p")]
+use crate::eeprom::{Eeprom, PIN_MAP_ADDRESS};
+
+/// Amount of digital pins, D0-D13 followed by A0-A5
+const PIN_COUNT: u8 = 20;
+/// Number of the first analog pin, A0
+const FIRST_ANALOG_PIN: u8 = 14;
+
+/// Port of the ATmega328P which a pin belongs to
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Port {
+	/// D8-D13
+	B,
+	/// A0-A5
+	C,
+	/// D0-D7
+	D,
+}
+
+/// Digital pin, labelled as on the board
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DigitalPin(u8);
+
+impl DigitalPin {
+	/// Pin D`number`
+	pub const fn d(number: u8) -> Self {
+		Self(number)
+	}
+
+	/// Pin A`number`, used as a digital pin
+	pub const fn a(number: u8) -> Self {
+		Self(FIRST_ANALOG_PIN + number)
+	}
+
+	/// Port of the pin, and its bit in the port's registers
+	pub fn port_bit(self) -> (Port, u8) {
+		match self.0 {
+			0..=7 => (Port::D, self.0),
+			8..=13 => (Port::B, self.0 - 8),
+			_ => (Port::C, self.0 - FIRST_ANALOG_PIN),
+		}
+	}
+
+	/// Whether the pin is used by a peripheral which can't be mapped
+	///
+	/// The serial connection is on D0/D1, the buzzer on D9, the pump on D11, the display's I2C bus
+	/// on A4/A5, the sensors on A0/A1, the buttons on A2 (unless the rotary encoder is used
+	/// instead), the rotary encoder on D4-D6, the DHT22 on D7, and the soil temperature probe or the
+	/// 433MHz receiver on A3.
+	fn is_reserved(self) -> bool {
+		match self.0 {
+			0 | 1 | 9 | 11 => true,
+			4..=6 => cfg!(feature = "encoder"),
+			7 => cfg!(feature = "dht22"),
+			16 => !cfg!(feature = "encoder"),
+			17 => cfg!(feature = "heat-mat") || cfg!(feature = "rain-gauge"),
+			_ => self.0 >= FIRST_ANALOG_PIN,
+		}
+	}
+}
+
+impl FromStr for DigitalPin {
+	type Err = ();
+
+	/// Parse a pin labelled as on the board, such as `D3` or `a3`
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if !s.is_char_boundary(1) {
+			return Err(());
+		}
+		let (prefix, number) = s.split_at(1);
+		let number = number.parse::<u8>().map_err(|_| ())?;
+		let pin = match prefix {
+			"D" | "d" if number < FIRST_ANALOG_PIN => Self::d(number),
+			"A" | "a" if number < PIN_COUNT - FIRST_ANALOG_PIN => Self::a(number),
+			_ => return Err(()),
+		};
+		Ok(pin)
+	}
+}
+
+impl uDisplay for DigitalPin {
+	fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		if self.0 < FIRST_ANALOG_PIN {
+			ufmt::uwrite!(f, "D{}", self.0)
+		} else {
+			ufmt::uwrite!(f, "A{}", self.0 - FIRST_ANALOG_PIN)
+		}
+	}
+}
+
+/// Digital output which can be mapped to a pin
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Role {
+	/// Valve relay, the H-bridge input which opens a latching valve, or the serial data of the
+	/// shift registers
+	Valve,
+	/// H-bridge input which closes a latching valve, or the clock of the shift registers
+	ValveClose,
+	StatusLed,
+	/// Fertilizer dosing pump (see [`crate::dosing`])
+	Doser,
+	/// Grow-light relay (see [`crate::photoperiod`])
+	GrowLight,
+	/// Ventilation fan relay (see [`crate::fan`])
+	Fan,
+	/// Heat mat relay (see [`crate::heatmat`])
+	HeatMat,
+	/// Master valve or pump-start relay, which is open whenever the valve is
+	Master,
+	/// Latch of the shift registers which switch the valve relay (see [`crate::shiftreg`])
+	Latch,
+}
+
+impl Role {
+	pub const ALL: [Role; 9] = [
+		Role::Valve,
+		Role::ValveClose,
+		Role::StatusLed,
+		Role::Doser,
+		Role::GrowLight,
+		Role::Fan,
+		Role::HeatMat,
+		Role::Master,
+		Role::Latch,
+	];
+
+	/// Name of the role, as used by the serial commands
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Valve => "valve",
+			Self::ValveClose => "valve-close",
+			Self::StatusLed => "led",
+			Self::Doser => "doser",
+			Self::GrowLight => "grow-light",
+			Self::Fan => "fan",
+			Self::HeatMat => "heat-mat",
+			Self::Master => "master",
+			Self::Latch => "latch",
+		}
+	}
+
+	/// Whether the output of the role is used by this build
+	///
+	/// The pins of the optional outputs are only checked while they are enabled, so that their
+	/// default pins don't stop another role, or a reserved peripheral, from using them.
+	pub fn is_enabled(self) -> bool {
+		match self {
+			Self::Valve | Self::ValveClose | Self::StatusLed => true,
+			Self::Doser => cfg!(feature = "dosing-pump"),
+			Self::GrowLight => cfg!(feature = "grow-light"),
+			Self::Fan => cfg!(feature = "fan"),
+			Self::HeatMat => cfg!(feature = "heat-mat"),
+			Self::Master => cfg!(feature = "master-valve"),
+			Self::Latch => cfg!(feature = "shift-register-valve"),
+		}
+	}
+
+	/// Parse the name of a role, as used by the serial commands
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.iter().copied().find(|role| role.name() == name)
+	}
+}
+
+/// Reason a pin can't be mapped to a role
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PinMapError {
+	/// The pin is used by a peripheral which can't be mapped
+	Reserved(DigitalPin),
+	/// The pin is already mapped to another role
+	InUse(DigitalPin, Role),
+}
+
+impl uDisplay for PinMapError {
+	fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+	where
+		W: uWrite + ?Sized,
+	{
+		match self {
+			Self::Reserved(pin) => ufmt::uwrite!(f, "{} is reserved", pin),
+			Self::InUse(pin, role) => ufmt::uwrite!(f, "{} is used by {}", pin, role.name()),
+		}
+	}
+}
+
+/// Pin of each [`Role`]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PinMap([DigitalPin; Role::ALL.len()]);
+
+impl PinMap {
+	/// Size in bytes of the pin map stored in the EEPROM, not including its checksum
+	pub const SIZE: usize = Role::ALL.len();
+
+	/// Create a new [`PinMap`] from the pin of each role
+	#[allow(clippy::too_many_arguments)]
+	pub const fn new(
+		valve: DigitalPin,
+		valve_close: DigitalPin,
+		status_led: DigitalPin,
+		doser: DigitalPin,
+		grow_light: DigitalPin,
+		fan: DigitalPin,
+		heat_mat: DigitalPin,
+		master: DigitalPin,
+		latch: DigitalPin,
+	) -> Self {
+		Self([
+			valve,
+			valve_close,
+			status_led,
+			doser,
+			grow_light,
+			fan,
+			heat_mat,
+			master,
+			latch,
+		])
+	}
+
+	/// Pin mapped to `role`
+	pub fn pin(&self, role: Role) -> DigitalPin {
+		self.0[role as usize]
+	}
+
+	/// Map `role` to `pin`, if the pin isn't reserved or mapped to another role
+	pub fn set(&mut self, role: Role, pin: DigitalPin) -> Result<(), PinMapError> {
+		self.check(role, pin)?;
+		self.0[role as usize] = pin;
+		Ok(())
+	}
+
+	/// Check that the pin of every enabled role can be used for it
+	pub fn validate(&self) -> Result<(), PinMapError> {
+		Role::ALL
+			.iter()
+			.filter(|role| role.is_enabled())
+			.try_for_each(|role| self.check(*role, self.pin(*role)))
+	}
+
+	/// Check that `pin` isn't reserved or mapped to an enabled role other than `role`
+	fn check(&self, role: Role, pin: DigitalPin) -> Result<(), PinMapError> {
+		if pin.is_reserved() {
+			return Err(PinMapError::Reserved(pin));
+		}
+		match Role::ALL
+			.iter()
+			.find(|other| **other != role && other.is_enabled() && self.pin(**other) == pin)
+		{
+			Some(other) => Err(PinMapError::InUse(pin, *other)),
+			None => Ok(()),
+		}
+	}
+
+	fn to_bytes(self) -> [u8; Self::SIZE] {
+		let mut buf = [0; Self::SIZE];
+		for (byte, pin) in buf.iter_mut().zip(self.0.iter()) {
+			*byte = pin.0;
+		}
+		buf
+	}
+
+	/// Read a pin map from bytes, if every pin exists
+	fn from_bytes(buf: &[u8; Self::SIZE]) -> Option<Self> {
+		let mut pins = [DigitalPin(0); Self::SIZE];
+		for (pin, byte) in pins.iter_mut().zip(buf.iter()) {
+			if *byte >= PIN_COUNT {
+				return None;
+			}
+			*pin = DigitalPin(*byte);
+		}
+		Some(Self(pins))
+	}
+
+	/// Load the pin map saved in the EEPROM, or `default` if one was never saved
+	///
+	/// If the saved pin map isn't valid for this build, `default` is used instead, and the reason
+	/// is returned along with it.
+	#[cfg(feature = "atmega328p")]
+	pub fn load(eeprom: &Eeprom, default: Self) -> (Self, Option<PinMapError>) {
+		let mut buf = [0; Self::SIZE];
+		if !eeprom.read_record(PIN_MAP_ADDRESS, &mut buf) {
+			return (default, None);
+		}
+
+		match Self::from_bytes(&buf).map(|map| (map, map.validate())) {
+			Some((map, Ok(()))) => (map, None),
+			Some((_, Err(err))) => (default, Some(err)),
+			None => (default, None),
+		}
+	}
+
+	/// Store the pin map in the EEPROM, to be used the next time the system starts up
+	#[cfg(feature = "atmega328p")]
+	pub fn save(&self, eeprom: &mut Eeprom) {
+		eeprom.write_record(PIN_MAP_ADDRESS, &self.to_bytes());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const DEFAULT: PinMap = PinMap::new(
+		DigitalPin::d(3),
+		DigitalPin::d(2),
+		DigitalPin::d(13),
+		DigitalPin::d(8),
+		DigitalPin::d(12),
+		DigitalPin::d(10),
+		if cfg!(feature = "encoder") {
+			DigitalPin::a(2)
+		} else {
+			DigitalPin::d(4)
+		},
+		if cfg!(feature = "encoder") {
+			DigitalPin::a(3)
+		} else {
+			DigitalPin::d(5)
+		},
+		if cfg!(feature = "encoder") {
+			DigitalPin::d(7)
+		} else {
+			DigitalPin::d(6)
+		},
+	);
+
+	/// Pin which is neither reserved nor mapped by default
+	///
+	/// The rotary encoder leaves no spare pin, as it takes D4 to D6 and moves the master valve and
+	/// the latch onto A3 and D7. Neither do the temperature sensor on D7 along with the soil probe
+	/// or the rain gauge on A3.
+	#[cfg(not(any(feature = "encoder", feature = "dht22")))]
+	const SPARE_PIN: DigitalPin = DigitalPin::d(7);
+	/// Pin which is neither reserved nor mapped by default, once the temperature sensor has D7
+	#[cfg(all(
+		not(feature = "encoder"),
+		feature = "dht22",
+		not(any(feature = "heat-mat", feature = "rain-gauge"))
+	))]
+	const SPARE_PIN: DigitalPin = DigitalPin::a(3);
+
+	#[test]
+	fn parses_board_labels() {
+		assert_eq!("D3".parse(), Ok(DigitalPin::d(3)));
+		assert_eq!("a3".parse(), Ok(DigitalPin::a(3)));
+		assert_eq!("D14".parse::<DigitalPin>(), Err(()));
+		assert_eq!("A6".parse::<DigitalPin>(), Err(()));
+		assert_eq!("B2".parse::<DigitalPin>(), Err(()));
+		assert_eq!("".parse::<DigitalPin>(), Err(()));
+
+		assert_eq!(DigitalPin::d(7).port_bit(), (Port::D, 7));
+		assert_eq!(DigitalPin::d(13).port_bit(), (Port::B, 5));
+		assert_eq!(DigitalPin::a(3).port_bit(), (Port::C, 3));
+	}
+
+	#[test]
+	fn rejects_reserved_and_mapped_pins() {
+		let mut map = DEFAULT;
+		assert_eq!(map.validate(), Ok(()));
+
+		assert_eq!(
+			map.set(Role::Valve, DigitalPin::d(9)),
+			Err(PinMapError::Reserved(DigitalPin::d(9)))
+		);
+		assert_eq!(
+			map.set(Role::Valve, DigitalPin::a(4)),
+			Err(PinMapError::Reserved(DigitalPin::a(4)))
+		);
+		assert_eq!(
+			map.set(Role::Valve, DigitalPin::d(13)),
+			Err(PinMapError::InUse(DigitalPin::d(13), Role::StatusLed))
+		);
+		assert_eq!(map.pin(Role::Valve), DEFAULT.pin(Role::Valve));
+	}
+
+	#[test]
+	#[cfg(not(any(
+		feature = "encoder",
+		all(feature = "dht22", any(feature = "heat-mat", feature = "rain-gauge"))
+	)))]
+	fn moves_to_spare_pin() {
+		let mut map = DEFAULT;
+		assert_eq!(map.set(Role::Valve, SPARE_PIN), Ok(()));
+		assert_eq!(map.pin(Role::Valve), SPARE_PIN);
+		assert_eq!(map.validate(), Ok(()));
+		assert_eq!(PinMap::from_bytes(&map.to_bytes()), Some(map));
+	}
+
+	#[test]
+	fn round_trips_through_bytes() {
+		assert_eq!(PinMap::from_bytes(&DEFAULT.to_bytes()), Some(DEFAULT));
+		assert_eq!(
+			PinMap::from_bytes(&[3, 2, 13, 8, 12, 10, 4, 5, PIN_COUNT]),
+			None
+		);
+	}
+}
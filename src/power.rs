@@ -0,0 +1,63 @@
+//! Power management
+//!
+//! Rather than reading the sensors and buttons as fast as possible, the system ticks at a fixed
+//! rate and the MCU sleeps in between, so that a battery or solar powered install lasts longer.
+//! While the battery is low (see [`crate::battery`]), the system only ticks once a second, which
+//! is enough to keep the watchdog fed and to notice when the battery has recovered.
+
+use arduino_hal::pac::CPU;
+
+use crate::{
+	hal::Clock,
+	timer::{Duration, TIMER},
+};
+
+/// How often the system ticks
+///
+/// The MCU sleeps for the rest of the time. A longer interval uses less power, but makes the
+/// buttons less responsive.
+const TICK_INTERVAL: Duration = Duration::from_millis(10);
+/// How often the system ticks in low-power mode
+const LOW_POWER_TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Puts the MCU to sleep between ticks
+pub struct Power {
+	cpu: CPU,
+	/// [`TIMER`] clock value of the start of the last tick
+	last_tick_ms: u32,
+}
+
+impl Power {
+	/// Create a new [`Power`] from the CPU peripheral
+	pub fn new(cpu: CPU) -> Self {
+		Self {
+			cpu,
+			last_tick_ms: TIMER.millis(),
+		}
+	}
+
+	/// Sleep until the next tick is due, which is further off in `low_power` mode
+	///
+	/// Any interrupt wakes the MCU up (the [`TIMER`] interrupt every millisecond, or a pin change
+	/// from the rotary encoder), so it goes back to sleep until enough time has passed.
+	pub fn wait_for_tick(&mut self, low_power: bool) {
+		let interval = if low_power {
+			LOW_POWER_TICK_INTERVAL
+		} else {
+			TICK_INTERVAL
+		};
+		while !TIMER.has_elapsed(self.last_tick_ms, interval) {
+			self.sleep();
+		}
+		self.last_tick_ms = TIMER.millis();
+	}
+
+	/// Sleep until the next interrupt
+	///
+	/// Idle mode is used so that the timers keep running while the MCU sleeps.
+	fn sleep(&self) {
+		self.cpu.smcr.write(|w| w.sm().idle().se().set_bit());
+		avr_device::asm::sleep();
+		self.cpu.smcr.write(|w| w.se().clear_bit());
+	}
+}
@@ -0,0 +1,209 @@
+//! Plant presets
+//!
+//! Working out a good moisture threshold and activation time takes a few days of watching the
+//! garden, so the presets screen offers a starting point for a few common plantings. Choosing a
+//! preset sets the **Moisture**, **Time** and **Profile** values, which can then be fine-tuned from
+//! the menu as usual.
+//!
+//! Left and Right move between the presets, Select applies the selected preset, and holding Select
+//! returns to the menu without changing anything.
+
+use crate::{
+	bus::Bus,
+	config::{SystemConfig, UpdateSystemValue},
+	control_pad::{ButtonEvent, ButtonStage, ButtonType},
+	display::BODY_START_ROW,
+	hal::DisplayBus,
+	lang,
+	profile::Profile,
+};
+
+/// Presets which can be chosen from
+pub const PRESETS: [Preset; 4] = [
+	// Tomatoes split if the soil swings between wet and dry, so keep it evenly moist with long,
+	// slow drip watering.
+	Preset {
+		name: "Tomatoes",
+		min_moisture: 400,
+		activate_mins: 15,
+		profile: Profile::Drip,
+	},
+	// Lawn is watered deeply once it starts to dry out.
+	Preset {
+		name: "Lawn",
+		min_moisture: 250,
+		activate_mins: 20,
+		profile: Profile::Spray,
+	},
+	// Herbs prefer to dry out a little between waterings.
+	Preset {
+		name: "Herbs",
+		min_moisture: 200,
+		activate_mins: 10,
+		profile: Profile::Spray,
+	},
+	// Succulents rot in wet soil, so they are only watered once the soil is almost dry.
+	Preset {
+		name: "Succulents",
+		min_moisture: 50,
+		activate_mins: 5,
+		profile: Profile::Drip,
+	},
+];
+
+/// Row of the presets screen which the first preset is shown on
+const FIRST_ROW: u8 = BODY_START_ROW + 1;
+/// Row of the presets screen which the values of the selected preset are shown on
+const VALUES_ROW: u8 = BODY_START_ROW + 5;
+
+/// Recommended configuration for a type of planting
+pub struct Preset {
+	/// Name shown on the presets screen
+	pub name: &'static str,
+	/// Minimum amount of moisture required for the system to potentially activate
+	pub min_moisture: u16,
+	/// How long in minutes the system should be activated for
+	pub activate_mins: u16,
+	/// Type of output which the valve feeds
+	pub profile: Profile,
+}
+
+impl Preset {
+	/// Set the values of the preset in `system_config`, leaving everything else as it is
+	pub fn apply(&self, system_config: &mut SystemConfig) {
+		system_config.min_moisture = self.min_moisture;
+		system_config.activate_mins = self.activate_mins;
+		system_config.profile = self.profile;
+	}
+}
+
+/// Presets screen, which keeps track of the selected preset
+pub struct Presets {
+	/// Index of the selected preset in [`PRESETS`]
+	selected: u8,
+}
+
+impl Presets {
+	/// Create a new [`Presets`] with the first preset selected
+	pub fn new() -> Self {
+		Self { selected: 0 }
+	}
+
+	/// Render the presets screen in the body of the display
+	pub fn render(&self, display: &mut impl DisplayBus) {
+		display.clear_body();
+		display.set_position(0, BODY_START_ROW);
+		let _ = ufmt::uwrite!(display, "{}", lang::PRESETS);
+		for idx in 0..PRESETS.len() as u8 {
+			self.render_preset(idx, display);
+		}
+		self.render_values(display);
+	}
+
+	/// Handle a button press on the presets screen
+	///
+	/// Returns whether to return to the menu. If a preset was chosen, a command to apply it is
+	/// sent onto `bus`.
+	pub fn on_press(
+		&mut self,
+		event: &ButtonEvent,
+		display: &mut impl DisplayBus,
+		bus: &mut Bus,
+	) -> bool {
+		let count = PRESETS.len() as u8;
+		let previous = self.selected;
+		match (&event.stage, &event.button) {
+			(ButtonStage::Release, ButtonType::Left) => {
+				self.selected = (self.selected + count - 1) % count
+			}
+			(ButtonStage::Release, ButtonType::Right) => {
+				self.selected = (self.selected + 1) % count
+			}
+			(ButtonStage::Release, ButtonType::Select) => {
+				bus.send(UpdateSystemValue::Preset(self.selected));
+				return true;
+			}
+			(ButtonStage::LongPress, ButtonType::Select) => return true,
+			_ => return false,
+		}
+
+		self.render_preset(previous, display);
+		self.render_preset(self.selected, display);
+		self.render_values(display);
+		false
+	}
+
+	/// Render the name of the preset at `idx`, with the selector if it is selected
+	fn render_preset(&self, idx: u8, display: &mut impl DisplayBus) {
+		display.clear_row(FIRST_ROW + idx);
+		let selector = if idx == self.selected { ">" } else { " " };
+		let _ = ufmt::uwrite!(display, "{} {}", selector, PRESETS[idx as usize].name);
+	}
+
+	/// Render the values of the selected preset
+	fn render_values(&self, display: &mut impl DisplayBus) {
+		let preset = &PRESETS[self.selected as usize];
+		display.clear_row(VALUES_ROW);
+		let _ = ufmt::uwrite!(
+			display,
+			"M{} {}m {}",
+			preset.min_moisture,
+			preset.activate_mins,
+			preset.profile.label()
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		config::{Number, ValueAction},
+		hal::mock::MockDisplay,
+	};
+
+	fn press(button: ButtonType) -> ButtonEvent {
+		ButtonEvent {
+			button,
+			stage: ButtonStage::Release,
+		}
+	}
+
+	#[test]
+	fn selects_and_applies_preset() {
+		let mut display = MockDisplay::new();
+		let mut system_config = SystemConfig::new();
+		let mut bus = Bus::new();
+		let mut presets = Presets::new();
+		presets.render(&mut display);
+		assert_eq!(display.row(FIRST_ROW).trim_end(), "> Tomatoes");
+
+		assert!(!presets.on_press(&press(ButtonType::Left), &mut display, &mut bus));
+		assert_eq!(display.row(FIRST_ROW).trim_end(), "  Tomatoes");
+		assert_eq!(display.row(FIRST_ROW + 3).trim_end(), "> Succulents");
+		assert_eq!(display.row(VALUES_ROW).trim_end(), "M50 5m Drip");
+
+		assert!(presets.on_press(&press(ButtonType::Select), &mut display, &mut bus));
+		bus.apply(&mut system_config);
+		assert_eq!(system_config.min_moisture, 50);
+		assert_eq!(system_config.activate_mins, 5);
+		assert_eq!(system_config.profile, Profile::Drip);
+	}
+
+	#[test]
+	fn presets_are_within_range() {
+		for preset in PRESETS.iter() {
+			let mut system_config = SystemConfig::new();
+			system_config.update(&UpdateSystemValue::Number(
+				Number::Moisture,
+				ValueAction::Set(preset.min_moisture),
+			));
+			system_config.update(&UpdateSystemValue::Number(
+				Number::Time,
+				ValueAction::Set(preset.activate_mins),
+			));
+			assert_eq!(system_config.min_moisture, preset.min_moisture);
+			assert_eq!(system_config.activate_mins, preset.activate_mins);
+		}
+	}
+}
@@ -0,0 +1,184 @@
+//! Output profiles
+//!
+//! The activation time is tuned for spray heads, which put water down quickly. Drip line and soaker
+//! hose put it down much more slowly, and the beds they feed are better watered deeply and less
+//! often. Rather than retuning every value when a bed is switched over, the output profile scales
+//! the activation time, splits it into runs with soaks in between so that the water can sink in,
+//! and sets the shortest time between activations started by the sensors.
+//!
+//! Only the time the valve is open counts towards the activation time, so an activation with soaks
+//! lasts longer than its activation time. The soaks also keep each run under the valve's fail-safe
+//! limit (see `VALVE_MAX_OPEN` in `src/system.rs`), however long the scaled activation time is.
+
+use ufmt::derive::uDebug;
+
+use crate::timer::Duration;
+
+/// Runs and soaks of drip line, in minutes
+const DRIP_CYCLE: SoakCycle = SoakCycle {
+	run_mins: 30,
+	soak_mins: 10,
+};
+/// Runs and soaks of soaker hose, in minutes
+const SOAKER_CYCLE: SoakCycle = SoakCycle {
+	run_mins: 20,
+	soak_mins: 20,
+};
+
+/// Runs and soaks which an activation is split into
+#[derive(Clone, Copy)]
+struct SoakCycle {
+	/// How long in minutes the valve is open for in each run
+	run_mins: u16,
+	/// How long in minutes the valve is closed for after each run
+	soak_mins: u16,
+}
+
+/// Type of output which the valve feeds
+#[derive(uDebug, Clone, Copy, PartialEq, Debug)]
+pub enum Profile {
+	/// Spray heads, which the activation time is tuned for
+	Spray,
+	/// Drip line
+	Drip,
+	/// Soaker hose
+	Soaker,
+}
+
+impl Profile {
+	/// Profile selected after this one in the menu
+	pub fn next(self) -> Self {
+		match self {
+			Self::Spray => Self::Drip,
+			Self::Drip => Self::Soaker,
+			Self::Soaker => Self::Spray,
+		}
+	}
+
+	/// Short name of the profile, shown in the menu and logged over serial
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::Spray => "Spray",
+			Self::Drip => "Drip",
+			Self::Soaker => "Soak",
+		}
+	}
+
+	/// Percentage of the activation time which the valve is open for in total
+	fn duration_percent(self) -> u32 {
+		match self {
+			Self::Spray => 100,
+			Self::Drip => 300,
+			Self::Soaker => 200,
+		}
+	}
+
+	/// Runs and soaks which an activation is split into, if it isn't watered in one go
+	fn soak_cycle(self) -> Option<SoakCycle> {
+		match self {
+			Self::Spray => None,
+			Self::Drip => Some(DRIP_CYCLE),
+			Self::Soaker => Some(SOAKER_CYCLE),
+		}
+	}
+
+	/// Shortest time between the end of an activation and the sensors starting the next one
+	pub fn min_interval(self) -> Duration {
+		match self {
+			Self::Spray => Duration::from_millis(0),
+			Self::Drip => Duration::from_config_mins(12 * 60),
+			Self::Soaker => Duration::from_config_mins(24 * 60),
+		}
+	}
+
+	/// Scale the activation time `duration` to how long the valve should be open for in total
+	pub fn scale(self, duration: Duration) -> Duration {
+		Duration::from_millis(duration.as_millis() / 100 * self.duration_percent())
+	}
+
+	/// How long the valve has been open for, `elapsed` into an activation
+	pub fn watered(self, elapsed: Duration) -> Duration {
+		match self.soak_cycle() {
+			Some(cycle) => {
+				let (run, soak) = cycle.durations();
+				let cycles = elapsed.as_millis() / (run + soak);
+				let into_cycle = elapsed.as_millis() % (run + soak);
+				Duration::from_millis(cycles * run + into_cycle.min(run))
+			}
+			None => elapsed,
+		}
+	}
+
+	/// Whether the valve is closed to let the water soak in, `elapsed` into an activation
+	pub fn is_soaking(self, elapsed: Duration) -> bool {
+		match self.soak_cycle() {
+			Some(cycle) => {
+				let (run, soak) = cycle.durations();
+				elapsed.as_millis() % (run + soak) >= run
+			}
+			None => false,
+		}
+	}
+}
+
+impl SoakCycle {
+	/// Length of each run and soak in milliseconds
+	fn durations(self) -> (u32, u32) {
+		(
+			Duration::from_config_mins(self.run_mins).as_millis(),
+			Duration::from_config_mins(self.soak_mins).as_millis(),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn mins(mins: u16) -> Duration {
+		Duration::from_config_mins(mins)
+	}
+
+	#[test]
+	fn spray_waters_in_one_go() {
+		assert_eq!(
+			Profile::Spray.scale(mins(10)).as_millis(),
+			mins(10).as_millis()
+		);
+		assert_eq!(
+			Profile::Spray.watered(mins(45)).as_millis(),
+			mins(45).as_millis()
+		);
+		assert!(!Profile::Spray.is_soaking(mins(45)));
+	}
+
+	#[test]
+	fn drip_waters_longer_in_runs() {
+		assert_eq!(
+			Profile::Drip.scale(mins(10)).as_millis(),
+			mins(30).as_millis()
+		);
+
+		// Run, soak, and then the second run.
+		assert!(!Profile::Drip.is_soaking(mins(29)));
+		assert!(Profile::Drip.is_soaking(mins(30)));
+		assert_eq!(
+			Profile::Drip.watered(mins(35)).as_millis(),
+			mins(30).as_millis()
+		);
+		assert!(!Profile::Drip.is_soaking(mins(40)));
+		assert_eq!(
+			Profile::Drip.watered(mins(45)).as_millis(),
+			mins(35).as_millis()
+		);
+	}
+
+	#[test]
+	fn cycles_through_profiles() {
+		let mut profile = Profile::Spray;
+		for _ in 0..3 {
+			profile = profile.next();
+		}
+		assert_eq!(profile, Profile::Spray);
+	}
+}
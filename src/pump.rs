@@ -0,0 +1,83 @@
+//! PWM pump speed control
+//!
+//! A small DC pump can be driven through a MOSFET on D11, using fast PWM on Timer2, so that its
+//! flow can be turned down rather than only switched on and off with a relay.
+
+use arduino_hal::{
+	hal::port::PB3,
+	pac::TC2,
+	port::{mode::Output, Pin},
+};
+
+use crate::{
+	hal::Clock,
+	timer::{Duration, TIMER},
+};
+
+/// How often the duty cycle is stepped up while the pump is starting
+const RAMP_INTERVAL: Duration = Duration::from_millis(20);
+/// Amount to step the duty cycle up by while the pump is starting
+///
+/// The pump takes roughly a second to get to full speed.
+const RAMP_STEP: u8 = 5;
+
+/// DC pump driven with PWM
+pub struct Pump {
+	tc2: TC2,
+	_pin: Pin<Output, PB3>,
+	/// Current duty cycle, out of 255
+	duty: u8,
+	/// [`TIMER`] clock value of the last time the duty cycle was stepped up
+	last_ramp_ms: u32,
+}
+
+impl Pump {
+	/// Create a new [`Pump`] from Timer2 and its output pin, with the pump stopped
+	pub fn new(tc2: TC2, pin: Pin<Output, PB3>) -> Self {
+		tc2.tccr2a.write(|w| w.wgm2().pwm_fast());
+		tc2.tccr2b.write(|w| w.cs2().prescale_64());
+
+		Self {
+			tc2,
+			_pin: pin,
+			duty: 0,
+			last_ramp_ms: 0,
+		}
+	}
+
+	/// Run the pump at `flow_percent` of its full speed, or stop it
+	///
+	/// The pump is started slowly (soft-started) to avoid a surge of current and water, but is
+	/// slowed down or stopped straight away.
+	pub fn update(&mut self, running: bool, flow_percent: u16) {
+		let target = if running {
+			(flow_percent.min(100) as u32 * 255 / 100) as u8
+		} else {
+			0
+		};
+
+		if target < self.duty {
+			self.set_duty(target);
+		} else if target > self.duty && TIMER.has_elapsed(self.last_ramp_ms, RAMP_INTERVAL) {
+			self.last_ramp_ms = TIMER.millis();
+			self.set_duty(self.duty.saturating_add(RAMP_STEP).min(target));
+		}
+	}
+
+	/// Set the duty cycle of the output
+	fn set_duty(&mut self, duty: u8) {
+		self.duty = duty;
+		if duty == 0 {
+			// Fast PWM still outputs a short pulse with a duty cycle of zero, so disconnect the
+			// output from the timer, which leaves the pin low.
+			self.tc2
+				.tccr2a
+				.write(|w| w.wgm2().pwm_fast().com2a().disconnected());
+		} else {
+			self.tc2.ocr2a.write(|w| unsafe { w.bits(duty) });
+			self.tc2
+				.tccr2a
+				.write(|w| w.wgm2().pwm_fast().com2a().match_clear());
+		}
+	}
+}
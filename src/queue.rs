@@ -0,0 +1,60 @@
+//! Fixed-capacity first-in, first-out queue
+
+/// Fixed-capacity first-in, first-out queue backed by a ring buffer
+pub struct Queue<T: Copy, const N: usize> {
+	buf: [Option<T>; N],
+	/// Index of the oldest item
+	head: usize,
+	/// Amount of items in the queue
+	len: usize,
+}
+
+impl<T: Copy, const N: usize> Queue<T, N> {
+	/// Create a new, empty [`Queue`]
+	pub fn new() -> Self {
+		Self {
+			buf: [None; N],
+			head: 0,
+			len: 0,
+		}
+	}
+
+	/// Amount of items which can still be added before the queue is full
+	pub fn remaining(&self) -> usize {
+		N - self.len
+	}
+
+	/// Add an item to the back of the queue
+	///
+	/// If the queue is full, the item is dropped and `false` is returned.
+	pub fn push(&mut self, item: T) -> bool {
+		if self.len == N {
+			return false;
+		}
+
+		self.buf[(self.head + self.len) % N] = Some(item);
+		self.len += 1;
+		true
+	}
+
+	/// Get the item `idx` places from the front of the queue, without removing it
+	pub fn get(&self, idx: usize) -> Option<T> {
+		if idx >= self.len {
+			return None;
+		}
+
+		self.buf[(self.head + idx) % N]
+	}
+
+	/// Remove the item at the front of the queue
+	pub fn pop(&mut self) -> Option<T> {
+		if self.len == 0 {
+			return None;
+		}
+
+		let item = self.buf[self.head].take();
+		self.head = (self.head + 1) % N;
+		self.len -= 1;
+		item
+	}
+}
@@ -0,0 +1,182 @@
+//! Rain delay
+//!
+//! Without a rain gauge (see [`crate::raingauge`]), rain is detected from two sensors instead: the
+//! hourly moisture sample (see [`crate::trend`]) rising without the system having watered, while
+//! the light sensor reads that it is overcast or dark. A rise in full sun is more likely to be a
+//! neighbour's sprinkler or a hose, so it doesn't count.
+//!
+//! After rain, the sensors are held off from activating the system for [`HOLD_MINS`]. The hold
+//! decays once a minute, by more while it is sunny or hot, as the soil then dries out faster. The
+//! hours of the hold left are shown on the soil screen.
+
+use crate::{
+	display::{align, BODY_START_ROW, ROW_LENGTH},
+	fmt::Buffer,
+	hal::{Clock, DisplayBus},
+	lang,
+	timer::{Duration, TIMER},
+};
+
+/// Least the moisture has to rise by in an hour without watering for it to have rained
+const MIN_RAIN_RISE: u16 = 30;
+/// Light reading below which it is overcast or dark, so that a rise in the moisture can be rain
+const OVERCAST_LIGHT: u16 = 400;
+/// Light reading above which it is sunny, which decays the hold faster
+const SUNNY_LIGHT: u16 = 700;
+/// Temperature in tenths of a degree above which it is hot, which decays the hold faster
+const HOT_TEMPERATURE: i16 = 250;
+/// Minutes the sensors are held off for after rain, while it is neither sunny nor hot
+const HOLD_MINS: u16 = 24 * 60;
+/// How often the hold decays
+const DECAY_INTERVAL: Duration = Duration::from_mins(1);
+/// Row of the soil screen the hold is shown on, between the axis of the sparkline and the anomaly
+const DELAY_ROW: u8 = BODY_START_ROW + 4;
+
+/// Hold on watering after rain
+pub struct RainDelay {
+	/// Minutes of the hold left, at the rate it decays while it is neither sunny nor hot
+	remaining_mins: u16,
+	/// [`TIMER`] clock value of the last time the hold decayed
+	last_decay_ms: u32,
+}
+
+impl RainDelay {
+	/// Create a new [`RainDelay`], which isn't holding
+	pub fn new() -> Self {
+		Self {
+			remaining_mins: 0,
+			last_decay_ms: 0,
+		}
+	}
+
+	/// Whether the sensors are held off from activating the system
+	pub fn is_holding(&self) -> bool {
+		self.remaining_mins != 0
+	}
+
+	/// Hours of the hold left, rounded up
+	pub fn hours_left(&self) -> u16 {
+		let (hours, mins) = (self.remaining_mins / 60, self.remaining_mins % 60);
+		hours + (mins > 0) as u16
+	}
+
+	/// Start the hold if the moisture rose by `rise` without watering while the light read `light`
+	///
+	/// Returns `true` if it has rained.
+	pub fn check_rain(&mut self, rise: u16, light: u16) -> bool {
+		if rise < MIN_RAIN_RISE || light >= OVERCAST_LIGHT {
+			return false;
+		}
+
+		self.start();
+		true
+	}
+
+	/// Start the hold, such as after the rain gauge counted rain
+	pub fn start(&mut self) {
+		self.remaining_mins = HOLD_MINS;
+		self.last_decay_ms = TIMER.millis();
+	}
+
+	/// Decay the hold once every [`DECAY_INTERVAL`], by a minute more each for it being sunny and it
+	/// being hot
+	///
+	/// `read_light` is only called when the hold decays. `temperature` is the latest temperature in
+	/// tenths of a degree, if there is one. Returns `true` if the hours left changed.
+	pub fn update(&mut self, read_light: impl FnOnce() -> u16, temperature: Option<i16>) -> bool {
+		if !self.is_holding() || !TIMER.has_elapsed(self.last_decay_ms, DECAY_INTERVAL) {
+			return false;
+		}
+		self.last_decay_ms = self.last_decay_ms.wrapping_add(DECAY_INTERVAL.as_millis());
+
+		let mut decay = 1;
+		if read_light() > SUNNY_LIGHT {
+			decay += 1;
+		}
+		if let Some(temperature) = temperature {
+			if temperature > HOT_TEMPERATURE {
+				decay += 1;
+			}
+		}
+
+		let hours_left = self.hours_left();
+		self.remaining_mins = self.remaining_mins.saturating_sub(decay);
+		self.hours_left() != hours_left
+	}
+
+	/// Render the hours of the hold left on the soil screen, such as `Rain delay:  14h`
+	pub fn render(&self, display: &mut impl DisplayBus) {
+		display.clear_row(DELAY_ROW);
+		if !self.is_holding() {
+			return;
+		}
+
+		let mut hours = Buffer::<6>::new();
+		let _ = ufmt::uwrite!(hours, "{}h", self.hours_left());
+		let _ = ufmt::uwrite!(
+			display,
+			"{}",
+			align(lang::RAIN_DELAY, hours.as_str(), ROW_LENGTH)
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		hal::mock::MockDisplay,
+		timer::{self, tests::TimersGuard},
+	};
+
+	/// Decay the hold for `mins` minutes with the given readings
+	fn decay_for(rain_delay: &mut RainDelay, mins: u16, light: u16, temperature: Option<i16>) {
+		for _ in 0..mins {
+			timer::advance(DECAY_INTERVAL.as_millis());
+			rain_delay.update(|| light, temperature);
+		}
+	}
+
+	#[test]
+	fn rain_needs_a_rise_while_overcast() {
+		let _timers = TimersGuard::take();
+		let mut rain_delay = RainDelay::new();
+		assert!(!rain_delay.check_rain(MIN_RAIN_RISE - 1, 100));
+		assert!(!rain_delay.check_rain(80, OVERCAST_LIGHT));
+		assert!(!rain_delay.is_holding());
+
+		assert!(rain_delay.check_rain(MIN_RAIN_RISE, 100));
+		assert!(rain_delay.is_holding());
+		assert_eq!(rain_delay.hours_left(), 24);
+	}
+
+	#[test]
+	fn hold_decays_faster_while_sunny_and_hot() {
+		let _timers = TimersGuard::take();
+		let mut rain_delay = RainDelay::new();
+		rain_delay.check_rain(80, 100);
+
+		// An hour without sun or heat takes an hour off.
+		decay_for(&mut rain_delay, 60, 300, Some(150));
+		assert_eq!(rain_delay.hours_left(), 23);
+
+		// A hot, sunny hour takes three hours off.
+		decay_for(&mut rain_delay, 60, 900, Some(300));
+		assert_eq!(rain_delay.hours_left(), 20);
+
+		// Without a temperature, only the sun counts.
+		decay_for(&mut rain_delay, 10 * 60, 900, None);
+		assert!(!rain_delay.is_holding());
+	}
+
+	#[test]
+	fn renders_hours_left() {
+		let _timers = TimersGuard::take();
+		let mut display = MockDisplay::new();
+		let mut rain_delay = RainDelay::new();
+		rain_delay.check_rain(80, 100);
+		decay_for(&mut rain_delay, 10 * 60 + 1, 300, None);
+		rain_delay.render(&mut display);
+		assert_eq!(display.row(DELAY_ROW), "Rain delay:  14h");
+	}
+}
@@ -0,0 +1,288 @@
+//! Wireless rain gauge
+//!
+//! With a 433MHz receiver module on A3 (see [`receiver`]), the rain gauge of a cheap weather
+//! station can stand in for detecting rain from the moisture and light sensors. Many of these
+//! sensors send their readings with the same on-off keyed, pulse-distance protocol: every bit is a
+//! short pulse, followed by a short gap for a 0 or a longer gap for a 1, and each message of
+//! [`MESSAGE_BITS`] bits is ended by a much longer sync gap. A message is sent several times in a
+//! row, every minute or so. Its bits, most significant first, are:
+//!
+//! - 0-7: ID of the sensor, which is chosen again whenever its batteries are changed;
+//! - 8: Whether its battery is low;
+//! - 9-10: `11` for a rain gauge, otherwise a temperature sensor;
+//! - 11: Whether the sensor's button was pressed, which is ignored;
+//! - 12-27: Rain gauge: total tips of the bucket, which wraps around. Temperature sensor: the
+//!   temperature in tenths of a degree in bits 12-23, as a signed 12-bit value;
+//! - 28-31: Unused;
+//! - 32-35: Sum of the first 8 nibbles, as a checksum.
+//!
+//! The gauge pairs with the first rain gauge it hears, so that a neighbour's isn't counted. Once
+//! [`MIN_RAIN_TIPS`] tips are counted within [`RAIN_WINDOW`], it has rained, and watering is held
+//! off (see [`crate::raindelay`]).
+
+#[cfg(all(feature = "atmega328p", feature = "rain-gauge"))]
+pub mod receiver;
+
+use crate::{
+	hal::Clock,
+	timer::{Duration, TIMER},
+};
+
+/// Amount of bits in a message
+const MESSAGE_BITS: u8 = 36;
+/// Shortest and longest pulse before each gap, in microseconds
+const PULSE_MICROS: (u16, u16) = (300, 800);
+/// Shortest and longest gap of a 0, in microseconds
+const ZERO_MICROS: (u16, u16) = (700, 1_400);
+/// Shortest and longest gap of a 1, in microseconds
+const ONE_MICROS: (u16, u16) = (1_600, 2_600);
+/// Shortest and longest sync gap after a message, in microseconds
+const SYNC_MICROS: (u16, u16) = (3_500, 10_000);
+/// Type bits of a rain gauge's message
+const RAIN_TYPE: u8 = 0b11;
+/// Most tips a shower can add between two messages, above which the total is assumed to have been
+/// reset, such as after the batteries were changed
+const MAX_NEW_TIPS: u16 = 400;
+/// Least tips which have to be counted within [`RAIN_WINDOW`] for it to have rained, at 0.25mm
+/// each for most gauges
+const MIN_RAIN_TIPS: u16 = 4;
+/// How long the tips are counted over
+const RAIN_WINDOW: Duration = Duration::from_mins(60);
+
+/// Whether `micros` is between the shortest and longest of `range`
+fn within(micros: u16, range: (u16, u16)) -> bool {
+	micros >= range.0 && micros <= range.1
+}
+
+/// Decodes the pulses and gaps received into messages
+pub struct Decoder {
+	/// Bits received so far, the latest in the least significant bit
+	bits: u64,
+	/// Amount of bits received so far
+	len: u8,
+}
+
+impl Decoder {
+	/// Create a new [`Decoder`] without any bits received
+	pub const fn new() -> Self {
+		Self { bits: 0, len: 0 }
+	}
+
+	/// Decode a pulse (if `high`) or a gap lasting `micros`
+	///
+	/// Returns the bits of a message once its sync gap ends it. Anything which doesn't fit the
+	/// protocol, such as noise, starts over.
+	pub fn on_level(&mut self, high: bool, micros: u16) -> Option<u64> {
+		if high {
+			if !within(micros, PULSE_MICROS) {
+				self.len = 0;
+			}
+			return None;
+		}
+
+		let bit = if within(micros, ZERO_MICROS) {
+			0
+		} else if within(micros, ONE_MICROS) {
+			1
+		} else {
+			let complete = within(micros, SYNC_MICROS) && self.len == MESSAGE_BITS;
+			self.len = 0;
+			return if complete { Some(self.bits) } else { None };
+		};
+
+		if self.len == MESSAGE_BITS {
+			self.len = 0;
+		}
+		self.bits = if self.len == 0 { 0 } else { self.bits << 1 } | bit;
+		self.len += 1;
+		None
+	}
+}
+
+/// Reading of a sensor
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Reading {
+	/// Total tips of a rain gauge's bucket
+	Rain(u16),
+	/// Temperature in tenths of a degree
+	Temperature(i16),
+}
+
+/// Message of a sensor
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Message {
+	/// ID of the sensor
+	pub id: u8,
+	/// Whether the sensor's battery is low
+	pub battery_low: bool,
+	pub reading: Reading,
+}
+
+impl Message {
+	/// Parse the bits of a message, if its checksum matches
+	pub fn parse(bits: u64) -> Option<Self> {
+		let nibble = |idx: u8| ((bits >> (32 - idx * 4)) & 0xf) as u8;
+		let sum = (0..8).fold(0u8, |sum, idx| sum.wrapping_add(nibble(idx)));
+		if sum & 0xf != nibble(8) {
+			return None;
+		}
+
+		let bits = (bits >> 4) as u32;
+		let reading = if (bits >> 21) as u8 & 0b11 == RAIN_TYPE {
+			Reading::Rain((bits >> 4) as u16)
+		} else {
+			// Shift the sign bit of the 12-bit temperature up into the sign bit of an i16.
+			Reading::Temperature(((bits >> 8) as i16) << 4 >> 4)
+		};
+		Some(Self {
+			id: (bits >> 24) as u8,
+			battery_low: bits & 1 << 23 != 0,
+			reading,
+		})
+	}
+}
+
+/// Rain counted by the paired rain gauge
+pub struct RainGauge {
+	/// ID of the rain gauge, once one has been heard
+	id: Option<u8>,
+	/// Total tips of the last message of the rain gauge
+	last_tips: Option<u16>,
+	/// Tips counted since the start of the window
+	window_tips: u16,
+	/// [`TIMER`] clock value of the first tip of the window
+	window_started_ms: u32,
+}
+
+impl RainGauge {
+	/// Create a new [`RainGauge`], which isn't paired yet
+	pub fn new() -> Self {
+		Self {
+			id: None,
+			last_tips: None,
+			window_tips: 0,
+			window_started_ms: 0,
+		}
+	}
+
+	/// ID of the paired rain gauge, if one has been heard
+	pub fn id(&self) -> Option<u8> {
+		self.id
+	}
+
+	/// Count the tips of a message, if it is from the paired rain gauge
+	///
+	/// Returns `true` once it has rained.
+	pub fn on_message(&mut self, message: &Message) -> bool {
+		let tips = match message.reading {
+			Reading::Rain(tips) => tips,
+			Reading::Temperature(_) => return false,
+		};
+		if *self.id.get_or_insert(message.id) != message.id {
+			return false;
+		}
+
+		// The first message only tells where the total starts from.
+		let new_tips = match self.last_tips.replace(tips) {
+			Some(last_tips) => tips.wrapping_sub(last_tips),
+			None => return false,
+		};
+		if new_tips == 0 || new_tips > MAX_NEW_TIPS {
+			return false;
+		}
+
+		if self.window_tips == 0 || TIMER.has_elapsed(self.window_started_ms, RAIN_WINDOW) {
+			self.window_tips = 0;
+			self.window_started_ms = TIMER.millis();
+		}
+		self.window_tips += new_tips;
+		if self.window_tips < MIN_RAIN_TIPS {
+			return false;
+		}
+
+		self.window_tips = 0;
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::timer::{self, tests::TimersGuard};
+
+	/// Bits of a message from sensor `id` with `payload` in bits 8-31, and its checksum
+	fn message_bits(id: u8, payload: u32) -> u64 {
+		let bits = (id as u64) << 24 | payload as u64 & 0xff_ffff;
+		let sum = (0..8).fold(0u64, |sum, idx| sum + (bits >> (idx * 4) & 0xf));
+		bits << 4 | sum & 0xf
+	}
+
+	/// Message of a rain gauge with `tips`
+	fn rain(id: u8, tips: u16) -> Message {
+		Message::parse(message_bits(id, 0b0110 << 20 | (tips as u32) << 4)).unwrap()
+	}
+
+	/// Feed the pulses and gaps of `bits` to `decoder`, followed by a sync gap
+	fn transmit(decoder: &mut Decoder, bits: u64) -> Option<u64> {
+		for idx in (0..MESSAGE_BITS).rev() {
+			decoder.on_level(true, 500);
+			let gap = if bits >> idx & 1 == 1 { 2_000 } else { 1_000 };
+			assert_eq!(decoder.on_level(false, gap), None);
+		}
+		decoder.on_level(true, 500);
+		decoder.on_level(false, 4_000)
+	}
+
+	#[test]
+	fn decodes_pulses_and_gaps() {
+		let mut decoder = Decoder::new();
+		let bits = message_bits(0x5a, 0x60_1234);
+		assert_eq!(transmit(&mut decoder, bits), Some(bits));
+		assert_eq!(transmit(&mut decoder, bits), Some(bits));
+
+		// A glitch in the middle of a message drops it.
+		decoder.on_level(false, 1_000);
+		decoder.on_level(true, 100);
+		assert_eq!(decoder.on_level(false, 4_000), None);
+	}
+
+	#[test]
+	fn parses_messages() {
+		assert_eq!(
+			Message::parse(message_bits(0x5a, 0xe0_1234)),
+			Some(Message {
+				id: 0x5a,
+				battery_low: true,
+				reading: Reading::Rain(0x0123),
+			})
+		);
+		// -12.5C
+		assert_eq!(
+			Message::parse(message_bits(0x21, 0x0f_830f)).map(|message| message.reading),
+			Some(Reading::Temperature(-125))
+		);
+		assert_eq!(Message::parse(message_bits(0x5a, 0xe0_1234) ^ 1), None);
+	}
+
+	#[test]
+	fn counts_tips_of_the_paired_gauge() {
+		let _timers = TimersGuard::take();
+		let mut gauge = RainGauge::new();
+		assert!(!gauge.on_message(&rain(7, 1000)));
+		assert_eq!(gauge.id(), Some(7));
+
+		// Another gauge is ignored, as is a reset of the total.
+		assert!(!gauge.on_message(&rain(8, 2000)));
+		assert!(!gauge.on_message(&rain(7, 3)));
+		assert!(!gauge.on_message(&rain(7, 5)));
+
+		// Tips which are too far apart don't add up to rain.
+		timer::advance(RAIN_WINDOW.as_millis());
+		assert!(!gauge.on_message(&rain(7, 6)));
+		assert!(gauge.on_message(&rain(7, 9)));
+
+		// The total wraps around.
+		assert!(!gauge.on_message(&rain(7, u16::MAX)));
+		assert!(gauge.on_message(&rain(7, 3)));
+	}
+}
@@ -0,0 +1,86 @@
+//! 433MHz receiver module, with its data output on A3
+//!
+//! The length of each pulse and gap is timed in the pin change interrupt, and decoded there (see
+//! [`Decoder`]), so that no edges are missed while the display is being rendered. The time is taken
+//! from TC0, which also drives [`TIMER`]. If TC0 overflows while the interrupt is running, an edge
+//! can be timed a millisecond short, which only drops that copy of the message.
+
+use arduino_hal::{
+	hal::port::PC3,
+	pac::{EXINT, PORTC, TC0},
+	port::{
+		mode::{Floating, Input},
+		Pin,
+	},
+};
+use avr_device::interrupt::Mutex;
+use core::cell::{Cell, RefCell};
+
+use super::{Decoder, Message};
+use crate::{hal::Clock, timer::TIMER};
+
+/// PCINT11 (A3)
+const RECEIVER_PCINT_MASK: u8 = 0b0000_1000;
+/// Pin change interrupt enable bit for PCINT[14:8]
+const PCIE1: u8 = 0b010;
+/// Microseconds of each count of TC0, which is prescaled by 64 from 16MHz
+const MICROS_PER_COUNT: u32 = 4;
+
+/// Decoder of the pulses and gaps received
+static DECODER: Mutex<RefCell<Decoder>> = Mutex::new(RefCell::new(Decoder::new()));
+/// Time of the last edge, in microseconds
+static LAST_EDGE_MICROS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+/// Bits of the latest message, which hasn't been taken yet
+static MESSAGE: Mutex<Cell<Option<u64>>> = Mutex::new(Cell::new(None));
+
+/// 433MHz receiver module
+pub struct Receiver {
+	/// Data output, held so that the pin can't be used for anything else
+	_data: Pin<Input<Floating>, PC3>,
+}
+
+impl Receiver {
+	/// Create a new [`Receiver`], and enable the pin change interrupt for its data output
+	pub fn new(data: Pin<Input<Floating>, PC3>, exint: &EXINT) -> Self {
+		exint
+			.pcmsk1
+			.modify(|r, w| unsafe { w.bits(r.bits() | RECEIVER_PCINT_MASK) });
+		exint
+			.pcicr
+			.modify(|r, w| unsafe { w.bits(r.bits() | PCIE1) });
+
+		Self { _data: data }
+	}
+
+	/// Take the latest message received since the last call, if its checksum matches
+	pub fn take(&mut self) -> Option<Message> {
+		avr_device::interrupt::free(|cs| MESSAGE.borrow(cs).take()).and_then(Message::parse)
+	}
+}
+
+/// Time since the system started up, in microseconds, which wraps around every 71 minutes
+fn micros() -> u32 {
+	let tc0 = unsafe { &*TC0::ptr() };
+	let counts = tc0.tcnt0.read().bits() as u32;
+	TIMER
+		.millis()
+		.wrapping_mul(1_000)
+		.wrapping_add(counts * MICROS_PER_COUNT)
+}
+
+#[avr_device::interrupt(atmega328p)]
+#[allow(non_snake_case)]
+fn PCINT1() {
+	let now = micros();
+	avr_device::interrupt::free(|cs| {
+		// The level which just ended is the opposite of the one the pin is at now.
+		let portc = unsafe { &*PORTC::ptr() };
+		let high = portc.pinc.read().bits() & RECEIVER_PCINT_MASK == 0;
+
+		let last = LAST_EDGE_MICROS.borrow(cs).replace(now);
+		let micros = now.wrapping_sub(last).min(u16::MAX as u32) as u16;
+		if let Some(bits) = DECODER.borrow(cs).borrow_mut().on_level(high, micros) {
+			MESSAGE.borrow(cs).set(Some(bits));
+		}
+	})
+}
@@ -0,0 +1,161 @@
+//! Recovery of the activation state after a power loss or reset
+//!
+//! The activation state, and how long the system has been in it, are saved to the EEPROM so that
+//! watering (or a suspension) carries on for the remaining time after the system restarts, rather
+//! than leaving the soil half watered. A fault is also saved, so that restarting the system doesn't
+//! clear it without it being acknowledged, and so is winterizing the system, so that it stays
+//! winterized until it is re-enabled.
+
+use crate::{
+	activation::{FaultKind, SystemState},
+	config::SystemConfig,
+	eeprom::{Eeprom, RECOVERY_ADDRESS},
+	hal::Clock,
+	timer::{Duration, SoftTimer, TIMER},
+};
+
+/// How often the time spent in the current state is saved while activated or suspended
+const SAVE_INTERVAL: Duration = Duration::from_mins(1);
+
+/// Stored value of a fault, less its code
+const FAULT_BYTE_OFFSET: u8 = 2;
+/// Stored value of the winterized state, which is clear of the stored values of the faults
+const WINTERIZED_BYTE: u8 = 0x10;
+
+/// Activation state as it is stored in the EEPROM
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SavedState {
+	Waiting,
+	Activated,
+	Suspended,
+	Fault(FaultKind),
+	Winterized,
+}
+
+impl SavedState {
+	/// Get the [`SavedState`] from its stored value
+	fn from_byte(byte: u8) -> Self {
+		match byte {
+			1 => Self::Activated,
+			2 => Self::Suspended,
+			WINTERIZED_BYTE => Self::Winterized,
+			_ => match byte
+				.checked_sub(FAULT_BYTE_OFFSET)
+				.and_then(FaultKind::from_code)
+			{
+				Some(kind) => Self::Fault(kind),
+				None => Self::Waiting,
+			},
+		}
+	}
+
+	/// Get the stored value of the [`SavedState`]
+	fn to_byte(self) -> u8 {
+		match self {
+			Self::Waiting => 0,
+			Self::Activated => 1,
+			Self::Suspended => 2,
+			Self::Fault(kind) => FAULT_BYTE_OFFSET + kind.code(),
+			Self::Winterized => WINTERIZED_BYTE,
+		}
+	}
+
+	/// Timer used to track how long the system has been in the state
+	fn timer(self) -> Option<SoftTimer> {
+		match self {
+			Self::Activated => Some(SoftTimer::Activation),
+			Self::Suspended => Some(SoftTimer::Suspension),
+			Self::Waiting | Self::Fault(_) | Self::Winterized => None,
+		}
+	}
+}
+
+/// Keeps the saved activation state up to date
+pub struct Recovery {
+	/// State which was last saved
+	saved_state: SavedState,
+	/// [`TIMER`] clock value of the last time the state was saved
+	last_save_ms: u32,
+}
+
+impl Recovery {
+	/// Size in bytes of the record stored in the EEPROM
+	pub const SIZE: usize = 5;
+
+	/// Create a new [`Recovery`]
+	pub fn new() -> Self {
+		Self {
+			saved_state: SavedState::Waiting,
+			last_save_ms: 0,
+		}
+	}
+
+	/// Restore the activation state saved before the system was last restarted
+	///
+	/// If the system was activated or suspended, it carries on for the remaining time. Otherwise,
+	/// if there is no remaining time, the system is left waiting. If the system had faulted, it is
+	/// stopped by the same fault again, and if it was winterized, it stays winterized.
+	pub fn restore(&mut self, eeprom: &Eeprom, system_config: &mut SystemConfig) {
+		let mut buf = [0; Self::SIZE];
+		if !eeprom.read_record(RECOVERY_ADDRESS, &mut buf) {
+			return;
+		}
+
+		let state = SavedState::from_byte(buf[0]);
+		if let SavedState::Fault(kind) = state {
+			system_config.activation_state = SystemState::Fault(kind);
+			self.saved_state = state;
+			return;
+		}
+		if let SavedState::Winterized = state {
+			system_config.activation_state = SystemState::Winterized;
+			self.saved_state = state;
+			return;
+		}
+
+		let elapsed_secs = u32::from_le_bytes([buf[1], buf[2], buf[3], buf[4]]);
+		let elapsed = Duration::from_millis(elapsed_secs.saturating_mul(1_000));
+		if elapsed >= system_config.activation_duration() {
+			return;
+		}
+
+		if let Some(timer) = state.timer() {
+			system_config.activation_state = match state {
+				SavedState::Activated => SystemState::Activated,
+				_ => SystemState::Suspended,
+			};
+			timer.start_from(elapsed);
+			self.saved_state = state;
+		}
+	}
+
+	/// Save the activation state if it has changed, or periodically save how long the system has
+	/// been activated or suspended for
+	pub fn update(&mut self, system_config: &SystemConfig, eeprom: &mut Eeprom) {
+		let state = match system_config.activation_state {
+			SystemState::Activated => SavedState::Activated,
+			SystemState::Suspended => SavedState::Suspended,
+			SystemState::Waiting => SavedState::Waiting,
+			SystemState::Fault(kind) => SavedState::Fault(kind),
+			SystemState::Winterized => SavedState::Winterized,
+		};
+
+		let due = state.timer().is_some() && TIMER.has_elapsed(self.last_save_ms, SAVE_INTERVAL);
+		if state == self.saved_state && !due {
+			return;
+		}
+
+		let elapsed_secs = match state.timer() {
+			Some(timer) => timer.elapsed().as_millis() / 1_000,
+			None => 0,
+		};
+
+		let mut buf = [0; Self::SIZE];
+		buf[0] = state.to_byte();
+		buf[1..5].copy_from_slice(&elapsed_secs.to_le_bytes());
+		eeprom.write_record(RECOVERY_ADDRESS, &buf);
+
+		self.saved_state = state;
+		self.last_save_ms = TIMER.millis();
+	}
+}
@@ -0,0 +1,72 @@
+//! Resuming the display where it was left
+//!
+//! The selected menu item and the screen being shown are saved to the EEPROM, so that the system
+//! starts up showing the same thing after a reset, rather than going back to the first item of the
+//! menu. A change is only saved once it has been left alone for [`SAVE_DELAY`], so that scrolling
+//! through the menu doesn't wear out the EEPROM.
+
+use crate::{
+	eeprom::{Eeprom, RESUME_ADDRESS},
+	hal::Clock,
+	timer::{Duration, TIMER},
+};
+
+/// How long the selection has to stay the same before it is saved
+const SAVE_DELAY: Duration = Duration::from_secs(10);
+
+/// Keeps the saved menu selection and screen up to date
+pub struct Resume {
+	/// Record which was last saved
+	saved: [u8; Self::SIZE],
+	/// Record which is waiting to be saved, and the [`TIMER`] clock value of when it changed
+	pending: Option<([u8; Self::SIZE], u32)>,
+}
+
+impl Resume {
+	/// Size in bytes of the record stored in the EEPROM
+	pub const SIZE: usize = 2;
+
+	/// Create a new [`Resume`]
+	pub fn new() -> Self {
+		Self {
+			saved: [0; Self::SIZE],
+			pending: None,
+		}
+	}
+
+	/// Read the menu item which was selected, and the code of the screen which was shown, before
+	/// the system was last restarted
+	///
+	/// Returns `None` if nothing has been saved yet.
+	pub fn restore(&mut self, eeprom: &Eeprom) -> Option<(u8, u8)> {
+		let mut buf = [0; Self::SIZE];
+		if !eeprom.read_record(RESUME_ADDRESS, &mut buf) {
+			return None;
+		}
+
+		self.saved = buf;
+		Some((buf[0], buf[1]))
+	}
+
+	/// Save the selected menu item and the code of the screen being shown, once they have stopped
+	/// changing
+	pub fn update(&mut self, menu_idx: u8, screen: u8, eeprom: &mut Eeprom) {
+		let record = [menu_idx, screen];
+		if record == self.saved {
+			self.pending = None;
+			return;
+		}
+
+		match self.pending {
+			Some((pending, changed_ms)) if pending == record => {
+				if TIMER.has_elapsed(changed_ms, SAVE_DELAY) {
+					eeprom.write_record(RESUME_ADDRESS, &record);
+					self.saved = record;
+					self.pending = None;
+				}
+			}
+			// If the selection has changed again, wait for it to settle from now.
+			_ => self.pending = Some((record, TIMER.millis())),
+		}
+	}
+}
@@ -0,0 +1,91 @@
+//! Safe mode
+//!
+//! If the system starts up with a corrupted configuration in the EEPROM, without a display, or with
+//! a sensor which failed the self-test, it can't be trusted to water when it should (or to stop).
+//! Rather than carrying on with the defaults, it starts up in safe mode: the valve is kept closed,
+//! and only Diagnostics and Reset can be selected in the menu, so that the fault can be found and
+//! the configuration cleared.
+
+use crate::{
+	display::BODY_START_ROW,
+	hal::DisplayBus,
+	lang,
+	selftest::{Outcome, Report},
+};
+
+/// Reason the system started up in safe mode
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Reason {
+	/// A configuration record in the EEPROM failed its checksum, or isn't valid for the firmware
+	Config,
+	/// The display didn't respond on the I2C bus
+	Display,
+	/// A sensor gave a reading at either end of its range
+	Sensor,
+}
+
+impl Reason {
+	/// Reason to start up in safe mode, if there is one
+	///
+	/// `config_valid` is whether the configuration records in the EEPROM are either valid or were
+	/// never written.
+	pub fn check(config_valid: bool, report: &Report) -> Option<Self> {
+		if !config_valid {
+			Some(Self::Config)
+		} else if report.display == Outcome::Fail {
+			Some(Self::Display)
+		} else if report.light == Outcome::Fail || report.moisture == Outcome::Fail {
+			Some(Self::Sensor)
+		} else {
+			None
+		}
+	}
+
+	/// Short description of the reason, shown on the display and logged over serial
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::Config => "Bad config",
+			Self::Display => "No display",
+			Self::Sensor => "Sensor fault",
+		}
+	}
+}
+
+/// Render the reason for safe mode in the body of the display
+pub fn render(reason: Reason, display: &mut impl DisplayBus) {
+	display.clear_body();
+	display.set_position(0, BODY_START_ROW);
+	let _ = ufmt::uwrite!(display, "{}", lang::SAFE_MODE);
+	display.set_position(0, BODY_START_ROW + 2);
+	let _ = ufmt::uwrite!(display, "{}", reason.label());
+	display.set_position(0, BODY_START_ROW + 3);
+	let _ = ufmt::uwrite!(display, "{}", lang::VALVE_CLOSED);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::hal::mock::MockDisplay;
+
+	#[test]
+	fn config_is_checked_first() {
+		let mut report = Report::new();
+		assert_eq!(Reason::check(true, &report), None);
+
+		report.moisture = Outcome::Fail;
+		assert_eq!(Reason::check(true, &report), Some(Reason::Sensor));
+		report.display = Outcome::Fail;
+		assert_eq!(Reason::check(true, &report), Some(Reason::Display));
+		assert_eq!(Reason::check(false, &report), Some(Reason::Config));
+	}
+
+	#[test]
+	fn renders_reason() {
+		let mut display = MockDisplay::new();
+		render(Reason::Sensor, &mut display);
+
+		assert_eq!(display.row(BODY_START_ROW).trim_end(), "SAFE MODE");
+		assert_eq!(display.row(BODY_START_ROW + 2).trim_end(), "Sensor fault");
+		assert_eq!(display.row(BODY_START_ROW + 3).trim_end(), "Valve closed");
+	}
+}
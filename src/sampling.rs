@@ -0,0 +1,202 @@
+//! Interrupt-driven analog sampling
+//!
+//! Rather than waiting for a conversion each time an analog input is read, the ADC converts the
+//! enabled inputs one after another in the background. Each conversion finishes with an interrupt
+//! which stores the result in the [`MAILBOX`] and starts converting the next input. Reading an
+//! input then only takes its latest result, so reading the buttons and sensors doesn't hold up the
+//! tick. Every input is converted again well within a tick, so the results are never stale.
+
+use core::cell::RefCell;
+
+use crate::hal::{free, Mutex};
+
+/// Amount of [`Channel`] variants
+const CHANNEL_COUNT: usize = 5;
+
+/// Analog input which is converted in the background
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Channel {
+	/// Resistor ladder of the buttons
+	Buttons,
+	/// Light sensor
+	Light,
+	/// Moisture sensor
+	Moisture,
+	/// Soil temperature probe of the heat mat
+	SoilProbe,
+	/// Internal bandgap reference, for measuring the supply voltage
+	Bandgap,
+}
+
+impl Channel {
+	/// Every channel, in the order they are converted
+	const ALL: [Self; CHANNEL_COUNT] = [
+		Self::Buttons,
+		Self::Light,
+		Self::Moisture,
+		Self::SoilProbe,
+		Self::Bandgap,
+	];
+
+	/// Whether the first conversion after switching to the channel is thrown away
+	///
+	/// The bandgap reference takes longer to settle than a conversion, so its first result is
+	/// inaccurate.
+	fn needs_settling(self) -> bool {
+		matches!(self, Self::Bandgap)
+	}
+}
+
+/// Latest result of each [`Channel`], written by the ADC interrupt and read by the main loop
+pub struct Mailbox {
+	/// Multiplexer setting of each channel, or `None` if it isn't converted
+	mux: [Option<u8>; CHANNEL_COUNT],
+	/// Latest result of each channel
+	readings: [Option<u16>; CHANNEL_COUNT],
+	/// Index of the channel being converted
+	current: usize,
+	/// Whether the conversion in progress is thrown away
+	settling: bool,
+}
+
+impl Mailbox {
+	/// Create a new [`Mailbox`] with no channels enabled
+	pub const fn new() -> Self {
+		Self {
+			mux: [None; CHANNEL_COUNT],
+			readings: [None; CHANNEL_COUNT],
+			current: 0,
+			settling: false,
+		}
+	}
+
+	/// Convert `channel`, with the ADC's multiplexer set to `mux`
+	pub fn enable(&mut self, channel: Channel, mux: u8) {
+		self.mux[channel as usize] = Some(mux);
+	}
+
+	/// Start again from the first enabled channel
+	///
+	/// Returns the multiplexer setting to start converting with, if any channels are enabled.
+	pub fn restart(&mut self) -> Option<u8> {
+		let first = Channel::ALL
+			.iter()
+			.position(|channel| self.mux[*channel as usize].is_some())?;
+		self.switch_to(first)
+	}
+
+	/// Store the result of the conversion in progress, and move on to the next enabled channel
+	///
+	/// Returns the multiplexer setting to start the next conversion with.
+	pub fn complete(&mut self, reading: u16) -> Option<u8> {
+		if self.settling {
+			// Convert the same channel again, now that it has settled.
+			self.settling = false;
+			return self.mux[self.current];
+		}
+
+		self.readings[self.current] = Some(reading);
+		let next = (1..=CHANNEL_COUNT)
+			.map(|offset| (self.current + offset) % CHANNEL_COUNT)
+			.find(|idx| self.mux[*idx].is_some())?;
+		self.switch_to(next)
+	}
+
+	/// Latest result of `channel`, if it has been converted yet
+	pub fn latest(&self, channel: Channel) -> Option<u16> {
+		self.readings[channel as usize]
+	}
+
+	/// Make the channel at `idx` the one being converted
+	fn switch_to(&mut self, idx: usize) -> Option<u8> {
+		self.current = idx;
+		self.settling = Channel::ALL[idx].needs_settling();
+		self.mux[idx]
+	}
+}
+
+/// Results of the conversions, shared with the ADC interrupt
+pub static MAILBOX: Mutex<RefCell<Mailbox>> = Mutex::new(RefCell::new(Mailbox::new()));
+
+/// Latest result of `channel`, if it has been converted yet
+pub fn latest(channel: Channel) -> Option<u16> {
+	free(|cs| MAILBOX.borrow(cs).borrow().latest(channel))
+}
+
+/// Reference voltage of the conversions (AVcc), in the ADC's multiplexer register
+#[cfg(feature = "atmega328p")]
+const ADMUX_AVCC: u8 = 0b0100_0000;
+/// Enable the ADC, start a conversion, and interrupt when it finishes, with the clock divided by
+/// 128 (the same as the HAL's default)
+#[cfg(feature = "atmega328p")]
+const ADCSRA_START: u8 = 0b1100_1111;
+
+/// Start converting the enabled channels in the background
+#[cfg(feature = "atmega328p")]
+pub fn start() {
+	if let Some(mux) = free(|cs| MAILBOX.borrow(cs).borrow_mut().restart()) {
+		start_conversion(mux);
+	}
+}
+
+/// Start a single conversion with the multiplexer set to `mux`
+#[cfg(feature = "atmega328p")]
+fn start_conversion(mux: u8) {
+	let adc = unsafe { &*arduino_hal::pac::ADC::ptr() };
+	adc.admux.write(|w| unsafe { w.bits(ADMUX_AVCC | mux) });
+	adc.adcsra.write(|w| unsafe { w.bits(ADCSRA_START) });
+}
+
+#[cfg(feature = "atmega328p")]
+#[avr_device::interrupt(atmega328p)]
+#[allow(non_snake_case)]
+fn ADC() {
+	let adc = unsafe { &*arduino_hal::pac::ADC::ptr() };
+	let reading = adc.adc.read().bits();
+	if let Some(mux) = free(|cs| MAILBOX.borrow(cs).borrow_mut().complete(reading)) {
+		start_conversion(mux);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn enabled_channels_are_converted_in_turn() {
+		let mut mailbox = Mailbox::new();
+		mailbox.enable(Channel::Light, 0);
+		mailbox.enable(Channel::Moisture, 1);
+		mailbox.enable(Channel::Buttons, 2);
+
+		assert_eq!(mailbox.restart(), Some(2));
+		assert_eq!(mailbox.complete(200), Some(0));
+		assert_eq!(mailbox.complete(500), Some(1));
+		assert_eq!(mailbox.complete(300), Some(2));
+
+		assert_eq!(mailbox.latest(Channel::Buttons), Some(200));
+		assert_eq!(mailbox.latest(Channel::Light), Some(500));
+		assert_eq!(mailbox.latest(Channel::Moisture), Some(300));
+		assert_eq!(mailbox.latest(Channel::SoilProbe), None);
+	}
+
+	#[test]
+	fn first_bandgap_conversion_is_thrown_away() {
+		let mut mailbox = Mailbox::new();
+		mailbox.enable(Channel::Light, 0);
+		mailbox.enable(Channel::Bandgap, 14);
+
+		assert_eq!(mailbox.restart(), Some(0));
+		assert_eq!(mailbox.complete(500), Some(14));
+		assert_eq!(mailbox.complete(1_023), Some(14));
+		assert_eq!(mailbox.latest(Channel::Bandgap), None);
+		assert_eq!(mailbox.complete(220), Some(0));
+		assert_eq!(mailbox.latest(Channel::Bandgap), Some(220));
+	}
+
+	#[test]
+	fn nothing_is_converted_without_channels() {
+		let mut mailbox = Mailbox::new();
+		assert_eq!(mailbox.restart(), None);
+	}
+}
@@ -0,0 +1,194 @@
+//! Cooperative scheduler for the main loop
+//!
+//! Rather than doing everything on every tick, the work of the system is split into tasks which
+//! run at their own rates:
+//!
+//! - [`Task::Input`]: The buttons, the USART and the activation state, 100 times a second;
+//! - [`Task::Sensors`]: The sensors which change slowly, such as the light for dimming the display
+//!   and the soil temperature, once a second;
+//! - [`Task::Render`]: Output queued for the display, on the ticks when there is some;
+//! - [`Task::Telemetry`]: A telemetry record, once every **Telem s** seconds (10 by default).
+//!
+//! The main loop asks the [`Scheduler`] which tasks are due each time it wakes up, and runs each of
+//! them to completion before sleeping until the next tick. None of them wait on anything, so a slow
+//! task only delays the others until the next tick.
+
+use crate::{
+	hal::Clock,
+	timer::{Duration, TIMER},
+};
+
+/// How often the input is handled, the same as `TICK_INTERVAL` in `src/power.rs`
+const INPUT_INTERVAL: Duration = Duration::from_millis(10);
+/// How often the slowly changing sensors are sampled
+const SENSORS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Amount of [`Task`] variants
+const TASK_COUNT: usize = 4;
+
+/// Work which the main loop runs when it is due
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Task {
+	/// Handle button presses and commands, and move the system on to its next state
+	Input,
+	/// Sample the sensors which change slowly
+	Sensors,
+	/// Send queued output to the display
+	Render,
+	/// Send a telemetry record
+	Telemetry,
+}
+
+/// Keeps track of when each [`Task`] last ran
+pub struct Scheduler {
+	/// [`TIMER`] clock value of when each task was last due
+	due_ms: [u32; TASK_COUNT],
+	/// Whether each task has been requested
+	requested: [bool; TASK_COUNT],
+	/// How often a telemetry record is sent, as configured
+	telemetry_interval: Duration,
+}
+
+impl Scheduler {
+	/// Create a new [`Scheduler`] which sends a telemetry record every `telemetry_interval`, with
+	/// every periodic task due straight away
+	pub fn new(telemetry_interval: Duration) -> Self {
+		let mut scheduler = Self {
+			due_ms: [0; TASK_COUNT],
+			requested: [false; TASK_COUNT],
+			telemetry_interval,
+		};
+
+		// Count each task as having been due one interval ago.
+		let now_ms = TIMER.millis();
+		for task in [Task::Input, Task::Sensors, Task::Render, Task::Telemetry].iter() {
+			scheduler.due_ms[*task as usize] =
+				now_ms.wrapping_sub(scheduler.interval(*task).map_or(0, Duration::as_millis));
+		}
+		scheduler
+	}
+
+	/// Send a telemetry record every `interval` from now on
+	///
+	/// The next record is due `interval` after the last one.
+	pub fn set_telemetry_interval(&mut self, interval: Duration) {
+		self.telemetry_interval = interval;
+	}
+
+	/// How often `task` runs, or `None` if it only runs when it is requested
+	fn interval(&self, task: Task) -> Option<Duration> {
+		match task {
+			Task::Input => Some(INPUT_INTERVAL),
+			Task::Sensors => Some(SENSORS_INTERVAL),
+			Task::Render => None,
+			Task::Telemetry => Some(self.telemetry_interval),
+		}
+	}
+
+	/// Ask for a task to run on the next tick
+	pub fn request(&mut self, task: Task) {
+		self.requested[task as usize] = true;
+	}
+
+	/// Whether `task` should run now, in which case it is counted as having run
+	///
+	/// A periodic task keeps to its rate even if the tick it is due on is late. If it falls more
+	/// than one interval behind, it runs once and starts again from now, rather than running again
+	/// and again to catch up.
+	pub fn is_due(&mut self, task: Task) -> bool {
+		let idx = task as usize;
+		if self.requested[idx] {
+			self.requested[idx] = false;
+			return true;
+		}
+
+		let interval = match self.interval(task) {
+			Some(interval) => interval,
+			None => return false,
+		};
+		if !TIMER.has_elapsed(self.due_ms[idx], interval) {
+			return false;
+		}
+
+		let next_due_ms = self.due_ms[idx].wrapping_add(interval.as_millis());
+		self.due_ms[idx] = if TIMER.has_elapsed(next_due_ms, interval) {
+			TIMER.millis()
+		} else {
+			next_due_ms
+		};
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::timer::{self, tests::TimersGuard};
+
+	/// Default telemetry interval
+	const TELEMETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+	/// Amount of times `task` runs over `millis`, with a tick every 10ms
+	fn runs(scheduler: &mut Scheduler, task: Task, millis: u32) -> u32 {
+		let mut runs = 0;
+		for _ in 0..millis / 10 {
+			timer::advance(10);
+			if scheduler.is_due(task) {
+				runs += 1;
+			}
+		}
+		runs
+	}
+
+	#[test]
+	fn periodic_tasks_run_at_their_rate() {
+		let _timers = TimersGuard::take();
+		for (task, millis, expected) in [
+			(Task::Input, 1_000, 100),
+			(Task::Sensors, 10_000, 10),
+			(Task::Telemetry, 60_000, 6),
+		]
+		.iter()
+		{
+			let mut scheduler = Scheduler::new(TELEMETRY_INTERVAL);
+			assert!(scheduler.is_due(*task));
+			assert_eq!(runs(&mut scheduler, *task, *millis), *expected);
+		}
+	}
+
+	#[test]
+	fn late_tasks_do_not_catch_up() {
+		let _timers = TimersGuard::take();
+		let mut scheduler = Scheduler::new(TELEMETRY_INTERVAL);
+		assert!(scheduler.is_due(Task::Sensors));
+
+		timer::advance(5_500);
+		assert!(scheduler.is_due(Task::Sensors));
+		assert!(!scheduler.is_due(Task::Sensors));
+		assert_eq!(runs(&mut scheduler, Task::Sensors, 900), 0);
+		assert_eq!(runs(&mut scheduler, Task::Sensors, 100), 1);
+	}
+
+	#[test]
+	fn telemetry_follows_the_configured_interval() {
+		let _timers = TimersGuard::take();
+		let mut scheduler = Scheduler::new(TELEMETRY_INTERVAL);
+		assert!(scheduler.is_due(Task::Telemetry));
+
+		scheduler.set_telemetry_interval(Duration::from_secs(30));
+		assert_eq!(runs(&mut scheduler, Task::Telemetry, 29_990), 0);
+		assert_eq!(runs(&mut scheduler, Task::Telemetry, 10), 1);
+		assert_eq!(runs(&mut scheduler, Task::Telemetry, 60_000), 2);
+	}
+
+	#[test]
+	fn render_only_runs_when_requested() {
+		let _timers = TimersGuard::take();
+		let mut scheduler = Scheduler::new(TELEMETRY_INTERVAL);
+		assert_eq!(runs(&mut scheduler, Task::Render, 1_000), 0);
+
+		scheduler.request(Task::Render);
+		assert!(scheduler.is_due(Task::Render));
+		assert!(!scheduler.is_due(Task::Render));
+	}
+}
@@ -0,0 +1,148 @@
+//! Self-test of the peripherals when the system starts up
+//!
+//! Catches wiring mistakes before the system sits waiting for a day without watering: the display
+//! has to respond on the I2C bus, and the sensors have to give readings away from either end of the
+//! ADC's range, which is where a disconnected or shorted sensor reads. The valve can also be opened
+//! briefly, so that the relay (or the click of the valve) can be checked by ear.
+
+use crate::{
+	display::{self, BODY_START_ROW, ROW_LENGTH},
+	hal::DisplayBus,
+};
+
+/// Lowest reading which a connected sensor is expected to give
+const PLAUSIBLE_MIN: u16 = 3;
+/// Highest reading which a connected sensor is expected to give
+const PLAUSIBLE_MAX: u16 = 1020;
+
+/// Result of a single check
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outcome {
+	Pass,
+	Fail,
+	/// The check wasn't run
+	Skipped,
+}
+
+impl Outcome {
+	/// Outcome of a check which passes if `passed` is `true`
+	pub fn from_bool(passed: bool) -> Self {
+		if passed {
+			Self::Pass
+		} else {
+			Self::Fail
+		}
+	}
+
+	/// Short name of the outcome, shown on the display and logged over serial
+	pub fn label(&self) -> &'static str {
+		match self {
+			Self::Pass => "ok",
+			Self::Fail => "FAIL",
+			Self::Skipped => "-",
+		}
+	}
+}
+
+/// Whether a sensor reading is away from either end of the ADC's range
+pub fn is_plausible(reading: u16) -> bool {
+	(PLAUSIBLE_MIN..=PLAUSIBLE_MAX).contains(&reading)
+}
+
+/// Outcome of each check
+pub struct Report {
+	/// The display responded on the I2C bus
+	pub display: Outcome,
+	/// The light sensor reading was plausible
+	pub light: Outcome,
+	/// The moisture sensor reading was plausible
+	pub moisture: Outcome,
+	/// The valve was opened and closed again
+	pub valve: Outcome,
+}
+
+impl Report {
+	/// Create a new [`Report`], with every check skipped
+	pub fn new() -> Self {
+		Self {
+			display: Outcome::Skipped,
+			light: Outcome::Skipped,
+			moisture: Outcome::Skipped,
+			valve: Outcome::Skipped,
+		}
+	}
+
+	/// Label and outcome of each check
+	pub fn checks(&self) -> [(&'static str, Outcome); 4] {
+		[
+			("Display", self.display),
+			("Light", self.light),
+			("Moisture", self.moisture),
+			("Valve", self.valve),
+		]
+	}
+
+	/// Whether none of the checks failed
+	pub fn passed(&self) -> bool {
+		self.checks()
+			.iter()
+			.all(|(_, outcome)| *outcome != Outcome::Fail)
+	}
+
+	/// Render the outcome of each check in the body of the display
+	pub fn render(&self, display: &mut impl DisplayBus) {
+		display.clear_body();
+		display.set_position(0, BODY_START_ROW);
+		let summary = if self.passed() { "PASS" } else { "FAIL" };
+		let _ = ufmt::uwrite!(display, "Self-test: {}", summary);
+
+		for (idx, (label, outcome)) in self.checks().iter().enumerate() {
+			display.set_position(0, BODY_START_ROW + 2 + idx as u8);
+			let _ = ufmt::uwrite!(
+				display,
+				"{}",
+				display::align(label, outcome.label(), ROW_LENGTH)
+			);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::hal::mock::MockDisplay;
+
+	#[test]
+	fn readings_at_either_end_are_implausible() {
+		assert!(!is_plausible(0));
+		assert!(is_plausible(PLAUSIBLE_MIN));
+		assert!(is_plausible(512));
+		assert!(is_plausible(PLAUSIBLE_MAX));
+		assert!(!is_plausible(1023));
+	}
+
+	#[test]
+	fn skipped_checks_dont_fail() {
+		let mut report = Report::new();
+		report.display = Outcome::Pass;
+		assert!(report.passed());
+
+		report.moisture = Outcome::Fail;
+		assert!(!report.passed());
+	}
+
+	#[test]
+	fn renders_summary_and_checks() {
+		let mut display = MockDisplay::new();
+		let mut report = Report::new();
+		report.display = Outcome::Pass;
+		report.light = Outcome::Pass;
+		report.moisture = Outcome::Fail;
+		report.render(&mut display);
+
+		assert_eq!(display.row(BODY_START_ROW).trim_end(), "Self-test: FAIL");
+		assert_eq!(display.row(BODY_START_ROW + 2), "Display:      ok");
+		assert_eq!(display.row(BODY_START_ROW + 4), "Moisture:   FAIL");
+		assert_eq!(display.row(BODY_START_ROW + 5), "Valve:         -");
+	}
+}
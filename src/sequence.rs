@@ -0,0 +1,276 @@
+//! Sequence of opening and closing the valve
+//!
+//! The master valve is opened before the valve, which is opened before the pump is started. The
+//! pump is stopped before the valve is closed, which is closed before the master valve. Each stage
+//! waits for its delay (**Mstr pre**, **Pump wait** and **Mstr post**), so that the line is
+//! pressurised before the valve opens and the pump never pushes against a closed valve. If the
+//! system is activated again part way through closing, the valve is opened again straight away.
+
+use crate::{
+	config::SystemConfig,
+	hal::Clock,
+	timer::{Duration, SoftTimer, TIMER},
+};
+
+/// Stage of opening the master valve and the valve and starting the pump, or stopping the pump and
+/// closing the valve and the master valve
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+	/// Master valve and valve closed, and pump stopped
+	Stopped,
+	/// Master valve open, and waiting to open the valve
+	Opening,
+	/// Valve open, and waiting to start the pump
+	Starting,
+	/// Valve open, and pump running
+	Running,
+	/// Pump stopped, and waiting to close the valve
+	Stopping,
+	/// Valve closed, and waiting to close the master valve
+	Closing,
+}
+
+/// How long each stage waits for before moving on to the next
+#[derive(Clone, Copy)]
+pub struct Delays {
+	/// How long the master valve is opened for before the valve
+	pub lead: Duration,
+	/// How long to wait between opening the valve and starting the pump, and between stopping the
+	/// pump and closing the valve
+	pub pump: Duration,
+	/// How long the master valve is kept open for after the valve is closed
+	pub lag: Duration,
+}
+
+impl Delays {
+	/// Delays of the configuration
+	///
+	/// Without a master valve or a pump, there is nothing to wait for.
+	pub fn of(system_config: &SystemConfig) -> Self {
+		let secs = |enabled: bool, secs: u16| Duration::from_secs(if enabled { secs } else { 0 });
+		Self {
+			lead: secs(
+				cfg!(feature = "master-valve"),
+				system_config.master_lead_secs,
+			),
+			pump: secs(cfg!(feature = "pwm-pump"), system_config.pump_delay_secs),
+			lag: secs(
+				cfg!(feature = "master-valve"),
+				system_config.master_lag_secs,
+			),
+		}
+	}
+}
+
+/// Opens and closes the master valve, the valve and the pump in order
+pub struct Sequence {
+	stage: Stage,
+	/// [`TIMER`] clock value of when the current stage started
+	started_ms: u32,
+}
+
+impl Sequence {
+	/// Create a new [`Sequence`], with everything closed
+	pub fn new() -> Self {
+		Self {
+			stage: Stage::Stopped,
+			started_ms: 0,
+		}
+	}
+
+	/// Current stage
+	pub fn stage(&self) -> Stage {
+		self.stage
+	}
+
+	/// Move on to the next stage if the current one has waited for long enough, towards the valve
+	/// being open if it `should_open`, or closed otherwise
+	///
+	/// [`SoftTimer::ValveOpen`] is started when the valve opens, and stopped when it closes.
+	pub fn update(&mut self, should_open: bool, delays: Delays) {
+		let waited = |delay| TIMER.has_elapsed(self.started_ms, delay);
+		match (self.stage, should_open) {
+			(Stage::Stopped, true) => {
+				// If the valve is off, but the system is activated, open the master valve and wait
+				// to turn the valve on.
+				self.next(Stage::Opening);
+			}
+			(Stage::Opening, true) if waited(delays.lead) => {
+				// If the master valve has been open for long enough, turn the valve on and wait to
+				// start the pump.
+				self.next(Stage::Starting);
+				SoftTimer::ValveOpen.start();
+			}
+			(Stage::Opening, false) => {
+				// If the valve hasn't been turned on yet, close the master valve straight away.
+				self.next(Stage::Stopped);
+			}
+			(Stage::Starting, true) if waited(delays.pump) => {
+				// If the valve has been open for long enough, start the pump.
+				self.next(Stage::Running);
+			}
+			(Stage::Starting, false) => {
+				// If the pump hasn't started yet, but the system is no longer activated (or the
+				// valve is faulty), turn the valve off straight away.
+				self.next(Stage::Closing);
+				SoftTimer::ValveOpen.stop();
+			}
+			(Stage::Running, false) => {
+				// If the valve is on but the system is not activated (or the valve is faulty),
+				// stop the pump and wait to turn the valve off.
+				self.next(Stage::Stopping);
+			}
+			(Stage::Stopping, true) => {
+				// If the system has been activated again before the valve was turned off, restart
+				// the pump straight away.
+				self.next(Stage::Running);
+			}
+			(Stage::Stopping, false) if waited(delays.pump) => {
+				// If the pump has been stopped for long enough, turn the valve off and wait to close
+				// the master valve.
+				self.next(Stage::Closing);
+				SoftTimer::ValveOpen.stop();
+			}
+			(Stage::Closing, true) => {
+				// If the system has been activated again before the master valve was closed, turn
+				// the valve on again straight away.
+				self.next(Stage::Starting);
+				SoftTimer::ValveOpen.start();
+			}
+			(Stage::Closing, false) if waited(delays.lag) => {
+				// If the valve has been off for long enough, close the master valve.
+				self.next(Stage::Stopped);
+			}
+			_ => {}
+		}
+	}
+
+	/// Close everything straight away, without waiting for the pump to stop first
+	pub fn stop(&mut self) {
+		self.next(Stage::Stopped);
+		SoftTimer::ValveOpen.stop();
+	}
+
+	/// Whether the master valve is open
+	pub fn is_master_open(&self) -> bool {
+		self.stage != Stage::Stopped
+	}
+
+	/// Whether the valve is open
+	pub fn is_valve_open(&self) -> bool {
+		matches!(
+			self.stage,
+			Stage::Starting | Stage::Running | Stage::Stopping
+		)
+	}
+
+	/// Whether the pump is running
+	pub fn is_pump_running(&self) -> bool {
+		self.stage == Stage::Running
+	}
+
+	/// Move on to the next stage
+	fn next(&mut self, stage: Stage) {
+		self.stage = stage;
+		self.started_ms = TIMER.millis();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::timer::{self, tests::TimersGuard};
+
+	const DELAYS: Delays = Delays {
+		lead: Duration::from_secs(2),
+		pump: Duration::from_secs(3),
+		lag: Duration::from_secs(5),
+	};
+
+	/// Move the clock on by `millis`, and then update the sequence
+	fn step(sequence: &mut Sequence, should_open: bool, millis: u32) -> Stage {
+		timer::advance(millis);
+		sequence.update(should_open, DELAYS);
+		sequence.stage()
+	}
+
+	/// Run a new sequence through to the pump running
+	fn start() -> Sequence {
+		let mut sequence = Sequence::new();
+		step(&mut sequence, true, 0);
+		step(&mut sequence, true, DELAYS.lead.as_millis());
+		assert_eq!(
+			step(&mut sequence, true, DELAYS.pump.as_millis()),
+			Stage::Running
+		);
+		sequence
+	}
+
+	#[test]
+	fn opens_and_closes_in_order() {
+		let _timers = TimersGuard::take();
+		let mut sequence = Sequence::new();
+
+		assert_eq!(step(&mut sequence, true, 0), Stage::Opening);
+		assert!(sequence.is_master_open());
+		assert!(!sequence.is_valve_open());
+		assert_eq!(step(&mut sequence, true, 1_999), Stage::Opening);
+		assert_eq!(step(&mut sequence, true, 1), Stage::Starting);
+		assert!(sequence.is_valve_open());
+		assert!(!sequence.is_pump_running());
+		assert!(SoftTimer::ValveOpen.is_running());
+		assert_eq!(step(&mut sequence, true, 2_999), Stage::Starting);
+		assert_eq!(step(&mut sequence, true, 1), Stage::Running);
+		assert!(sequence.is_pump_running());
+
+		assert_eq!(step(&mut sequence, false, 0), Stage::Stopping);
+		assert!(!sequence.is_pump_running());
+		assert!(sequence.is_valve_open());
+		assert_eq!(step(&mut sequence, false, 2_999), Stage::Stopping);
+		assert_eq!(step(&mut sequence, false, 1), Stage::Closing);
+		assert!(!sequence.is_valve_open());
+		assert!(sequence.is_master_open());
+		assert!(!SoftTimer::ValveOpen.is_running());
+		assert_eq!(step(&mut sequence, false, 4_999), Stage::Closing);
+		assert_eq!(step(&mut sequence, false, 1), Stage::Stopped);
+		assert!(!sequence.is_master_open());
+	}
+
+	#[test]
+	fn restarts_pump_when_activated_while_stopping() {
+		let _timers = TimersGuard::take();
+		let mut sequence = start();
+
+		assert_eq!(step(&mut sequence, false, 0), Stage::Stopping);
+		assert_eq!(step(&mut sequence, true, 1_000), Stage::Running);
+		assert!(SoftTimer::ValveOpen.is_running());
+	}
+
+	#[test]
+	fn reopens_valve_when_activated_while_closing() {
+		let _timers = TimersGuard::take();
+		let mut sequence = start();
+
+		step(&mut sequence, false, 0);
+		assert_eq!(
+			step(&mut sequence, false, DELAYS.pump.as_millis()),
+			Stage::Closing
+		);
+		assert_eq!(step(&mut sequence, true, 1_000), Stage::Starting);
+		assert!(sequence.is_valve_open());
+		assert!(SoftTimer::ValveOpen.is_running());
+		// The pump waits for the valve to be open for long enough again.
+		assert_eq!(step(&mut sequence, true, 2_999), Stage::Starting);
+		assert_eq!(step(&mut sequence, true, 1), Stage::Running);
+	}
+
+	#[test]
+	fn stops_straight_away() {
+		let _timers = TimersGuard::take();
+		let mut sequence = start();
+
+		sequence.stop();
+		assert_eq!(sequence.stage(), Stage::Stopped);
+		assert!(!SoftTimer::ValveOpen.is_running());
+	}
+}
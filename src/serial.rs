@@ -1,44 +1,248 @@
 //! Write formatted data to the USART peripheral.
+//!
+//! Written data is buffered, and sent from the data register empty interrupt so that logging
+//! doesn't stall the control loop. Received data is buffered from the receive complete interrupt
+//! so that it isn't lost while the display is being redrawn.
+//!
+//! Writes only return straight away while there is space in the buffer ([`TX_BUFFER_SIZE`] bytes,
+//! to save RAM). Once it is full they block until the rest has been buffered, so a longer burst,
+//! such as a telemetry record or a link frame, still holds up the control loop while it is sent.
+//! Nothing is dropped, so that frames aren't cut short.
+//!
+//! Diagnostic messages are logged with the `error!`, `warn!`, `info!`, `debug!` and `trace!`
+//! macros. Messages which are more verbose than [`MAX_LEVEL`] (or the level set for their module in
+//! [`MODULE_LEVELS`]) are removed at compile time, so they don't take up any space in the firmware.
 
-use arduino_hal::{clock::MHz16, hal::usart::Usart0};
-use core::{convert::Infallible, str};
+use arduino_hal::{
+	clock::MHz16,
+	hal::usart::Usart0,
+	pac::{usart0::RegisterBlock, CPU, USART0},
+};
+use avr_device::interrupt::Mutex;
+use core::{cell::RefCell, convert::Infallible, str};
 use ufmt::uWrite;
 
-pub struct SerialWriter {
-	inner: Option<Usart0<MHz16>>,
+use crate::queue::Queue;
+
+/// Amount of bytes which can be waiting to be sent before writes block
+const TX_BUFFER_SIZE: usize = 64;
+/// Amount of bytes which can be received before they are read
+const RX_BUFFER_SIZE: usize = 32;
+
+/// Most verbose level which is logged, chosen with the `log-*` features
+pub const MAX_LEVEL: Level = if cfg!(feature = "log-trace") {
+	Level::Trace
+} else if cfg!(feature = "log-debug") {
+	Level::Debug
+} else if cfg!(feature = "log-warn") {
+	Level::Warn
+} else if cfg!(feature = "log-error") {
+	Level::Error
+} else {
+	Level::Info
+};
+
+/// Most verbose level which is logged by specific modules, overriding [`MAX_LEVEL`]
+///
+/// Modules are matched by the start of their path, so an entry for `garden_system::control_pad`
+/// also applies to `garden_system::control_pad::encoder`. For example, to trace the sensor
+/// readings while only logging warnings from everything else (with the `log-warn` feature):
+///
+/// ```
+/// const MODULE_LEVELS: &[(&str, Level)] = &[("garden_system::system", Level::Trace)];
+/// ```
+const MODULE_LEVELS: &[(&str, Level)] = &[];
+
+/// Importance of a log message, from the most to the least important
+#[derive(Clone, Copy)]
+pub enum Level {
+	/// Something has gone wrong, and the system can't carry on as normal
+	Error = 1,
+	/// Something unexpected happened, but the system can carry on
+	Warn = 2,
+	/// Something happened which is worth knowing about
+	Info = 3,
+	/// Details which are useful when tracking down a problem
+	Debug = 4,
+	/// Very frequent details, such as every sensor reading
+	Trace = 5,
+}
+
+impl Level {
+	/// Written at the start of each message, so that the level can be told apart
+	pub fn prefix(self) -> &'static str {
+		match self {
+			Self::Error => "E: ",
+			Self::Warn => "W: ",
+			Self::Info => "I: ",
+			Self::Debug => "D: ",
+			Self::Trace => "T: ",
+		}
+	}
+}
+
+/// Whether a message at `level` from the module at `module_path` should be logged
+///
+/// Evaluated at compile time by the logging macros.
+pub const fn is_enabled(level: Level, module_path: &str) -> bool {
+	let mut max_level = MAX_LEVEL;
+	let mut idx = 0;
+	while idx < MODULE_LEVELS.len() {
+		let (prefix, module_level) = MODULE_LEVELS[idx];
+		if starts_with(module_path, prefix) {
+			max_level = module_level;
+		}
+		idx += 1;
+	}
+
+	level as u8 <= max_level as u8
+}
+
+/// Whether `s` starts with `prefix`, which can be used in a `const fn`
+const fn starts_with(s: &str, prefix: &str) -> bool {
+	let (s, prefix) = (s.as_bytes(), prefix.as_bytes());
+	if s.len() < prefix.len() {
+		return false;
+	}
+
+	let mut idx = 0;
+	while idx < prefix.len() {
+		if s[idx] != prefix[idx] {
+			return false;
+		}
+		idx += 1;
+	}
+	true
 }
 
+/// Buffer of bytes shared with an interrupt
+///
+/// Only `None` until [`set_serial`] is called.
+type SharedBuffer<const N: usize> = Mutex<RefCell<Option<Queue<u8, N>>>>;
+
+/// Bytes waiting to be sent
+static TX_BUFFER: SharedBuffer<TX_BUFFER_SIZE> = Mutex::new(RefCell::new(None));
+/// Bytes which have been received, but not read yet
+static RX_BUFFER: SharedBuffer<RX_BUFFER_SIZE> = Mutex::new(RefCell::new(None));
+/// The USART, held so that nothing else uses it once [`set_serial`] has been called
+static USART: Mutex<RefCell<Option<Usart0<MHz16>>>> = Mutex::new(RefCell::new(None));
+
+/// Handle for writing formatted data to the USART, which can be used from anywhere
+///
+/// While interrupts are disabled, such as from another interrupt or inside
+/// [`avr_device::interrupt::free`], the buffer can't be sent from the data register empty
+/// interrupt, so once it is full the USART is polled to send it instead.
+///
+/// The handle holds nothing itself, so any number of them can be taken with [`writer`].
+pub struct SerialWriter;
+
 impl uWrite for SerialWriter {
 	type Error = Infallible;
 
+	/// Add `s` to the buffer of bytes to send
+	///
+	/// Only blocks if the buffer is full, until there is space for the rest of `s`. Nothing is sent
+	/// if [`set_serial`] hasn't been called yet.
 	fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
-		match &mut self.inner {
-			Some(serial) => {
-				let _ = serial.write_str(s);
-			}
-			None => {
-				panic!();
-			}
-		}
-
+		write_bytes(s.as_bytes());
 		Ok(())
 	}
 }
 
-pub static mut SERIAL: SerialWriter = SerialWriter { inner: None };
+/// Take a handle for writing to the USART
+pub fn writer() -> SerialWriter {
+	SerialWriter
+}
 
+/// Start buffering the data sent and received with `serial`
+///
+/// Only the first call has any effect.
 pub fn set_serial(serial: Usart0<MHz16>) {
-	unsafe {
-		if SERIAL.inner.is_none() {
-			SERIAL.inner = Some(serial);
+	avr_device::interrupt::free(|cs| {
+		let mut usart = USART.borrow(cs).borrow_mut();
+		if usart.is_none() {
+			TX_BUFFER.borrow(cs).replace(Some(Queue::new()));
+			RX_BUFFER.borrow(cs).replace(Some(Queue::new()));
+			registers().ucsr0b.modify(|_, w| w.rxcie0().set_bit());
+			*usart = Some(serial);
 		}
-	}
+	});
 }
 
-/// Convenience wrapper so that `unsafe { ... }` isn't required whenever something should be
-/// logged to serial output.
+/// Add raw `bytes` to the buffer of bytes to send
 ///
-/// This macro requires that `SERIAL` is in scope whenever it is used.
+/// Only blocks if the buffer is full, until there is space for the rest of `bytes`. Nothing is
+/// sent if [`set_serial`] hasn't been called yet.
+pub fn write_bytes(bytes: &[u8]) {
+	for &byte in bytes {
+		loop {
+			match with_buffer(&TX_BUFFER, |buffer| buffer.push(byte)) {
+				Some(true) => break,
+				// The interrupt can't make space while interrupts are disabled, so send the next
+				// byte from here instead.
+				Some(false) if !interrupts_enabled() => send_next(),
+				Some(false) => {}
+				None => return,
+			}
+		}
+		// Make sure the interrupt is enabled, so that the byte is sent.
+		avr_device::interrupt::free(|_cs| {
+			registers().ucsr0b.modify(|_, w| w.udrie0().set_bit());
+		});
+	}
+}
+
+/// Read a single byte which has been received, if there is one
+pub fn read_byte() -> Option<u8> {
+	with_buffer(&RX_BUFFER, |buffer| buffer.pop()).flatten()
+}
+
+/// USART registers, for use from the interrupts
+fn registers() -> &'static RegisterBlock {
+	unsafe { &*USART0::ptr() }
+}
+
+/// Whether interrupts are enabled, so that the data register empty interrupt can run
+fn interrupts_enabled() -> bool {
+	unsafe { &*CPU::ptr() }.sreg.read().i().bit_is_set()
+}
+
+/// Wait for the data register to be empty, and then send the next byte in the buffer
+fn send_next() {
+	while registers().ucsr0a.read().udre0().bit_is_clear() {}
+	if let Some(byte) = with_buffer(&TX_BUFFER, |buffer| buffer.pop()).flatten() {
+		registers().udr0.write(|w| unsafe { w.bits(byte) });
+	}
+}
+
+/// Run `f` with a buffer, if it has been created
+fn with_buffer<R, const N: usize>(
+	buffer: &SharedBuffer<N>,
+	f: impl FnOnce(&mut Queue<u8, N>) -> R,
+) -> Option<R> {
+	avr_device::interrupt::free(|cs| buffer.borrow(cs).borrow_mut().as_mut().map(f))
+}
+
+#[avr_device::interrupt(atmega328p)]
+#[allow(non_snake_case)]
+fn USART_UDRE() {
+	match with_buffer(&TX_BUFFER, |buffer| buffer.pop()).flatten() {
+		// Send the next byte.
+		Some(byte) => registers().udr0.write(|w| unsafe { w.bits(byte) }),
+		// Nothing left to send, so disable the interrupt until there is.
+		None => registers().ucsr0b.modify(|_, w| w.udrie0().clear_bit()),
+	}
+}
+
+#[avr_device::interrupt(atmega328p)]
+#[allow(non_snake_case)]
+fn USART_RX() {
+	let byte = registers().udr0.read().bits();
+	// If the buffer is full, the byte is dropped.
+	with_buffer(&RX_BUFFER, |buffer| buffer.push(byte));
+}
+
+/// Convenience wrapper for writing a line to the serial output
 ///
 /// ```
 /// log!("{}, {}", my_value_1, my_value_2);
@@ -46,9 +250,58 @@ pub fn set_serial(serial: Usart0<MHz16>) {
 #[allow(unused_macros)]
 macro_rules! log {
     ($fmt:expr) => {{
-		let _ = unsafe { ufmt::uwriteln!(SERIAL, $fmt) };
+		let _ = ufmt::uwriteln!(crate::serial::writer(), $fmt);
 	}};
     ($fmt:expr, $($values:expr),*) => {{
-		let _ = unsafe { ufmt::uwriteln!(SERIAL, $fmt, $($values),*) };
+		let _ = ufmt::uwriteln!(crate::serial::writer(), $fmt, $($values),*);
 	}}
 }
+
+/// Log a message at a [`Level`], if the level is enabled for the calling module
+///
+/// The `error!`, `warn!`, `info!`, `debug!` and `trace!` shorthands should be used instead.
+#[allow(unused_macros)]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)+) => {{
+		const ENABLED: bool = crate::serial::is_enabled($level, module_path!());
+		if ENABLED {
+			let _ = ufmt::uwrite!(crate::serial::writer(), "{}", $level.prefix());
+			log!($($arg)+);
+		}
+	}};
+}
+
+/// Log an error
+#[allow(unused_macros)]
+macro_rules! error {
+    ($($arg:tt)+) => { log_at!(crate::serial::Level::Error, $($arg)+) };
+}
+
+/// Log a warning
+#[allow(unused_macros)]
+macro_rules! warn {
+    ($($arg:tt)+) => { log_at!(crate::serial::Level::Warn, $($arg)+) };
+}
+
+/// Log an informational message
+#[allow(unused_macros)]
+macro_rules! info {
+    ($($arg:tt)+) => { log_at!(crate::serial::Level::Info, $($arg)+) };
+}
+
+/// Log a debugging message
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($($arg:tt)+) => { log_at!(crate::serial::Level::Debug, $($arg)+) };
+}
+
+/// Log a very verbose debugging message
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)+) => { log_at!(crate::serial::Level::Trace, $($arg)+) };
+}
+
+// Declared after `log!` so that the macro can be used in the submodules.
+#[cfg(feature = "bluetooth")]
+pub mod bluetooth;
+pub mod cli;
@@ -0,0 +1,108 @@
+//! Command mode for an HC-05 Bluetooth module sharing the USART
+//!
+//! An HC-05 passes on whatever it receives, so the bytes sent while a phone is pairing, or by
+//! another serial tool which connects to it, could otherwise be taken as commands. Instead, a line
+//! is only a command when it is framed like a telemetry record: starting with `$`, followed by `*`
+//! and the CRC-8 checksum of the command in hex, such as `$activate*A1`. Anything else is ignored
+//! without a reply.
+//!
+//! Commands are only run once the session has been authenticated with `auth <code>`, where the
+//! code is shown by the Bluetooth control menu item while it is enabled. A new code is chosen each
+//! time it is enabled from the menu. After [`MAX_FAILURES`] wrong codes in a row, Bluetooth
+//! control is disabled again, and the session ends once no commands have been received for
+//! [`SESSION_TIMEOUT`].
+
+use core::str;
+
+use crate::{
+	hal::Clock,
+	storage::crc8,
+	timer::{Duration, TIMER},
+};
+
+/// Wrong codes in a row after which Bluetooth control is disabled
+const MAX_FAILURES: u8 = 3;
+/// How long a session lasts without any commands
+const SESSION_TIMEOUT: Duration = Duration::from_mins(10);
+
+/// Command of a framed line, if its checksum matches
+pub fn unframe(line: &[u8]) -> Option<&[u8]> {
+	if line.len() < 4 || line[0] != b'$' || line[line.len() - 3] != b'*' {
+		return None;
+	}
+
+	let command = &line[1..line.len() - 3];
+	let checksum = str::from_utf8(&line[line.len() - 2..])
+		.ok()
+		.and_then(|hex| u8::from_str_radix(hex, 16).ok())?;
+	if checksum == crc8(command) {
+		Some(command)
+	} else {
+		None
+	}
+}
+
+/// Outcome of trying to authenticate a session
+pub enum Auth {
+	/// The code was right, and the session can run commands
+	Ok,
+	/// The code was wrong
+	Denied,
+	/// The code was wrong too many times, so Bluetooth control should be disabled
+	Locked,
+}
+
+/// Session of commands received over Bluetooth
+pub struct Session {
+	/// Pairing code the session was authenticated with, and the [`TIMER`] clock value of its last
+	/// command
+	authenticated: Option<(u16, u32)>,
+	/// Wrong codes received in a row
+	failures: u8,
+}
+
+impl Session {
+	/// Create a new [`Session`], which isn't authenticated
+	pub fn new() -> Self {
+		Self {
+			authenticated: None,
+			failures: 0,
+		}
+	}
+
+	/// Authenticate the session with `code`, if it is the pairing code shown in the menu
+	pub fn authenticate(&mut self, code: u16, pairing_code: u16) -> Auth {
+		if code == pairing_code {
+			self.authenticated = Some((pairing_code, TIMER.millis()));
+			self.failures = 0;
+			return Auth::Ok;
+		}
+
+		self.authenticated = None;
+		self.failures += 1;
+		if self.failures < MAX_FAILURES {
+			return Auth::Denied;
+		}
+		self.failures = 0;
+		Auth::Locked
+	}
+
+	/// Whether the session can run a command, which keeps it going for another
+	/// [`SESSION_TIMEOUT`]
+	///
+	/// The session ends if the pairing code has changed since it was authenticated.
+	pub fn allows(&mut self, pairing_code: u16) -> bool {
+		match self.authenticated {
+			Some((code, last_ms))
+				if code == pairing_code && !TIMER.has_elapsed(last_ms, SESSION_TIMEOUT) =>
+			{
+				self.authenticated = Some((code, TIMER.millis()));
+				true
+			}
+			_ => {
+				self.authenticated = None;
+				false
+			}
+		}
+	}
+}
@@ -0,0 +1,226 @@
+//! Line-based command-line interface
+//!
+//! Commands are received from the USART a byte at a time, and run once a full line has been
+//! received:
+//!
+//! - `set <time|light|moisture|flow|brightness|telemetry> <value>`: Set a configuration value;
+//! - `set remote <on|off>`: Enable or disable remote control. It can only be enabled from the
+//!   menu;
+//! - `get config`: Print the configuration;
+//! - `pin <valve|valve-close|led|doser|grow-light|fan|heat-mat> <pin>`: Move a digital output to
+//!   another pin, such as `D7`, the next time the system starts up (see [`crate::pinmap`]);
+//! - `get pins`: Print the pin of each digital output;
+//! - `activate`: Activate the system;
+//! - `suspend`: Suspend the system;
+//! - `log dump`: Print the event log;
+//! - `log changes [n]`: Print the last `n` changes to the configuration, or all of them (see
+//!   [`crate::log::changes`]);
+//! - `log export`: Print the event history stored in the EEPROM;
+//! - `log samples`: Print the sensor samples stored in external storage, with the
+//!   `external-storage` feature (see [`crate::log::samples`]);
+//! - `status`: Print the activation state and sensor readings;
+//! - `mem`: Print the free SRAM and the deepest the stack has been;
+//! - `rain <percent>`: Set the chance of rain, to shorten or skip watering (see
+//!   [`crate::forecast`]);
+//! - `trace <secs> <light> <moisture>`: Replay a sample of a sensor trace (see [`crate::trace`]);
+//! - `trace end`: Stop replaying, and go back to reading the sensors;
+//! - `auth <code>`: Authenticate commands received over Bluetooth, with the `bluetooth` feature
+//!   (see [`super::bluetooth`]).
+//!
+//! With the `bluetooth` feature, each line also has to be framed with `$` and its checksum, and
+//! other lines are ignored.
+
+use core::str;
+
+#[cfg(feature = "bluetooth")]
+use super::bluetooth;
+use crate::{
+	config::{Number, UpdateSystemValue, ValueAction},
+	pinmap::{DigitalPin, Role},
+	trace::Sample,
+};
+
+/// Longest line which can be received
+const LINE_LENGTH: usize = 32;
+
+/// Command received over the USART
+pub enum Command {
+	/// Set a configuration value
+	Set(UpdateSystemValue),
+	/// Print the configuration
+	GetConfig,
+	/// Map a digital output to a pin, the next time the system starts up
+	SetPin(Role, DigitalPin),
+	/// Print the pin of each digital output
+	GetPins,
+	/// Activate the system, if it isn't already
+	Activate,
+	/// Suspend the system, if it isn't already
+	Suspend,
+	/// Enable or disable remote control
+	RemoteControl(bool),
+	/// Print the event log
+	LogDump,
+	/// Print the last changes to the configuration, up to the amount given
+	LogChanges(usize),
+	/// Print the event history stored in the EEPROM
+	LogExport,
+	/// Print the sensor samples stored in external storage
+	#[cfg(feature = "external-storage")]
+	LogSamples,
+	/// Print the activation state and sensor readings
+	Status,
+	/// Print the free SRAM and the deepest the stack has been
+	Memory,
+	/// Set the chance of rain in percent
+	Forecast(u8),
+	/// Replay a sample of a sensor trace
+	Trace(Sample),
+	/// Stop replaying the sensor trace
+	TraceEnd,
+	/// Authenticate the Bluetooth session with a pairing code
+	#[cfg(feature = "bluetooth")]
+	Auth(u16),
+}
+
+/// Collects received bytes into lines, and parses them into commands
+pub struct Cli {
+	/// Bytes received for the current line
+	line: [u8; LINE_LENGTH],
+	/// Amount of bytes received for the current line
+	len: usize,
+	/// Whether the current line is too long, and should be discarded
+	overflowed: bool,
+}
+
+impl Cli {
+	/// Create a new [`Cli`]
+	pub fn new() -> Self {
+		Self {
+			line: [0; LINE_LENGTH],
+			len: 0,
+			overflowed: false,
+		}
+	}
+
+	/// Add a received byte to the current line, and parse the line once it is complete
+	///
+	/// Returns the command if a full, valid line has been received.
+	pub fn receive(&mut self, byte: u8) -> Option<Command> {
+		match byte {
+			b'\r' | b'\n' => {
+				// If the line is complete, parse it and start the next line.
+				let command = self.parse_line();
+				self.len = 0;
+				self.overflowed = false;
+				return command;
+			}
+			_ if self.len < LINE_LENGTH => {
+				self.line[self.len] = byte;
+				self.len += 1;
+			}
+			_ => self.overflowed = true,
+		}
+
+		None
+	}
+
+	/// Parse the current line into a command
+	///
+	/// Prints an error if the line isn't a valid command.
+	fn parse_line(&self) -> Option<Command> {
+		if self.overflowed {
+			log!("error: line too long");
+			return None;
+		}
+
+		// Over Bluetooth, lines which aren't framed are ignored without a reply, so that stray bytes
+		// don't get one either.
+		#[cfg(feature = "bluetooth")]
+		let line = match bluetooth::unframe(&self.line[..self.len]) {
+			Some(line) => line,
+			None => return None,
+		};
+		#[cfg(not(feature = "bluetooth"))]
+		let line = &self.line[..self.len];
+
+		let line = match str::from_utf8(line) {
+			Ok(line) => line,
+			Err(_) => {
+				log!("error: invalid line");
+				return None;
+			}
+		};
+
+		let mut words = line.split_whitespace();
+		let command = match (words.next(), words.next(), words.next()) {
+			// Ignore empty lines (such as the \n following a \r).
+			(None, _, _) => return None,
+			(Some("set"), Some("remote"), Some("on")) => Some(Command::RemoteControl(true)),
+			(Some("set"), Some("remote"), Some("off")) => Some(Command::RemoteControl(false)),
+			(Some("set"), Some(name), Some(value)) => match (name, value.parse::<u16>()) {
+				("time", Ok(value)) => Some(UpdateSystemValue::Number(
+					Number::Time,
+					ValueAction::Set(value),
+				)),
+				("light", Ok(value)) => Some(UpdateSystemValue::Number(
+					Number::Light,
+					ValueAction::Set(value),
+				)),
+				("moisture", Ok(value)) => Some(UpdateSystemValue::Number(
+					Number::Moisture,
+					ValueAction::Set(value),
+				)),
+				("flow", Ok(value)) => Some(UpdateSystemValue::Number(
+					Number::Flow,
+					ValueAction::Set(value),
+				)),
+				("brightness", Ok(value)) => Some(UpdateSystemValue::Number(
+					Number::Brightness,
+					ValueAction::Set(value),
+				)),
+				("telemetry", Ok(value)) => Some(UpdateSystemValue::Number(
+					Number::Telemetry,
+					ValueAction::Set(value),
+				)),
+				_ => None,
+			}
+			.map(Command::Set),
+			(Some("get"), Some("config"), None) => Some(Command::GetConfig),
+			(Some("pin"), Some(role), Some(pin)) => match (Role::from_name(role), pin.parse()) {
+				(Some(role), Ok(pin)) => Some(Command::SetPin(role, pin)),
+				_ => None,
+			},
+			(Some("get"), Some("pins"), None) => Some(Command::GetPins),
+			(Some("activate"), None, _) => Some(Command::Activate),
+			(Some("suspend"), None, _) => Some(Command::Suspend),
+			(Some("log"), Some("dump"), None) => Some(Command::LogDump),
+			(Some("log"), Some("changes"), None) => Some(Command::LogChanges(usize::MAX)),
+			(Some("log"), Some("changes"), Some(count)) => {
+				count.parse().ok().map(Command::LogChanges)
+			}
+			(Some("log"), Some("export"), None) => Some(Command::LogExport),
+			#[cfg(feature = "external-storage")]
+			(Some("log"), Some("samples"), None) => Some(Command::LogSamples),
+			(Some("status"), None, _) => Some(Command::Status),
+			(Some("mem"), None, _) => Some(Command::Memory),
+			(Some("rain"), Some(percent), None) => match percent.parse::<u8>() {
+				Ok(percent) if percent <= 100 => Some(Command::Forecast(percent)),
+				_ => None,
+			},
+			#[cfg(feature = "bluetooth")]
+			(Some("auth"), Some(code), None) => code.parse().ok().map(Command::Auth),
+			(Some("trace"), Some("end"), None) => Some(Command::TraceEnd),
+			(Some("trace"), Some(secs), Some(light)) => {
+				// The moisture reading is the fourth word, which is still left in `words`.
+				Sample::parse([secs, light].iter().copied().chain(words)).map(Command::Trace)
+			}
+			_ => None,
+		};
+
+		if command.is_none() {
+			log!("error: unknown command");
+		}
+		command
+	}
+}
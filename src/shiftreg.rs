@@ -0,0 +1,187 @@
+//! 74HC595 shift registers
+//!
+//! As on OpenSprinkler boards, the valve relay can be switched through a daisy chain of 74HC595
+//! shift registers, which only needs three pins however long the chain is: the serial data is on
+//! the valve pin, the clock on the valve-close pin (which is otherwise only used by a latching
+//! valve), and the latch on its own pin (see [`crate::pinmap`]). Whenever an output changes, every
+//! output is shifted out, furthest first, and then latched onto the registers' outputs together.
+//!
+//! Each register has 8 outputs, enough for a valve in each of up to 8 zones. There is a single
+//! valve so far, so it is wired to [`VALVE_CHANNEL`], and the other outputs are only switched on
+//! to test them from the diagnostics screen.
+
+use crate::hal::ValveOutput;
+
+/// Amount of shift registers in the chain
+const REGISTERS: u8 = 1;
+/// Amount of outputs of the chain
+pub const CHANNELS: u8 = REGISTERS * 8;
+/// Output of the first register which switches the valve relay
+const VALVE_CHANNEL: u8 = 0;
+
+/// Daisy chain of shift registers, driven through the pins `P`
+pub struct ShiftRegister<P> {
+	data: P,
+	clock: P,
+	latch: P,
+	/// Levels of the outputs, one bit for each channel
+	outputs: u32,
+	/// Channel being tested, and the level it is held at
+	test: Option<(u8, bool)>,
+	/// Whether the current levels of the outputs have been shifted out
+	shifted: bool,
+}
+
+impl<P: ValveOutput> ShiftRegister<P> {
+	/// Create a new [`ShiftRegister`], with every output low
+	///
+	/// The outputs aren't shifted out until the valve's output is first set.
+	pub fn new(data: P, clock: P, latch: P) -> Self {
+		Self {
+			data,
+			clock,
+			latch,
+			outputs: 0,
+			test: None,
+			shifted: false,
+		}
+	}
+
+	/// Hold `channel` at `level` for a test, or stop testing with `None`
+	pub fn set_test(&mut self, test: Option<(u8, bool)>) {
+		if test != self.test {
+			self.test = test;
+			self.shift_out();
+		}
+	}
+
+	/// Levels of the outputs, including the channel being tested
+	fn levels(&self) -> u32 {
+		match self.test {
+			Some((channel, true)) => self.outputs | 1 << channel,
+			Some((channel, false)) => self.outputs & !(1 << channel),
+			None => self.outputs,
+		}
+	}
+
+	/// Set the level of the valve's output, and shift the outputs out if it changed
+	fn set(&mut self, high: bool) {
+		let outputs = if high {
+			self.outputs | 1 << VALVE_CHANNEL
+		} else {
+			self.outputs & !(1 << VALVE_CHANNEL)
+		};
+		if outputs != self.outputs {
+			self.outputs = outputs;
+			self.shifted = false;
+		}
+		if !self.shifted {
+			self.shift_out();
+		}
+	}
+
+	/// Shift the levels of the outputs into the chain, and latch them onto its outputs
+	fn shift_out(&mut self) {
+		let levels = self.levels();
+		for channel in (0..CHANNELS).rev() {
+			if levels & 1 << channel != 0 {
+				self.data.set_high();
+			} else {
+				self.data.set_low();
+			}
+			self.clock.set_high();
+			self.clock.set_low();
+		}
+		self.latch.set_high();
+		self.latch.set_low();
+		self.shifted = true;
+	}
+}
+
+impl<P: ValveOutput> ValveOutput for ShiftRegister<P> {
+	fn set_high(&mut self) {
+		self.set(true);
+	}
+
+	fn set_low(&mut self) {
+		self.set(false);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{cell::RefCell, rc::Rc};
+
+	/// Pin of a simulated chain, which shifts the data in on each rising edge of the clock
+	struct MockPin {
+		/// Index of the pin: data, clock or latch
+		idx: usize,
+		chain: Rc<RefCell<MockChain>>,
+	}
+
+	/// Shift registers of a simulated chain
+	#[derive(Default)]
+	struct MockChain {
+		data: bool,
+		shifted: u32,
+		/// Levels of the outputs each time they were latched
+		latched: Vec<u32>,
+	}
+
+	impl ValveOutput for MockPin {
+		fn set_high(&mut self) {
+			let mut chain = self.chain.borrow_mut();
+			match self.idx {
+				0 => chain.data = true,
+				1 => {
+					let shifted = chain.shifted << 1 | chain.data as u32;
+					chain.shifted = shifted & ((1 << CHANNELS) - 1);
+				}
+				_ => {
+					let shifted = chain.shifted;
+					chain.latched.push(shifted);
+				}
+			}
+		}
+
+		fn set_low(&mut self) {
+			if self.idx == 0 {
+				self.chain.borrow_mut().data = false;
+			}
+		}
+	}
+
+	fn shift_register() -> (ShiftRegister<MockPin>, Rc<RefCell<MockChain>>) {
+		let chain = Rc::new(RefCell::new(MockChain::default()));
+		let pin = |idx| MockPin {
+			idx,
+			chain: chain.clone(),
+		};
+		(ShiftRegister::new(pin(0), pin(1), pin(2)), chain)
+	}
+
+	#[test]
+	fn shifts_out_when_the_valve_changes() {
+		let (mut shift_register, chain) = shift_register();
+		shift_register.set_low();
+		shift_register.set_low();
+		shift_register.set_high();
+		shift_register.set_high();
+		shift_register.set_low();
+		assert_eq!(chain.borrow().latched, vec![0, 1, 0]);
+	}
+
+	#[test]
+	fn tests_a_channel() {
+		let (mut shift_register, chain) = shift_register();
+		shift_register.set_high();
+		shift_register.set_test(Some((CHANNELS - 1, true)));
+		shift_register.set_test(Some((VALVE_CHANNEL, false)));
+		shift_register.set_test(None);
+		assert_eq!(
+			chain.borrow().latched,
+			vec![1, 1 | 1 << (CHANNELS - 1), 0, 1]
+		);
+	}
+}
@@ -0,0 +1,341 @@
+//! Host simulation of the control logic
+//!
+//! Runs the menu and the activation state machine against a script of sensor readings and button
+//! presses, and prints the display to the terminal, so that the watering logic can be tried out
+//! without flashing the firmware. Each line of the script is one of:
+//!
+//! - `light <value>`, `moisture <value>`: Set a sensor reading;
+//! - `press <select|left|right|up|down>`: Press and release a button;
+//! - `hold <select|left|right|up|down>`: Hold a button down until it is long pressed;
+//! - `wait <secs>`: Let the system run;
+//! - `replay <file>`: Replay a sensor trace (see `src/trace.rs`), letting the system run until the
+//!   time of each sample before using its readings;
+//! - `show`: Print the display.
+//!
+//! Blank lines, and lines starting with `#`, are ignored. Changes to the activation state, and
+//! dusk (see `src/evening.rs`), are printed as they happen. Selecting About prints the about
+//! screen, and then returns to the menu.
+
+use std::{
+	env, fs,
+	io::{self, Read},
+	process,
+};
+
+use crate::{
+	about,
+	activation::{self, FaultKind, Transition},
+	band::Smoother,
+	bus::{Bus, Notification},
+	config::{SystemConfig, UpdateSystemValue},
+	control_pad::{ButtonEvent, ButtonStage, ButtonType},
+	display::{
+		statusbar::{Status, StatusBar},
+		ROW_LENGTH,
+	},
+	evening::Evening,
+	hal::{Adc, AnalogReader, Clock, LightSensor, MoistureSensor, VirtualDisplay, CLOCK},
+	menu::{Menu, MenuAction},
+	scheduler::{Scheduler, Task},
+	timer::{self, Duration},
+	trace::{Replay, Sample},
+};
+
+/// How often the system ticks, the same as `TICK_INTERVAL` in `src/power.rs`
+const TICK_MS: u32 = 10;
+
+/// Control logic of the system, with the hardware replaced by the host implementation
+struct Simulation {
+	system_config: SystemConfig,
+	bus: Bus,
+	menu: Menu,
+	display: VirtualDisplay,
+	statusbar: StatusBar,
+	evening: Evening,
+	band_moisture: Smoother,
+	adc: Adc,
+	scheduler: Scheduler,
+}
+
+impl Simulation {
+	/// Create a new [`Simulation`], and render the header and menu
+	fn new() -> Self {
+		let system_config = SystemConfig::new();
+		let menu = Menu::new(&system_config);
+		let mut display = VirtualDisplay::new();
+		let _ = ufmt::uwriteln!(display, "{}\nsim", about::NAME);
+		menu.render(&mut display);
+		let scheduler = Scheduler::new(Duration::from_secs(system_config.telemetry_secs));
+
+		let mut simulation = Self {
+			system_config,
+			bus: Bus::new(),
+			menu,
+			display,
+			statusbar: StatusBar::new(),
+			evening: Evening::new(),
+			band_moisture: Smoother::new(),
+			adc: Adc::new(),
+			scheduler,
+		};
+		simulation.update_statusbar();
+		simulation
+	}
+
+	/// Run a line of the script
+	fn run_line(&mut self, line: &str) -> Result<(), String> {
+		let mut words = line.split_whitespace();
+		let command = match words.next() {
+			Some(command) if !command.starts_with('#') => command,
+			_ => return Ok(()),
+		};
+
+		match (command, words.next()) {
+			("light", Some(value)) => self.adc.light = parse_value(value)?,
+			("moisture", Some(value)) => self.adc.moisture = parse_value(value)?,
+			("press", Some(button)) => {
+				let button = parse_button(button)?;
+				self.press(button, ButtonStage::Down);
+				self.press(button, ButtonStage::Release);
+			}
+			("hold", Some(button)) => {
+				let button = parse_button(button)?;
+				self.press(button, ButtonStage::Down);
+				self.press(button, ButtonStage::LongPress);
+			}
+			("wait", Some(secs)) => self.wait(Duration::from_secs(parse_value(secs)?)),
+			("replay", Some(path)) => self.replay(path)?,
+			("show", None) => self.show(),
+			_ => return Err(format!("unknown command `{}`", line.trim())),
+		}
+		Ok(())
+	}
+
+	/// Handle a button event, as the system does, and then tick once
+	fn press(&mut self, button: ButtonType, stage: ButtonStage) {
+		let event = ButtonEvent { stage, button };
+		if matches!(
+			(&event.stage, &event.button),
+			(ButtonStage::LongPress, ButtonType::Right)
+		) && !self.menu.is_editing()
+		{
+			// Holding down Right is the manual override, which toggles activation.
+			self.bus.send(UpdateSystemValue::Activate);
+		} else if let Some(action) = self.menu.on_press(&event, &mut self.display, &mut self.bus) {
+			let screen = match action {
+				MenuAction::ShowInfo => Some("info"),
+				MenuAction::ShowAbout => {
+					// Print the about screen, and then go straight back to the menu.
+					self.menu.hide();
+					about::render(&mut self.display, CLOCK.millis() / 1_000, "Unknown");
+					self.show();
+					self.menu.show(&mut self.display);
+					None
+				}
+				MenuAction::ShowWeek => Some("week"),
+				MenuAction::ShowSoil => Some("soil"),
+				MenuAction::ShowLog => Some("log"),
+				MenuAction::ShowHistory => Some("history"),
+				MenuAction::ShowDiagnostics => Some("diagnostics"),
+				MenuAction::ShowPresets => Some("presets"),
+				MenuAction::ShowName => Some("name"),
+			};
+			// The other screens aren't simulated.
+			if let Some(screen) = screen {
+				self.print(&format!("{} screen isn't simulated", screen));
+			}
+		}
+		self.apply_update();
+		self.tick();
+	}
+
+	/// Let the system run for `duration`
+	fn wait(&mut self, duration: Duration) {
+		for _ in 0..duration.as_millis() / TICK_MS {
+			self.tick();
+		}
+	}
+
+	/// Replay the sensor trace in a file
+	fn replay(&mut self, path: &str) -> Result<(), String> {
+		let trace = fs::read_to_string(path).map_err(|err| format!("`{}`: {}", path, err))?;
+		let mut replay = Replay::new();
+		for (idx, line) in trace.lines().enumerate() {
+			if line.trim().is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let sample = Sample::parse(line.split_whitespace())
+				.ok_or_else(|| format!("`{}` line {}: invalid sample", path, idx + 1))?;
+			self.wait(replay.gap(&sample));
+			replay.apply(&sample);
+			self.adc.light = sample.light;
+			self.adc.moisture = sample.moisture;
+		}
+		Ok(())
+	}
+
+	/// Move the clock on, and run each task which is due, as the system does
+	fn tick(&mut self) {
+		timer::advance(TICK_MS);
+		if self.scheduler.is_due(Task::Sensors) {
+			let adc = &mut self.adc;
+			if self
+				.evening
+				.update(&self.system_config, || adc.read(&LightSensor))
+			{
+				self.print("dusk");
+			}
+		}
+		if !self.scheduler.is_due(Task::Input) {
+			return;
+		}
+
+		self.menu.tick(&mut self.display);
+
+		let can_trigger = self.evening.is_open(&self.system_config);
+		let band_control = self.system_config.is_band_control();
+		let (adc, band_moisture) = (&mut self.adc, &mut self.band_moisture);
+		activation::update_state(
+			&mut self.system_config,
+			&mut self.bus,
+			true,
+			can_trigger,
+			None,
+			|| {
+				let (light, moisture) = (adc.read(&LightSensor), adc.read(&MoistureSensor));
+				if band_control {
+					(light, band_moisture.update(moisture))
+				} else {
+					(light, moisture)
+				}
+			},
+		);
+		self.apply_update();
+		self.update_statusbar();
+	}
+
+	/// Show any change to the activation state in the header
+	///
+	/// The link, faults and the keypad lock aren't simulated.
+	fn update_statusbar(&mut self) {
+		let state = &self.system_config.activation_state;
+		let status = Status {
+			link_up: false,
+			watering: state.is_activated(),
+			suspended: state.is_suspended(),
+			fault: false,
+			fault_code: state.fault().map(FaultKind::code),
+			soil_alert: false,
+			service_due: false,
+			locked: false,
+		};
+		self.statusbar.update(&status, &mut self.display);
+	}
+
+	/// Apply the commands sent onto the bus, and update the menu to match
+	fn apply_update(&mut self) {
+		self.bus.apply(&mut self.system_config);
+		while let Some(notification) = self.bus.next_notification() {
+			match notification {
+				Notification::Transition(transition) => {
+					let message = match transition {
+						Transition::Started => "watering started",
+						Transition::Stopped => "watering stopped",
+						Transition::Suspended => "suspended",
+						Transition::Resumed => "resumed",
+						Transition::Faulted(_) => "faulted",
+						Transition::Cleared => "fault acknowledged",
+						Transition::Winterized => "winterized",
+						Transition::Reenabled => "re-enabled",
+					};
+					self.print(message);
+				}
+				// If there was any update to the activation state, update both the suspend and
+				// activate menu items so that they're consistent with the configuration state.
+				Notification::Changed(UpdateSystemValue::Suspend, _)
+				| Notification::Changed(UpdateSystemValue::Activate, _)
+				| Notification::Changed(UpdateSystemValue::Winterize, _)
+				| Notification::Changed(UpdateSystemValue::ActivationState, _)
+				| Notification::Changed(UpdateSystemValue::Fault(_), _)
+				| Notification::Changed(UpdateSystemValue::Acknowledge, _) => {
+					for update in [
+						UpdateSystemValue::Suspend,
+						UpdateSystemValue::Activate,
+						UpdateSystemValue::Winterize,
+					]
+					.iter()
+					{
+						self.menu
+							.update(*update, &self.system_config, &mut self.display);
+					}
+				}
+				// Otherwise, update the relevant menu item.
+				Notification::Changed(update_value, _) => {
+					self.menu
+						.update(update_value, &self.system_config, &mut self.display)
+				}
+			}
+		}
+	}
+
+	/// Print the display, with a border around it
+	fn show(&self) {
+		let border = format!("+{}+", "-".repeat(ROW_LENGTH as usize));
+		println!("{}", border);
+		for row in self.display.rows() {
+			println!("|{}|", row);
+		}
+		println!("{}", border);
+	}
+
+	/// Print a message with the time since the simulation started
+	fn print(&self, message: &str) {
+		let millis = CLOCK.millis();
+		println!(
+			"[{:>6}.{}s] {}",
+			millis / 1_000,
+			millis % 1_000 / 100,
+			message
+		);
+	}
+}
+
+/// Parse a sensor reading or amount of seconds
+fn parse_value(value: &str) -> Result<u16, String> {
+	value
+		.parse()
+		.map_err(|_| format!("`{}` isn't a number from 0 to 65535", value))
+}
+
+/// Parse the name of a button
+fn parse_button(name: &str) -> Result<ButtonType, String> {
+	ButtonType::ALL
+		.iter()
+		.copied()
+		.find(|button| button.label().eq_ignore_ascii_case(name))
+		.ok_or_else(|| format!("unknown button `{}`", name))
+}
+
+/// Run the script in the file given as the first argument, or read it from stdin
+pub fn run() {
+	let script = match env::args().nth(1) {
+		Some(path) => fs::read_to_string(path),
+		None => {
+			let mut script = String::new();
+			io::stdin().read_to_string(&mut script).map(|_| script)
+		}
+	};
+	let script = script.unwrap_or_else(|err| {
+		eprintln!("error: {}", err);
+		process::exit(1);
+	});
+
+	let mut simulation = Simulation::new();
+	for (idx, line) in script.lines().enumerate() {
+		if let Err(err) = simulation.run_line(line) {
+			eprintln!("error on line {}: {}", idx + 1, err);
+			process::exit(1);
+		}
+	}
+}
@@ -0,0 +1,218 @@
+//! Uptime and watering statistics
+//!
+//! The counters are saved to the EEPROM periodically, so that they carry on from where they were
+//! after the system is restarted. The time the dosing pump has run for (see [`crate::dosing`]) is
+//! counted for each week of uptime, in a record of its own so that the weekly cap carries on across
+//! restarts too. So is the time spent watering on each of the last few days (see [`crate::week`]),
+//! and the amount of times the valve has been opened since it was last serviced.
+
+use crate::{
+	config::SystemConfig,
+	display::{Display, BODY_START_ROW},
+	eeprom::{Eeprom, DOSING_ADDRESS, STATS_ADDRESS, VALVE_CYCLES_ADDRESS},
+	fmt::DaysHoursMins,
+	hal::Clock,
+	lang,
+	timer::{Duration, TIMER},
+	week::Week,
+};
+
+/// How often the counters are saved to the EEPROM
+///
+/// Saving less often loses more of the counters on a power cut, but wears out the EEPROM slower.
+const SAVE_INTERVAL: Duration = Duration::from_mins(30);
+/// Length of a week of uptime in seconds, which the dosing time is counted for
+const WEEK_SECS: u32 = 7 * 24 * 60 * 60;
+/// Size in bytes of the dosing record stored in the EEPROM
+const DOSING_SIZE: usize = 4;
+/// Size in bytes of the valve cycle record stored in the EEPROM
+const VALVE_CYCLES_SIZE: usize = 4;
+
+/// Uptime and watering counters
+pub struct Stats {
+	/// Total amount of seconds the system has been running for
+	uptime_secs: u32,
+	/// Amount of times the system has been activated
+	activations: u32,
+	/// Total amount of seconds the system has been activated for
+	watering_secs: u32,
+	/// Week of uptime which [`Stats::dosing_secs`] is counted for
+	dosing_week: u16,
+	/// Amount of seconds the dosing pump has run for this week
+	dosing_secs: u16,
+	/// Time spent watering on each of the last few days
+	week: Week,
+	/// Amount of times the valve has been opened since it was last serviced
+	valve_cycles: u32,
+	/// [`TIMER`] clock value of the last time the counters were updated
+	last_update_ms: u32,
+	/// [`TIMER`] clock value of the last time the counters were saved
+	last_save_ms: u32,
+}
+
+impl Stats {
+	/// Size in bytes of the record stored in the EEPROM
+	const SIZE: usize = 12;
+
+	/// Load the counters from the EEPROM
+	///
+	/// The counters start from zero if they haven't been saved before.
+	pub fn load(eeprom: &Eeprom) -> Self {
+		let now = TIMER.millis();
+		let mut stats = Self {
+			uptime_secs: 0,
+			activations: 0,
+			watering_secs: 0,
+			dosing_week: 0,
+			dosing_secs: 0,
+			week: Week::load(eeprom),
+			valve_cycles: 0,
+			last_update_ms: now,
+			last_save_ms: now,
+		};
+
+		let mut buf = [0; Self::SIZE];
+		if eeprom.read_record(STATS_ADDRESS, &mut buf) {
+			stats.uptime_secs = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+			stats.activations = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+			stats.watering_secs = u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]);
+		}
+
+		let mut buf = [0; DOSING_SIZE];
+		if eeprom.read_record(DOSING_ADDRESS, &mut buf) {
+			stats.dosing_week = u16::from_le_bytes([buf[0], buf[1]]);
+			stats.dosing_secs = u16::from_le_bytes([buf[2], buf[3]]);
+		}
+
+		let mut buf = [0; VALVE_CYCLES_SIZE];
+		if eeprom.read_record(VALVE_CYCLES_ADDRESS, &mut buf) {
+			stats.valve_cycles = u32::from_le_bytes(buf);
+		}
+
+		stats
+	}
+
+	/// Store the counters in the EEPROM
+	pub fn save(&mut self, eeprom: &mut Eeprom) {
+		let mut buf = [0; Self::SIZE];
+		buf[0..4].copy_from_slice(&self.uptime_secs.to_le_bytes());
+		buf[4..8].copy_from_slice(&self.activations.to_le_bytes());
+		buf[8..12].copy_from_slice(&self.watering_secs.to_le_bytes());
+
+		eeprom.write_record(STATS_ADDRESS, &buf);
+
+		let mut buf = [0; DOSING_SIZE];
+		buf[0..2].copy_from_slice(&self.dosing_week.to_le_bytes());
+		buf[2..4].copy_from_slice(&self.dosing_secs.to_le_bytes());
+		eeprom.write_record(DOSING_ADDRESS, &buf);
+		self.week.save(eeprom);
+		eeprom.write_record(VALVE_CYCLES_ADDRESS, &self.valve_cycles.to_le_bytes());
+
+		self.last_save_ms = TIMER.millis();
+	}
+
+	/// Total amount of seconds the system has been running for
+	pub fn uptime_secs(&self) -> u32 {
+		self.uptime_secs
+	}
+
+	/// Time spent watering on each of the last few days
+	pub fn week(&self) -> &Week {
+		&self.week
+	}
+
+	/// Amount of seconds the dosing pump has run for this week
+	pub fn dosing_secs(&self) -> u32 {
+		self.dosing_secs as u32
+	}
+
+	/// Count a new activation
+	pub fn record_activation(&mut self) {
+		self.activations = self.activations.saturating_add(1);
+	}
+
+	/// Amount of times the valve has been opened since it was last serviced
+	pub fn valve_cycles(&self) -> u32 {
+		self.valve_cycles
+	}
+
+	/// Count the valve being opened
+	pub fn record_valve_cycle(&mut self) {
+		self.valve_cycles = self.valve_cycles.saturating_add(1);
+	}
+
+	/// Start counting the valve cycles again, once the valve has been serviced
+	pub fn reset_valve_cycles(&mut self, eeprom: &mut Eeprom) {
+		self.valve_cycles = 0;
+		self.save(eeprom);
+	}
+
+	/// Whether the valve has been opened enough times to be due a service
+	pub fn is_service_due(&self, system_config: &SystemConfig) -> bool {
+		system_config.service_kcycles != 0
+			&& self.valve_cycles >= system_config.service_kcycles as u32 * 1_000
+	}
+
+	/// Add the time passed since the last update to the counters, and save them if they are due
+	/// to be saved
+	///
+	/// Returns `true` if the counters changed.
+	pub fn update(&mut self, watering: bool, dosing: bool, eeprom: &mut Eeprom) -> bool {
+		let elapsed_secs = TIMER.elapsed_since(self.last_update_ms) / 1_000;
+		if elapsed_secs == 0 {
+			return false;
+		}
+
+		// Only whole seconds are counted, so carry the remainder over to the next update.
+		self.last_update_ms = self.last_update_ms.wrapping_add(elapsed_secs * 1_000);
+		self.uptime_secs = self.uptime_secs.saturating_add(elapsed_secs);
+		if watering {
+			self.watering_secs = self.watering_secs.saturating_add(elapsed_secs);
+		}
+
+		// If a new week has started, the dosing pump can run for the full weekly cap again.
+		let week = (self.uptime_secs / WEEK_SECS) as u16;
+		if week != self.dosing_week {
+			self.dosing_week = week;
+			self.dosing_secs = 0;
+		}
+		if dosing {
+			self.dosing_secs = self.dosing_secs.saturating_add(elapsed_secs as u16);
+		}
+		self.week
+			.record(self.uptime_secs, if watering { elapsed_secs } else { 0 });
+
+		if TIMER.has_elapsed(self.last_save_ms, SAVE_INTERVAL) {
+			self.save(eeprom);
+		}
+
+		true
+	}
+
+	/// Render the info screen
+	pub fn render(&self, display: &mut Display) {
+		display.clear_body();
+		display.set_position(0, BODY_START_ROW);
+		let _ = ufmt::uwrite!(display, "{}", lang::INFO);
+		self.render_values(display);
+	}
+
+	/// Render the counters on the info screen
+	///
+	/// Faster than [`Stats::render`], so should be used whenever the counters change.
+	pub fn render_values(&self, display: &mut Display) {
+		display.clear_row(BODY_START_ROW + 2);
+		let _ = ufmt::uwrite!(
+			display,
+			"{}: {}",
+			lang::UPTIME,
+			DaysHoursMins(self.uptime_secs)
+		);
+
+		display.clear_row(BODY_START_ROW + 3);
+		let _ = ufmt::uwrite!(display, "{}: {}", lang::RUNS, self.activations);
+
+		display.clear_row(BODY_START_ROW + 4);
+		let _ = ufmt::uwrite!(display, "{}: {}m", lang::WATERED, self.watering_secs / 60);
+	}
+}
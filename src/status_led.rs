@@ -0,0 +1,97 @@
+//! Status LED
+//!
+//! The on-board LED on D13 blinks in a pattern showing the state of the system, so that it can be
+//! seen from across the garden.
+
+use crate::{
+	hal::{Clock, MappedOutput},
+	timer::{Duration, TIMER},
+};
+
+/// Blink patterns for each state of the system
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LedPattern {
+	/// Short blink every 2 seconds
+	Waiting,
+	/// Mostly on
+	Activated,
+	/// Slow, even blink
+	Suspended,
+	/// Fast blink
+	Fault,
+	/// Short blink every 10 seconds
+	Winterized,
+}
+
+impl LedPattern {
+	/// Lengths of time in milliseconds the LED is on and then off for, repeated for as long as the
+	/// pattern is shown
+	fn steps(self) -> [u16; 2] {
+		match self {
+			Self::Waiting => [50, 1_950],
+			Self::Activated => [900, 100],
+			Self::Suspended => [500, 500],
+			Self::Fault => [100, 100],
+			Self::Winterized => [50, 9_950],
+		}
+	}
+}
+
+/// LED which shows the state of the system
+pub struct StatusLed {
+	pin: MappedOutput,
+	/// Pattern being shown
+	pattern: LedPattern,
+	/// Whether the LED is in the on step of the pattern
+	on: bool,
+	/// [`TIMER`] clock value of when the current step started
+	step_started_ms: u32,
+}
+
+impl StatusLed {
+	/// Create a new [`StatusLed`] from the LED pin
+	pub fn new(pin: MappedOutput) -> Self {
+		let mut led = Self {
+			pin,
+			pattern: LedPattern::Waiting,
+			on: false,
+			step_started_ms: 0,
+		};
+		led.set_step(true);
+		led
+	}
+
+	/// Show `pattern`, and move on to the next step of it if it is time to
+	///
+	/// Should be called on every tick of the system.
+	pub fn update(&mut self, pattern: LedPattern) {
+		// Start the new pattern from the beginning if it has changed.
+		if pattern != self.pattern {
+			self.pattern = pattern;
+			self.set_step(true);
+			return;
+		}
+
+		let [on_ms, off_ms] = self.pattern.steps();
+		let step_ms = if self.on { on_ms } else { off_ms };
+		if TIMER.has_elapsed(self.step_started_ms, Duration::from_millis(step_ms as u32)) {
+			self.set_step(!self.on);
+		}
+	}
+
+	/// Turn the LED off until the next update
+	pub fn off(&mut self) {
+		self.set_step(false);
+	}
+
+	/// Turn the LED on or off, starting a new step of the pattern
+	fn set_step(&mut self, on: bool) {
+		self.on = on;
+		self.step_started_ms = TIMER.millis();
+		if on {
+			self.pin.set_high();
+		} else {
+			self.pin.set_low();
+		}
+	}
+}
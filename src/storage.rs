@@ -0,0 +1,207 @@
+//! Circular logs of records in non-volatile storage
+//!
+//! The event history (and the sensor samples, with an external chip) are appended to a circular
+//! region of a [`Storage`], so that they survive a power cycle. Each record is written to the slot
+//! after the previous one, which spreads the writes over the whole region rather than wearing out
+//! a single address. Records hold a sequence number, which is used to find the newest record when
+//! the system starts up.
+//!
+//! The internal EEPROM only lasts for around 100,000 writes of each byte, and has room for 100
+//! events. With the `external-storage` feature, the logs are kept on a 24LC256 EEPROM on the
+//! display's I2C bus instead (see [`i2c`]), or on a FRAM chip with the `fram` feature, which can
+//! be written to as often as needed.
+
+#[cfg(any(feature = "external-storage", test))]
+pub mod i2c;
+
+use crate::hal::Storage;
+
+/// Longest record which can be kept in a [`Ring`], including its sequence number
+const MAX_RECORD_SIZE: usize = 16;
+
+/// Circular region of records in a [`Storage`]
+#[derive(Clone, Copy)]
+pub struct Region {
+	/// Address of the first slot
+	pub address: u16,
+	/// Amount of records which are kept, before the oldest are overwritten
+	pub slots: u16,
+}
+
+/// Records of `size` bytes in a region, each after a sequence number
+pub struct Ring {
+	region: Region,
+	/// Size in bytes of each record, not including its sequence number or checksum
+	size: usize,
+	/// Slot the next record is written to
+	next_slot: u16,
+	/// Sequence number of the next record
+	next_seq: u16,
+}
+
+impl Ring {
+	/// Find where the next record of `size` bytes should be written in `region`
+	///
+	/// The newest record is the last one which is followed by a record with the next sequence
+	/// number. If no records have been written yet, the ring starts from the first slot.
+	pub fn load(storage: &mut impl Storage, region: Region, size: usize) -> Self {
+		let mut ring = Self {
+			region,
+			size,
+			next_slot: 0,
+			next_seq: 0,
+		};
+
+		let mut record = [0; MAX_RECORD_SIZE];
+		for slot in 0..region.slots {
+			let seq = match ring.read(storage, slot, &mut record) {
+				Some(seq) => seq,
+				None => continue,
+			};
+
+			let next_seq = seq.wrapping_add(1);
+			let is_newest = match ring.read(storage, (slot + 1) % region.slots, &mut record) {
+				Some(next) => next != next_seq,
+				None => true,
+			};
+			if is_newest {
+				ring.next_slot = (slot + 1) % region.slots;
+				ring.next_seq = next_seq;
+				break;
+			}
+		}
+
+		ring
+	}
+
+	/// Amount of records which are kept
+	pub fn slots(&self) -> u16 {
+		self.region.slots
+	}
+
+	/// Append a record, overwriting the oldest record if the region is full
+	pub fn append(&mut self, storage: &mut impl Storage, record: &[u8]) {
+		let mut buf = [0; MAX_RECORD_SIZE];
+		buf[0..2].copy_from_slice(&self.next_seq.to_le_bytes());
+		buf[2..2 + self.size].copy_from_slice(record);
+
+		storage.write_record(self.address(self.next_slot), &buf[..2 + self.size]);
+		self.next_slot = (self.next_slot + 1) % self.region.slots;
+		self.next_seq = self.next_seq.wrapping_add(1);
+	}
+
+	/// Read the record `age` places before the newest into `record`, if it exists and is part of
+	/// the current log
+	///
+	/// Returns the sequence number of the record.
+	pub fn nth_newest(
+		&self,
+		storage: &mut impl Storage,
+		age: u16,
+		record: &mut [u8],
+	) -> Option<u16> {
+		let slots = self.region.slots;
+		let slot = (self.next_slot + slots - 1 - age) % slots;
+		let seq = self.next_seq.wrapping_sub(1).wrapping_sub(age);
+		self.read(storage, slot, record).filter(|&read| read == seq)
+	}
+
+	/// Read the record in a slot into `record`, if it is valid
+	///
+	/// Returns the sequence number of the record.
+	fn read(&self, storage: &mut impl Storage, slot: u16, record: &mut [u8]) -> Option<u16> {
+		let mut buf = [0; MAX_RECORD_SIZE];
+		if !storage.read_record(self.address(slot), &mut buf[..2 + self.size]) {
+			return None;
+		}
+
+		record[..self.size].copy_from_slice(&buf[2..2 + self.size]);
+		Some(u16::from_le_bytes([buf[0], buf[1]]))
+	}
+
+	/// Address of a slot
+	fn address(&self, slot: u16) -> u16 {
+		self.region.address + slot * (2 + self.size as u16 + 1)
+	}
+}
+
+/// CRC-8 checksum (polynomial 0x07) of `data`
+pub fn crc8(data: &[u8]) -> u8 {
+	crc8_update(0, data)
+}
+
+/// Continue a CRC-8 checksum with more `data`, for data which isn't in a single slice
+pub fn crc8_update(mut crc: u8, data: &[u8]) -> u8 {
+	for byte in data {
+		crc ^= byte;
+		for _ in 0..8 {
+			crc = if crc & 0x80 != 0 {
+				(crc << 1) ^ 0x07
+			} else {
+				crc << 1
+			};
+		}
+	}
+	crc
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::hal::mock::MockStorage;
+
+	const REGION: Region = Region {
+		address: 4,
+		slots: 3,
+	};
+
+	/// Sequence numbers and records from the newest to the oldest
+	fn newest_first(ring: &Ring, storage: &mut MockStorage) -> Vec<(u16, u8)> {
+		let mut record = [0];
+		(0..ring.slots())
+			.filter_map(|age| {
+				ring.nth_newest(storage, age, &mut record)
+					.map(|seq| (seq, record[0]))
+			})
+			.collect()
+	}
+
+	#[test]
+	fn appends_around_the_region() {
+		let mut storage = MockStorage::new(32);
+		let mut ring = Ring::load(&mut storage, REGION, 1);
+		assert_eq!(newest_first(&ring, &mut storage), vec![]);
+
+		for record in 10..14 {
+			ring.append(&mut storage, &[record]);
+		}
+		assert_eq!(
+			newest_first(&ring, &mut storage),
+			vec![(3, 13), (2, 12), (1, 11)]
+		);
+		// Nothing is written outside of the region.
+		assert!(storage.bytes[..4].iter().all(|&byte| byte == 0xff));
+		assert!(storage.bytes[4 + 3 * 4..].iter().all(|&byte| byte == 0xff));
+	}
+
+	#[test]
+	fn carries_on_after_the_newest_record() {
+		let mut storage = MockStorage::new(32);
+		let mut ring = Ring::load(&mut storage, REGION, 1);
+		for record in 10..15 {
+			ring.append(&mut storage, &[record]);
+		}
+		// The corrupted record is skipped.
+		storage.bytes[REGION.address as usize] ^= 1;
+
+		let mut ring = Ring::load(&mut storage, REGION, 1);
+		ring.append(&mut storage, &[15]);
+		assert_eq!(newest_first(&ring, &mut storage), vec![(5, 15), (4, 14)]);
+	}
+
+	#[test]
+	fn crc8_matches_known_checksums() {
+		assert_eq!(crc8(b"123456789"), 0xf4);
+		assert_eq!(crc8_update(crc8(b"1234"), b"56789"), 0xf4);
+	}
+}
@@ -0,0 +1,211 @@
+//! External I2C EEPROM or FRAM
+//!
+//! A 24LC256 EEPROM (or another 32KiB chip of the family, such as an AT24C256), or with the `fram`
+//! feature an MB85RC256V or FM24C256 FRAM, on the display's I2C bus with its address pins tied low.
+//! The EEPROM lasts for around a million writes of each byte, and the FRAM for as many as needed.
+//!
+//! Both are addressed with two bytes, followed by the data. The EEPROM is written a page at a time,
+//! and ignores anything sent to it for a few milliseconds afterwards, so writes are split at the
+//! page boundaries and each one is followed by polling the chip until it answers again. FRAM is
+//! written straight away.
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+use crate::hal::Storage;
+
+/// Address of the chip, with its address pins tied low
+const ADDRESS: u8 = 0x50;
+/// Size in bytes of the EEPROM's pages, which a single write can't cross
+const PAGE_SIZE: u16 = 64;
+/// Most bytes sent in a single write, after the address
+const CHUNK_SIZE: usize = 16;
+/// Most times the EEPROM is polled after a write, which covers its 5ms write cycle at 400kHz
+const MAX_POLLS: u16 = 250;
+/// Value read back when the chip doesn't answer, which is the same as an erased byte so that the
+/// record fails its checksum
+const ERASED: u8 = 0xff;
+
+/// Storage chip on the bus
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Chip {
+	/// Written a page at a time, followed by a write cycle
+	Eeprom,
+	/// Written straight away, without pages
+	Fram,
+}
+
+/// Storage chip the firmware is built for
+const CHIP: Chip = if cfg!(feature = "fram") {
+	Chip::Fram
+} else {
+	Chip::Eeprom
+};
+
+/// EEPROM or FRAM chip on the I2C bus `I`
+pub struct I2cStorage<I> {
+	bus: I,
+	chip: Chip,
+}
+
+impl<I: Write + WriteRead> I2cStorage<I> {
+	/// Create a new [`I2cStorage`] for the chip on `bus`
+	pub fn new(bus: I) -> Self {
+		Self::with_chip(bus, CHIP)
+	}
+
+	/// Create a new [`I2cStorage`] for a `chip` on `bus`
+	fn with_chip(bus: I, chip: Chip) -> Self {
+		Self { bus, chip }
+	}
+
+	/// Whether the chip acknowledges its address
+	pub fn is_responding(&mut self) -> bool {
+		self.bus.write(ADDRESS, &[]).is_ok()
+	}
+
+	/// Wait for the EEPROM to finish writing, until it acknowledges its address again
+	fn wait_for_write(&mut self) {
+		if self.chip == Chip::Fram {
+			return;
+		}
+		for _ in 0..MAX_POLLS {
+			if self.is_responding() {
+				return;
+			}
+		}
+	}
+}
+
+impl<I: Write + WriteRead> Storage for I2cStorage<I> {
+	/// Read bytes into `buf`, which are all erased if the chip doesn't answer
+	fn read(&mut self, address: u16, buf: &mut [u8]) {
+		if self
+			.bus
+			.write_read(ADDRESS, &address.to_be_bytes(), buf)
+			.is_err()
+		{
+			for byte in buf.iter_mut() {
+				*byte = ERASED;
+			}
+		}
+	}
+
+	/// Write `data`, which is left out if the chip doesn't answer
+	fn write(&mut self, address: u16, data: &[u8]) {
+		let mut offset = 0;
+		while offset < data.len() {
+			let start = address + offset as u16;
+			let mut len = (data.len() - offset).min(CHUNK_SIZE);
+			if self.chip == Chip::Eeprom {
+				len = len.min((PAGE_SIZE - start % PAGE_SIZE) as usize);
+			}
+
+			let mut buf = [0; 2 + CHUNK_SIZE];
+			buf[..2].copy_from_slice(&start.to_be_bytes());
+			buf[2..2 + len].copy_from_slice(&data[offset..offset + len]);
+			if self.bus.write(ADDRESS, &buf[..2 + len]).is_ok() {
+				self.wait_for_write();
+			}
+			offset += len;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Chip on a bus which records the address and length of each write, and answers while
+	/// `responding`
+	struct MockBus {
+		memory: Vec<u8>,
+		writes: Vec<(u16, usize)>,
+		polls: usize,
+		responding: bool,
+	}
+
+	impl Write for MockBus {
+		type Error = ();
+
+		fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+			if !self.responding || address != ADDRESS {
+				return Err(());
+			}
+			if bytes.is_empty() {
+				self.polls += 1;
+				return Ok(());
+			}
+
+			let start = u16::from_be_bytes([bytes[0], bytes[1]]);
+			let data = &bytes[2..];
+			self.memory[start as usize..start as usize + data.len()].copy_from_slice(data);
+			self.writes.push((start, data.len()));
+			Ok(())
+		}
+	}
+
+	impl WriteRead for MockBus {
+		type Error = ();
+
+		fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+			if !self.responding || address != ADDRESS {
+				return Err(());
+			}
+			let start = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+			buffer.copy_from_slice(&self.memory[start..start + buffer.len()]);
+			Ok(())
+		}
+	}
+
+	fn storage(chip: Chip) -> I2cStorage<MockBus> {
+		I2cStorage::with_chip(
+			MockBus {
+				memory: vec![ERASED; 256],
+				writes: Vec::new(),
+				polls: 0,
+				responding: true,
+			},
+			chip,
+		)
+	}
+
+	#[test]
+	fn eeprom_writes_are_split_at_pages() {
+		let mut storage = storage(Chip::Eeprom);
+		let data: Vec<u8> = (0..40).collect();
+		storage.write(60, &data);
+		assert_eq!(
+			storage.bus.writes,
+			vec![(60, 4), (64, 16), (80, 16), (96, 4)]
+		);
+		assert_eq!(storage.bus.polls, 4);
+
+		let mut buf = [0; 40];
+		storage.read(60, &mut buf);
+		assert_eq!(buf.to_vec(), data);
+	}
+
+	#[test]
+	fn fram_is_written_without_pages() {
+		let mut storage = storage(Chip::Fram);
+		storage.write_record(60, &[1, 2, 3, 4, 5]);
+		assert_eq!(storage.bus.writes, vec![(60, 5), (65, 1)]);
+		assert_eq!(storage.bus.polls, 0);
+
+		let mut buf = [0; 5];
+		assert!(storage.read_record(60, &mut buf));
+		assert_eq!(buf, [1, 2, 3, 4, 5]);
+	}
+
+	#[test]
+	fn unanswered_reads_are_erased() {
+		let mut storage = storage(Chip::Eeprom);
+		storage.write_record(0, &[1, 2]);
+		storage.bus.responding = false;
+		assert!(!storage.is_responding());
+
+		let mut buf = [0; 2];
+		assert!(!storage.read_record(0, &mut buf));
+		assert_eq!(buf, [ERASED; 2]);
+	}
+}
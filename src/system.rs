@@ -1,209 +1,2013 @@
 //! Logic for coordinating peripheral inputs and outputs
 
-use arduino_hal::{
-	clock::MHz16,
-	hal::{
-		port::{PC0, PC1, PD3},
-		Adc,
+#[cfg(not(feature = "encoder"))]
+use crate::calibration;
+#[cfg(feature = "heat-mat")]
+use crate::hal::SoilProbe;
+#[cfg(feature = "external-storage")]
+use crate::log::samples::SampleLog;
+#[cfg(feature = "pwm-pump")]
+use crate::pump::Pump;
+#[cfg(feature = "rain-gauge")]
+use crate::raingauge::{receiver::Receiver, Message, RainGauge};
+#[cfg(feature = "bluetooth")]
+use crate::serial::bluetooth::{Auth, Session};
+use crate::{
+	about,
+	activation::{self, FaultKind, Strategy, Transition},
+	band::Smoother,
+	battery::Battery,
+	boards,
+	bus::{Bus, Notification},
+	buzzer::{Buzzer, Pattern},
+	climate::{Climate, Reading},
+	config::{Number, SystemConfig, SystemValue, UpdateSystemValue, ValueAction},
+	control_pad::{ButtonEvent, ButtonStage, ButtonThresholds, ButtonType, ControlInput},
+	diag::{memory, Diagnostics, Readings},
+	display::{
+		dimmer::Dimmer,
+		statusbar::{Status, StatusBar},
+		Display, BODY_START_ROW,
+	},
+	dosing,
+	eeprom::{
+		Eeprom, BUTTON_THRESHOLDS_ADDRESS, PIN_MAP_ADDRESS, RECOVERY_ADDRESS, RELAY_WIRING_ADDRESS,
+		ZONE_NAME_ADDRESS,
+	},
+	evening::Evening,
+	fan::Fan,
+	forecast::{Adjustment, Forecast},
+	hal::{Adc, AnalogReader, ButtonInput, Clock, LightSensor, LogStorage, MoistureSensor},
+	heatmat::HeatMat,
+	lang,
+	link::{
+		topics::{Fault, Message, Topics},
+		Ack, Event, Input, Link, Request,
 	},
-	port::{
-		mode::{Analog, Output},
-		Pin,
+	lock::KeypadLock,
+	log::{
+		changes::{ChangeLog, Source},
+		history::History,
+		EventLog, LogEvent,
 	},
+	menu::{Menu, MenuAction},
+	photoperiod::Photoperiod,
+	pinmap::{PinMap, Role},
+	presets::Presets,
+	raindelay::RainDelay,
+	recovery::Recovery,
+	resume::Resume,
+	safemode::{self, Reason},
+	scheduler::{Scheduler, Task},
+	selftest::{self, Outcome, Report},
+	sequence::{Delays, Sequence},
+	serial::{
+		self,
+		cli::{Cli, Command},
+	},
+	stats::Stats,
+	status_led::{LedPattern, StatusLed},
+	telemetry::{self, Record},
+	timer::{self, Duration, SoftTimer, TIMER},
+	trace::{Replay, Sample},
+	trend::{Anomaly, MoistureTrend},
+	vacation::{self, Vacation},
+	valve::{RelayWiring, Valve, ValveDriver},
+	watchdog::Watchdog,
+	zonename::{Edit, NameEditor, ZoneName},
 };
-use core::sync::atomic::Ordering;
-
+#[cfg(feature = "dht22")]
 use crate::{
-	config::{SystemConfig, UpdateSystemValue},
-	control_pad::ControlPad,
-	display::Display,
-	menu::Menu,
-	timer::TIMER,
+	climate::dht22::Dht22,
+	fmt::{Celsius, Percent},
 };
+#[cfg(any(
+	feature = "dosing-pump",
+	feature = "grow-light",
+	feature = "fan",
+	feature = "heat-mat",
+	feature = "master-valve"
+))]
+use crate::{hal::MappedOutput, valve::switch_relay};
 
+/// How long in milliseconds to show the splash screen for when the system starts up
+const SPLASH_DELAY_MS: u16 = 1_500;
+/// How long in milliseconds to show the self-test results for when every check passed
+const SELF_TEST_PASS_DELAY_MS: u16 = 1_000;
+/// How long in milliseconds to show the self-test results for when a check failed
+const SELF_TEST_FAIL_DELAY_MS: u16 = 5_000;
+/// How long to open the valve for, and then to close it for, during the self-test
+#[cfg(feature = "self-test-valve")]
+const SELF_TEST_VALVE_PULSE: Duration = Duration::from_millis(250);
+/// How long in milliseconds to show a fault from the last reset for when the system starts up
+const RESET_FAULT_DELAY_MS: u16 = 5_000;
+/// How long in milliseconds to show the reason for safe mode for when the system starts up
+const SAFE_MODE_DELAY_MS: u16 = 5_000;
+/// Amount of outputs of the shift registers, which can be tested from the diagnostics screen
+#[cfg(feature = "shift-register-valve")]
+const TEST_CHANNELS: u8 = crate::shiftreg::CHANNELS;
+/// Without shift registers, there are no outputs to test
+#[cfg(not(feature = "shift-register-valve"))]
+const TEST_CHANNELS: u8 = 0;
+/// Address and size of each configuration record in the EEPROM, which are checked when the system
+/// starts up, and erased by Reset in safe mode
+const CONFIG_RECORDS: [(u16, usize); 5] = [
+	(BUTTON_THRESHOLDS_ADDRESS, ButtonThresholds::SIZE),
+	(RECOVERY_ADDRESS, Recovery::SIZE),
+	(PIN_MAP_ADDRESS, PinMap::SIZE),
+	(ZONE_NAME_ADDRESS, ZoneName::SIZE),
+	(RELAY_WIRING_ADDRESS, RelayWiring::SIZE),
+];
+/// Longest amount of time the valve can be open for, regardless of the configured activation time
+///
+/// If the valve is open for longer than this, something has gone wrong and the valve is closed
+/// until the system is restarted.
+const VALVE_MAX_OPEN: Duration = Duration::from_mins(90);
 /// Holds peripherals for reading sensor values and controlling hardware
 pub struct SystemPeripherals {
-	/// Solenoid valve relay
-	valve: Pin<Output, PD3>,
+	/// Solenoid valve
+	valve: Valve,
+	/// PWM pump, which runs whenever the valve is open
+	#[cfg(feature = "pwm-pump")]
+	pump: Pump,
+	/// Stage of opening or closing the valve
+	sequence: Sequence,
 	/// Light sensor
-	light_sensor: Pin<Analog, PC0>,
+	light_sensor: LightSensor,
 	/// Moisture sensor
-	moisture_sensor: Pin<Analog, PC1>,
+	moisture_sensor: MoistureSensor,
+	/// Whether the valve has been open for longer than [`VALVE_MAX_OPEN`]
+	///
+	/// Once set, the valve stays closed until the system is restarted.
+	valve_fault: bool,
+	/// Sensor trace being replayed in place of the sensors
+	replay: Replay,
+	/// Temperature and humidity sensor
+	#[cfg(feature = "dht22")]
+	climate_sensor: Dht22,
+	/// Fertilizer dosing pump
+	#[cfg(feature = "dosing-pump")]
+	doser: MappedOutput,
+	/// Grow-light relay
+	#[cfg(feature = "grow-light")]
+	grow_light: MappedOutput,
+	/// Runs the fan while it is too hot or humid
+	fan: Fan,
+	/// Ventilation fan relay
+	#[cfg(feature = "fan")]
+	fan_relay: MappedOutput,
+	/// Soil temperature probe of the heat mat
+	#[cfg(feature = "heat-mat")]
+	soil_probe: SoilProbe,
+	/// Heat mat relay
+	#[cfg(feature = "heat-mat")]
+	heat_mat: MappedOutput,
+	/// Master valve or pump-start relay
+	#[cfg(feature = "master-valve")]
+	master: MappedOutput,
+	/// 433MHz receiver of the rain gauge
+	#[cfg(feature = "rain-gauge")]
+	rain_receiver: Receiver,
 }
 
 impl SystemPeripherals {
 	/// Create a new [`SystemPeripherals`] from [Pin]'s
 	pub fn new(
-		valve: Pin<Output, PD3>,
-		light_sensor: Pin<Analog, PC0>,
-		moisture_sensor: Pin<Analog, PC1>,
+		valve: Valve,
+		#[cfg(feature = "pwm-pump")] pump: Pump,
+		light_sensor: LightSensor,
+		moisture_sensor: MoistureSensor,
+		#[cfg(feature = "dosing-pump")] doser: MappedOutput,
+		#[cfg(feature = "grow-light")] grow_light: MappedOutput,
+		#[cfg(feature = "fan")] fan_relay: MappedOutput,
+		#[cfg(feature = "heat-mat")] soil_probe: SoilProbe,
+		#[cfg(feature = "heat-mat")] heat_mat: MappedOutput,
+		#[cfg(feature = "master-valve")] master: MappedOutput,
+		#[cfg(feature = "rain-gauge")] rain_receiver: Receiver,
 	) -> Self {
 		Self {
 			valve,
+			#[cfg(feature = "pwm-pump")]
+			pump,
+			sequence: Sequence::new(),
 			light_sensor,
 			moisture_sensor,
+			valve_fault: false,
+			replay: Replay::new(),
+			#[cfg(feature = "dht22")]
+			climate_sensor: Dht22::new(),
+			#[cfg(feature = "dosing-pump")]
+			doser,
+			#[cfg(feature = "grow-light")]
+			grow_light,
+			fan: Fan::new(),
+			#[cfg(feature = "fan")]
+			fan_relay,
+			#[cfg(feature = "heat-mat")]
+			soil_probe,
+			#[cfg(feature = "heat-mat")]
+			heat_mat,
+			#[cfg(feature = "master-valve")]
+			master,
+			#[cfg(feature = "rain-gauge")]
+			rain_receiver,
+		}
+	}
+
+	/// Run or stop the dosing pump
+	#[cfg(feature = "dosing-pump")]
+	pub fn set_dosing(&mut self, dosing: bool, system_config: &SystemConfig) {
+		switch_relay(&mut self.doser, dosing, system_config.relay_active_low);
+	}
+
+	/// Without a dosing pump, there is nothing to run
+	#[cfg(not(feature = "dosing-pump"))]
+	pub fn set_dosing(&mut self, _dosing: bool, _system_config: &SystemConfig) {}
+
+	/// Switch the grow lights on or off
+	#[cfg(feature = "grow-light")]
+	pub fn set_grow_light(&mut self, on: bool, system_config: &SystemConfig) {
+		switch_relay(&mut self.grow_light, on, system_config.relay_active_low);
+	}
+
+	/// Without a grow-light relay, there is nothing to switch
+	#[cfg(not(feature = "grow-light"))]
+	pub fn set_grow_light(&mut self, _on: bool, _system_config: &SystemConfig) {}
+
+	/// Run or stop the fan
+	#[cfg(feature = "fan")]
+	fn set_fan(&mut self, running: bool, system_config: &SystemConfig) {
+		switch_relay(&mut self.fan_relay, running, system_config.relay_active_low);
+	}
+
+	/// Without a fan relay, there is nothing to run
+	#[cfg(not(feature = "fan"))]
+	fn set_fan(&mut self, _running: bool, _system_config: &SystemConfig) {}
+
+	/// Switch the heat mat on or off
+	#[cfg(feature = "heat-mat")]
+	pub fn set_heat_mat(&mut self, on: bool, system_config: &SystemConfig) {
+		switch_relay(&mut self.heat_mat, on, system_config.relay_active_low);
+	}
+
+	/// Without a heat mat relay, there is nothing to switch
+	#[cfg(not(feature = "heat-mat"))]
+	pub fn set_heat_mat(&mut self, _on: bool, _system_config: &SystemConfig) {}
+
+	/// Open or close the master valve
+	#[cfg(feature = "master-valve")]
+	fn set_master(&mut self, open: bool, system_config: &SystemConfig) {
+		switch_relay(&mut self.master, open, system_config.relay_active_low);
+	}
+
+	/// Without a master valve relay, there is nothing to open
+	#[cfg(not(feature = "master-valve"))]
+	fn set_master(&mut self, _open: bool, _system_config: &SystemConfig) {}
+
+	/// Switch off the valve, the pump and every other output, for low-power mode
+	///
+	/// The outputs are switched on again by the tasks which drive them, once the battery has
+	/// recovered.
+	pub fn power_down(&mut self, system_config: &SystemConfig) {
+		self.shut_off(system_config);
+		self.set_dosing(false, system_config);
+		self.set_grow_light(false, system_config);
+		self.set_fan(false, system_config);
+		self.set_heat_mat(false, system_config);
+	}
+
+	/// Energize the relay on `channel` of the shift registers to test it, or stop testing with
+	/// `None`
+	#[cfg(feature = "shift-register-valve")]
+	pub fn test_valve_channel(&mut self, channel: Option<u8>, system_config: &SystemConfig) {
+		self.valve.test_channel(channel, system_config);
+	}
+
+	/// Without shift registers, there are no outputs to test
+	#[cfg(not(feature = "shift-register-valve"))]
+	pub fn test_valve_channel(&mut self, _channel: Option<u8>, _system_config: &SystemConfig) {}
+
+	/// Read the soil temperature probe
+	#[cfg(feature = "heat-mat")]
+	pub fn read_soil_probe(&self, adc: &mut Adc) -> u16 {
+		adc.read(&self.soil_probe)
+	}
+
+	/// Without a probe, the reading is out of range, so the heat mat is never switched on
+	#[cfg(not(feature = "heat-mat"))]
+	pub fn read_soil_probe(&self, _adc: &mut Adc) -> u16 {
+		0
+	}
+
+	/// Toggles valve activation and the fan if necessary
+	///
+	/// The master valve, the valve and the pump are opened and closed in order (see
+	/// [`crate::sequence`]). The fan runs according to the latest `climate` reading.
+	///
+	/// Returns `true` if the valve is being closed because it was open for too long.
+	pub fn update(&mut self, system_config: &SystemConfig, climate: Option<Reading>) -> bool {
+		// If the valve has been open for too long, latch the fault so that it stays closed.
+		let tripped = self.is_valve_open()
+			&& !self.valve_fault
+			&& SoftTimer::ValveOpen.elapsed() >= VALVE_MAX_OPEN;
+		if tripped {
+			self.valve_fault = true;
+		}
+
+		let should_open = system_config.activation_state.is_activated()
+			&& !activation::is_soaking(system_config)
+			&& !self.valve_fault;
+		self.sequence.update(should_open, Delays::of(system_config));
+
+		self.set_master(self.sequence.is_master_open(), system_config);
+		self.valve.set_open(self.is_valve_open(), system_config);
+		#[cfg(feature = "pwm-pump")]
+		self.pump
+			.update(self.sequence.is_pump_running(), system_config.flow_percent);
+
+		let running = self.fan.update(system_config, climate);
+		self.set_fan(running, system_config);
+
+		tripped
+	}
+
+	/// Whether the valve is open
+	pub fn is_valve_open(&self) -> bool {
+		self.sequence.is_valve_open()
+	}
+
+	/// Close the valve and the master valve and stop the pump straight away, without waiting for
+	/// the pump to stop first
+	///
+	/// The valve opens again once the system is activated.
+	pub fn shut_off(&mut self, system_config: &SystemConfig) {
+		self.sequence.stop();
+		self.set_master(false, system_config);
+		self.valve.set_open(false, system_config);
+		#[cfg(feature = "pwm-pump")]
+		self.pump.update(false, system_config.flow_percent);
+	}
+
+	/// Let the valve open again once its fault has been acknowledged
+	pub fn clear_valve_fault(&mut self) {
+		self.valve_fault = false;
+	}
+
+	/// Read the light and moisture sensors
+	///
+	/// While a sensor trace is being replayed, the readings of its current sample are used instead.
+	pub fn read_sensors(&self, adc: &mut Adc) -> (u16, u16) {
+		if let Some(readings) = self.replay.readings() {
+			return readings;
+		}
+
+		let (light, moisture) = self.read_raw_sensors(adc);
+		trace!("light={} moisture={}", light, moisture);
+
+		(light, moisture)
+	}
+
+	/// Read the temperature and humidity, if the sensor answers
+	#[cfg(feature = "dht22")]
+	pub fn read_climate(&mut self) -> Option<Reading> {
+		let reading = self.climate_sensor.read();
+		if reading.is_none() {
+			debug!("dht22 read failed");
 		}
+		reading
+	}
+
+	/// Read the temperature and humidity, which always fails without a sensor
+	#[cfg(not(feature = "dht22"))]
+	pub fn read_climate(&mut self) -> Option<Reading> {
+		None
+	}
+
+	/// Take the latest message received from the weather station's sensors, if there is one
+	#[cfg(feature = "rain-gauge")]
+	pub fn take_rain_message(&mut self) -> Option<Message> {
+		self.rain_receiver.take()
+	}
+
+	/// Read the light and moisture sensors, even while a sensor trace is being replayed
+	pub fn read_raw_sensors(&self, adc: &mut Adc) -> (u16, u16) {
+		(
+			adc.read(&self.light_sensor),
+			adc.read(&self.moisture_sensor),
+		)
 	}
 
-	/// Toggles valve activation if necessary
-	pub fn update(&mut self, system_config: &SystemConfig) {
-		if self.valve.is_set_high() && !system_config.activation_state.is_activated() {
-			// If the valve is on but the system is not activated, turn the valve off.
-			self.valve.set_low();
-		} else if self.valve.is_set_low() && system_config.activation_state.is_activated() {
-			// If the valve is off, but the system is activated, turn it on.
-			self.valve.set_high();
+	/// Open the valve briefly and close it again, blocking until it has closed
+	///
+	/// Used by the self-test, before the system starts running.
+	#[cfg(feature = "self-test-valve")]
+	pub fn pulse_valve(&mut self, system_config: &SystemConfig) {
+		for &open in [true, false].iter() {
+			let started_ms = TIMER.millis();
+			// A latching valve is pulsed over a number of updates, so keep updating the driver.
+			while !TIMER.has_elapsed(started_ms, SELF_TEST_VALVE_PULSE) {
+				self.valve.set_open(open, system_config);
+			}
 		}
 	}
 
-	/// Whether the valve should be turned on
-	pub fn should_activate(&self, system_config: &SystemConfig, adc: &mut Adc<MHz16>) -> bool {
-		let light = self.light_sensor.analog_read(adc);
-		let moisture = self.moisture_sensor.analog_read(adc);
+	/// Move the clock on to the time of a sample from a sensor trace, and use its readings in
+	/// place of the sensors
+	///
+	/// Every timer is moved on along with the clock, so the system catches up with the time of
+	/// the sample on the next tick.
+	pub fn replay_sample(&mut self, sample: &Sample) {
+		timer::advance(self.replay.gap(sample).as_millis());
+		self.replay.apply(sample);
+		debug!(
+			"replaying secs={} light={} moisture={}",
+			sample.secs, sample.light, sample.moisture
+		);
+	}
+
+	/// Stop replaying the sensor trace, and go back to reading the sensors
+	pub fn stop_replay(&mut self) {
+		self.replay.stop();
+	}
+}
 
-		moisture < system_config.min_moisture && light < system_config.min_light
+/// Screen being shown in the body of the display
+#[derive(Clone, Copy)]
+enum Screen {
+	/// Menu for configuring and controlling the system
+	Menu,
+	/// Uptime and watering statistics
+	Info,
+	/// Firmware information
+	About,
+	/// Recent events
+	Log,
+	/// Events stored in the EEPROM
+	History,
+	/// Raw readings of the analog inputs
+	Diagnostics,
+	/// Plant presets to choose from
+	Presets,
+	/// Watering of each of the last few days
+	Week,
+	/// Trend of the soil moisture
+	Soil,
+	/// Name of the zone, being edited
+	Name,
+}
+
+impl Screen {
+	/// Code of the screen, for storing it in the EEPROM
+	fn code(self) -> u8 {
+		self as u8
+	}
+
+	/// Screen with the given code, if it is valid
+	fn from_code(code: u8) -> Option<Self> {
+		match code {
+			0 => Some(Self::Menu),
+			1 => Some(Self::Info),
+			2 => Some(Self::About),
+			3 => Some(Self::Log),
+			4 => Some(Self::History),
+			5 => Some(Self::Diagnostics),
+			6 => Some(Self::Presets),
+			7 => Some(Self::Week),
+			8 => Some(Self::Soil),
+			// The name screen isn't resumed, as the name being edited is lost on a restart.
+			_ => None,
+		}
 	}
 }
 
 /// Central type which connects the components of the system
 pub struct System {
 	/// Analog to digital converter used for reading analog input values
-	adc: Adc<MHz16>,
+	adc: Adc,
 	/// Relevant peripherals
 	peripherals: SystemPeripherals,
 	/// Menu
 	menu: Menu,
 	/// Display controller
 	display: Display,
-	/// Buttons
-	control_pad: ControlPad,
+	/// Status icons in the header
+	statusbar: StatusBar,
+	/// Chooses the contrast of the display
+	dimmer: Dimmer,
+	/// Hourly samples of the soil moisture
+	moisture_trend: MoistureTrend,
+	/// Counts the days of vacation mode
+	vacation: Vacation,
+	/// Holds off watering after rain
+	rain_delay: RainDelay,
+	/// Counts the rain of the wireless rain gauge
+	#[cfg(feature = "rain-gauge")]
+	rain_gauge: RainGauge,
+	/// Smooths the moisture readings for band control
+	band_moisture: Smoother,
+	/// Detects dusk, for evening-only watering
+	evening: Evening,
+	/// Counts the daylight, and tops it up with the grow lights after dusk
+	photoperiod: Photoperiod,
+	/// Thermostat of the heat mat
+	heat_mat: HeatMat,
+	/// Latest chance of rain received over the USART
+	forecast: Forecast,
+	/// Recent temperature, humidity and light, for evapotranspiration scaling
+	climate: Climate,
+	/// Buttons, or rotary encoder
+	control_pad: ControlInput,
 	/// System configuration
 	system_config: SystemConfig,
+	/// Commands for the system from the menu, the USART and the state machine
+	bus: Bus,
+	/// Persistent storage
+	eeprom: Eeprom,
+	/// Keypad lock
+	keypad_lock: KeypadLock,
+	/// Uptime and watering statistics
+	stats: Stats,
+	/// Screen being shown in the body of the display
+	screen: Screen,
+	/// Resets the system if it stops responding
+	watchdog: Watchdog,
+	/// Saves the activation state so that it can be recovered after a power loss
+	recovery: Recovery,
+	/// Keeps the saved menu selection and screen up to date
+	resume: Resume,
+	/// Supply voltage monitor
+	battery: Battery,
+	/// Piezo buzzer for audible alerts
+	buzzer: Buzzer,
+	/// LED showing the state of the system
+	status_led: StatusLed,
+	/// Command-line interface over the USART
+	cli: Cli,
+	/// Authenticates the commands received over Bluetooth
+	#[cfg(feature = "bluetooth")]
+	bt_session: Session,
+	/// Framed link to a co-processor over the USART
+	link: Link,
+	/// Publishes the state of the system over the link
+	topics: Topics,
+	/// Recent events
+	event_log: EventLog,
+	/// Recent changes to the configuration, with where they came from
+	changes: ChangeLog,
+	/// Events stored in the EEPROM (or the external chip), which survive a power cycle
+	history: History,
+	/// Sensor readings stored in the external chip
+	#[cfg(feature = "external-storage")]
+	samples: SampleLog,
+	/// Internal EEPROM or external chip which the history is stored in
+	log_storage: LogStorage,
+	/// Raw readings of the analog inputs
+	diagnostics: Diagnostics,
+	/// Plant presets screen
+	presets: Presets,
+	/// Name of the zone, as saved in the EEPROM
+	zone_name: ZoneName,
+	/// Name screen
+	name_editor: NameEditor,
+	/// Pins of the digital outputs, as saved in the EEPROM to be used on the next startup
+	pin_map: PinMap,
+	/// Why the system started up in safe mode, if it did
+	safe_mode: Option<Reason>,
+	/// Decides which tasks run on each tick
+	scheduler: Scheduler,
 }
 
 impl System {
 	pub fn new(
-		adc: Adc<MHz16>,
+		adc: Adc,
 		peripherals: SystemPeripherals,
 		display: Display,
-		control_pad: ControlPad,
+		control_pad: ControlInput,
+		eeprom: Eeprom,
+		mut log_storage: LogStorage,
+		watchdog: Watchdog,
+		buzzer: Buzzer,
+		status_led: StatusLed,
 	) -> Self {
-		let system_config = SystemConfig::new();
+		let mut system_config = SystemConfig::new();
+		RelayWiring::load(&eeprom).apply(&mut system_config);
+		// Carry on from where the system was if it was restarted while activated or suspended.
+		let mut recovery = Recovery::new();
+		recovery.restore(&eeprom, &mut system_config);
 		let menu = Menu::new(&system_config);
+		let stats = Stats::load(&eeprom);
+		let history = History::load(&mut log_storage);
+		#[cfg(feature = "external-storage")]
+		let samples = SampleLog::load(&mut log_storage);
+		let (pin_map, _) = PinMap::load(&eeprom, boards::PIN_MAP);
+		let scheduler = Scheduler::new(Duration::from_secs(system_config.telemetry_secs));
 
 		Self {
 			adc,
 			peripherals,
 			display,
+			statusbar: StatusBar::new(),
+			dimmer: Dimmer::new(),
+			moisture_trend: MoistureTrend::new(),
+			vacation: Vacation::new(),
+			rain_delay: RainDelay::new(),
+			#[cfg(feature = "rain-gauge")]
+			rain_gauge: RainGauge::new(),
+			band_moisture: Smoother::new(),
+			evening: Evening::new(),
+			photoperiod: Photoperiod::new(),
+			heat_mat: HeatMat::new(),
+			forecast: Forecast::new(),
+			climate: Climate::new(),
 			control_pad,
 			menu,
 			system_config,
+			bus: Bus::new(),
+			eeprom,
+			keypad_lock: KeypadLock::new(),
+			stats,
+			screen: Screen::Menu,
+			watchdog,
+			recovery,
+			resume: Resume::new(),
+			battery: Battery::new(),
+			buzzer,
+			status_led,
+			cli: Cli::new(),
+			#[cfg(feature = "bluetooth")]
+			bt_session: Session::new(),
+			link: Link::new(),
+			topics: Topics::new(),
+			event_log: EventLog::new(),
+			changes: ChangeLog::new(),
+			history,
+			#[cfg(feature = "external-storage")]
+			samples,
+			log_storage,
+			diagnostics: Diagnostics::new(),
+			presets: Presets::new(),
+			zone_name: ZoneName::load(&eeprom),
+			name_editor: NameEditor::new(),
+			pin_map,
+			safe_mode: None,
+			scheduler,
 		}
 	}
 
-	/// Setup the display and render system header and menu
+	/// Setup the display, show the splash screen, and render system header and menu
+	///
+	/// If the Select button is held down while the system starts up, the buttons are calibrated
+	/// first (unless a rotary encoder is being used). The peripherals are then checked by the
+	/// self-test.
+	///
+	/// The watchdog is started once everything is setup.
 	pub fn init(&mut self) {
 		self.display.init();
+		about::render_splash(&mut self.display, self.watchdog.reset_cause().label());
+		self.display.flush();
+		arduino_hal::delay_ms(SPLASH_DELAY_MS);
+
+		#[cfg(not(feature = "encoder"))]
+		self.calibrate_buttons();
+		let report = self.self_test();
+		if let Some(reason) = Reason::check(self.is_config_valid(), &report) {
+			self.enter_safe_mode(reason);
+		}
+
 		self.render_header();
-		self.menu.render(&mut self.display);
+		self.render_reset_fault();
+		// Carry on from the menu item and screen which were shown before the system restarted,
+		// unless only some of the items can be selected.
+		let mut screen = Screen::Menu;
+		if let Some((menu_idx, code)) = self.resume.restore(&self.eeprom) {
+			if self.safe_mode.is_none() {
+				self.menu.select(menu_idx);
+				screen = Screen::from_code(code).unwrap_or(Screen::Menu);
+			}
+		}
+		self.show_screen(screen);
+
+		info!("started");
+		self.record_event(LogEvent::Startup);
+		if self.safe_mode.is_some() {
+			self.record_event(LogEvent::SafeMode);
+		}
+		info!("reset: {}", self.watchdog.reset_cause().label());
+		if let Some(event) = self.watchdog.reset_cause().event() {
+			self.record_event(event);
+		}
+		if self.watchdog.caused_reset() {
+			warn!("reset by the watchdog");
+			self.enter_fault(FaultKind::Watchdog);
+		}
+
+		self.watchdog.start();
 	}
 
-	/// Update the state of the system
+	/// Run each task which is due (see [`crate::scheduler`])
 	pub fn tick(&mut self) {
+		// Let the watchdog know that the system is still running.
+		self.watchdog.feed();
+
+		// In low-power mode, nothing but the supply voltage is measured until the battery has
+		// recovered.
+		if self.battery.is_low() {
+			if self.scheduler.is_due(Task::Sensors) {
+				self.check_battery();
+			}
+			return;
+		}
+
+		if self.scheduler.is_due(Task::Input) {
+			self.handle_input();
+			// Messages of the weather station's sensors only wait for one tick before being
+			// replaced by the next, so they are checked as often as the buttons.
+			#[cfg(feature = "rain-gauge")]
+			self.count_rain();
+		}
+		if self.scheduler.is_due(Task::Sensors) {
+			self.sample_sensors();
+			self.count_vacation_days();
+		}
+		// Follow any change to the telemetry interval.
+		self.scheduler
+			.set_telemetry_interval(Duration::from_secs(self.system_config.telemetry_secs));
+		if self.scheduler.is_due(Task::Telemetry) {
+			self.send_telemetry();
+		}
+
+		// Send some of what has been rendered to the display, leaving the rest for the next ticks
+		// so that a full redraw doesn't hold up the system.
+		if self.display.has_queued() {
+			self.scheduler.request(Task::Render);
+		}
+		if self.scheduler.is_due(Task::Render) {
+			self.display.render_queued();
+		}
+	}
+
+	/// Handle button presses and commands, and move the system on to its next state
+	fn handle_input(&mut self) {
 		// Check for button presses
 		self.control_pad.update(&mut self.adc);
 
-		// If a button was pressed, tell the menu about it.
-		if let Some(button_state) = &self.control_pad.state {
-			self.menu
-				.on_press(button_state, &mut self.display, &mut self.system_config)
+		// If any buttons were pressed, tell the menu about each of them. Any resulting update to
+		// the configuration is performed straight away so that it isn't replaced by the next.
+		while let Some(event) = self.control_pad.next_event() {
+			// Confirm each button press with a click.
+			if let ButtonStage::Down = event.stage {
+				self.beep(Pattern::Click);
+			}
+
+			let was_editing = self.menu.is_editing();
+			if let Screen::Diagnostics = self.screen {
+				// Button presses are shown by their readings, so only holding Select down returns
+				// to the menu. The keypad lock and the manual override are ignored.
+				if let (ButtonStage::LongPress, ButtonType::Select) = (&event.stage, &event.button)
+				{
+					self.show_screen(Screen::Menu);
+				} else {
+					self.on_valve_test_press(&event);
+				}
+			} else if self.keypad_lock.on_press(&event, self.menu.is_editing()) {
+				// The event was used by the keypad lock, so the lock icon is updated at the end of
+				// the tick.
+			} else if self.is_manual_override(&event) {
+				// Toggle activation regardless of which menu item is selected.
+				self.bus.send(UpdateSystemValue::Activate);
+			} else if matches!(
+				self.screen,
+				Screen::Info | Screen::Week | Screen::Soil | Screen::About | Screen::History
+			) {
+				// Any button press returns from the info, week, soil, about and history screens to
+				// the menu.
+				if let ButtonStage::Release = event.stage {
+					self.show_screen(Screen::Menu);
+				}
+			} else if let Screen::Log = self.screen {
+				// Left and Right move between the pages of the log, and Select returns to the
+				// menu.
+				match (&event.stage, &event.button) {
+					(ButtonStage::Release, ButtonType::Left) => {
+						self.event_log.previous_page(&mut self.display)
+					}
+					(ButtonStage::Release, ButtonType::Right) => {
+						self.event_log.next_page(&mut self.display)
+					}
+					(ButtonStage::Release, ButtonType::Select) => self.show_screen(Screen::Menu),
+					_ => {}
+				}
+			} else if let Screen::Presets = self.screen {
+				// Left and Right move between the presets, and Select applies the selected one.
+				// Either way, Select returns to the menu.
+				if self
+					.presets
+					.on_press(&event, &mut self.display, &mut self.bus)
+				{
+					self.show_screen(Screen::Menu);
+				}
+			} else if let Screen::Name = self.screen {
+				// Left and Right change the character above the cursor, and Select moves on to the
+				// next one. Once the last one is selected, or Select is held, return to the menu.
+				match self.name_editor.on_press(&event, &mut self.display) {
+					Edit::Editing => {}
+					Edit::Saved(name) => {
+						self.rename_zone(name);
+						self.show_screen(Screen::Menu);
+					}
+					Edit::Cancelled => self.show_screen(Screen::Menu),
+				}
+			} else if let Some(action) =
+				self.menu.on_press(&event, &mut self.display, &mut self.bus)
+			{
+				match action {
+					MenuAction::ShowInfo => self.show_screen(Screen::Info),
+					MenuAction::ShowWeek => self.show_screen(Screen::Week),
+					MenuAction::ShowSoil => self.show_screen(Screen::Soil),
+					MenuAction::ShowAbout => self.show_screen(Screen::About),
+					MenuAction::ShowLog => self.show_screen(Screen::Log),
+					MenuAction::ShowHistory => self.show_screen(Screen::History),
+					MenuAction::ShowDiagnostics => self.show_screen(Screen::Diagnostics),
+					MenuAction::ShowPresets => self.show_screen(Screen::Presets),
+					MenuAction::ShowName => self.show_screen(Screen::Name),
+				}
+			}
+			self.apply_update(Source::Buttons);
+
+			// Log a change to the configuration once a value has been edited, rather than every
+			// time it is incremented or decremented.
+			if was_editing && !self.menu.is_editing() {
+				self.changes.finish_editing();
+				self.record_event(LogEvent::ConfigChanged);
+			}
+		}
+
+		// Run any command or request received over the USART.
+		while let Some(byte) = serial::read_byte() {
+			// A Bluetooth module has the USART to itself, so stray bytes aren't taken for a frame.
+			#[cfg(feature = "bluetooth")]
+			let input = Some(Input::Text(byte));
+			#[cfg(not(feature = "bluetooth"))]
+			let input = self.link.receive(byte);
+
+			match input {
+				// If the byte isn't part of a frame, it's part of a command line.
+				Some(Input::Text(byte)) => {
+					if let Some(command) = self.cli.receive(byte) {
+						self.run_command(command);
+						self.apply_update(Source::Serial);
+					}
+				}
+				// If a full frame has been received, handle the request.
+				Some(Input::Request(request)) => {
+					self.handle_request(request);
+					self.apply_update(Source::Link);
+				}
+				None => {}
+			}
+		}
+
+		// Blink the edit indicator if a menu item is being edited.
+		self.menu.tick(&mut self.display);
+
+		// Move the system on to its next activation state, reading the sensors if it is waiting.
+		let can_water = self.can_water();
+		let can_trigger = self.evening.is_open(&self.system_config)
+			&& self.forecast.adjustment() != Adjustment::Skip
+			&& !self.rain_delay.is_holding();
+		let temperature = self.climate.latest().map(|reading| reading.temperature);
+		let band_control = self.system_config.is_band_control();
+		let (peripherals, adc, band_moisture) =
+			(&self.peripherals, &mut self.adc, &mut self.band_moisture);
+		match activation::update_state(
+			&mut self.system_config,
+			&mut self.bus,
+			can_water,
+			can_trigger,
+			temperature,
+			|| {
+				// Band control works from the smoothed moisture.
+				let (light, moisture) = peripherals.read_sensors(adc);
+				if band_control {
+					(light, band_moisture.update(moisture))
+				} else {
+					(light, moisture)
+				}
+			},
+		) {
+			Some(Strategy::Sensors) => {
+				// If the sensors started the activation, adjust how long it lasts for.
+				self.system_config.adjusted_duration = self.sensor_run_duration();
+				if let Some(duration) = self.system_config.adjusted_duration {
+					info!("watering for {}s", duration.as_millis() / 1_000);
+				}
+			}
+			Some(Strategy::Band) => info!(
+				"watering into band {}-{}",
+				self.system_config.band_low, self.system_config.band_high
+			),
+			Some(Strategy::Misting) => info!("misting for {}s", self.system_config.mist_secs),
+			None => {}
 		}
 
-		let timer_paused = TIMER.paused.load(Ordering::SeqCst);
+		// Perform the update to the configuration if necessary.
+		self.apply_update(Source::System);
 
-		// If the system is either _suspending_ or activated, but the timer is paused, then reset
-		// the timer and resume timing.
-		let should_reset_timer = self.system_config.activation_state.is_suspending()
-			|| (self.system_config.activation_state.is_activated() && timer_paused);
-		if should_reset_timer {
-			TIMER.reset();
-			TIMER.resume();
+		// Toggle relays if necessary.
+		// The climate isn't sampled while winterized, so the fan is kept off.
+		let was_open = self.peripherals.is_valve_open();
+		let climate = if self.system_config.activation_state.is_winterized() {
+			None
+		} else {
+			self.climate.latest()
+		};
+		if self.peripherals.update(&self.system_config, climate) {
+			error!("valve open for too long, keeping it closed");
+			self.enter_fault(FaultKind::Valve);
 		}
 
-		// If the system is in a waiting state, but the timer hasn't been paused yet, pause it.
-		if self.system_config.activation_state.is_waiting() && !timer_paused {
-			TIMER.pause();
+		// Count each time the valve opens, and remind that it needs servicing once it has opened
+		// enough times.
+		if !was_open && self.peripherals.is_valve_open() {
+			let was_due = self.stats.is_service_due(&self.system_config);
+			self.stats.record_valve_cycle();
+			if !was_due && self.stats.is_service_due(&self.system_config) {
+				self.beep(Pattern::Fault);
+				self.record_event(LogEvent::ServiceDue);
+				warn!(
+					"valve due for service, cycles={}",
+					self.stats.valve_cycles()
+				);
+			}
 		}
 
-		// If the system is suspending, make sure it is moved to the suspended state.
-		if self.system_config.activation_state.is_suspending() {
-			self.system_config
-				.update_next_tick(UpdateSystemValue::ActivationState);
+		// Run the dosing pump at the start of each activation, until it reaches the weekly cap.
+		let dosing = cfg!(feature = "dosing-pump")
+			&& self.peripherals.is_valve_open()
+			&& dosing::should_dose(&self.system_config, self.stats.dosing_secs());
+		self.peripherals.set_dosing(dosing, &self.system_config);
+
+		// Play the next step of the buzzer pattern.
+		self.buzzer.update();
+
+		// Blink the status LED.
+		let led_pattern = self.led_pattern();
+		self.status_led.update(led_pattern);
+
+		// Announce a new fault, or that the fault has cleared.
+		let fault = self.fault();
+		self.topics.update_fault(&self.link, fault);
+
+		// Show any change to the state of the system in the header.
+		let status = self.status();
+		self.statusbar.update(&status, &mut self.display);
+
+		// Save the activation state in case the power is lost.
+		self.recovery.update(&self.system_config, &mut self.eeprom);
+		// Save what is being shown, so that the system starts up showing it again.
+		self.resume
+			.update(self.menu.selected(), self.screen.code(), &mut self.eeprom);
+
+		// Count the time which has passed, and refresh the info or week screen if it is being shown.
+		let watering = self.system_config.activation_state.is_activated();
+		if self.stats.update(watering, dosing, &mut self.eeprom) {
+			if let Screen::Info = self.screen {
+				self.stats.render_values(&mut self.display);
+				self.forecast.render(&mut self.display);
+				self.heat_mat.render(&self.system_config, &mut self.display);
+			} else if let Screen::Week = self.screen {
+				self.stats.week().render(&mut self.display);
+			}
 		}
 
-		if self.system_config.activation_state.is_suspended() {
-			// If the system is suspended and the timer has reached the suspension time, move it
-			// into the waiting state.
-			// TODO do minute conversion
-			// TODO add suspension time value
-			if TIMER.elapsed_s() >= self.system_config.activate_mins {
-				self.system_config
-					.update_next_tick(UpdateSystemValue::ActivationState);
+		// Refresh the readings if the diagnostics screen is being shown.
+		if let Screen::Diagnostics = self.screen {
+			if self.diagnostics.is_due() {
+				let readings = self.raw_readings();
+				self.diagnostics
+					.render_readings(&readings, &mut self.display);
 			}
-		} else {
-			if self.system_config.activation_state.is_activated() {
-				// If the system is activated and the timer has reached the activation time, move
-				// it into the waiting state.
-				// TODO do minute conversion
-				if TIMER.elapsed_s() >= self.system_config.activate_mins {
-					self.system_config
-						.update_next_tick(UpdateSystemValue::ActivationState);
-				}
-			} else if self
-				.peripherals
-				.should_activate(&mut self.system_config, &mut self.adc)
+		}
+	}
+
+	/// Whether the battery is low, in which case the system is in low-power mode
+	pub fn is_low_power(&self) -> bool {
+		self.battery.is_low()
+	}
+
+	/// Measure the supply voltage every so often, and enter or leave low-power mode
+	fn check_battery(&mut self) {
+		let was_low = self.battery.is_low();
+		let was_critical = self.battery.is_critical();
+		if !self.battery.update(&mut self.adc, &self.system_config) {
+			return;
+		}
+
+		if !was_low && self.battery.is_low() {
+			self.link.send_event(Event::LowBattery);
+			self.record_event(LogEvent::LowBattery);
+			warn!("battery low, powering down");
+			// The buzzer and the LED aren't stepped through their patterns in low-power mode, so
+			// they are silenced rather than left on.
+			self.peripherals.power_down(&self.system_config);
+			self.buzzer.stop();
+			self.status_led.off();
+			self.display.set_on(false);
+		} else if was_low && !self.battery.is_low() {
+			info!("battery recovered");
+			self.display.set_on(true);
+			self.render_header();
+			self.show_screen(self.screen);
+		}
+		// If the supply is about to brown out, close the valve and save the counters while there
+		// is still enough voltage to. The low battery keeps the valve closed from then on.
+		if !was_critical && self.battery.is_critical() {
+			self.peripherals.shut_off(&self.system_config);
+			self.stats.save(&mut self.eeprom);
+			warn!("supply critical, valve closed");
+		}
+
+		if !self.battery.is_low() {
+			self.battery.render_status(&mut self.display);
+			if let Screen::Info = self.screen {
+				self.battery.render(&mut self.display);
+			}
+		}
+	}
+
+	/// Sample the sensors which change slowly, and act on their readings
+	fn sample_sensors(&mut self) {
+		self.check_battery();
+		if self.battery.is_low() {
+			return;
+		}
+
+		// While winterized, the sensors aren't sampled to save power, and the outputs they drive
+		// are kept off. The supply voltage is still measured, so that a low battery is noticed.
+		if self.system_config.activation_state.is_winterized() {
+			return;
+		}
+
+		// Dim the display at night, and apply any change to the configured brightness.
+		let peripherals = &self.peripherals;
+		let adc = &mut self.adc;
+		if let Some(percent) = self
+			.dimmer
+			.update(&self.system_config, || peripherals.read_sensors(adc).0)
+		{
+			self.display.set_contrast(percent);
+		}
+
+		// Keep a sample of the readings on the external chip every minute.
+		#[cfg(feature = "external-storage")]
+		self.samples.update(
+			self.stats.uptime_secs(),
+			|| peripherals.read_sensors(adc),
+			&mut self.log_storage,
+		);
+
+		// Sample the moisture for its trend every so often, warn if it changed unusually, and
+		// refresh the soil screen if it is being shown.
+		let (peripherals, adc) = (&self.peripherals, &mut self.adc);
+		let had_anomaly = self.moisture_trend.anomaly();
+		if self
+			.moisture_trend
+			.update(|| peripherals.read_sensors(adc).1)
+		{
+			let anomaly = self.moisture_trend.anomaly();
+			if anomaly != had_anomaly {
+				match anomaly {
+					Some(Anomaly::FastDryOut) => {
+						self.beep(Pattern::Fault);
+						self.link.send_event(Event::FastDryOut);
+						self.record_event(LogEvent::FastDryOut);
+						warn!("soil drying too fast");
+					}
+					Some(Anomaly::NoRise) => {
+						self.beep(Pattern::Fault);
+						self.link.send_event(Event::NoRise);
+						self.record_event(LogEvent::NoRise);
+						warn!("moisture didn't rise after watering");
+					}
+					None => info!("moisture back to normal"),
+				}
+			}
+
+			// If the moisture rose without watering while it was overcast, it has rained, so hold
+			// off watering for a while.
+			let (light, _) = self.peripherals.read_sensors(&mut self.adc);
+			if self
+				.rain_delay
+				.check_rain(self.moisture_trend.unwatered_rise(), light)
 			{
-				// If the sensors indicate that the system should be activated, move it into the
-				// activated state.
-				self.system_config
-					.update_next_tick(UpdateSystemValue::ActivationState);
-			}
-		}
-
-		// Perform the update to the configuration if necessary and...
-		if let Some(update_value) = self.system_config.update() {
-			match update_value {
-				// If there was any update to the activation state, update both the suspend and
-				// activate menu items so that they're consistent with the configuration state.
-				UpdateSystemValue::Suspend
-				| UpdateSystemValue::Activate
-				| UpdateSystemValue::ActivationState => {
-					self.menu.update(
-						UpdateSystemValue::Suspend,
-						&self.system_config,
-						&mut self.display,
-					);
-					self.menu.update(
-						UpdateSystemValue::Activate,
-						&self.system_config,
-						&mut self.display,
-					);
-				}
-				// Otherwise, update the relevant menu item.
-				_ => {
+				self.record_event(LogEvent::RainDelay);
+				info!("rain, watering held for {}h", self.rain_delay.hours_left());
+			}
+			if let Screen::Soil = self.screen {
+				self.moisture_trend.render(&mut self.display);
+				self.rain_delay.render(&mut self.display);
+			}
+		}
+
+		// Shorten the rain delay, by more while it is sunny or hot, and refresh the soil screen if
+		// it is being shown.
+		let temperature = self.climate.latest().map(|reading| reading.temperature);
+		let (peripherals, adc) = (&self.peripherals, &mut self.adc);
+		if self
+			.rain_delay
+			.update(|| peripherals.read_sensors(adc).0, temperature)
+		{
+			if !self.rain_delay.is_holding() {
+				info!("rain delay over");
+			}
+			if let Screen::Soil = self.screen {
+				self.rain_delay.render(&mut self.display);
+			}
+		}
+
+		// Look for dusk, so that the sensors only activate the system in the evening if
+		// evening-only watering is enabled.
+		let (peripherals, adc) = (&self.peripherals, &mut self.adc);
+		let dusk = self
+			.evening
+			.update(&self.system_config, || peripherals.read_sensors(adc).0);
+		if dusk {
+			info!("dusk");
+		}
+
+		// Count the daylight, and top it up with the grow lights after dusk.
+		let (peripherals, adc) = (&self.peripherals, &mut self.adc);
+		if let Some(duration) = self.photoperiod.update(&self.system_config, dusk, || {
+			peripherals.read_sensors(adc).0
+		}) {
+			info!("grow light on for {}m", duration.as_millis() / 60_000);
+		}
+		let grow_light = self.photoperiod.is_on(&self.system_config);
+		self.peripherals
+			.set_grow_light(grow_light, &self.system_config);
+
+		// Sample the temperature, humidity and light every so often.
+		let (peripherals, adc) = (&mut self.peripherals, &mut self.adc);
+		self.climate.update(|| {
+			let reading = peripherals.read_climate();
+			(reading, peripherals.read_sensors(adc).0)
+		});
+
+		// Keep the soil warm with the heat mat, and cut it out if it has been on for too long.
+		let (peripherals, adc) = (&self.peripherals, &mut self.adc);
+		if self
+			.heat_mat
+			.update(&self.system_config, || peripherals.read_soil_probe(adc))
+		{
+			self.beep(Pattern::Fault);
+			error!("heat mat on for too long, keeping it off");
+		}
+		let heat_mat = self.heat_mat.is_on();
+		self.peripherals.set_heat_mat(heat_mat, &self.system_config);
+	}
+
+	/// Count down the days of vacation mode, going back to normal once there are none left
+	fn count_vacation_days(&mut self) {
+		if !self.vacation.update(&self.system_config) {
+			return;
+		}
+
+		self.bus.send(UpdateSystemValue::Number(
+			Number::VacationDays,
+			ValueAction::Decrement,
+		));
+		self.apply_update(Source::System);
+		if self.system_config.is_on_vacation() {
+			info!("vacation: {}d left", self.system_config.vacation_days);
+		} else {
+			info!("vacation over");
+		}
+	}
+
+	/// Load the button calibration, or calibrate the buttons if Select is being held down
+	#[cfg(not(feature = "encoder"))]
+	fn calibrate_buttons(&mut self) {
+		self.control_pad.load_thresholds(&self.eeprom);
+		if self.control_pad.read_button(&mut self.adc) == Some(ButtonType::Select) {
+			calibration::run(
+				&mut self.control_pad,
+				&mut self.adc,
+				&mut self.display,
+				&mut self.eeprom,
+			);
+		}
+	}
+
+	/// Switch the body of the display to another screen
+	fn show_screen(&mut self, screen: Screen) {
+		// Leaving the diagnostics screen stops testing the outputs.
+		self.stop_valve_test();
+		match screen {
+			Screen::Menu => self.menu.show(&mut self.display),
+			Screen::Info => {
+				self.menu.hide();
+				self.stats.render(&mut self.display);
+				self.heat_mat.render(&self.system_config, &mut self.display);
+				self.forecast.render(&mut self.display);
+				self.battery.render(&mut self.display);
+			}
+			Screen::Week => {
+				self.menu.hide();
+				self.stats.week().render(&mut self.display);
+			}
+			Screen::Soil => {
+				self.menu.hide();
+				self.moisture_trend.render(&mut self.display);
+				self.rain_delay.render(&mut self.display);
+			}
+			Screen::About => {
+				self.menu.hide();
+				about::render(
+					&mut self.display,
+					TIMER.millis() / 1_000,
+					self.watchdog.reset_cause().label(),
+				);
+			}
+			Screen::Log => {
+				self.menu.hide();
+				self.event_log.render(&mut self.display);
+			}
+			Screen::History => {
+				self.menu.hide();
+				self.history.render(
+					&mut self.log_storage,
+					self.stats.uptime_secs(),
+					&mut self.display,
+				);
+			}
+			Screen::Diagnostics => {
+				self.menu.hide();
+				let readings = self.raw_readings();
+				self.diagnostics.render(&readings, &mut self.display);
+			}
+			Screen::Presets => {
+				self.menu.hide();
+				self.presets.render(&mut self.display);
+			}
+			Screen::Name => {
+				self.menu.hide();
+				self.name_editor.start(self.zone_name, &mut self.display);
+			}
+		}
+		self.screen = screen;
+	}
+
+	/// Run a command received over the USART
+	fn run_command(&mut self, command: Command) {
+		// Over Bluetooth, commands are only run once the session has been authenticated.
+		#[cfg(feature = "bluetooth")]
+		if !self.is_bt_allowed(&command) {
+			return;
+		}
+
+		let allowed = match command {
+			Command::Set(update_value) => self.remote_update(update_value),
+			Command::GetConfig => {
+				let config = &self.system_config;
+				log!(
+					"time={} light={} moisture={} flow={} trigger={} profile={} remote={}",
+					config.activate_mins,
+					config.min_light,
+					config.min_moisture,
+					config.flow_percent,
+					config.trigger.label(),
+					config.profile.label(),
+					config.remote_control as u8
+				);
+				return;
+			}
+			Command::Activate => self.activate(),
+			Command::Suspend => self.suspend(),
+			Command::RemoteControl(enabled) => self.set_remote_control(enabled),
+			Command::LogDump => {
+				self.event_log.dump();
+				return;
+			}
+			Command::LogChanges(count) => {
+				self.changes.dump(count);
+				return;
+			}
+			Command::LogExport => {
+				self.history.export(&mut self.log_storage, &self.watchdog);
+				return;
+			}
+			#[cfg(feature = "external-storage")]
+			Command::LogSamples => {
+				self.samples.export(&mut self.log_storage, &self.watchdog);
+				return;
+			}
+			Command::Status => {
+				let (light, moisture) = self.peripherals.read_sensors(&mut self.adc);
+				log!(
+					"state={:?} light={} moisture={} valve_cycles={}",
+					self.system_config.activation_state,
+					light,
+					moisture,
+					self.stats.valve_cycles()
+				);
+				#[cfg(feature = "dht22")]
+				self.log_climate();
+				return;
+			}
+			Command::GetPins => {
+				for role in Role::ALL.iter() {
+					log!("{}={}", role.name(), self.pin_map.pin(*role));
+				}
+				return;
+			}
+			// Moving the valve could open it (or leave it open), so it needs remote control to be
+			// enabled.
+			Command::SetPin(role, pin) => {
+				if !self.system_config.remote_control {
+					log!("error: remote control disabled");
+					return;
+				}
+				if let Err(err) = self.pin_map.set(role, pin) {
+					log!("error: {}", err);
+					return;
+				}
+				self.pin_map.save(&mut self.eeprom);
+				log!("ok, restart to apply");
+				return;
+			}
+			Command::Memory => {
+				let usage = memory::usage();
+				log!("free={} stack={}", usage.free, usage.stack_peak);
+				return;
+			}
+			// Replaying a trace could open the valve, so it needs remote control to be enabled.
+			Command::Trace(sample) => {
+				if self.system_config.remote_control {
+					self.peripherals.replay_sample(&sample);
+				}
+				self.system_config.remote_control
+			}
+			Command::TraceEnd => {
+				self.peripherals.stop_replay();
+				true
+			}
+			Command::Forecast(rain_percent) => self.set_forecast(rain_percent),
+			#[cfg(feature = "bluetooth")]
+			Command::Auth(code) => {
+				self.authenticate_bt(code);
+				return;
+			}
+		};
+
+		if allowed {
+			log!("ok");
+		} else {
+			log!("error: remote control disabled");
+		}
+	}
+
+	/// Whether a command received over Bluetooth can be run, logging why not
+	///
+	/// Authenticating is always allowed while Bluetooth control is enabled.
+	#[cfg(feature = "bluetooth")]
+	fn is_bt_allowed(&mut self, command: &Command) -> bool {
+		let pairing_code = match self.system_config.bt_code {
+			Some(pairing_code) => pairing_code,
+			None => {
+				log!("error: bluetooth control disabled");
+				return false;
+			}
+		};
+		if let Command::Auth(_) = command {
+			return true;
+		}
+		if !self.bt_session.allows(pairing_code) {
+			log!("error: not authenticated");
+			return false;
+		}
+		true
+	}
+
+	/// Authenticate the Bluetooth session with `code`, and disable Bluetooth control after too
+	/// many wrong codes
+	#[cfg(feature = "bluetooth")]
+	fn authenticate_bt(&mut self, code: u16) {
+		let pairing_code = match self.system_config.bt_code {
+			Some(pairing_code) => pairing_code,
+			None => return,
+		};
+		match self.bt_session.authenticate(code, pairing_code) {
+			Auth::Ok => {
+				info!("bluetooth: authenticated");
+				log!("ok");
+			}
+			Auth::Denied => log!("error: wrong code"),
+			Auth::Locked => {
+				// A new code has to be shown in the menu before trying again.
+				self.bus.send(UpdateSystemValue::BtControl);
+				warn!("bluetooth: too many wrong codes, control disabled");
+				log!("error: wrong code");
+			}
+		}
+	}
+
+	/// Handle a request received over the link
+	fn handle_request(&mut self, request: Request) {
+		let allowed = match request {
+			Request::GetStatus => {
+				let record = self.record();
+				self.link.send_status(&record);
+				return;
+			}
+			Request::GetConfig => {
+				self.link.send_config(&self.system_config);
+				return;
+			}
+			Request::Connected => {
+				self.link.send_ack(Ack::Ok);
+				let record = self.record();
+				let fault = self.fault();
+				self.topics
+					.announce(&self.link, &record, fault, self.zone_name.as_str());
+				return;
+			}
+			Request::Set(update_value) | Request::Publish(Message::Set(update_value)) => {
+				self.remote_update(update_value)
+			}
+			Request::Activate | Request::Publish(Message::Valve(true)) => self.activate(),
+			Request::Publish(Message::Valve(false)) => self.deactivate(),
+			Request::Suspend => self.suspend(),
+			Request::RemoteControl(enabled) | Request::Publish(Message::RemoteControl(enabled)) => {
+				self.set_remote_control(enabled)
+			}
+			Request::Forecast(rain_percent) => self.set_forecast(rain_percent),
+		};
+
+		self.link
+			.send_ack(if allowed { Ack::Ok } else { Ack::Denied });
+	}
+
+	/// Print the latest temperature and humidity, and the estimated evapotranspiration
+	#[cfg(feature = "dht22")]
+	fn log_climate(&self) {
+		match self.climate.latest() {
+			Some(reading) => log!(
+				"temp={} humidity={} et={}%",
+				Celsius(reading.temperature),
+				Percent(reading.humidity),
+				self.climate.et_percent().unwrap_or(100)
+			),
+			None => log!("temp=? humidity=?"),
+		}
+	}
+
+	/// How long an activation started by the sensors should last for, if it differs from the
+	/// activation time
+	///
+	/// The activation time is scaled by the estimated evapotranspiration if that is enabled, and
+	/// then shortened if rain is forecast.
+	fn sensor_run_duration(&self) -> Option<Duration> {
+		let config = &self.system_config;
+		let et_percent = self.climate.et_percent().filter(|_| config.et_scaling);
+		if et_percent.is_none() && self.forecast.adjustment() == Adjustment::None {
+			return None;
+		}
+
+		let duration = match et_percent {
+			Some(et_percent) => config.et_duration(et_percent),
+			None => config.watering_duration(),
+		};
+		Some(self.forecast.shorten(duration))
+	}
+
+	/// Use the chance of rain received over the USART to adjust the activations started by the
+	/// sensors
+	///
+	/// A forecast can only hold back watering, so it doesn't need remote control to be enabled.
+	fn set_forecast(&mut self, rain_percent: u8) -> bool {
+		if !self.forecast.set(rain_percent) {
+			return false;
+		}
+		info!("rain={}%", rain_percent);
+		if let Screen::Info = self.screen {
+			self.forecast.render(&mut self.display);
+		}
+		true
+	}
+
+	/// Send an update requested by a command received over the USART onto the bus
+	///
+	/// Returns `false` if the update isn't allowed while remote control is disabled, or if too many
+	/// commands are already waiting to be applied.
+	fn remote_update(&mut self, update_value: UpdateSystemValue) -> bool {
+		if !update_value.is_remote_allowed(&self.system_config) {
+			return false;
+		}
+
+		self.bus.send(update_value)
+	}
+
+	/// Activate the system on the next update, unless it is already activated
+	///
+	/// Returns `false` if remote control is disabled.
+	fn activate(&mut self) -> bool {
+		if self.safe_mode.is_some() || self.system_config.activation_state.fault().is_some() {
+			return false;
+		}
+
+		// Activate toggles the activation, so only use it if the system isn't activated.
+		let state = &self.system_config.activation_state;
+		if state.is_activated() {
+			return true;
+		}
+		self.remote_update(UpdateSystemValue::Activate)
+	}
+
+	/// Stop the activation on the next update, if the system is activated
+	fn deactivate(&mut self) -> bool {
+		// Activate toggles the activation, so only use it if the system is activated.
+		let state = &self.system_config.activation_state;
+		if !state.is_activated() {
+			return true;
+		}
+		self.remote_update(UpdateSystemValue::Activate)
+	}
+
+	/// Suspend the system on the next update, unless it is already suspended
+	fn suspend(&mut self) -> bool {
+		// Suspend toggles the suspension, so only use it if the system isn't suspended.
+		let state = &self.system_config.activation_state;
+		if state.is_suspended() {
+			return true;
+		}
+		self.remote_update(UpdateSystemValue::Suspend)
+	}
+
+	/// Enable or disable remote control on the next update
+	///
+	/// Returns `false` if remote control is disabled, and so can't be enabled remotely.
+	fn set_remote_control(&mut self, enabled: bool) -> bool {
+		// RemoteControl toggles remote control, so only use it if it needs to change.
+		if self.system_config.remote_control == enabled {
+			return true;
+		}
+		self.remote_update(UpdateSystemValue::RemoteControl)
+	}
+
+	/// Record of the current state of the system
+	fn record(&mut self) -> Record {
+		let (light, moisture) = self.peripherals.read_sensors(&mut self.adc);
+		let state = self.system_config.activation_state;
+		let elapsed = if state.is_activated() {
+			SoftTimer::Activation.elapsed()
+		} else {
+			SoftTimer::Suspension.elapsed()
+		};
+
+		Record {
+			light,
+			moisture,
+			valve_open: self.peripherals.is_valve_open(),
+			state,
+			elapsed,
+		}
+	}
+
+	/// Send a telemetry record with the current state of the system
+	fn send_telemetry(&mut self) {
+		let record = self.record();
+		telemetry::send(&record);
+		self.topics.publish_state(&self.link, &record);
+	}
+
+	/// Add an event to the log and the history, and refresh the log screen if it is being shown
+	fn record_event(&mut self, event: LogEvent) {
+		self.event_log.record(event);
+		self.history
+			.append(event, self.stats.uptime_secs(), &mut self.log_storage);
+		if let Screen::Log = self.screen {
+			self.event_log.render_page(&mut self.display);
+		}
+	}
+
+	/// Play a pattern on the buzzer, unless it has been silenced
+	fn beep(&mut self, pattern: Pattern) {
+		if self.system_config.sound {
+			self.buzzer.play(pattern);
+		}
+	}
+
+	/// Pattern for the status LED to show for the state of the system
+	fn led_pattern(&self) -> LedPattern {
+		let state = &self.system_config.activation_state;
+		if !self.can_water() || state.fault().is_some() {
+			LedPattern::Fault
+		} else if state.is_activated() {
+			LedPattern::Activated
+		} else if state.is_suspended() {
+			LedPattern::Suspended
+		} else if state.is_winterized() {
+			LedPattern::Winterized
+		} else {
+			LedPattern::Waiting
+		}
+	}
+
+	/// Reason the valve can't be opened, if there is one
+	fn fault(&self) -> Fault {
+		let state = self.system_config.activation_state;
+		if self.safe_mode.is_some() {
+			Fault::SafeMode
+		} else if let Some(kind) = state.fault() {
+			match kind {
+				FaultKind::Sensor => Fault::Sensor,
+				FaultKind::Valve => Fault::Valve,
+				FaultKind::Watchdog => Fault::Watchdog,
+			}
+		} else if self.battery.is_low() {
+			Fault::Battery
+		} else {
+			Fault::None
+		}
+	}
+
+	/// Whether the valve can be opened
+	///
+	/// The valve is kept closed in safe mode, or if the battery is low so that it isn't drained by
+	/// the valve. A fault keeps it closed by stopping the system (see [`FaultKind`]).
+	fn can_water(&self) -> bool {
+		self.safe_mode.is_none() && !self.battery.is_low()
+	}
+
+	/// Whether a button event is the manual override, which toggles activation
+	///
+	/// Holding down the Right button while a menu item isn't being edited is the manual override.
+	/// There is no manual override in safe mode.
+	fn is_manual_override(&self, event: &ButtonEvent) -> bool {
+		matches!(
+			(&event.stage, &event.button),
+			(ButtonStage::LongPress, ButtonType::Right)
+		) && !self.menu.is_editing()
+			&& self.safe_mode.is_none()
+	}
+
+	/// Apply the commands sent onto the bus from `source`, and handle the changes they made
+	fn apply_update(&mut self, source: Source) {
+		self.bus.apply(&mut self.system_config);
+		while let Some(notification) = self.bus.next_notification() {
+			match notification {
+				Notification::Transition(transition) => self.on_transition(transition),
+				Notification::Changed(update_value, old) => {
+					self.on_change(update_value, old, source)
+				}
+			}
+		}
+	}
+
+	/// Update the menu to match a change to the configuration from `source`, and keep the change
+	/// in the audit trail
+	fn on_change(
+		&mut self,
+		update_value: UpdateSystemValue,
+		old: Option<SystemValue>,
+		source: Source,
+	) {
+		// Values being edited in the menu are kept once the editing is finished.
+		let editing = source == Source::Buttons && self.menu.is_editing();
+		let new = update_value.to_value(&self.system_config);
+		self.changes.record(update_value, old, new, source, editing);
+
+		match update_value {
+			// If the configuration in the EEPROM caused safe mode, Reset also erases it so
+			// that the system starts up normally next time.
+			UpdateSystemValue::Reset if self.safe_mode == Some(Reason::Config) => {
+				for (address, len) in CONFIG_RECORDS.iter() {
+					self.eeprom.erase_record(*address, *len);
+				}
+				self.pin_map = boards::PIN_MAP;
+				RelayWiring::DEFAULT.apply(&mut self.system_config);
+				self.zone_name = ZoneName::new();
+				self.render_header();
+				warn!("safe mode: configuration erased, restart to leave safe mode");
+				self.record_event(LogEvent::ConfigChanged);
+				self.menu
+					.update(update_value, &self.system_config, &mut self.display);
+			}
+			// Once the valve has been serviced, start counting its cycles again.
+			UpdateSystemValue::Serviced => {
+				info!("valve serviced after {} cycles", self.stats.valve_cycles());
+				self.stats.reset_valve_cycles(&mut self.eeprom);
+				self.record_event(LogEvent::Serviced);
+			}
+			// If there was any update to the activation state, update both the suspend and
+			// activate menu items so that they're consistent with the configuration state.
+			UpdateSystemValue::Suspend
+			| UpdateSystemValue::Activate
+			| UpdateSystemValue::Winterize
+			| UpdateSystemValue::ActivationState
+			| UpdateSystemValue::Fault(_)
+			| UpdateSystemValue::Acknowledge => {
+				for update in [
+					UpdateSystemValue::Suspend,
+					UpdateSystemValue::Activate,
+					UpdateSystemValue::Winterize,
+				]
+				.iter()
+				{
 					self.menu
-						.update(update_value, &self.system_config, &mut self.display);
+						.update(*update, &self.system_config, &mut self.display);
+				}
+			}
+			// Otherwise, update the relevant menu item.
+			_ => {
+				// Values being edited are logged when the editing is finished.
+				if !self.menu.is_editing() {
+					self.record_event(LogEvent::ConfigChanged);
+				}
+				self.menu
+					.update(update_value, &self.system_config, &mut self.display);
+				// Show the days of vacation left in the header.
+				if let UpdateSystemValue::Number(Number::VacationDays, _) = update_value {
+					self.render_header();
+				}
+				// Keep the relay wiring, so that the valve is closed the right way when the system
+				// next starts up.
+				if matches!(
+					update_value,
+					UpdateSystemValue::RelayPolarity | UpdateSystemValue::ValveType
+				) {
+					RelayWiring::of(&self.system_config).save(&mut self.eeprom);
 				}
 			}
 		}
+	}
 
-		// Toggle relays if necessary.
-		self.peripherals.update(&self.system_config);
+	/// Announce a change to the state of the system
+	///
+	/// The valve follows the state on the next update of the peripherals, and the header on the
+	/// next update of the status bar.
+	fn on_transition(&mut self, transition: Transition) {
+		match transition {
+			Transition::Started => {
+				self.stats.record_activation();
+				self.beep(Pattern::ActivationStart);
+				self.link.send_event(Event::ActivationStarted);
+				self.topics.publish_valve(&self.link, true);
+				self.record_event(LogEvent::ActivationStarted);
+				info!("{}: watering started", self.zone_name.as_str());
+				self.stop_valve_test();
+			}
+			Transition::Stopped => {
+				self.moisture_trend.watered();
+				self.beep(Pattern::ActivationStop);
+				self.link.send_event(Event::ActivationStopped);
+				self.topics.publish_valve(&self.link, false);
+				self.record_event(LogEvent::ActivationStopped);
+				info!("{}: watering stopped", self.zone_name.as_str());
+			}
+			Transition::Suspended => self.record_event(LogEvent::Suspended),
+			Transition::Resumed => self.record_event(LogEvent::Resumed),
+			Transition::Faulted(kind) => {
+				self.beep(Pattern::Fault);
+				self.link.send_event(Event::Fault);
+				self.record_event(match kind {
+					FaultKind::Sensor => LogEvent::SensorFault,
+					FaultKind::Valve => LogEvent::ValveFault,
+					FaultKind::Watchdog => LogEvent::WatchdogReset,
+				});
+				error!("fault E{}, stopped until acknowledged", kind.code());
+			}
+			Transition::Cleared => {
+				self.peripherals.clear_valve_fault();
+				self.record_event(LogEvent::FaultCleared);
+				info!("fault acknowledged");
+			}
+			Transition::Winterized => {
+				// Park every output straight away, rather than waiting for the pump to stop.
+				self.peripherals.shut_off(&self.system_config);
+				self.peripherals.set_grow_light(false, &self.system_config);
+				self.peripherals.set_heat_mat(false, &self.system_config);
+				self.record_event(LogEvent::Winterized);
+				self.render_header();
+				info!("winterized, stopped until re-enabled");
+			}
+			Transition::Reenabled => {
+				self.record_event(LogEvent::Reenabled);
+				self.render_header();
+				info!("re-enabled");
+			}
+		}
+	}
+
+	/// Stop the system with a fault, until it is acknowledged from the menu
+	fn enter_fault(&mut self, kind: FaultKind) {
+		self.bus.send(UpdateSystemValue::Fault(kind));
+		self.apply_update(Source::System);
+	}
+
+	/// Raw readings of the analog inputs, for the diagnostics screen
+	fn raw_readings(&mut self) -> Readings {
+		let (light, moisture) = self.peripherals.read_raw_sensors(&mut self.adc);
+		#[cfg(not(feature = "encoder"))]
+		let buttons = Some(self.control_pad.read_raw(&mut self.adc));
+		#[cfg(feature = "encoder")]
+		let buttons = None;
+
+		Readings {
+			light,
+			moisture,
+			buttons,
+			memory: memory::usage(),
+		}
+	}
+
+	/// Check the peripherals, and show the results for a while
+	fn self_test(&mut self) -> Report {
+		let mut report = Report::new();
+		report.display = Outcome::from_bool(self.display.responded());
+		let (light, moisture) = self.peripherals.read_sensors(&mut self.adc);
+		report.light = Outcome::from_bool(selftest::is_plausible(light));
+		report.moisture = Outcome::from_bool(selftest::is_plausible(moisture));
+		// There is no way to tell whether the valve opened, so it passes once it has been pulsed.
+		#[cfg(feature = "self-test-valve")]
+		{
+			self.peripherals.pulse_valve(&self.system_config);
+			report.valve = Outcome::Pass;
+		}
+
+		for (label, outcome) in report.checks().iter() {
+			if let Outcome::Fail = outcome {
+				warn!("self-test: {} failed", label);
+			}
+		}
+		let passed = report.passed();
+		info!(
+			"self-test: {} light={} moisture={}",
+			if passed { "pass" } else { "fail" },
+			light,
+			moisture
+		);
+
+		report.render(&mut self.display);
+		self.display.flush();
+		arduino_hal::delay_ms(if passed {
+			SELF_TEST_PASS_DELAY_MS
+		} else {
+			SELF_TEST_FAIL_DELAY_MS
+		});
+
+		report
+	}
+
+	/// Whether every configuration record in the EEPROM is valid, or was never written
+	fn is_config_valid(&self) -> bool {
+		let records_valid = CONFIG_RECORDS
+			.iter()
+			.all(|(address, len)| !self.eeprom.is_record_corrupted(*address, *len));
+		let (_, pin_map_error) = PinMap::load(&self.eeprom, boards::PIN_MAP);
+		records_valid && pin_map_error.is_none()
+	}
+
+	/// Keep the valve closed, and only allow Diagnostics and Reset to be selected in the menu
+	///
+	/// The reason is shown for a while, and logged.
+	fn enter_safe_mode(&mut self, reason: Reason) {
+		self.safe_mode = Some(reason);
+		error!("safe mode: {}", reason.label());
+		self.menu.enter_safe_mode(&mut self.display);
+
+		safemode::render(reason, &mut self.display);
+		self.display.flush();
+		arduino_hal::delay_ms(SAFE_MODE_DELAY_MS);
+	}
+
+	/// Show a fault screen for a while if the last reset was caused by the watchdog
+	fn render_reset_fault(&mut self) {
+		if !self.watchdog.caused_reset() {
+			return;
+		}
+
+		self.display.clear_body();
+		self.display.set_position(0, BODY_START_ROW);
+		let _ = ufmt::uwrite!(self.display, "{}", lang::FAULT);
+		self.display.clear_row(BODY_START_ROW + 2);
+		let _ = ufmt::uwrite!(self.display, "{}", lang::WATCHDOG_RESET);
+
+		self.display.flush();
+		arduino_hal::delay_ms(RESET_FAULT_DELAY_MS);
 	}
 
 	/// Render the system header
+	///
+	/// While the system is winterized, the name is replaced with [`lang::WINTER`], so that it can't be
+	/// mistaken for being suspended, and while it is on vacation, with the days of vacation left.
 	fn render_header(&mut self) {
-		let _ = self.display.set_position(0, 0);
-		let _ = ufmt::uwriteln!(self.display, "Garden System\nv0.1");
+		if self.system_config.activation_state.is_winterized() {
+			self.display.clear_row(0);
+			let _ = ufmt::uwrite!(self.display, "{}", lang::WINTER);
+		} else if self.system_config.is_on_vacation() {
+			vacation::render_title(&self.system_config, &mut self.display);
+		} else {
+			self.display.clear_row(0);
+			let _ = ufmt::uwrite!(self.display, "{}", self.zone_name.as_str());
+		}
+		self.display.clear_row(1);
+		self.battery.render_status(&mut self.display);
+		let status = self.status();
+		self.statusbar.render(&status, &mut self.display);
+	}
+
+	/// Count the rain of the rain gauge's latest message, and hold off watering once it has rained
+	#[cfg(feature = "rain-gauge")]
+	fn count_rain(&mut self) {
+		let message = match self.peripherals.take_rain_message() {
+			Some(message) => message,
+			None => return,
+		};
+		let paired = self.rain_gauge.id().is_some();
+		let rained = self.rain_gauge.on_message(&message);
+		if !paired && self.rain_gauge.id().is_some() {
+			info!("rain gauge: paired with {}", message.id);
+		}
+		if message.battery_low && self.rain_gauge.id() == Some(message.id) {
+			debug!("rain gauge: battery low");
+		}
+		if !rained {
+			return;
+		}
+
+		self.rain_delay.start();
+		self.record_event(LogEvent::RainDelay);
+		info!(
+			"rain gauge: rain, watering held for {}h",
+			self.rain_delay.hours_left()
+		);
+		if let Screen::Soil = self.screen {
+			self.rain_delay.render(&mut self.display);
+		}
+	}
+
+	/// Step through the outputs of the shift registers with Left and Right on the diagnostics
+	/// screen, energizing the relay on each one in turn
+	fn on_valve_test_press(&mut self, event: &ButtonEvent) {
+		let forwards = match (&event.stage, &event.button) {
+			(ButtonStage::Release, ButtonType::Left) => false,
+			(ButtonStage::Release, ButtonType::Right) => true,
+			_ => return,
+		};
+		// If the valve has to be kept closed, or is being opened to water, leave the outputs alone.
+		if self.safe_mode.is_some() || self.peripherals.is_valve_open() {
+			return;
+		}
+
+		let channel =
+			self.diagnostics
+				.step_test_channel(forwards, TEST_CHANNELS, &mut self.display);
+		self.peripherals
+			.test_valve_channel(channel, &self.system_config);
+		match channel {
+			Some(channel) => info!("valve test: output {}", channel + 1),
+			None if TEST_CHANNELS > 0 => info!("valve test: stopped"),
+			None => {}
+		}
+	}
+
+	/// Stop testing an output of the shift registers, if one is being tested
+	fn stop_valve_test(&mut self) {
+		if self.diagnostics.test_channel().is_some() {
+			self.diagnostics.stop_test();
+			self.peripherals
+				.test_valve_channel(None, &self.system_config);
+			info!("valve test: stopped");
+		}
+	}
+
+	/// Rename the zone, and save the name so that it is kept after a restart
+	fn rename_zone(&mut self, name: ZoneName) {
+		if name == self.zone_name {
+			return;
+		}
+
+		self.zone_name = name;
+		self.zone_name.save(&mut self.eeprom);
+		info!("zone renamed to {}", name.as_str());
+		self.topics.publish_name(&self.link, name.as_str());
+		self.record_event(LogEvent::ConfigChanged);
+		self.render_header();
+	}
+
+	/// State of the system shown by the status icons in the header
+	fn status(&self) -> Status {
+		let state = &self.system_config.activation_state;
+		Status {
+			link_up: self.topics.is_connected(),
+			watering: state.is_activated(),
+			suspended: state.is_suspended(),
+			fault: !matches!(self.fault(), Fault::None),
+			fault_code: state.fault().map(FaultKind::code),
+			soil_alert: self.moisture_trend.anomaly().is_some(),
+			service_due: self.stats.is_service_due(&self.system_config),
+			locked: self.keypad_lock.is_locked(),
+		}
 	}
 }
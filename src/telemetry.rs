@@ -0,0 +1,105 @@
+//! Machine-readable telemetry
+//!
+//! A record of the state of the system is written to the USART every **Telem s** seconds (10 by
+//! default, see [`crate::scheduler`]), so that it can be logged by an attached computer without
+//! having to scrape the free-form log output. Each record is a single line of space separated
+//! `key=value` pairs, prefixed with `@` and followed by `*` and the CRC-8 checksum of the record in
+//! hex:
+//!
+//! ```text
+//! @light=512 moisture=300 valve=1 state=Activated elapsed=120*4F
+//! ```
+
+use core::str;
+
+use ufmt::uWrite;
+
+use crate::{activation::SystemState, storage::crc8, timer::Duration};
+
+/// Longest record which can be sent
+const RECORD_LENGTH: usize = 80;
+
+/// State of the system to send
+pub struct Record {
+	/// Light sensor reading
+	pub light: u16,
+	/// Moisture sensor reading
+	pub moisture: u16,
+	/// Whether the valve is open
+	pub valve_open: bool,
+	/// Activation state of the system
+	pub state: SystemState,
+	/// How long the system has been activated or suspended for
+	pub elapsed: Duration,
+}
+
+/// Send a record
+pub fn send(record: &Record) {
+	let mut line = LineBuffer::new();
+	let _ = ufmt::uwrite!(
+		line,
+		"light={} moisture={} valve={} state={:?} elapsed={}",
+		record.light,
+		record.moisture,
+		record.valve_open as u8,
+		record.state,
+		record.elapsed.as_millis() / 1_000
+	);
+
+	let checksum = crc8(line.as_bytes());
+	log!(
+		"@{}*{}{}",
+		line.as_str(),
+		hex_digit(checksum >> 4),
+		hex_digit(checksum & 0x0f)
+	);
+}
+
+/// Hex digit of a value between 0 and 15
+fn hex_digit(value: u8) -> char {
+	match value {
+		0..=9 => (b'0' + value) as char,
+		_ => (b'A' + value - 10) as char,
+	}
+}
+
+/// Fixed-size buffer for formatting a record into, so that its checksum can be worked out
+///
+/// Anything written past the end of the buffer is dropped.
+struct LineBuffer {
+	buf: [u8; RECORD_LENGTH],
+	len: usize,
+}
+
+impl LineBuffer {
+	fn new() -> Self {
+		Self {
+			buf: [0; RECORD_LENGTH],
+			len: 0,
+		}
+	}
+
+	fn as_bytes(&self) -> &[u8] {
+		&self.buf[..self.len]
+	}
+
+	fn as_str(&self) -> &str {
+		// Only whole `str`s are written to the buffer, and every field is ASCII.
+		str::from_utf8(self.as_bytes()).unwrap_or("")
+	}
+}
+
+impl uWrite for LineBuffer {
+	type Error = ();
+
+	fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+		let remaining = RECORD_LENGTH - self.len;
+		if s.len() > remaining {
+			return Err(());
+		}
+
+		self.buf[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+		self.len += s.len();
+		Ok(())
+	}
+}
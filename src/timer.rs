@@ -1,22 +1,70 @@
 //! Ref: https://blog.rahix.de/005-avr-hal-millis/
 
-use avr_device::interrupt::Mutex;
-use core::{
-	cell::Cell,
-	sync::atomic::{AtomicBool, Ordering},
-};
+use core::cell::{Cell, RefCell};
+
+use crate::hal::{free, Clock, Mutex};
+
+const PRESCALER: u32 = 64;
+const TIMER_COUNTS: u32 = 250;
+
+const MILLIS_INCREMENT: u32 = PRESCALER * TIMER_COUNTS / 16000;
+
+/// Amount of [`SoftTimer`] variants
+const SOFT_TIMER_COUNT: usize = 6;
 
-const PRESCALER: u16 = 64;
-const TIMER_COUNTS: u16 = 250;
+/// Length of time
+///
+/// Stored in milliseconds, but only created from a value with an explicit unit so that minutes
+/// can't be mistaken for seconds (or milliseconds).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration(u32);
+
+impl Duration {
+	/// Create a new [`Duration`] from milliseconds
+	pub const fn from_millis(millis: u32) -> Self {
+		Self(millis)
+	}
+
+	/// Create a new [`Duration`] from seconds
+	pub const fn from_secs(secs: u16) -> Self {
+		Self(secs as u32 * 1_000)
+	}
 
-const MILLIS_INCREMENT: u16 = PRESCALER * TIMER_COUNTS / 16000;
+	/// Create a new [`Duration`] from minutes
+	pub const fn from_mins(mins: u16) -> Self {
+		Self(mins as u32 * 60_000)
+	}
+
+	/// Create a new [`Duration`] from minutes configured in [`crate::config::SystemConfig`]
+	///
+	/// With the `seconds-mode` feature, configured minutes are treated as seconds so that the
+	/// system can be tested on the bench without waiting around.
+	#[cfg(not(feature = "seconds-mode"))]
+	pub const fn from_config_mins(mins: u16) -> Self {
+		Self::from_mins(mins)
+	}
+
+	/// Create a new [`Duration`] from minutes configured in [`crate::config::SystemConfig`]
+	///
+	/// With the `seconds-mode` feature, configured minutes are treated as seconds so that the
+	/// system can be tested on the bench without waiting around.
+	#[cfg(feature = "seconds-mode")]
+	pub const fn from_config_mins(mins: u16) -> Self {
+		Self::from_secs(mins)
+	}
+
+	/// Length of time in milliseconds
+	pub const fn as_millis(self) -> u32 {
+		self.0
+	}
+}
 
+/// Monotonic millisecond clock, driven by TC0
 pub struct Timer {
-	pub paused: AtomicBool,
-	pub millis: Mutex<Cell<u16>>,
-	pub seconds: Mutex<Cell<u16>>,
+	millis: Mutex<Cell<u32>>,
 }
 
+#[cfg(feature = "atmega328p")]
 impl Timer {
 	pub fn init(tc0: arduino_hal::pac::TC0) {
 		// Configure the timer for the above interval (in CTC mode)
@@ -32,52 +80,184 @@ impl Timer {
 		});
 		tc0.timsk0.write(|w| w.ocie0a().set_bit());
 	}
+}
 
-	pub fn pause(&self) {
-		avr_device::interrupt::free(|_cs| {
-			self.paused.store(true, Ordering::SeqCst);
-		});
+impl Clock for Timer {
+	/// Milliseconds since the timer was initialized
+	fn millis(&self) -> u32 {
+		free(|cs| self.millis.borrow(cs).get())
+	}
+}
+
+pub static TIMER: Timer = Timer {
+	millis: Mutex::new(Cell::new(0)),
+};
+
+/// Independent software timers, driven by the same interrupt as [`TIMER`]
+///
+/// Each timer either counts up from when it was started, or counts down from a duration.
+#[derive(Clone, Copy)]
+pub enum SoftTimer {
+	/// Time since the system was activated
+	Activation,
+	/// Time since the system was suspended
+	Suspension,
+	/// Countdown until the sensors should be read again
+	SensorSampling,
+	/// Time since the valve was opened
+	ValveOpen,
+	/// Time since the system last stopped watering
+	SinceWatering,
+	/// Time since the sensors started giving implausible readings
+	SensorFault,
+}
+
+/// State of a single [`SoftTimer`]
+#[derive(Clone, Copy)]
+struct SoftTimerState {
+	/// Whether the timer is running
+	running: bool,
+	/// Whether the timer is counting down, rather than up
+	countdown: bool,
+	/// Milliseconds elapsed, or remaining if counting down
+	millis: u32,
+}
+
+impl SoftTimerState {
+	/// A timer which isn't running
+	const STOPPED: Self = Self {
+		running: false,
+		countdown: false,
+		millis: 0,
+	};
+
+	/// Move the timer on by `millis`
+	fn advance(&mut self, millis: u32) {
+		if !self.running {
+			return;
+		}
+
+		self.millis = if self.countdown {
+			self.millis.saturating_sub(millis)
+		} else {
+			self.millis.saturating_add(millis)
+		};
 	}
+}
+
+static SOFT_TIMERS: Mutex<RefCell<[SoftTimerState; SOFT_TIMER_COUNT]>> =
+	Mutex::new(RefCell::new([SoftTimerState::STOPPED; SOFT_TIMER_COUNT]));
 
-	pub fn resume(&self) {
-		avr_device::interrupt::free(|_cs| {
-			self.paused.store(false, Ordering::SeqCst);
+impl SoftTimer {
+	/// Run `f` with the state of the timer
+	fn with<R>(self, f: impl FnOnce(&mut SoftTimerState) -> R) -> R {
+		free(|cs| f(&mut SOFT_TIMERS.borrow(cs).borrow_mut()[self as usize]))
+	}
+
+	/// (Re)start counting up from zero
+	pub fn start(self) {
+		self.start_from(Duration::from_millis(0));
+	}
+
+	/// (Re)start counting up from `elapsed`
+	pub fn start_from(self, elapsed: Duration) {
+		self.with(|timer| {
+			*timer = SoftTimerState {
+				running: true,
+				countdown: false,
+				millis: elapsed.as_millis(),
+			}
 		});
 	}
 
-	pub fn reset(&self) {
-		avr_device::interrupt::free(|cs| {
-			self.millis.borrow(cs).set(0);
-			self.seconds.borrow(cs).set(0);
+	/// (Re)start counting down from `duration`
+	pub fn start_countdown(self, duration: Duration) {
+		self.with(|timer| {
+			*timer = SoftTimerState {
+				running: true,
+				countdown: true,
+				millis: duration.as_millis(),
+			}
 		});
 	}
 
-	pub fn elapsed_s(&self) -> u16 {
-		avr_device::interrupt::free(|cs| self.seconds.borrow(cs).get())
+	/// Stop the timer
+	pub fn stop(self) {
+		self.with(|timer| *timer = SoftTimerState::STOPPED);
+	}
+
+	/// Whether the timer is running
+	pub fn is_running(self) -> bool {
+		self.with(|timer| timer.running)
+	}
+
+	/// Time since the timer was started, or zero if it isn't running
+	pub fn elapsed(self) -> Duration {
+		self.with(|timer| match (timer.running, timer.countdown) {
+			(true, false) => Duration::from_millis(timer.millis),
+			_ => Duration::from_millis(0),
+		})
+	}
+
+	/// Whether the timer is counting down and has reached zero
+	pub fn has_expired(self) -> bool {
+		self.with(|timer| timer.running && timer.countdown && timer.millis == 0)
 	}
 }
 
-pub static TIMER: Timer = Timer {
-	paused: AtomicBool::new(true),
-	millis: Mutex::new(Cell::new(0)),
-	seconds: Mutex::new(Cell::new(0)),
-};
+/// Move [`TIMER`] and every [`SoftTimer`] on by `millis`
+///
+/// Called from the TC0 interrupt, or by the simulation in place of it.
+pub fn advance(millis: u32) {
+	free(|cs| {
+		let millis_cell = TIMER.millis.borrow(cs);
+		millis_cell.set(millis_cell.get().wrapping_add(millis));
 
+		for timer in SOFT_TIMERS.borrow(cs).borrow_mut().iter_mut() {
+			timer.advance(millis);
+		}
+	})
+}
+
+#[cfg(feature = "atmega328p")]
 #[avr_device::interrupt(atmega328p)]
 #[allow(non_snake_case)]
 fn TIMER0_COMPA() {
-	avr_device::interrupt::free(|cs| {
-		if !TIMER.paused.load(Ordering::SeqCst) {
-			let millis_cell = TIMER.millis.borrow(cs);
-			let millis = millis_cell.get();
-			if millis >= 1_000 {
-				millis_cell.set(0);
-				let seconds_cell = TIMER.seconds.borrow(cs);
-				let seconds = seconds_cell.get();
-				seconds_cell.set(seconds + 1);
-			} else {
-				millis_cell.set(millis + MILLIS_INCREMENT);
+	advance(MILLIS_INCREMENT);
+}
+
+#[cfg(test)]
+pub mod tests {
+	use std::{
+		sync::atomic::{AtomicBool, Ordering},
+		thread,
+	};
+
+	use super::{free, SoftTimerState, SOFT_TIMERS, SOFT_TIMER_COUNT};
+
+	/// Whether a test is using the soft timers
+	static TIMERS_IN_USE: AtomicBool = AtomicBool::new(false);
+
+	/// Exclusive use of the soft timers for a test, which are shared by every test
+	pub struct TimersGuard(());
+
+	impl TimersGuard {
+		/// Wait until no other test is using the soft timers, and stop all of them
+		pub fn take() -> Self {
+			while TIMERS_IN_USE.swap(true, Ordering::Acquire) {
+				thread::yield_now();
 			}
+
+			free(|cs| {
+				*SOFT_TIMERS.borrow(cs).borrow_mut() = [SoftTimerState::STOPPED; SOFT_TIMER_COUNT];
+			});
+			Self(())
 		}
-	})
+	}
+
+	impl Drop for TimersGuard {
+		fn drop(&mut self) {
+			TIMERS_IN_USE.store(false, Ordering::Release);
+		}
+	}
 }
@@ -0,0 +1,120 @@
+//! Recorded sensor traces
+//!
+//! A trace is a sequence of light and moisture readings, each with the time it was recorded at, so
+//! that the conditions which led the system to water (or not) can be reproduced. Each line of a
+//! trace is a sample:
+//!
+//! ```text
+//! <secs> <light> <moisture>
+//! ```
+//!
+//! Where `secs` is the time since the start of the trace. A trace is replayed by moving the clock
+//! straight on to the time of each sample, rather than waiting for it, and using its readings in
+//! place of the sensors until the next sample.
+
+use crate::timer::Duration;
+
+/// Light and moisture readings recorded at a point in a trace
+#[derive(Clone, Copy)]
+pub struct Sample {
+	/// Seconds since the start of the trace
+	pub secs: u32,
+	pub light: u16,
+	pub moisture: u16,
+}
+
+impl Sample {
+	/// Parse a sample from the words of a line
+	///
+	/// Returns `None` if there aren't exactly three numbers.
+	pub fn parse<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<Self> {
+		let sample = Self {
+			secs: words.next()?.parse().ok()?,
+			light: words.next()?.parse().ok()?,
+			moisture: words.next()?.parse().ok()?,
+		};
+
+		match words.next() {
+			None => Some(sample),
+			Some(_) => None,
+		}
+	}
+}
+
+/// Progress through a trace which is being replayed
+pub struct Replay {
+	/// Sample whose readings are being used, if the replay has started
+	current: Option<Sample>,
+}
+
+impl Replay {
+	/// Create a new [`Replay`], which hasn't started
+	pub fn new() -> Self {
+		Self { current: None }
+	}
+
+	/// How far the clock should be moved on before the readings of `sample` are used
+	///
+	/// The first sample is used straight away, as is a sample which is older than the current one.
+	pub fn gap(&self, sample: &Sample) -> Duration {
+		let secs = match &self.current {
+			Some(current) => sample.secs.saturating_sub(current.secs),
+			None => 0,
+		};
+		Duration::from_millis(secs.saturating_mul(1_000))
+	}
+
+	/// Use the readings of `sample` from now on
+	pub fn apply(&mut self, sample: &Sample) {
+		self.current = Some(*sample);
+	}
+
+	/// Stop the replay, so that the next sample starts a new one
+	pub fn stop(&mut self) {
+		self.current = None;
+	}
+
+	/// Light and moisture readings of the current sample, if the replay has started
+	pub fn readings(&self) -> Option<(u16, u16)> {
+		self.current
+			.as_ref()
+			.map(|sample| (sample.light, sample.moisture))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_sample() {
+		let sample = Sample::parse("7200 40 310".split_whitespace()).unwrap();
+		assert_eq!(sample.secs, 7200);
+		assert_eq!(sample.light, 40);
+		assert_eq!(sample.moisture, 310);
+
+		assert!(Sample::parse("7200 40".split_whitespace()).is_none());
+		assert!(Sample::parse("7200 40 310 1".split_whitespace()).is_none());
+		assert!(Sample::parse("7200 dark 310".split_whitespace()).is_none());
+	}
+
+	#[test]
+	fn gap_is_time_since_current_sample() {
+		let mut replay = Replay::new();
+		let first = Sample::parse("60 500 500".split_whitespace()).unwrap();
+		let second = Sample::parse("180 40 40".split_whitespace()).unwrap();
+
+		assert_eq!(replay.gap(&first).as_millis(), 0);
+		assert!(replay.readings().is_none());
+
+		replay.apply(&first);
+		assert_eq!(replay.gap(&second).as_millis(), 120_000);
+		assert_eq!(replay.gap(&first).as_millis(), 0);
+
+		replay.apply(&second);
+		assert_eq!(replay.readings(), Some((40, 40)));
+
+		replay.stop();
+		assert!(replay.readings().is_none());
+	}
+}
@@ -0,0 +1,337 @@
+//! Trend of the soil moisture
+//!
+//! The moisture is sampled once an hour, and the last [`SAMPLES`] samples are shown on the soil
+//! screen as a sparkline across a single row, with the oldest on the left. The sparkline is scaled
+//! to the lowest and highest samples rather than to the range of the sensor, so that how quickly
+//! the soil is drying out can be seen even when the readings barely change.
+//!
+//! Each sample is also checked for an [`Anomaly`]: the soil drying out much faster than it usually
+//! does, which could be a broken drip line, or the moisture not rising after watering, which could
+//! be a clogged line or a sensor which has come out of the soil. An anomaly is kept until a later
+//! watering is followed by the moisture rising.
+
+use crate::{
+	display::{align, BODY_START_ROW, ROW_LENGTH},
+	fmt::Buffer,
+	hal::{Clock, DisplayBus},
+	lang,
+	timer::{Duration, TIMER},
+};
+
+/// How often the moisture is sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_mins(60);
+/// Amount of samples which are kept, one for each column of the sparkline
+const SAMPLES: usize = ROW_LENGTH as usize;
+/// Row of the sparkline
+const SPARKLINE_ROW: u8 = BODY_START_ROW + 2;
+/// Row of the age of the oldest and newest samples, under the sparkline
+const AXIS_ROW: u8 = SPARKLINE_ROW + 1;
+/// Characters of the sparkline, from the lowest sample to the highest
+///
+/// The display only has the ASCII characters of its terminal font, so these stand in for proper
+/// sparkline glyphs.
+const LEVELS: &[u8] = b"_.-^";
+/// Row of the anomaly, if there is one
+const ANOMALY_ROW: u8 = BODY_START_ROW + 5;
+/// How many times faster than usual the moisture has to fall in an hour for the soil to be drying
+/// out too fast
+const FAST_DRY_FACTOR: u16 = 3;
+/// Least the moisture has to fall in an hour for the soil to be drying out too fast, so that noise
+/// in the readings isn't mistaken for it
+const MIN_FAST_DRY_FALL: u16 = 20;
+/// Amount of hours the moisture has to have fallen in, before how fast it usually falls is known
+const MIN_USUAL_FALLS: u16 = 3;
+/// Least the moisture has to rise by after watering
+const MIN_WATERING_RISE: u16 = 10;
+
+/// Unusual change in the moisture
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Anomaly {
+	/// The moisture fell much faster than usual
+	FastDryOut,
+	/// The moisture didn't rise after watering
+	NoRise,
+}
+
+impl Anomaly {
+	/// Description of the anomaly on the soil screen
+	fn label(self) -> &'static str {
+		match self {
+			Self::FastDryOut => lang::DRYING_FAST,
+			Self::NoRise => lang::NO_RISE,
+		}
+	}
+}
+
+/// Hourly samples of the soil moisture
+pub struct MoistureTrend {
+	/// Samples of the moisture, oldest first, of which only the last [`MoistureTrend::len`] have
+	/// been taken
+	samples: [u16; SAMPLES],
+	/// Amount of samples taken, up to [`SAMPLES`]
+	len: usize,
+	/// [`TIMER`] clock value of the last time the moisture was sampled, if it has been
+	last_sample_ms: Option<u32>,
+	/// Whether the system has watered since the last sample
+	watered: bool,
+	/// Whether the system watered between the previous sample and the latest one
+	latest_watered: bool,
+	/// Anomaly found in the samples, if there is one
+	anomaly: Option<Anomaly>,
+}
+
+impl MoistureTrend {
+	/// Create a new [`MoistureTrend`] without any samples
+	pub fn new() -> Self {
+		Self {
+			samples: [0; SAMPLES],
+			len: 0,
+			last_sample_ms: None,
+			watered: false,
+			latest_watered: false,
+			anomaly: None,
+		}
+	}
+
+	/// Anomaly found in the samples, if there is one
+	pub fn anomaly(&self) -> Option<Anomaly> {
+		self.anomaly
+	}
+
+	/// How much the latest sample rose above the previous one without the system watering, such as
+	/// after rain
+	pub fn unwatered_rise(&self) -> u16 {
+		if self.len < 2 || self.latest_watered {
+			return 0;
+		}
+		self.samples[SAMPLES - 1].saturating_sub(self.samples[SAMPLES - 2])
+	}
+
+	/// Count a watering, which the moisture should rise after by the next sample
+	pub fn watered(&mut self) {
+		self.watered = true;
+	}
+
+	/// Sample the moisture if it is due to be sampled, dropping the oldest sample once there are
+	/// [`SAMPLES`] of them, and check it for an anomaly
+	///
+	/// Returns `true` if a sample was taken.
+	pub fn update(&mut self, read_moisture: impl FnOnce() -> u16) -> bool {
+		let due = match self.last_sample_ms {
+			Some(last_sample_ms) => TIMER.has_elapsed(last_sample_ms, SAMPLE_INTERVAL),
+			None => true,
+		};
+		if !due {
+			return false;
+		}
+
+		self.last_sample_ms = Some(TIMER.millis());
+		self.samples.rotate_left(1);
+		self.samples[SAMPLES - 1] = read_moisture();
+		self.len = (self.len + 1).min(SAMPLES);
+		self.check();
+		true
+	}
+
+	/// Check the latest sample for an anomaly
+	fn check(&mut self) {
+		let watered = self.watered;
+		self.watered = false;
+		self.latest_watered = watered;
+		if self.len < 2 {
+			return;
+		}
+
+		let (previous, latest) = (self.samples[SAMPLES - 2], self.samples[SAMPLES - 1]);
+		if watered {
+			// If the moisture rose, the line and the sensor are working again.
+			self.anomaly = if latest >= previous.saturating_add(MIN_WATERING_RISE) {
+				None
+			} else {
+				Some(Anomaly::NoRise)
+			};
+			return;
+		}
+
+		// Compare the fall in the last hour to the average fall of the hours before it, leaving
+		// out the hours in which the moisture rose.
+		let fall = previous.saturating_sub(latest);
+		let (mut usual_total, mut usual_falls) = (0u32, 0u16);
+		for pair in self.samples[SAMPLES - self.len..SAMPLES - 1].windows(2) {
+			if pair[1] < pair[0] {
+				usual_total += (pair[0] - pair[1]) as u32;
+				usual_falls += 1;
+			}
+		}
+		if usual_falls < MIN_USUAL_FALLS || fall < MIN_FAST_DRY_FALL {
+			return;
+		}
+		let usual = usual_total / usual_falls as u32;
+		if fall as u32 >= usual * FAST_DRY_FACTOR as u32 {
+			self.anomaly = Some(Anomaly::FastDryOut);
+		}
+	}
+
+	/// Render the soil screen
+	pub fn render(&self, display: &mut impl DisplayBus) {
+		display.clear_body();
+		let samples = &self.samples[SAMPLES - self.len..];
+
+		// Show the latest sample next to the title.
+		let mut latest = Buffer::<6>::new();
+		if let Some(moisture) = samples.last() {
+			let _ = ufmt::uwrite!(latest, "{}", moisture);
+		}
+		display.set_position(0, BODY_START_ROW);
+		let _ = ufmt::uwrite!(
+			display,
+			"{}",
+			align(lang::SOIL, latest.as_str(), ROW_LENGTH)
+		);
+
+		let lowest = samples.iter().copied().min().unwrap_or(0);
+		let highest = samples.iter().copied().max().unwrap_or(0);
+		let start = (SAMPLES - self.len) as u8;
+		display.set_position(start, SPARKLINE_ROW);
+		for &moisture in samples {
+			// If the moisture hasn't changed, draw a flat line through the middle.
+			let level = if highest == lowest {
+				LEVELS.len() / 2
+			} else {
+				let steps = (LEVELS.len() - 1) as u32;
+				((moisture - lowest) as u32 * steps / (highest - lowest) as u32) as usize
+			};
+			let glyph = [LEVELS[level]];
+			let _ = display.write_str(core::str::from_utf8(&glyph).unwrap_or(" "));
+		}
+
+		// Label the ends of the sparkline with how many hours ago they were sampled.
+		if self.len > 1 {
+			display.set_position(start, AXIS_ROW);
+			let _ = ufmt::uwrite!(display, "-{}h", self.len - 1);
+		}
+		if self.len > 0 {
+			display.set_position(ROW_LENGTH - 2, AXIS_ROW);
+			let _ = display.write_str("0h");
+		}
+
+		if let Some(anomaly) = self.anomaly {
+			display.set_position(0, ANOMALY_ROW);
+			let _ = display.write_str(anomaly.label());
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		hal::mock::MockDisplay,
+		timer::{self, tests::TimersGuard},
+	};
+
+	/// Take a sample of `moisture` for each hour
+	fn sample_hourly(trend: &mut MoistureTrend, moistures: &[u16]) {
+		for &moisture in moistures {
+			assert!(trend.update(|| moisture));
+			timer::advance(SAMPLE_INTERVAL.as_millis());
+		}
+	}
+
+	#[test]
+	fn samples_hourly() {
+		let _timers = TimersGuard::take();
+		let mut trend = MoistureTrend::new();
+		assert!(trend.update(|| 500));
+		timer::advance(SAMPLE_INTERVAL.as_millis() - 1);
+		assert!(!trend.update(|| 490));
+		timer::advance(1);
+		assert!(trend.update(|| 480));
+		assert_eq!(&trend.samples[SAMPLES - 2..], &[500, 480]);
+		assert_eq!(trend.len, 2);
+	}
+
+	#[test]
+	fn renders_sparkline_scaled_to_samples() {
+		let _timers = TimersGuard::take();
+		let mut display = MockDisplay::new();
+		let mut trend = MoistureTrend::new();
+		sample_hourly(&mut trend, &[520, 520, 510, 490, 460, 430, 400]);
+		trend.render(&mut display);
+
+		assert_eq!(display.row(BODY_START_ROW), "Soil:        400");
+		assert_eq!(display.row(SPARKLINE_ROW), "         ^^--.__");
+		assert_eq!(display.row(AXIS_ROW), "         -6h  0h");
+	}
+
+	#[test]
+	fn oldest_samples_are_dropped() {
+		let _timers = TimersGuard::take();
+		let mut display = MockDisplay::new();
+		let mut trend = MoistureTrend::new();
+		sample_hourly(&mut trend, &[300; SAMPLES]);
+		sample_hourly(&mut trend, &[310]);
+		trend.render(&mut display);
+
+		assert_eq!(trend.len, SAMPLES);
+		assert_eq!(display.row(SPARKLINE_ROW), "_______________^");
+		assert_eq!(display.row(AXIS_ROW), "-15h          0h");
+	}
+
+	#[test]
+	fn fast_dry_out_is_an_anomaly() {
+		let _timers = TimersGuard::take();
+		let mut display = MockDisplay::new();
+		let mut trend = MoistureTrend::new();
+		sample_hourly(&mut trend, &[600, 590, 580, 570]);
+		assert_eq!(trend.anomaly(), None);
+
+		sample_hourly(&mut trend, &[540]);
+		assert_eq!(trend.anomaly(), Some(Anomaly::FastDryOut));
+		trend.render(&mut display);
+		assert_eq!(display.row(ANOMALY_ROW).trim_end(), "Drying too fast");
+
+		// It is kept until a watering raises the moisture.
+		sample_hourly(&mut trend, &[535]);
+		assert_eq!(trend.anomaly(), Some(Anomaly::FastDryOut));
+		trend.watered();
+		sample_hourly(&mut trend, &[620]);
+		assert_eq!(trend.anomaly(), None);
+	}
+
+	#[test]
+	fn no_rise_after_watering_is_an_anomaly() {
+		let _timers = TimersGuard::take();
+		let mut trend = MoistureTrend::new();
+		sample_hourly(&mut trend, &[400]);
+		trend.watered();
+		sample_hourly(&mut trend, &[405]);
+		assert_eq!(trend.anomaly(), Some(Anomaly::NoRise));
+
+		trend.watered();
+		sample_hourly(&mut trend, &[480]);
+		assert_eq!(trend.anomaly(), None);
+	}
+
+	#[test]
+	fn rise_without_watering_is_counted() {
+		let _timers = TimersGuard::take();
+		let mut trend = MoistureTrend::new();
+		sample_hourly(&mut trend, &[400, 460]);
+		assert_eq!(trend.unwatered_rise(), 60);
+
+		trend.watered();
+		sample_hourly(&mut trend, &[520]);
+		assert_eq!(trend.unwatered_rise(), 0);
+		sample_hourly(&mut trend, &[510]);
+		assert_eq!(trend.unwatered_rise(), 0);
+	}
+
+	#[test]
+	fn steady_drying_is_not_an_anomaly() {
+		let _timers = TimersGuard::take();
+		let mut trend = MoistureTrend::new();
+		sample_hourly(&mut trend, &[600, 580, 570, 550, 530, 515, 500]);
+		assert_eq!(trend.anomaly(), None);
+	}
+}
@@ -0,0 +1,123 @@
+//! Vacation mode
+//!
+//! While the **Vacation** setting isn't 0, the activations are shortened and the intervals between
+//! them are lengthened by the configured factors (see [`SystemConfig::watering_duration`] and
+//! [`SystemConfig::min_interval`]), so that a garden left alone for a while isn't watered as much.
+//! The setting is the amount of days left, which is counted down once a day until the system goes
+//! back to normal. There is no clock, so a day is counted every 24 hours from when vacation mode
+//! was enabled.
+
+use crate::{
+	config::SystemConfig,
+	display::{align, ROW_LENGTH},
+	fmt::Buffer,
+	hal::{Clock, DisplayBus},
+	lang,
+	timer::{Duration, TIMER},
+};
+
+/// Length of a day of vacation
+const DAY: Duration = Duration::from_mins(24 * 60);
+/// Header row which the days left are shown on, in place of the name
+const TITLE_ROW: u8 = 0;
+
+/// Counts the days of vacation mode
+pub struct Vacation {
+	/// [`TIMER`] clock value of when the current day of vacation started, if on vacation
+	day_started_ms: Option<u32>,
+}
+
+impl Vacation {
+	/// Create a new [`Vacation`], which isn't counting
+	pub fn new() -> Self {
+		Self {
+			day_started_ms: None,
+		}
+	}
+
+	/// Start counting the days once vacation mode is enabled, and stop once it ends
+	///
+	/// Returns `true` once a day of vacation has passed, for the caller to count down the days left.
+	pub fn update(&mut self, system_config: &SystemConfig) -> bool {
+		if !system_config.is_on_vacation() {
+			self.day_started_ms = None;
+			return false;
+		}
+
+		match self.day_started_ms {
+			Some(day_started_ms) if TIMER.has_elapsed(day_started_ms, DAY) => {
+				self.day_started_ms = Some(day_started_ms.wrapping_add(DAY.as_millis()));
+				true
+			}
+			Some(_) => false,
+			None => {
+				self.day_started_ms = Some(TIMER.millis());
+				false
+			}
+		}
+	}
+}
+
+/// Render the days of vacation left in place of the name in the header, such as `Vacation:5d left`
+pub fn render_title(system_config: &SystemConfig, display: &mut impl DisplayBus) {
+	let mut left = Buffer::<8>::new();
+	let _ = ufmt::uwrite!(left, "{}{}", system_config.vacation_days, lang::DAYS_LEFT);
+	display.clear_row(TITLE_ROW);
+	let _ = ufmt::uwrite!(
+		display,
+		"{}",
+		align(lang::VACATION, left.as_str(), ROW_LENGTH)
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		config::{Number, UpdateSystemValue, ValueAction},
+		hal::mock::MockDisplay,
+		timer::{self, tests::TimersGuard},
+	};
+
+	#[test]
+	fn counts_a_day_every_24_hours() {
+		let _timers = TimersGuard::take();
+		let mut system_config = SystemConfig::new();
+		let mut vacation = Vacation::new();
+		assert!(!vacation.update(&system_config));
+
+		system_config.update(&UpdateSystemValue::Number(
+			Number::VacationDays,
+			ValueAction::Set(2),
+		));
+		assert!(!vacation.update(&system_config));
+		timer::advance(DAY.as_millis() - 1);
+		assert!(!vacation.update(&system_config));
+		timer::advance(1);
+		assert!(vacation.update(&system_config));
+		assert!(!vacation.update(&system_config));
+
+		// Ending vacation mode stops the counting, and enabling it again starts a new day.
+		system_config.vacation_days = 0;
+		assert!(!vacation.update(&system_config));
+		timer::advance(DAY.as_millis() / 2);
+		system_config.vacation_days = 1;
+		assert!(!vacation.update(&system_config));
+		timer::advance(DAY.as_millis() / 2);
+		assert!(!vacation.update(&system_config));
+	}
+
+	#[test]
+	fn renders_days_left_in_header() {
+		let mut display = MockDisplay::new();
+		let mut system_config = SystemConfig::new();
+		system_config.vacation_days = 5;
+		render_title(&system_config, &mut display);
+		assert_eq!(display.row(TITLE_ROW), "Vacation:5d left");
+
+		// The label is cut short to make room for more days.
+		system_config.vacation_days = 12;
+		render_title(&system_config, &mut display);
+		assert_eq!(display.row(TITLE_ROW), "Vaca...:12d left");
+	}
+}
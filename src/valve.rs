@@ -0,0 +1,289 @@
+//! Solenoid valve drivers
+//!
+//! A regular solenoid valve is switched with a relay, and has to be powered for as long as it
+//! should be open (or closed, if it is normally-open). A 2-wire latching solenoid only needs a
+//! short pulse to open, and a short pulse in the opposite direction to close, through an H-bridge.
+
+#[cfg(feature = "expander-valve")]
+use crate::expander::ExpanderPin;
+#[cfg(feature = "shift-register-valve")]
+use crate::shiftreg::ShiftRegister;
+use crate::{
+	config::{SystemConfig, DEFAULT_RELAY_ACTIVE_LOW, DEFAULT_VALVE_NORMALLY_OPEN},
+	eeprom::{Eeprom, RELAY_WIRING_ADDRESS},
+	hal::{ValveOutput, ValvePin},
+};
+#[cfg(any(
+	feature = "dosing-pump",
+	feature = "grow-light",
+	feature = "fan",
+	feature = "heat-mat",
+	feature = "master-valve"
+))]
+use crate::{hal::MappedOutput, pinmap::DigitalPin};
+#[cfg(feature = "latching-valve")]
+use crate::{
+	hal::{Clock, ValveClosePin, CLOCK},
+	timer::Duration,
+};
+#[cfg(feature = "expander-valve")]
+use embedded_hal::blocking::i2c::Write;
+
+/// How long to pulse a latching solenoid for to open or close it
+#[cfg(feature = "latching-valve")]
+const PULSE_DURATION: Duration = Duration::from_millis(50);
+
+/// Valve driver used by the system
+#[cfg(not(feature = "latching-valve"))]
+pub type Valve = RelayValve<ValvePin>;
+/// Valve driver used by the system
+#[cfg(feature = "latching-valve")]
+pub type Valve = LatchingValve<ValvePin, ValveClosePin>;
+
+/// Bit of the relay wiring record which is set if the relay is active-low
+const ACTIVE_LOW_BIT: u8 = 1 << 0;
+/// Bit of the relay wiring record which is set if the valve is normally-open
+const NORMALLY_OPEN_BIT: u8 = 1 << 1;
+
+/// How the valve relay is wired, as set by **Relay low** and **Valve NO** in the menu
+///
+/// It is kept in the EEPROM so that the valve is closed the right way as soon as the system starts
+/// up, rather than going back to the defaults the firmware was built with.
+#[derive(Clone, Copy)]
+pub struct RelayWiring {
+	/// Whether the relay is energized when its input is low
+	pub relay_active_low: bool,
+	/// Whether the valve lets water through while it isn't powered
+	pub valve_normally_open: bool,
+}
+
+impl RelayWiring {
+	/// Size in bytes of the wiring stored in the EEPROM, not including its checksum
+	pub const SIZE: usize = 1;
+	/// Wiring the firmware was built for, until it is changed in the menu
+	pub const DEFAULT: Self = Self {
+		relay_active_low: DEFAULT_RELAY_ACTIVE_LOW,
+		valve_normally_open: DEFAULT_VALVE_NORMALLY_OPEN,
+	};
+
+	/// Wiring of the configuration
+	pub fn of(system_config: &SystemConfig) -> Self {
+		Self {
+			relay_active_low: system_config.relay_active_low,
+			valve_normally_open: system_config.valve_normally_open,
+		}
+	}
+
+	/// Load the wiring saved in the EEPROM, or the defaults if it was never saved
+	pub fn load(eeprom: &Eeprom) -> Self {
+		let mut buf = [0; Self::SIZE];
+		if !eeprom.read_record(RELAY_WIRING_ADDRESS, &mut buf)
+			|| buf[0] & !(ACTIVE_LOW_BIT | NORMALLY_OPEN_BIT) != 0
+		{
+			return Self::DEFAULT;
+		}
+		Self {
+			relay_active_low: buf[0] & ACTIVE_LOW_BIT != 0,
+			valve_normally_open: buf[0] & NORMALLY_OPEN_BIT != 0,
+		}
+	}
+
+	/// Store the wiring in the EEPROM, to be used the next time the system starts up
+	pub fn save(&self, eeprom: &mut Eeprom) {
+		let mut flags = 0;
+		if self.relay_active_low {
+			flags |= ACTIVE_LOW_BIT;
+		}
+		if self.valve_normally_open {
+			flags |= NORMALLY_OPEN_BIT;
+		}
+		eeprom.write_record(RELAY_WIRING_ADDRESS, &[flags]);
+	}
+
+	/// Set the wiring of the configuration
+	pub fn apply(&self, system_config: &mut SystemConfig) {
+		system_config.relay_active_low = self.relay_active_low;
+		system_config.valve_normally_open = self.valve_normally_open;
+	}
+
+	/// Make `pin` the input of a relay, which is switched off before the pin becomes an output
+	#[cfg(any(
+		feature = "dosing-pump",
+		feature = "grow-light",
+		feature = "fan",
+		feature = "heat-mat",
+		feature = "master-valve"
+	))]
+	pub fn relay_output(&self, pin: DigitalPin) -> MappedOutput {
+		MappedOutput::new_at(pin, relay_level(false, self.relay_active_low))
+	}
+}
+
+/// Level of the input of a relay which should be energized if `on`
+///
+/// An active-low relay is energized when its input is low.
+pub fn relay_level(on: bool, relay_active_low: bool) -> bool {
+	on != relay_active_low
+}
+
+/// Switch the relay with its input on `relay` on or off
+#[cfg(any(
+	feature = "dosing-pump",
+	feature = "grow-light",
+	feature = "fan",
+	feature = "heat-mat",
+	feature = "master-valve"
+))]
+pub fn switch_relay(relay: &mut MappedOutput, on: bool, relay_active_low: bool) {
+	if relay_level(on, relay_active_low) {
+		relay.set_high();
+	} else {
+		relay.set_low();
+	}
+}
+
+/// Opens and closes a solenoid valve
+pub trait ValveDriver {
+	/// Open or close the valve
+	///
+	/// Called on every tick of the system, whether or not the valve should change.
+	fn set_open(&mut self, open: bool, system_config: &SystemConfig);
+}
+
+/// Solenoid valve switched by a relay on D3, by default
+pub struct RelayValve<P> {
+	pin: P,
+}
+
+impl<P: ValveOutput> RelayValve<P> {
+	/// Create a new [`RelayValve`] wired as `wiring`, and close the valve
+	///
+	/// The pin is low when it becomes an output, which opens the valve with some relays, so it is
+	/// closed straight away.
+	pub fn new(pin: P, wiring: RelayWiring) -> Self {
+		let mut valve = Self { pin };
+		valve.write(false, wiring.relay_active_low, wiring.valve_normally_open);
+		valve
+	}
+
+	/// Set the relay output to match whether the valve should be open
+	fn write(&mut self, open: bool, relay_active_low: bool, valve_normally_open: bool) {
+		// A normally-open valve has to be energized to stop the water.
+		let energized = open != valve_normally_open;
+		if relay_level(energized, relay_active_low) {
+			self.pin.set_high();
+		} else {
+			self.pin.set_low();
+		}
+	}
+}
+
+#[cfg(feature = "expander-valve")]
+impl<I: Write> RelayValve<ExpanderPin<I>> {
+	/// Whether the expander the relay is on acknowledged the last write of its output
+	pub fn is_responding(&self) -> bool {
+		self.pin.is_responding()
+	}
+}
+
+#[cfg(feature = "shift-register-valve")]
+impl<P: ValveOutput> RelayValve<ShiftRegister<P>> {
+	/// Energize the relay on `channel` of the shift registers to test it, or stop testing with
+	/// `None`
+	pub fn test_channel(&mut self, channel: Option<u8>, system_config: &SystemConfig) {
+		// An active-low relay is energized when its input is low.
+		let level = !system_config.relay_active_low;
+		self.pin.set_test(channel.map(|channel| (channel, level)));
+	}
+}
+
+impl<P: ValveOutput> ValveDriver for RelayValve<P> {
+	fn set_open(&mut self, open: bool, system_config: &SystemConfig) {
+		// The output is always written, so that a change to the relay configuration takes effect
+		// straight away.
+		self.write(
+			open,
+			system_config.relay_active_low,
+			system_config.valve_normally_open,
+		);
+	}
+}
+
+/// Close the valve wired as `wiring` without relying on the rest of the system, once it has
+/// panicked
+#[cfg(not(feature = "latching-valve"))]
+pub fn close_after_panic(pin: impl ValveOutput, wiring: RelayWiring) {
+	// Creating the valve closes it straight away.
+	RelayValve::new(pin, wiring);
+}
+
+/// Close the valve without relying on the rest of the system, once it has panicked
+///
+/// Blocks until the valve has been pulsed closed.
+#[cfg(feature = "latching-valve")]
+pub fn close_after_panic(open_pin: impl ValveOutput, close_pin: impl ValveOutput) {
+	// Creating the valve starts pulsing it closed, so wait for the pulse to finish.
+	let mut valve = LatchingValve::new(open_pin, close_pin);
+	arduino_hal::delay_ms(PULSE_DURATION.as_millis() as u16);
+	valve.open_pin.set_low();
+	valve.close_pin.set_low();
+}
+
+/// Latching solenoid valve driven by an H-bridge on D3 (open) and D2 (close), by default
+#[cfg(feature = "latching-valve")]
+pub struct LatchingValve<O, C> {
+	open_pin: O,
+	close_pin: C,
+	/// Whether the valve was last pulsed open
+	open: bool,
+	/// [`CLOCK`] value of when the current pulse started, if there is one
+	pulse_started_ms: Option<u32>,
+}
+
+#[cfg(feature = "latching-valve")]
+impl<O: ValveOutput, C: ValveOutput> LatchingValve<O, C> {
+	/// Create a new [`LatchingValve`], and pulse the valve closed
+	///
+	/// The valve could have been left open if the system was reset, so it is always closed first.
+	pub fn new(open_pin: O, close_pin: C) -> Self {
+		let mut valve = Self {
+			open_pin,
+			close_pin,
+			open: true,
+			pulse_started_ms: None,
+		};
+		valve.pulse(false);
+		valve
+	}
+
+	/// Start a pulse to open or close the valve
+	fn pulse(&mut self, open: bool) {
+		if open {
+			self.open_pin.set_high();
+		} else {
+			self.close_pin.set_high();
+		}
+		self.open = open;
+		self.pulse_started_ms = Some(CLOCK.millis());
+	}
+}
+
+#[cfg(feature = "latching-valve")]
+impl<O: ValveOutput, C: ValveOutput> ValveDriver for LatchingValve<O, C> {
+	fn set_open(&mut self, open: bool, _system_config: &SystemConfig) {
+		// Finish the current pulse before starting another.
+		if let Some(pulse_started_ms) = self.pulse_started_ms {
+			if !CLOCK.has_elapsed(pulse_started_ms, PULSE_DURATION) {
+				return;
+			}
+
+			self.open_pin.set_low();
+			self.close_pin.set_low();
+			self.pulse_started_ms = None;
+		}
+
+		if self.open != open {
+			self.pulse(open);
+		}
+	}
+}
@@ -0,0 +1,147 @@
+//! Watchdog timer supervision
+//!
+//! If the main loop stops running (for example, if the I2C bus locks up while the display is being
+//! written to), the watchdog resets the MCU. The valve is closed while the MCU resets, so a lock-up
+//! can't keep water flowing.
+
+use arduino_hal::pac::{CPU, WDT};
+
+use crate::log::LogEvent;
+
+/// Watchdog change enable bit in WDTCSR
+const WDCE: u8 = 1 << 4;
+/// Watchdog system reset enable bit in WDTCSR
+const WDE: u8 = 1 << 3;
+/// Prescaler bits in WDTCSR for a timeout of roughly 2 seconds
+const TIMEOUT_2S: u8 = 0b111;
+/// Prescaler bits in WDTCSR for a timeout of roughly 8 seconds (WDP3 and WDP0)
+const TIMEOUT_8S: u8 = 0b10_0001;
+
+/// Cause of the last reset, from the reset flags in MCUSR
+#[derive(Clone, Copy)]
+pub enum ResetCause {
+	/// The watchdog timed out
+	Watchdog,
+	/// The supply voltage dropped below the brown-out threshold
+	BrownOut,
+	/// The reset pin was pulled low, such as by the reset button
+	External,
+	/// The power was switched on
+	PowerOn,
+	/// None of the flags were set, which happens if the bootloader cleared them
+	Unknown,
+}
+
+impl ResetCause {
+	/// Short name of the cause, which fits on the display after `Reset: `
+	pub fn label(&self) -> &'static str {
+		match self {
+			Self::Watchdog => "Watchdog",
+			Self::BrownOut => "Brown-out",
+			Self::External => "External",
+			Self::PowerOn => "Power-on",
+			Self::Unknown => "Unknown",
+		}
+	}
+
+	/// Event which records the reset in the event log
+	///
+	/// A watchdog reset is recorded when its fault is raised, and an unknown cause isn't recorded.
+	pub fn event(&self) -> Option<LogEvent> {
+		match self {
+			Self::BrownOut => Some(LogEvent::BrownOutReset),
+			Self::External => Some(LogEvent::ExternalReset),
+			Self::PowerOn => Some(LogEvent::PowerOnReset),
+			Self::Watchdog | Self::Unknown => None,
+		}
+	}
+}
+
+/// Wrapper around the watchdog timer peripheral
+pub struct Watchdog {
+	inner: WDT,
+	/// Cause of the last reset
+	reset_cause: ResetCause,
+}
+
+impl Watchdog {
+	/// Create a new [`Watchdog`], and check what caused the last reset
+	///
+	/// The watchdog stays enabled after it has reset the MCU, so it is disabled until
+	/// [`Watchdog::start`] is called. This should be called as early as possible.
+	pub fn new(wdt: WDT, cpu: &CPU) -> Self {
+		let flags = cpu.mcusr.read();
+		// A power-on reset clears the other flags, so any other flag which is set is more recent.
+		let reset_cause = if flags.wdrf().bit_is_set() {
+			ResetCause::Watchdog
+		} else if flags.borf().bit_is_set() {
+			ResetCause::BrownOut
+		} else if flags.extrf().bit_is_set() {
+			ResetCause::External
+		} else if flags.porf().bit_is_set() {
+			ResetCause::PowerOn
+		} else {
+			ResetCause::Unknown
+		};
+		// The watchdog can't be disabled while its reset flag is set, and the flags are only
+		// cleared by writing to them, so clear them all for the next reset.
+		cpu.mcusr.write(|w| {
+			w.wdrf()
+				.clear_bit()
+				.borf()
+				.clear_bit()
+				.extrf()
+				.clear_bit()
+				.porf()
+				.clear_bit()
+		});
+
+		let watchdog = Self {
+			inner: wdt,
+			reset_cause,
+		};
+		watchdog.configure(0);
+		watchdog
+	}
+
+	/// Whether the last reset was caused by the watchdog
+	pub fn caused_reset(&self) -> bool {
+		matches!(self.reset_cause, ResetCause::Watchdog)
+	}
+
+	/// Cause of the last reset
+	pub fn reset_cause(&self) -> ResetCause {
+		self.reset_cause
+	}
+
+	/// Enable the watchdog
+	///
+	/// [`Watchdog::feed`] must be called at least every 2 seconds from then on, otherwise the MCU
+	/// is reset.
+	pub fn start(&mut self) {
+		self.configure(WDE | TIMEOUT_2S);
+	}
+
+	/// Let the watchdog reset the MCU in roughly 8 seconds
+	///
+	/// Used after a panic, so that the fault can be shown for a while before the system restarts.
+	pub fn reset_after_delay(&mut self) {
+		self.configure(WDE | TIMEOUT_8S);
+	}
+
+	/// Reset the watchdog timer
+	pub fn feed(&self) {
+		avr_device::asm::wdr();
+	}
+
+	/// Write a new configuration to WDTCSR
+	fn configure(&self, bits: u8) {
+		// The new configuration must be written within 4 clock cycles of setting WDCE, so make
+		// sure that an interrupt can't happen between the two.
+		avr_device::interrupt::free(|_cs| {
+			avr_device::asm::wdr();
+			self.inner.wdtcsr.write(|w| unsafe { w.bits(WDCE | WDE) });
+			self.inner.wdtcsr.write(|w| unsafe { w.bits(bits) });
+		});
+	}
+}
@@ -0,0 +1,199 @@
+//! Watering of each of the last few days
+//!
+//! The time spent watering is counted for each day of uptime, and the last [`DAYS`] days are shown
+//! as a bar chart on the week screen, with the oldest day on the left and today on the right. The
+//! bars are scaled to the busiest day, so that a day which didn't water at all stands out. There is
+//! no clock, so a day starts every 24 hours of uptime rather than at midnight.
+
+#[cfg(feature = "atmega328p")]
+use crate::eeprom::{Eeprom, WEEK_ADDRESS};
+use crate::{
+	display::{align, BODY_START_ROW, ROW_LENGTH},
+	fmt::Buffer,
+	hal::DisplayBus,
+	lang,
+};
+
+/// Length of a day of uptime in seconds
+const DAY_SECS: u32 = 24 * 60 * 60;
+/// Amount of days which are kept, including today
+const DAYS: usize = 7;
+/// Amount of rows the bars take up
+const CHART_ROWS: u8 = 4;
+/// Row of the bottom of the bars
+const CHART_BOTTOM_ROW: u8 = BODY_START_ROW + CHART_ROWS;
+/// Row of the label under each bar, which is how many days ago it was
+const LABEL_ROW: u8 = CHART_BOTTOM_ROW + 1;
+/// Character the bars are made of
+///
+/// The display only has the ASCII characters of its terminal font, so this stands in for a block
+/// character.
+const BAR: &str = "#";
+
+/// Time spent watering on each of the last [`DAYS`] days
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Week {
+	/// Day of uptime which the last entry of [`Week::daily_secs`] is counted for
+	day: u16,
+	/// Seconds spent watering on each day, oldest first
+	daily_secs: [u32; DAYS],
+}
+
+impl Week {
+	/// Size in bytes of the record stored in the EEPROM
+	const SIZE: usize = 2 + DAYS * 4;
+
+	/// Create a new [`Week`] without any watering
+	pub fn new() -> Self {
+		Self {
+			day: 0,
+			daily_secs: [0; DAYS],
+		}
+	}
+
+	/// Count `watering_secs` towards the day of uptime at `uptime_secs`
+	///
+	/// If a new day has started, the days are moved along first, dropping the oldest.
+	pub fn record(&mut self, uptime_secs: u32, watering_secs: u32) {
+		let day = (uptime_secs / DAY_SECS) as u16;
+		if day != self.day {
+			let passed = (day.wrapping_sub(self.day) as usize).min(DAYS);
+			self.daily_secs.rotate_left(passed);
+			for secs in self.daily_secs[DAYS - passed..].iter_mut() {
+				*secs = 0;
+			}
+			self.day = day;
+		}
+
+		let today = &mut self.daily_secs[DAYS - 1];
+		*today = today.saturating_add(watering_secs);
+	}
+
+	/// Render the week screen
+	pub fn render(&self, display: &mut impl DisplayBus) {
+		display.clear_body();
+
+		// Show the busiest day next to the title, which the bars are scaled to.
+		let busiest_secs = self.daily_secs.iter().copied().max().unwrap_or(0);
+		let mut busiest = Buffer::<8>::new();
+		let _ = ufmt::uwrite!(busiest, "{}m", round_up(busiest_secs, 60, 1));
+		display.set_position(0, BODY_START_ROW);
+		let _ = ufmt::uwrite!(
+			display,
+			"{}",
+			align(lang::WEEK, busiest.as_str(), ROW_LENGTH)
+		);
+
+		for (idx, &secs) in self.daily_secs.iter().enumerate() {
+			let column = Self::column(idx);
+			// Round up, so that any watering at all shows as a bar.
+			let height = round_up(secs, busiest_secs, CHART_ROWS as u32);
+			for row in 0..height as u8 {
+				display.set_position(column, CHART_BOTTOM_ROW - row);
+				let _ = display.write_str(BAR);
+			}
+
+			display.set_position(column, LABEL_ROW);
+			let _ = ufmt::uwrite!(display, "{}", DAYS - 1 - idx);
+		}
+	}
+
+	/// Column of the bar of the day at `idx`, leaving a space between each bar
+	fn column(idx: usize) -> u8 {
+		let width = DAYS as u8 * 2 - 1;
+		(ROW_LENGTH - width) / 2 + idx as u8 * 2
+	}
+
+	fn to_bytes(self) -> [u8; Self::SIZE] {
+		let mut buf = [0; Self::SIZE];
+		buf[0..2].copy_from_slice(&self.day.to_le_bytes());
+		for (bytes, secs) in buf[2..].chunks_exact_mut(4).zip(self.daily_secs.iter()) {
+			bytes.copy_from_slice(&secs.to_le_bytes());
+		}
+		buf
+	}
+
+	/// Read the watering of each day from bytes
+	fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+		let mut week = Self::new();
+		week.day = u16::from_le_bytes([buf[0], buf[1]]);
+		for (secs, bytes) in week.daily_secs.iter_mut().zip(buf[2..].chunks_exact(4)) {
+			*secs = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+		}
+		week
+	}
+
+	/// Load the watering of each day from the EEPROM
+	///
+	/// Every day starts without any watering if it hasn't been saved before.
+	#[cfg(feature = "atmega328p")]
+	pub fn load(eeprom: &Eeprom) -> Self {
+		let mut buf = [0; Self::SIZE];
+		if eeprom.read_record(WEEK_ADDRESS, &mut buf) {
+			Self::from_bytes(&buf)
+		} else {
+			Self::new()
+		}
+	}
+
+	/// Store the watering of each day in the EEPROM
+	#[cfg(feature = "atmega328p")]
+	pub fn save(&self, eeprom: &mut Eeprom) {
+		eeprom.write_record(WEEK_ADDRESS, &self.to_bytes());
+	}
+}
+
+/// `value` multiplied by `scale` and divided by `divisor`, rounded up, or zero if `divisor` is zero
+fn round_up(value: u32, divisor: u32, scale: u32) -> u32 {
+	if divisor == 0 {
+		return 0;
+	}
+	let scaled = value as u64 * scale as u64;
+	let remainder = scaled % divisor as u64;
+	(scaled / divisor as u64) as u32 + (remainder != 0) as u32
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::hal::mock::MockDisplay;
+
+	#[test]
+	fn days_are_moved_along() {
+		let mut week = Week::new();
+		week.record(0, 60);
+		week.record(DAY_SECS, 120);
+		week.record(DAY_SECS + 10, 30);
+		assert_eq!(week.daily_secs, [0, 0, 0, 0, 0, 60, 150]);
+
+		// Skipped days have no watering, and the oldest days are dropped.
+		week.record(DAY_SECS * 3, 10);
+		assert_eq!(week.daily_secs, [0, 0, 0, 60, 150, 0, 10]);
+		week.record(DAY_SECS * 20, 5);
+		assert_eq!(week.daily_secs, [0, 0, 0, 0, 0, 0, 5]);
+	}
+
+	#[test]
+	fn saved_week_is_read_back() {
+		let mut week = Week::new();
+		week.record(DAY_SECS * 9, 70_000);
+		assert_eq!(Week::from_bytes(&week.to_bytes()), week);
+	}
+
+	#[test]
+	fn renders_bars_scaled_to_busiest_day() {
+		let mut display = MockDisplay::new();
+		let mut week = Week::new();
+		for (day, secs) in [600, 0, 1_200, 2_400, 1, 0, 1_800].iter().enumerate() {
+			week.record(day as u32 * DAY_SECS, *secs);
+		}
+		week.render(&mut display);
+
+		assert_eq!(display.row(BODY_START_ROW), "Week:        40m");
+		assert_eq!(display.row(BODY_START_ROW + 1), "       #        ");
+		assert_eq!(display.row(BODY_START_ROW + 2), "       #     #  ");
+		assert_eq!(display.row(BODY_START_ROW + 3), "     # #     #  ");
+		assert_eq!(display.row(BODY_START_ROW + 4), " #   # # #   #  ");
+		assert_eq!(display.row(LABEL_ROW), " 6 5 4 3 2 1 0  ");
+	}
+}
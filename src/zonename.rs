@@ -0,0 +1,232 @@
+//! Name of the zone
+//!
+//! The zone can be given a short name, such as `Veggies`, which is shown in the header in place of
+//! the name of the system, logged over serial when watering starts and stops, and published to
+//! `garden/name`. It is edited a character at a time from **Name** in the menu: Left and Right
+//! change the character above the cursor, and Select moves on to the next character. Selecting the
+//! last character saves the name in the EEPROM, and holding Select leaves without saving it.
+//!
+//! There is a single valve, so there is only one zone to name.
+
+#[cfg(feature = "atmega328p")]
+use crate::eeprom::{Eeprom, ZONE_NAME_ADDRESS};
+use crate::{
+	control_pad::{ButtonEvent, ButtonStage, ButtonType},
+	display::BODY_START_ROW,
+	hal::DisplayBus,
+	lang,
+};
+
+/// Most characters in a name
+const NAME_LENGTH: usize = 8;
+/// Characters a name can be made of, in the order Right moves through them
+const CHARACTERS: &[u8] = b" ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-";
+/// Name of the zone until it is renamed
+const DEFAULT_NAME: [u8; NAME_LENGTH] = *b"Zone 1  ";
+/// Row of the name being edited
+const NAME_ROW: u8 = BODY_START_ROW + 2;
+/// Row of the cursor, under the character being edited
+const CURSOR_ROW: u8 = NAME_ROW + 1;
+
+/// Name of the zone, padded with spaces
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ZoneName([u8; NAME_LENGTH]);
+
+impl ZoneName {
+	/// Size in bytes of the name stored in the EEPROM, not including its checksum
+	pub const SIZE: usize = NAME_LENGTH;
+
+	/// Create a new [`ZoneName`] with the default name
+	pub fn new() -> Self {
+		Self(DEFAULT_NAME)
+	}
+
+	/// Name without the padding
+	pub fn as_str(&self) -> &str {
+		let len = self
+			.0
+			.iter()
+			.rposition(|&character| character != b' ')
+			.map_or(0, |idx| idx + 1);
+		// Only ASCII characters are allowed in a name.
+		core::str::from_utf8(&self.0[..len]).unwrap_or("")
+	}
+
+	/// Read a name from bytes, if every character is allowed
+	fn from_bytes(buf: &[u8; Self::SIZE]) -> Option<Self> {
+		if buf.iter().all(|character| CHARACTERS.contains(character)) {
+			Some(Self(*buf))
+		} else {
+			None
+		}
+	}
+
+	/// Load the name saved in the EEPROM, or the default name if one was never saved
+	#[cfg(feature = "atmega328p")]
+	pub fn load(eeprom: &Eeprom) -> Self {
+		let mut buf = [0; Self::SIZE];
+		if !eeprom.read_record(ZONE_NAME_ADDRESS, &mut buf) {
+			return Self::new();
+		}
+		Self::from_bytes(&buf).unwrap_or_else(Self::new)
+	}
+
+	/// Store the name in the EEPROM, to be used the next time the system starts up
+	#[cfg(feature = "atmega328p")]
+	pub fn save(&self, eeprom: &mut Eeprom) {
+		eeprom.write_record(ZONE_NAME_ADDRESS, &self.0);
+	}
+
+	/// Character after (or before) the one at `position`, wrapping around [`CHARACTERS`]
+	fn step(&mut self, position: usize, forwards: bool) {
+		let count = CHARACTERS.len();
+		let idx = CHARACTERS
+			.iter()
+			.position(|character| *character == self.0[position])
+			.unwrap_or(0);
+		let idx = if forwards {
+			(idx + 1) % count
+		} else {
+			(idx + count - 1) % count
+		};
+		self.0[position] = CHARACTERS[idx];
+	}
+}
+
+/// Outcome of a button press on the name screen
+#[derive(PartialEq, Debug)]
+pub enum Edit {
+	/// The name is still being edited
+	Editing,
+	/// The last character was selected, and the name should be saved
+	Saved(ZoneName),
+	/// Select was held, and the name should be left as it was
+	Cancelled,
+}
+
+/// Name screen, which keeps track of the name being edited and the character under the cursor
+pub struct NameEditor {
+	/// Name being edited
+	name: ZoneName,
+	/// Index of the character being edited
+	position: usize,
+}
+
+impl NameEditor {
+	/// Create a new [`NameEditor`]
+	pub fn new() -> Self {
+		Self {
+			name: ZoneName::new(),
+			position: 0,
+		}
+	}
+
+	/// Start editing `name` from its first character, and render the name screen
+	pub fn start(&mut self, name: ZoneName, display: &mut impl DisplayBus) {
+		self.name = name;
+		self.position = 0;
+		display.clear_body();
+		display.set_position(0, BODY_START_ROW);
+		let _ = ufmt::uwrite!(display, "{}", lang::NAME);
+		self.render(display);
+	}
+
+	/// Handle a button press on the name screen
+	pub fn on_press(&mut self, event: &ButtonEvent, display: &mut impl DisplayBus) -> Edit {
+		match (&event.stage, &event.button) {
+			(ButtonStage::Release, ButtonType::Left) => self.name.step(self.position, false),
+			(ButtonStage::Release, ButtonType::Right) => self.name.step(self.position, true),
+			(ButtonStage::Release, ButtonType::Select) => {
+				self.position += 1;
+				if self.position == NAME_LENGTH {
+					return Edit::Saved(self.name);
+				}
+			}
+			(ButtonStage::LongPress, ButtonType::Select) => return Edit::Cancelled,
+			_ => return Edit::Editing,
+		}
+
+		self.render(display);
+		Edit::Editing
+	}
+
+	/// Render the name, and the cursor under the character being edited
+	fn render(&self, display: &mut impl DisplayBus) {
+		display.clear_row(NAME_ROW);
+		// Only ASCII characters are allowed in a name.
+		let _ = display.write_str(core::str::from_utf8(&self.name.0).unwrap_or(""));
+		display.clear_row(CURSOR_ROW);
+		display.set_position(self.position as u8, CURSOR_ROW);
+		let _ = display.write_str("^");
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::hal::mock::MockDisplay;
+
+	fn press(editor: &mut NameEditor, button: ButtonType, display: &mut MockDisplay) -> Edit {
+		let event = ButtonEvent {
+			button,
+			stage: ButtonStage::Release,
+		};
+		editor.on_press(&event, display)
+	}
+
+	#[test]
+	fn edits_a_character_at_a_time() {
+		let mut display = MockDisplay::new();
+		let mut editor = NameEditor::new();
+		editor.start(ZoneName::new(), &mut display);
+		assert_eq!(display.row(NAME_ROW).trim_end(), "Zone 1");
+		assert_eq!(display.row(CURSOR_ROW).trim_end(), "^");
+
+		// Right and Left wrap around the characters.
+		press(&mut editor, ButtonType::Right, &mut display);
+		press(&mut editor, ButtonType::Left, &mut display);
+		press(&mut editor, ButtonType::Left, &mut display);
+		assert_eq!(display.row(NAME_ROW).trim_end(), "Yone 1");
+
+		press(&mut editor, ButtonType::Select, &mut display);
+		assert_eq!(display.row(CURSOR_ROW).trim_end(), " ^");
+		for _ in 0..6 {
+			press(&mut editor, ButtonType::Select, &mut display);
+		}
+		for _ in 0..2 {
+			press(&mut editor, ButtonType::Right, &mut display);
+		}
+		let mut expected = ZoneName(*b"Yone 1 B");
+		assert_eq!(
+			press(&mut editor, ButtonType::Select, &mut display),
+			Edit::Saved(expected)
+		);
+		assert_eq!(expected.as_str(), "Yone 1 B");
+
+		// Trailing spaces are left out.
+		expected.step(7, false);
+		expected.step(7, false);
+		assert_eq!(expected.as_str(), "Yone 1");
+	}
+
+	#[test]
+	fn holding_select_cancels() {
+		let mut display = MockDisplay::new();
+		let mut editor = NameEditor::new();
+		editor.start(ZoneName::new(), &mut display);
+		let event = ButtonEvent {
+			button: ButtonType::Select,
+			stage: ButtonStage::LongPress,
+		};
+		assert_eq!(editor.on_press(&event, &mut display), Edit::Cancelled);
+	}
+
+	#[test]
+	fn only_allowed_characters_are_loaded() {
+		assert_eq!(
+			ZoneName::from_bytes(b"Veggies "),
+			Some(ZoneName(*b"Veggies "))
+		);
+		assert_eq!(ZoneName::from_bytes(&[0xff; ZoneName::SIZE]), None);
+	}
+}